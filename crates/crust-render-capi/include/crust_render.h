@@ -0,0 +1,109 @@
+/*
+ * The C API of Crust Render, to embed the renderer in C and C++ applications.
+ *
+ * A scene is built with crust_scene_new, its materials and objects added to it, then
+ * rendered into a buffer of the caller with crust_render:
+ *
+ *     CrustScene *scene = crust_scene_new();
+ *     int32_t red = crust_scene_add_lambertian(scene, 0.8, 0.1, 0.1);
+ *     double center[3] = {0.0, 0.0, 0.0};
+ *     crust_scene_add_sphere(scene, center, 1.0, red);
+ *     CrustRenderSettings settings = crust_render_settings_default();
+ *     float *pixels = malloc(3 * sizeof(float) * settings.width * settings.height);
+ *     if (crust_render(scene, &settings, pixels) != CRUST_OK)
+ *         fprintf(stderr, "%s\n", crust_last_error());
+ *     crust_scene_free(scene);
+ *
+ * Link with -lcrust_render_capi, built by `cargo build -p crust-render-capi --release`.
+ */
+#ifndef CRUST_RENDER_H
+#define CRUST_RENDER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The outcome of a call, the message of a failure given by crust_last_error. */
+typedef enum CrustStatus {
+    CRUST_OK = 0,
+    /* A pointer argument was null. */
+    CRUST_NULL_POINTER = 1,
+    /* An argument was out of its range, e.g. an unknown material or a zero width. */
+    CRUST_INVALID_ARGUMENT = 2,
+    /* The renderer panicked, the scene should not be used anymore. */
+    CRUST_PANIC = 3,
+} CrustStatus;
+
+/* The settings of a render, see crust_render_settings_default. */
+typedef struct CrustRenderSettings {
+    uint32_t width;
+    uint32_t height;
+    uint32_t samples_per_pixel;
+    uint32_t max_depth;
+    uint32_t seed;
+} CrustRenderSettings;
+
+/* A scene, opaque. */
+typedef struct CrustScene CrustScene;
+
+/* The default settings: a 400x225 image of 64 samples per pixel and paths of up to
+ * 32 bounces. */
+CrustRenderSettings crust_render_settings_default(void);
+
+/* Creates an empty scene under the sky, seen from (0, 0, 5) towards the origin, to
+ * free with crust_scene_free. */
+CrustScene *crust_scene_new(void);
+
+void crust_scene_free(CrustScene *scene);
+
+/* Sets the camera: its position, the point it looks at and its up direction, of 3
+ * doubles each, its vertical field of view in degrees, the diameter of its lens, 0
+ * for a pinhole, and the distance in focus. */
+CrustStatus crust_scene_set_camera(CrustScene *scene, const double *lookfrom,
+                                   const double *lookat, const double *vup, double vfov,
+                                   double aperture, double focus_dist);
+
+/* Sets the radiance seen in every direction by the rays escaping the scene, instead
+ * of the sky. */
+CrustStatus crust_scene_set_background(CrustScene *scene, double r, double g, double b);
+
+/* The materials, returning their index in the scene, or -1 if the scene is null. */
+int32_t crust_scene_add_lambertian(CrustScene *scene, double r, double g, double b);
+int32_t crust_scene_add_metal(CrustScene *scene, double r, double g, double b,
+                              double fuzz);
+int32_t crust_scene_add_dielectric(CrustScene *scene, double ior);
+int32_t crust_scene_add_cook_torrance(CrustScene *scene, double r, double g, double b,
+                                      double roughness, double metallic);
+/* An emissive material, the objects of which are the lights of the scene. */
+int32_t crust_scene_add_emissive(CrustScene *scene, double r, double g, double b);
+
+/* Adds a sphere of a material of the scene, its center of 3 doubles. */
+CrustStatus crust_scene_add_sphere(CrustScene *scene, const double *center, double radius,
+                                   uint32_t material);
+
+/* Adds a triangle mesh of a material of the scene: vertex_count vertices of 3 floats
+ * and index_count indices, 3 per triangle. Counts too large for the arrays to fit in
+ * memory are an invalid argument. */
+CrustStatus crust_scene_add_mesh(CrustScene *scene, const float *vertices,
+                                 size_t vertex_count, const uint32_t *indices,
+                                 size_t index_count, uint32_t material);
+
+/* Renders the scene into pixels, the linear RGB of the image, top row first, of
+ * 3 * width * height floats. */
+CrustStatus crust_render(const CrustScene *scene, const CrustRenderSettings *settings,
+                         float *pixels);
+
+/* The message of the last error of the thread, valid until the next failure on it. */
+const char *crust_last_error(void);
+
+/* The version of the library, e.g. "0.1.0". */
+const char *crust_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRUST_RENDER_H */
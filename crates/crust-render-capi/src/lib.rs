@@ -0,0 +1,560 @@
+//! A C ABI over `crust_render`, to embed the renderer in C and C++ applications.
+//!
+//! A scene is built with `crust_scene_new`, its materials and objects added to it,
+//! then rendered into a buffer of the caller with `crust_render`. The functions
+//! return a `CrustStatus`, the message of the last error being kept per thread for
+//! `crust_last_error`. The declarations are in `include/crust_render.h`.
+// The casts from `Float` are needed in double precision
+#![allow(clippy::unnecessary_cast)]
+use crust_render::{
+    Background, Camera, CookTorrance, Dielectric, DocObject, Emissive, Lambertian, MaterialType,
+    Metal, ObjectList, Primitive, RenderSettings, Renderer, Scene,
+};
+use std::cell::RefCell;
+use std::ffi::{CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use utils::{Color, Float, Point3, Vec3};
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrustStatus {
+    Ok = 0,
+    /// A pointer argument was null.
+    NullPointer = 1,
+    /// An argument was out of its range, e.g. an unknown material or a zero width.
+    InvalidArgument = 2,
+    /// The renderer panicked, the scene should not be used anymore.
+    Panic = 3,
+}
+
+/// The settings of a render, see `crust_render_settings_default`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CrustRenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub seed: u32,
+}
+
+/// The camera of a scene, its aspect ratio taken from the image rendered.
+struct CameraParams {
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    vfov: Float,
+    aperture: Float,
+    focus_dist: Float,
+}
+
+/// A scene built from C, opaque to it.
+pub struct CrustScene {
+    camera: CameraParams,
+    materials: Vec<MaterialType>,
+    objects: ObjectList,
+    object_count: usize,
+    background: Background,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(status: CrustStatus, message: &str) -> CrustStatus {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+    status
+}
+
+/// Runs `f`, turning its panics into `CrustStatus::Panic`.
+fn guard(f: impl FnOnce() -> CrustStatus) -> CrustStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(status) => status,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "the renderer panicked".to_string());
+            set_error(CrustStatus::Panic, &message)
+        }
+    }
+}
+
+/// The length of a slice of `count` items of `values` values of `T` each, `None` when
+/// it overflows or is larger than a slice can be.
+fn slice_len<T>(count: usize, values: usize) -> Option<usize> {
+    let len = count.checked_mul(values)?;
+    (len.checked_mul(size_of::<T>())? <= isize::MAX as usize).then_some(len)
+}
+
+fn point(p: [f64; 3]) -> Point3 {
+    Point3::new(p[0] as Float, p[1] as Float, p[2] as Float)
+}
+
+fn color(r: f64, g: f64, b: f64) -> Color {
+    Color::new(r as Float, g as Float, b as Float)
+}
+
+/// Adds a material to a scene.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+fn add_material(scene: *mut CrustScene, material: MaterialType) -> i32 {
+    // SAFETY: the scene comes from `crust_scene_new`, see the callers
+    match unsafe { scene.as_mut() } {
+        Some(scene) => {
+            scene.materials.push(material);
+            scene.materials.len() as i32 - 1
+        }
+        None => {
+            set_error(CrustStatus::NullPointer, "the scene is null");
+            -1
+        }
+    }
+}
+
+/// Adds an object of a material of a scene to it, an emissive material being sampled
+/// as a light on the sphere bounding the object.
+fn add_object(
+    scene: *mut CrustScene,
+    primitive: Primitive,
+    bounds: (Point3, Float),
+    material: u32,
+) -> CrustStatus {
+    // SAFETY: the scene comes from `crust_scene_new`, see the callers
+    let Some(scene) = (unsafe { scene.as_mut() }) else {
+        return set_error(CrustStatus::NullPointer, "the scene is null");
+    };
+    let Some(material) = scene.materials.get(material as usize).cloned() else {
+        return set_error(
+            CrustStatus::InvalidArgument,
+            &format!("the scene has no material {material}"),
+        );
+    };
+    let material = match material {
+        MaterialType::Emissive(emissive) => {
+            MaterialType::Emissive(Emissive::new(emissive.color(), bounds.0, bounds.1))
+        }
+        material => material,
+    };
+    scene.object_count += 1;
+    let name = format!("object{}", scene.object_count);
+    scene.objects.add(DocObject::new(name, primitive, material));
+    CrustStatus::Ok
+}
+
+/// The default settings: a 400x225 image of 64 samples per pixel and paths of up to
+/// 32 bounces.
+#[unsafe(no_mangle)]
+pub extern "C" fn crust_render_settings_default() -> CrustRenderSettings {
+    let settings = RenderSettings::default();
+    let (width, height) = settings.get_dimensions();
+    CrustRenderSettings {
+        width: width as u32,
+        height: height as u32,
+        samples_per_pixel: settings.samples_per_pixel(),
+        max_depth: settings.max_depth(),
+        seed: settings.seed(),
+    }
+}
+
+/// Creates an empty scene under the sky, seen from `(0, 0, 5)` towards the origin.
+///
+/// # Returns
+/// - The scene, to free with `crust_scene_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn crust_scene_new() -> *mut CrustScene {
+    Box::into_raw(Box::new(CrustScene {
+        camera: CameraParams {
+            lookfrom: Point3::new(0.0, 0.0, 5.0),
+            lookat: Point3::zero(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 5.0,
+        },
+        materials: Vec::new(),
+        objects: ObjectList::new(Vec::new()),
+        object_count: 0,
+        background: Background::default(),
+    }))
+}
+
+/// Frees a scene.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new` not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_free(scene: *mut CrustScene) {
+    if !scene.is_null() {
+        // SAFETY: the scene was boxed by `crust_scene_new`
+        drop(unsafe { Box::from_raw(scene) });
+    }
+}
+
+/// Sets the camera of a scene.
+///
+/// # Parameters
+/// - `lookfrom`, `lookat`, `vup`: The position of the camera, the point it looks at and
+///   its up direction.
+/// - `vfov`: The vertical field of view, in degrees.
+/// - `aperture`, `focus_dist`: The diameter of the lens, 0 for a pinhole, and the
+///   distance in focus.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`, the vectors null or of 3 doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_set_camera(
+    scene: *mut CrustScene,
+    lookfrom: *const f64,
+    lookat: *const f64,
+    vup: *const f64,
+    vfov: f64,
+    aperture: f64,
+    focus_dist: f64,
+) -> CrustStatus {
+    // SAFETY: see the safety section
+    let (scene, lookfrom, lookat, vup) = unsafe {
+        (
+            scene.as_mut(),
+            lookfrom.cast::<[f64; 3]>().as_ref(),
+            lookat.cast::<[f64; 3]>().as_ref(),
+            vup.cast::<[f64; 3]>().as_ref(),
+        )
+    };
+    let (Some(scene), Some(lookfrom), Some(lookat), Some(vup)) = (scene, lookfrom, lookat, vup)
+    else {
+        return set_error(CrustStatus::NullPointer, "the scene or a vector is null");
+    };
+    scene.camera = CameraParams {
+        lookfrom: point(*lookfrom),
+        lookat: point(*lookat),
+        vup: point(*vup),
+        vfov: vfov as Float,
+        aperture: aperture as Float,
+        focus_dist: focus_dist as Float,
+    };
+    CrustStatus::Ok
+}
+
+/// Sets the radiance seen in every direction by the rays escaping a scene, instead of
+/// the sky.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_set_background(
+    scene: *mut CrustScene,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> CrustStatus {
+    // SAFETY: see the safety section
+    let Some(scene) = (unsafe { scene.as_mut() }) else {
+        return set_error(CrustStatus::NullPointer, "the scene is null");
+    };
+    scene.background = Background::Uniform(color(r, g, b));
+    CrustStatus::Ok
+}
+
+/// Adds a diffuse material to a scene.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_lambertian(
+    scene: *mut CrustScene,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> i32 {
+    add_material(
+        scene,
+        MaterialType::Lambertian(Lambertian::new(color(r, g, b))),
+    )
+}
+
+/// Adds a metal material to a scene, blurred by `fuzz` from 0 to 1.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_metal(
+    scene: *mut CrustScene,
+    r: f64,
+    g: f64,
+    b: f64,
+    fuzz: f64,
+) -> i32 {
+    add_material(
+        scene,
+        MaterialType::Metal(Metal::new(color(r, g, b), fuzz as Float)),
+    )
+}
+
+/// Adds a glass material of index of refraction `ior` to a scene.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_dielectric(scene: *mut CrustScene, ior: f64) -> i32 {
+    add_material(
+        scene,
+        MaterialType::Dielectric(Dielectric::new(ior as Float)),
+    )
+}
+
+/// Adds a microfacet material to a scene, of `roughness` and `metallic` from 0 to 1.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_cook_torrance(
+    scene: *mut CrustScene,
+    r: f64,
+    g: f64,
+    b: f64,
+    roughness: f64,
+    metallic: f64,
+) -> i32 {
+    add_material(
+        scene,
+        MaterialType::CookTorrance(CookTorrance::new(
+            color(r, g, b),
+            roughness as Float,
+            metallic as Float,
+        )),
+    )
+}
+
+/// Adds an emissive material of radiance `(r, g, b)` to a scene, the objects of which
+/// are its lights.
+///
+/// # Returns
+/// - The index of the material, or -1 if `scene` is null.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_emissive(
+    scene: *mut CrustScene,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> i32 {
+    add_material(
+        scene,
+        MaterialType::Emissive(Emissive::new(color(r, g, b), Point3::zero(), 1.0)),
+    )
+}
+
+/// Adds a sphere of a material of the scene to it.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`, `center` null or of 3 doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_sphere(
+    scene: *mut CrustScene,
+    center: *const f64,
+    radius: f64,
+    material: u32,
+) -> CrustStatus {
+    guard(|| {
+        // SAFETY: see the safety section
+        let Some(center) = (unsafe { center.cast::<[f64; 3]>().as_ref() }) else {
+            return set_error(CrustStatus::NullPointer, "the center is null");
+        };
+        if radius.is_nan() || radius <= 0.0 {
+            return set_error(CrustStatus::InvalidArgument, "the radius is not positive");
+        }
+        let center = point(*center);
+        let radius = radius as Float;
+        add_object(
+            scene,
+            Primitive::Sphere { center, radius },
+            (center, radius),
+            material,
+        )
+    })
+}
+
+/// Adds a triangle mesh of a material of the scene to it.
+///
+/// # Parameters
+/// - `vertices`: The `vertex_count` vertices, 3 floats each.
+/// - `indices`: The `index_count` indices of the vertices of the triangles, 3 each.
+///
+/// # Returns
+/// - `InvalidArgument` for indices out of the vertices, or counts too large for the
+///   arrays to fit in memory.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`, `vertices` null or of
+/// `3 * vertex_count` floats and `indices` null or of `index_count` integers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_scene_add_mesh(
+    scene: *mut CrustScene,
+    vertices: *const f32,
+    vertex_count: usize,
+    indices: *const u32,
+    index_count: usize,
+    material: u32,
+) -> CrustStatus {
+    guard(|| {
+        if vertices.is_null() || indices.is_null() {
+            return set_error(CrustStatus::NullPointer, "the vertices or indices are null");
+        }
+        let (Some(vertices_len), Some(indices_len)) = (
+            slice_len::<f32>(vertex_count, 3),
+            slice_len::<u32>(index_count, 1),
+        ) else {
+            return set_error(
+                CrustStatus::InvalidArgument,
+                "the vertex or index count is too large",
+            );
+        };
+        // SAFETY: see the safety section
+        let (vertices, indices) = unsafe {
+            (
+                std::slice::from_raw_parts(vertices, vertices_len),
+                std::slice::from_raw_parts(indices, indices_len),
+            )
+        };
+        if !index_count.is_multiple_of(3) || indices.iter().any(|&i| i as usize >= vertex_count) {
+            return set_error(
+                CrustStatus::InvalidArgument,
+                "the indices are not triangles of the vertices",
+            );
+        }
+        let vertices: Vec<Point3> = vertices
+            .chunks_exact(3)
+            .map(|v| Point3::new(v[0] as Float, v[1] as Float, v[2] as Float))
+            .collect();
+        let center = vertices.iter().fold(Point3::zero(), |sum, &v| sum + v)
+            / vertices.len().max(1) as Float;
+        let radius = vertices
+            .iter()
+            .map(|&v| (v - center).length())
+            .fold(0.0, Float::max);
+        add_object(
+            scene,
+            Primitive::Mesh {
+                vertices,
+                indices: indices.to_vec(),
+            },
+            (center, radius),
+            material,
+        )
+    })
+}
+
+/// Renders a scene into `pixels`, the linear RGB of the image, top row first, 3 floats
+/// a pixel.
+///
+/// # Safety
+/// `scene` is null or a scene of `crust_scene_new`, `settings` null or valid and
+/// `pixels` null or of `3 * width * height` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crust_render(
+    scene: *const CrustScene,
+    settings: *const CrustRenderSettings,
+    pixels: *mut f32,
+) -> CrustStatus {
+    guard(|| {
+        // SAFETY: see the safety section
+        let (Some(scene), Some(settings)) =
+            (unsafe { scene.as_ref() }, unsafe { settings.as_ref() })
+        else {
+            return set_error(CrustStatus::NullPointer, "the scene or settings are null");
+        };
+        if pixels.is_null() {
+            return set_error(CrustStatus::NullPointer, "the pixels are null");
+        }
+        if settings.width == 0 || settings.height == 0 {
+            return set_error(CrustStatus::InvalidArgument, "the image is empty");
+        }
+        let (width, height) = (settings.width as usize, settings.height as usize);
+        let Some(pixels_len) = width
+            .checked_mul(height)
+            .and_then(|count| slice_len::<f32>(count, 3))
+        else {
+            return set_error(CrustStatus::InvalidArgument, "the image is too large");
+        };
+        let params = &scene.camera;
+        let camera = Camera::new(
+            params.lookfrom,
+            params.lookat,
+            params.vup,
+            params.vfov,
+            width as Float / height as Float,
+            params.aperture,
+            params.focus_dist,
+        );
+        let (world, lights) = scene.objects.get_world();
+        let settings = RenderSettings::default()
+            .with_dimensions(width, height)
+            .with_samples_per_pixel(settings.samples_per_pixel)
+            .with_max_depth(settings.max_depth)
+            .with_seed(settings.seed);
+        let scene = Scene::new(camera, world, lights).with_background(scene.background);
+        let film = Renderer::new(scene, settings).render();
+        // SAFETY: see the safety section
+        let pixels = unsafe { std::slice::from_raw_parts_mut(pixels, pixels_len) };
+        for (index, pixel) in pixels.chunks_exact_mut(3).enumerate() {
+            let (r, g, b) = film.get_rgb(index % width, index / width);
+            pixel.copy_from_slice(&[r as f32, g as f32, b as f32]);
+        }
+        CrustStatus::Ok
+    })
+}
+
+/// The message of the last error of the thread.
+///
+/// # Returns
+/// - A string valid until the next call failing on the thread, empty if none did.
+#[unsafe(no_mangle)]
+pub extern "C" fn crust_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+/// The version of the library, e.g. `"0.1.0"`.
+#[unsafe(no_mangle)]
+pub extern "C" fn crust_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::NonNull;
+
+    #[test]
+    fn rejects_overflowing_mesh_counts() {
+        let scene = crust_scene_new();
+        let (vertices, indices) = (NonNull::dangling().as_ptr(), NonNull::dangling().as_ptr());
+        for (vertex_count, index_count) in [(usize::MAX / 2, 0), (0, usize::MAX / 2)] {
+            // SAFETY: the counts are rejected before the arrays are read
+            let status = unsafe {
+                crust_scene_add_mesh(scene, vertices, vertex_count, indices, index_count, 0)
+            };
+            assert_eq!(status, CrustStatus::InvalidArgument);
+        }
+        // SAFETY: the scene comes from `crust_scene_new`
+        unsafe { crust_scene_free(scene) };
+    }
+}
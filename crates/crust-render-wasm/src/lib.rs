@@ -0,0 +1,87 @@
+//! `crust_render` in the browser, for the demo of `www/` rendering the random scene
+//! into a canvas.
+//!
+//! Each web worker renders the whole image with its own seeds, a few samples per
+//! pixel at a time, and the page averages the renders of the workers as they come,
+//! the image refining as the samples add up. Build it with
+//! `wasm-pack build --target web crates/crust-render-wasm`.
+// The casts from `Float` are needed in double precision
+#![allow(clippy::unnecessary_cast)]
+use crust_render::{DisplayTransform, RandomScene, Renderer};
+use utils::Float;
+use wasm_bindgen::prelude::*;
+
+/// A renderer of the random scene of spheres, at the 16:9 aspect ratio of its camera.
+#[wasm_bindgen]
+pub struct RandomSceneRenderer {
+    renderer: Renderer,
+}
+
+#[wasm_bindgen]
+impl RandomSceneRenderer {
+    /// Draws the random scene, its spheres spanning `[-grid, grid)` from the seed
+    /// `scene_seed`, the same in every worker.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, grid: u32, scene_seed: u32) -> RandomSceneRenderer {
+        let doc = RandomScene::default()
+            .with_grid(grid)
+            .with_seed(scene_seed)
+            .document();
+        let settings = doc
+            .settings()
+            .with_dimensions(width, (width * 9 / 16).max(1))
+            .with_max_depth(8);
+        RandomSceneRenderer {
+            renderer: doc.renderer(settings),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.renderer.settings.get_dimensions().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.renderer.settings.get_dimensions().1
+    }
+
+    /// Renders the image at `samples` samples per pixel, its random numbers drawn from
+    /// `seed`, to be averaged with the renders of the other seeds.
+    ///
+    /// # Returns
+    /// - The linear RGB of the image, top row first, 3 floats a pixel.
+    pub fn render(&mut self, samples: u32, seed: u32) -> Vec<f32> {
+        let settings = &self.renderer.settings;
+        self.renderer.settings = settings
+            .with_samples_per_pixel(samples)
+            .with_pass_samples(samples)
+            .with_seed(seed);
+        let film = self.renderer.render();
+        let (width, height) = (film.width(), film.height());
+        let mut pixels = Vec::with_capacity(3 * width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = film.get_rgb(x, y);
+                pixels.extend_from_slice(&[r as f32, g as f32, b as f32]);
+            }
+        }
+        pixels
+    }
+
+    /// Brings the sum of `renders` renders to the display, as the RGBA of an
+    /// `ImageData` of the canvas.
+    pub fn to_rgba(&self, sum: &[f32], renders: u32) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let transform = DisplayTransform::new(&self.renderer.settings, width, height);
+        let scale = 1.0 / renders.max(1) as Float;
+        let pixel = |x, y| {
+            let index = 3 * (y * width + x);
+            [0, 1, 2].map(|c| sum[index + c] as Float * scale)
+        };
+        (0..width * height)
+            .flat_map(|index| {
+                let [r, g, b] = transform.apply(index % width, index / width, pixel);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
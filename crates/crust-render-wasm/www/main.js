@@ -0,0 +1,41 @@
+// Renders the random scene progressively: each worker renders the whole image with its
+// own seeds, a few samples per pixel at a time, and the renders are averaged as they
+// come.
+import init, { RandomSceneRenderer } from "../pkg/crust_render_wasm.js";
+
+const WIDTH = 400;
+const GRID = 11;
+const SCENE_SEED = 1;
+const SAMPLES = 2;
+
+await init();
+const display = new RandomSceneRenderer(WIDTH, GRID, SCENE_SEED);
+const [width, height] = [display.width(), display.height()];
+const canvas = document.getElementById("canvas");
+const status = document.getElementById("status");
+canvas.width = width;
+canvas.height = height;
+const context = canvas.getContext("2d");
+
+const sum = new Float32Array(3 * width * height);
+let renders = 0;
+let seed = 0;
+const start = performance.now();
+
+const workers = navigator.hardwareConcurrency || 4;
+for (let i = 0; i < workers; i++) {
+  const worker = new Worker(new URL("worker.js", import.meta.url), { type: "module" });
+  worker.onmessage = ({ data }) => {
+    if (data.pixels) {
+      const pixels = new Float32Array(data.pixels);
+      for (let j = 0; j < sum.length; j++) sum[j] += pixels[j];
+      renders += 1;
+      const rgba = display.to_rgba(sum, renders);
+      context.putImageData(new ImageData(new Uint8ClampedArray(rgba), width, height), 0, 0);
+      const seconds = ((performance.now() - start) / 1000).toFixed(1);
+      status.textContent = `${renders * SAMPLES} samples per pixel, ${workers} workers, ${seconds}s`;
+    }
+    worker.postMessage({ samples: SAMPLES, seed: seed++ });
+  };
+  worker.postMessage({ width: WIDTH, grid: GRID, sceneSeed: SCENE_SEED });
+}
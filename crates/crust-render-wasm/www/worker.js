@@ -0,0 +1,17 @@
+// Renders the random scene with the seeds it is sent, sending back the linear RGB of
+// each render.
+import init, { RandomSceneRenderer } from "../pkg/crust_render_wasm.js";
+
+const ready = init();
+let renderer;
+
+onmessage = async ({ data }) => {
+  await ready;
+  if (data.width) {
+    renderer = new RandomSceneRenderer(data.width, data.grid, data.sceneSeed);
+    postMessage({});
+    return;
+  }
+  const pixels = renderer.render(data.samples, data.seed);
+  postMessage({ pixels: pixels.buffer }, [pixels.buffer]);
+};
@@ -1,14 +1,8 @@
 use criterion::{Criterion, criterion_group, criterion_main};
 use crust_render::Camera;
-use crust_render::simple_scene;
-use crust_render::{RenderSettings, Renderer};
-use utils::{Point3, Vec3};
-
-const ASPECT_RATIO: f32 = 16.0 / 9.0;
-const IMAGE_WIDTH: usize = 400;
-const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f32 / ASPECT_RATIO) as usize;
-const MIN_SAMPLES: u32 = 8;
-const VARIANCE_THRESHOLD: f32 = 0.0005; // You can tweak this!
+use crust_render::{RenderSettings, Renderer, Scene};
+use crust_render::{builtin_scene, simple_scene};
+use utils::{Float, Point3, Vec3};
 
 fn bench_dot(c: &mut Criterion) {
     let vec1 = Vec3::new(1.0, 2.0, 3.0);
@@ -24,6 +18,10 @@ fn bench_dot(c: &mut Criterion) {
 fn bench_simple_world(c: &mut Criterion) {
     c.bench_function("simple world", |b| {
         b.iter(|| {
+            let render_settings = RenderSettings::default()
+                .with_samples_per_pixel(10)
+                .with_max_depth(20);
+            let (width, height) = render_settings.get_dimensions();
             let (world, lights) = simple_scene();
             let lookfrom = Point3::new(13.0, 2.0, 3.0);
             let lookat = Point3::new(0.0, 0.0, 0.0);
@@ -36,23 +34,32 @@ fn bench_simple_world(c: &mut Criterion) {
                 lookat,
                 vup,
                 20.0,
-                ASPECT_RATIO,
+                width as Float / height as Float,
                 aperture,
                 dist_to_focus,
             );
-            let render_settings = RenderSettings::new(
-                10,
-                20,
-                IMAGE_WIDTH,
-                IMAGE_HEIGHT,
-                MIN_SAMPLES,
-                VARIANCE_THRESHOLD,
-            );
-            let renderer = Renderer::new(cam, world, lights, render_settings);
+            let renderer = Renderer::new(Scene::new(cam, world, lights), render_settings);
+            let _ = renderer.render();
+        })
+    });
+}
+
+// Shades every kind of material, to compare the dispatch of the materials: run it
+// with and without the `enum-dispatch` feature.
+fn bench_materials(c: &mut Criterion) {
+    let doc = builtin_scene("material-test-spheres").unwrap();
+    let settings = doc
+        .settings()
+        .with_dimensions(200, 100)
+        .with_samples_per_pixel(4)
+        .with_seed(1);
+    let renderer = Renderer::new(doc.scene(), settings);
+    c.bench_function("material dispatch", |b| {
+        b.iter(|| {
             let _ = renderer.render();
         })
     });
 }
 
-criterion_group!(name = benches;config = Criterion::default(); targets= bench_dot,bench_simple_world);
+criterion_group!(name = benches;config = Criterion::default(); targets= bench_dot,bench_simple_world,bench_materials);
 criterion_main!(benches);
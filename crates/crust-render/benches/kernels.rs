@@ -0,0 +1,196 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use crust_render::{
+    AABB, BlinnPhong, CookTorrance, Dielectric, Disney, Emissive, HitRecord, Hittable,
+    IndependentSampler, Lambertian, Material, MaterialType, Metal, Object, RandomScene, Ray,
+};
+use std::hint::black_box;
+use utils::{Color, Float, Point3, Vec3};
+
+/// The number of rays traced by each iteration of the intersection benches.
+const RAYS: usize = 1024;
+
+/// Rays from in front of the origin towards a grid of points around it, about half of
+/// them hitting a unit sphere at the origin.
+fn rays() -> Vec<Ray> {
+    let side = (RAYS as Float).sqrt() as usize;
+    let origin = Point3::new(0.0, 0.0, 5.0);
+    (0..RAYS)
+        .map(|i| {
+            let (u, v) = ((i % side) as Float, (i / side) as Float);
+            let target = Point3::new(
+                3.0 * (u / side as Float - 0.5),
+                3.0 * (v / side as Float - 0.5),
+                0.0,
+            );
+            Ray::new(origin, target - origin)
+        })
+        .collect()
+}
+
+/// The vertices and indices of a unit sphere of `stacks` by `sectors` quads, each
+/// split in two triangles.
+fn sphere_mesh(stacks: usize, sectors: usize) -> (Vec<Point3>, Vec<u32>) {
+    let pi = std::f64::consts::PI as Float;
+    let mut vertices = Vec::new();
+    for i in 0..=stacks {
+        let phi = pi * i as Float / stacks as Float;
+        for j in 0..=sectors {
+            let theta = 2.0 * pi * j as Float / sectors as Float;
+            vertices.push(Point3::new(
+                phi.sin() * theta.cos(),
+                phi.cos(),
+                phi.sin() * theta.sin(),
+            ));
+        }
+    }
+    let mut indices = Vec::new();
+    for i in 0..stacks {
+        for j in 0..sectors {
+            let a = (i * (sectors + 1) + j) as u32;
+            let b = a + sectors as u32 + 1;
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn grey() -> MaterialType {
+    MaterialType::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+}
+
+// Traces the same rays against each primitive alone.
+fn bench_primitives(c: &mut Criterion) {
+    let rays = rays();
+    let sphere = Object::new_sphere(Point3::new(0.0, 0.0, 0.0), 1.0, grey().get_material());
+    let triangle = Object::new_triangle(
+        Point3::new(-1.0, -1.0, 0.0),
+        Point3::new(1.0, -1.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+        grey().get_material(),
+    );
+    let aabb = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    let mut group = c.benchmark_group("intersection");
+    group.bench_function("sphere", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                black_box(sphere.hit(ray, 0.001, Float::INFINITY));
+            }
+        })
+    });
+    group.bench_function("triangle", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                black_box(triangle.hit(ray, 0.001, Float::INFINITY));
+            }
+        })
+    });
+    group.bench_function("aabb", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                black_box(aabb.hit(ray, 0.001, Float::INFINITY));
+            }
+        })
+    });
+    group.finish();
+}
+
+// Builds and traverses the BVHs of meshes of increasing triangle counts.
+fn bench_bvh(c: &mut Criterion) {
+    let rays = rays();
+    let mut build = c.benchmark_group("bvh build");
+    for stacks in [16, 64, 128] {
+        let (vertices, indices) = sphere_mesh(stacks, 2 * stacks);
+        let triangles = indices.len() / 3;
+        build.bench_with_input(BenchmarkId::from_parameter(triangles), &stacks, |b, _| {
+            b.iter(|| {
+                // The BVH is built on the first hit
+                let mesh =
+                    Object::new_mesh(vertices.clone(), indices.clone(), grey().get_material());
+                black_box(mesh.hit(&rays[0], 0.001, Float::INFINITY));
+            })
+        });
+    }
+    build.finish();
+    let mut traversal = c.benchmark_group("bvh traversal");
+    for stacks in [16, 64, 128] {
+        let (vertices, indices) = sphere_mesh(stacks, 2 * stacks);
+        let triangles = indices.len() / 3;
+        let mesh = Object::new_mesh(vertices, indices, grey().get_material());
+        mesh.hit(&rays[0], 0.001, Float::INFINITY);
+        traversal.bench_with_input(BenchmarkId::from_parameter(triangles), &mesh, |b, mesh| {
+            b.iter(|| {
+                for ray in &rays {
+                    black_box(mesh.hit(ray, 0.001, Float::INFINITY));
+                }
+            })
+        });
+    }
+    traversal.finish();
+}
+
+// Traces camera rays through random scenes of increasing numbers of spheres, their
+// grids spanning `[-grid, grid)`.
+fn bench_world(c: &mut Criterion) {
+    let mut group = c.benchmark_group("world hit");
+    for grid in [2, 11, 32] {
+        let doc = RandomScene::default().with_grid(grid).document();
+        let (world, _) = doc.get_world();
+        let camera = doc.camera();
+        let side = (RAYS as Float).sqrt() as usize;
+        let rays: Vec<Ray> = (0..RAYS)
+            .map(|i| {
+                camera.get_ray(
+                    (i % side) as Float / side as Float,
+                    (i / side) as Float / side as Float,
+                )
+            })
+            .collect();
+        group.bench_with_input(BenchmarkId::new("grid", grid), &rays, |b, rays| {
+            b.iter(|| {
+                for ray in rays {
+                    black_box(world.hit(ray, 0.001, Float::INFINITY));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// Samples the scattered direction of each material, at a hit facing the ray.
+fn bench_materials(c: &mut Criterion) {
+    let albedo = Color::new(0.8, 0.6, 0.2);
+    let materials = [
+        MaterialType::Lambertian(Lambertian::new(albedo)),
+        MaterialType::Metal(Metal::new(albedo, 0.2)),
+        MaterialType::Dielectric(Dielectric::new(1.5)),
+        MaterialType::BlinnPhong(BlinnPhong::new(
+            albedo,
+            Color::new(1.0, 1.0, 1.0),
+            32.0,
+            Vec3::new(0.0, 1.0, 0.0),
+        )),
+        MaterialType::CookTorrance(CookTorrance::new(albedo, 0.3, 0.5)),
+        MaterialType::Emissive(Emissive::new(albedo, Point3::new(0.0, 0.0, 0.0), 1.0)),
+        MaterialType::Disney(Disney::new(albedo, 0.5, 0.3, 0.5, 0.0, 0.0, 0.5, 0.2, 0.8)),
+    ];
+    let ray = Ray::new(Point3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+    let mut group = c.benchmark_group("scatter_importance");
+    for material in &materials {
+        let scene_material = material.get_material();
+        let rec = HitRecord::new(&ray, 1.0, Vec3::new(0.0, 1.0, 0.0), scene_material.as_ref());
+        group.bench_function(material.kind(), |b| {
+            b.iter(|| {
+                black_box(Material::scatter_importance(
+                    rec.mat,
+                    &ray,
+                    &rec,
+                    &mut IndependentSampler,
+                ))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(name = benches;config = Criterion::default(); targets= bench_primitives,bench_bvh,bench_world,bench_materials);
+criterion_main!(benches);
@@ -3,12 +3,11 @@ use std::path;
 use crust_render::{
     Camera, DocObject, Document, MaterialType, ObjectList, Primitive, UVSphere, UVTorus,
 };
-use utils::Point3;
+use utils::{Float, Point3};
 
 fn main() {
-    const ASPECT_RATIO: f32 = 16.0 / 9.0;
-    const IMAGE_WIDTH: usize = 400;
-    const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f32 / ASPECT_RATIO) as usize;
+    let render_settings = crust_render::RenderSettings::default();
+    let (width, height) = render_settings.get_dimensions();
     let lookfrom = Point3::new(13.0, 2.0, 3.0);
     let lookat = Point3::new(0.0, 0.0, 0.0);
     let vup = Point3::new(0.0, 1.0, 0.0);
@@ -20,12 +19,10 @@ fn main() {
         lookat,
         vup,
         20.0,
-        ASPECT_RATIO,
+        width as Float / height as Float,
         aperture,
         dist_to_focus,
     );
-    let render_settings =
-        crust_render::RenderSettings::new(64, 32, IMAGE_WIDTH, IMAGE_HEIGHT, 32, 0.05);
     let mut object_list = ObjectList::new(vec![]);
     // Add objects to the object_list here
     let ground: DocObject = DocObject::new(
@@ -1,6 +1,7 @@
 use crate::ray::Ray;
-use utils::Vec3;
+use utils::{Float, Vec3};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub struct AABB {
     pub minimum: Vec3,
@@ -29,19 +30,19 @@ impl AABB {
         }
     }
 
-    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+    pub fn hit(&self, ray: &Ray, mut t_min: Float, mut t_max: Float) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / ray.direction()[a];
             let mut t0 = (self.minimum[a] - ray.origin()[a]) * inv_d;
             let mut t1 = (self.maximum[a] - ray.origin()[a]) * inv_d;
-    
+
             if inv_d < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
-    
+
             t_min = t_min.max(t0);
             t_max = t_max.min(t1);
-    
+
             if t_max <= t_min {
                 return false;
             }
@@ -62,7 +63,10 @@ impl AABB {
     }
 }
 
+/// The box of a triangle, padded along the axes it is flat along so that the rays can
+/// hit it.
 pub fn triangle_aabb(v0: Vec3, v1: Vec3, v2: Vec3) -> AABB {
+    const PADDING: Float = 1e-4;
     let min = Vec3::new(
         v0[0].min(v1[0]).min(v2[0]),
         v0[1].min(v1[1]).min(v2[1]),
@@ -73,5 +77,15 @@ pub fn triangle_aabb(v0: Vec3, v1: Vec3, v2: Vec3) -> AABB {
         v0[1].max(v1[1]).max(v2[1]),
         v0[2].max(v1[2]).max(v2[2]),
     );
-    AABB::new(min, max)
+    // Relative to the coordinates, so that flat boxes keep a thickness at any scale
+    let pad = |axis: usize| {
+        let padding = PADDING * min[axis].abs().max(max[axis].abs()).max(1.0);
+        if max[axis] - min[axis] < padding {
+            padding / 2.0
+        } else {
+            0.0
+        }
+    };
+    let padding = Vec3::new(pad(0), pad(1), pad(2));
+    AABB::new(min - padding, max + padding)
 }
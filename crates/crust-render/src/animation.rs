@@ -0,0 +1,315 @@
+use crate::camera::Camera;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use utils::{Float, Mat4, Point3, Vec3};
+
+/// The animation of a scene, rendered frame by frame to an image sequence.
+///
+/// The camera follows its keyframes, and the objects with a velocity move by it every
+/// frame, their motion blur spanning the frame they are rendered at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Animation {
+    /// The first and last frames, both rendered.
+    #[serde(default = "default_frames")]
+    frames: (u32, u32),
+    /// The cameras at key frames, interpolated in between and held before the first
+    /// key and after the last. Without keys, the camera of the scene is used.
+    #[serde(default)]
+    camera_keys: Vec<(Float, Camera)>,
+}
+
+fn default_frames() -> (u32, u32) {
+    (1, 1)
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self::new(1, 1)
+    }
+}
+
+impl Animation {
+    /// Creates an animation over a frame range, with the camera of the scene.
+    ///
+    /// # Parameters
+    /// - `first`: The first frame.
+    /// - `last`: The last frame, rendered too.
+    pub fn new(first: u32, last: u32) -> Self {
+        Self {
+            frames: (first, last),
+            camera_keys: Vec::new(),
+        }
+    }
+
+    /// Adds a keyframe of the camera.
+    ///
+    /// # Parameters
+    /// - `frame`: The frame of the key, which may fall between two frames.
+    /// - `camera`: The camera at that frame.
+    pub fn with_camera_key(mut self, frame: Float, camera: Camera) -> Self {
+        self.camera_keys.push((frame, camera));
+        self.camera_keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self
+    }
+
+    /// Scales the lengths of the camera keys by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        for (_, camera) in &mut self.camera_keys {
+            camera.rescale(factor);
+        }
+    }
+
+    /// The problems of the animation, e.g. keys out of order, for the validation of
+    /// the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let (first, last) = self.frames;
+        if first > last {
+            problems.push(format!("the first frame {first} is after the last {last}"));
+        }
+        if !self.camera_keys.is_sorted_by(|a, b| a.0 <= b.0) {
+            problems.push("the camera keys are not in frame order".to_string());
+        }
+        for (frame, camera) in &self.camera_keys {
+            for problem in camera.problems() {
+                problems.push(format!("camera key at frame {frame}: {problem}"));
+            }
+        }
+        problems
+    }
+
+    /// A turntable: the camera orbits around the world vertical axis through the
+    /// center of its focus plane, which is the `lookat` point of a camera focused on
+    /// it, evenly over the frames.
+    ///
+    /// # Parameters
+    /// - `camera`: The camera at the first frame.
+    /// - `frames`: The number of frames, from 1.
+    /// - `degrees`: The angle of the orbit, a full turn of 360 degrees looping without
+    ///   repeating the first frame.
+    pub fn turntable(camera: Camera, frames: u32, degrees: Float) -> Self {
+        let frames = frames.max(1);
+        let step = degrees.to_radians() / frames as Float;
+        (1..=frames).fold(Self::new(1, frames), |animation, frame| {
+            let yaw = step * (frame - 1) as Float;
+            animation.with_camera_key(frame as Float, camera.orbit(yaw, 0.0))
+        })
+    }
+
+    /// Sets the frame range, e.g. from the command line.
+    pub fn with_frames(mut self, first: u32, last: u32) -> Self {
+        self.frames = (first, last);
+        self
+    }
+
+    /// The frames of the animation, in order.
+    pub fn frames(&self) -> RangeInclusive<u32> {
+        self.frames.0..=self.frames.1
+    }
+
+    /// The number of frames elapsed since the first one, which the objects move by.
+    pub fn elapsed(&self, frame: u32) -> Float {
+        frame as Float - self.frames.0 as Float
+    }
+
+    /// The camera at a frame, interpolated between the keyframes.
+    ///
+    /// # Parameters
+    /// - `frame`: The frame.
+    /// - `camera`: The camera of the scene, used when there are no keys.
+    pub fn camera_at(&self, frame: u32, camera: Camera) -> Camera {
+        let frame = frame as Float;
+        let next = self.camera_keys.partition_point(|(key, _)| *key <= frame);
+        match (next.checked_sub(1), self.camera_keys.get(next)) {
+            (None, None) => camera,
+            (None, Some((_, after))) => *after,
+            (Some(previous), None) => self.camera_keys[previous].1,
+            (Some(previous), Some((key, after))) => {
+                let (previous_key, before) = self.camera_keys[previous];
+                before.lerp(after, (frame - previous_key) / (key - previous_key))
+            }
+        }
+    }
+}
+
+/// How the pose of an object goes from a keyframe to the next.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// At a constant pace.
+    #[default]
+    Linear,
+    /// Easing out of the key and into the next one.
+    Smooth,
+    /// Holding the pose until the next key.
+    Step,
+}
+
+/// The pose of an object at a keyframe, around the pivot of its animation: its
+/// scale, then its rotation, in degrees around the x, y then z axes, then its
+/// position.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ObjectKey {
+    /// The frame of the key, which may fall between two frames.
+    pub frame: Float,
+    #[serde(default)]
+    pub position: Vec3,
+    #[serde(default)]
+    pub rotation: Vec3,
+    #[serde(default = "unit_scale")]
+    pub scale: Vec3,
+    /// How the pose goes to that of the next key.
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+fn unit_scale() -> Vec3 {
+    Vec3::new(1.0, 1.0, 1.0)
+}
+
+impl ObjectKey {
+    /// A key of the object where it is defined, to be moved with the `with_*` methods.
+    pub fn new(frame: Float) -> Self {
+        Self {
+            frame,
+            position: Vec3::zero(),
+            rotation: Vec3::zero(),
+            scale: unit_scale(),
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Sets the rotation, in degrees around the x, y then z axes.
+    pub fn with_rotation(mut self, rotation: Vec3) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+}
+
+/// The keyframed transform of an object, evaluated at each frame of the animation
+/// and, for motion blur, over the shutter interval which spans a frame.
+///
+/// The poses are interpolated between the keys and held before the first key and
+/// after the last.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectAnimation {
+    /// The point the object is rotated and scaled around, where it is defined.
+    #[serde(default)]
+    pivot: Point3,
+    keys: Vec<ObjectKey>,
+}
+
+impl ObjectAnimation {
+    /// Creates an animation without keys, rotating and scaling around `pivot`.
+    pub fn new(pivot: Point3) -> Self {
+        Self {
+            pivot,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Adds a keyframe.
+    pub fn with_key(mut self, key: ObjectKey) -> Self {
+        self.keys.push(key);
+        self.keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        self
+    }
+
+    /// The transform of the object at a frame, from where it is defined.
+    pub(crate) fn transform_at(&self, frame: Float) -> Mat4 {
+        let next = self.keys.partition_point(|key| key.frame <= frame);
+        let (position, rotation, scale) = match (next.checked_sub(1), self.keys.get(next)) {
+            (None, None) => return Mat4::identity(),
+            (None, Some(key)) => (key.position, key.rotation, key.scale),
+            (Some(previous), None) => {
+                let key = &self.keys[previous];
+                (key.position, key.rotation, key.scale)
+            }
+            (Some(previous), Some(after)) => {
+                let before = &self.keys[previous];
+                let t = (frame - before.frame) / (after.frame - before.frame);
+                let t = match before.interpolation {
+                    Interpolation::Linear => t,
+                    Interpolation::Smooth => t * t * (3.0 - 2.0 * t),
+                    Interpolation::Step => 0.0,
+                };
+                (
+                    before.position.lerp(after.position, t),
+                    before.rotation.lerp(after.rotation, t),
+                    before.scale.lerp(after.scale, t),
+                )
+            }
+        };
+        Mat4::translate(self.pivot + position)
+            .compose(&Mat4::rotate(rotation.z(), Vec3::new(0.0, 0.0, 1.0)))
+            .compose(&Mat4::rotate(rotation.y(), Vec3::new(0.0, 1.0, 0.0)))
+            .compose(&Mat4::rotate(rotation.x(), Vec3::new(1.0, 0.0, 0.0)))
+            .compose(&Mat4::scale(scale.x(), scale.y(), scale.z()))
+            .compose(&Mat4::translate(-self.pivot))
+    }
+
+    /// Scales the pivot and the positions of the keys by `factor`, their rotations and
+    /// scales being kept.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.pivot = factor * self.pivot;
+        for key in &mut self.keys {
+            key.position = factor * key.position;
+        }
+    }
+
+    /// The problems of the keys, e.g. a zero scale, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.keys.is_empty() {
+            problems.push("the animation has no keys".to_string());
+        }
+        if !self.keys.is_sorted_by(|a, b| a.frame <= b.frame) {
+            problems.push("the keys are not in frame order".to_string());
+        }
+        for key in &self.keys {
+            let finite = [key.position, key.rotation, key.scale]
+                .into_iter()
+                .all(crate::validate::is_finite);
+            if !key.frame.is_finite() || !finite {
+                problems.push(format!("the key at frame {} is not finite", key.frame));
+            } else if key.scale.x() * key.scale.y() * key.scale.z() == 0.0 {
+                problems.push(format!("the key at frame {} has a zero scale", key.frame));
+            }
+        }
+        problems
+    }
+}
+
+/// Expands the printf-style frame number of an output path, `%d` or a zero-padded
+/// `%04d`, e.g. `frame_%04d.exr` into `frame_0012.exr`.
+///
+/// # Returns
+/// - The path of the frame, or `None` if the path has no frame number.
+pub fn frame_path(pattern: &str, frame: u32) -> Option<String> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    if !rest[digits..].starts_with('d') {
+        return None;
+    }
+    let width: usize = rest[..digits].parse().unwrap_or(0);
+    Some(format!(
+        "{}{frame:0width$}{}",
+        &pattern[..start],
+        &rest[digits + 1..]
+    ))
+}
@@ -0,0 +1,353 @@
+use crate::error::RenderError;
+use crate::validate;
+use obj::raw::object::Polygon;
+use obj::raw::parse_obj;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use utils::{Color, Float, Mat4, Point3, Vec3};
+
+/// Texels filled around the UV islands of a lightmap, so that the bilinear filtering
+/// and the mipmaps of the engines do not bleed the empty texels into the seams.
+const PADDING: usize = 4;
+
+/// What a lightmap holds at the texels of a mesh.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BakeMode {
+    /// The irradiance reaching the surface, direct and indirect, that the engines
+    /// multiply by the albedo of their textures.
+    #[default]
+    Irradiance,
+    /// The radiance the surface reflects, its irradiance shaded by its albedo as a
+    /// diffuse surface, with its emission.
+    Lighting,
+    /// The ambient occlusion: the fraction of the cosine-weighted hemisphere that is
+    /// unoccluded within `max_distance`, white where nothing is in the way.
+    Occlusion { max_distance: Float },
+}
+
+impl BakeMode {
+    /// The problems of the mode, e.g. a negative distance, for the validation of the
+    /// bake.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let BakeMode::Occlusion { max_distance } = self {
+            validate::check_positive(&mut problems, "occlusion distance", *max_distance);
+        }
+        problems
+    }
+}
+
+/// A corner of a triangle of a `UvMesh`.
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: Point3,
+    /// The shading normal of the file, or `None` for the normal of the triangle.
+    normal: Option<Vec3>,
+    uv: (Float, Float),
+}
+
+/// A triangle mesh with texture coordinates, placed in the scene, whose lighting is
+/// baked into a lightmap over its UV layout.
+#[derive(Debug, Clone)]
+pub struct UvMesh {
+    triangles: Vec<[Corner; 3]>,
+}
+
+/// A texel covered by a mesh: the point of the surface at its center and the normal
+/// there.
+pub(crate) type Texel = (Point3, Vec3);
+
+impl UvMesh {
+    /// Reads the triangles of an OBJ file with their texture coordinates, the
+    /// polygons being split into fans of triangles.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the OBJ file.
+    /// - `scale`: The scale of its vertices, e.g. to convert them to the unit of the
+    ///   scene.
+    ///
+    /// # Returns
+    /// - The mesh, or an error if the file does not parse or a polygon has no texture
+    ///   coordinates.
+    pub fn read_obj(path: &str, scale: Float) -> Result<Self, RenderError> {
+        let input = BufReader::new(File::open(path).map_err(|e| RenderError::file(path, e))?);
+        let raw = parse_obj(input).map_err(|e| RenderError::Parse(format!("{path}: {e}")))?;
+        let position = |i: usize| {
+            let (x, y, z, _) = raw.positions[i];
+            scale * Point3::new(x as Float, y as Float, z as Float)
+        };
+        let uv = |i: usize| {
+            let (u, v, _) = raw.tex_coords[i];
+            (u as Float, v as Float)
+        };
+        let normal = |i: usize| {
+            let (x, y, z) = raw.normals[i];
+            Vec3::new(x as Float, y as Float, z as Float)
+        };
+        let mut triangles = Vec::with_capacity(raw.polygons.len());
+        for polygon in &raw.polygons {
+            let corners: Vec<Corner> = match polygon {
+                Polygon::PT(vertices) => vertices
+                    .iter()
+                    .map(|&(p, t)| Corner {
+                        position: position(p),
+                        normal: None,
+                        uv: uv(t),
+                    })
+                    .collect(),
+                Polygon::PTN(vertices) => vertices
+                    .iter()
+                    .map(|&(p, t, n)| Corner {
+                        position: position(p),
+                        normal: Some(normal(n)),
+                        uv: uv(t),
+                    })
+                    .collect(),
+                Polygon::P(_) | Polygon::PN(_) => {
+                    return Err(RenderError::Parse(format!(
+                        "{path}: a polygon has no texture coordinates to bake into"
+                    )));
+                }
+            };
+            for i in 1..corners.len().saturating_sub(1) {
+                triangles.push([corners[0], corners[i], corners[i + 1]]);
+            }
+        }
+        Ok(Self { triangles })
+    }
+
+    /// The mesh moved by a transform, e.g. of the keyframes of its object.
+    pub fn transformed(mut self, transform: &Mat4) -> Self {
+        let inverse = transform.inverse().unwrap_or_else(Mat4::identity);
+        for corner in self.triangles.iter_mut().flatten() {
+            corner.position = transform.transform_point(corner.position);
+            corner.normal = corner
+                .normal
+                .map(|n| inverse.transpose_direction(n).unit_vector());
+        }
+        self
+    }
+
+    /// The number of triangles of the mesh.
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// The length of the diagonal of the bounding box of the mesh.
+    pub(crate) fn extent(&self) -> Float {
+        extent(self.triangles.iter().flatten().map(|c| c.position))
+    }
+
+    /// Rasterizes the triangles over the texels of a square lightmap, top row first,
+    /// `v` going up as in the engines.
+    ///
+    /// A texel is covered by the first triangle its center falls into; those of no
+    /// triangle are `None`.
+    pub(crate) fn rasterize(&self, resolution: usize) -> Vec<Option<Texel>> {
+        let mut texels = vec![None; resolution * resolution];
+        let size = resolution as Float;
+        for triangle in &self.triangles {
+            // The corners in texels, the centers of the texels being at integers
+            let [a, b, c] =
+                triangle.map(|corner| (corner.uv.0 * size - 0.5, (1.0 - corner.uv.1) * size - 0.5));
+            let area = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+            if area == 0.0 {
+                continue;
+            }
+            let range = |lo: Float, hi: Float| {
+                let lo = lo.ceil().max(0.0) as usize;
+                let hi = (hi.floor() + 1.0).clamp(0.0, size) as usize;
+                lo..hi
+            };
+            let face_normal = utils::cross(
+                triangle[1].position - triangle[0].position,
+                triangle[2].position - triangle[0].position,
+            )
+            .unit_vector();
+            for y in range(a.1.min(b.1).min(c.1), a.1.max(b.1).max(c.1)) {
+                for x in range(a.0.min(b.0).min(c.0), a.0.max(b.0).max(c.0)) {
+                    let texel = &mut texels[y * resolution + x];
+                    if texel.is_some() {
+                        continue;
+                    }
+                    let (px, py) = (x as Float, y as Float);
+                    let edge = |p: (Float, Float), q: (Float, Float)| {
+                        ((q.0 - p.0) * (py - p.1) - (px - p.0) * (q.1 - p.1)) / area
+                    };
+                    let weights = [edge(b, c), edge(c, a), edge(a, b)];
+                    if weights.iter().any(|&w| w < -1e-6) {
+                        continue;
+                    }
+                    let mut position = Point3::zero();
+                    let mut normal = Vec3::zero();
+                    for (corner, w) in triangle.iter().zip(weights) {
+                        position += w * corner.position;
+                        normal += w * corner.normal.unwrap_or(face_normal);
+                    }
+                    *texel = Some((position, normal.unit_vector()));
+                }
+            }
+        }
+        texels
+    }
+}
+
+/// The length of the diagonal of the bounding box of points.
+pub(crate) fn extent(mut points: impl Iterator<Item = Point3>) -> Float {
+    let Some(first) = points.next() else {
+        return 0.0;
+    };
+    let (min, max) = points.fold((first, first), |(min, max), p| {
+        (
+            Point3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())),
+            Point3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())),
+        )
+    });
+    (max - min).length()
+}
+
+/// The vertices of a triangle mesh with their normals, the average of those of
+/// their triangles weighted by their areas, for the bakes at the vertices.
+pub(crate) fn vertex_normals(vertices: &[Point3], indices: &[u32]) -> Vec<(Point3, Vec3)> {
+    let mut normals = vec![Vec3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        // The cross product is twice the area of the triangle
+        let normal = utils::cross(vertices[b] - vertices[a], vertices[c] - vertices[a]);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    vertices
+        .iter()
+        .zip(normals)
+        .map(|(&p, n)| {
+            let n = if n.length_squared() > 0.0 {
+                n.unit_vector()
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            (p, n)
+        })
+        .collect()
+}
+
+/// Writes an OBJ file with a color on each of its vertices, e.g. baked, after its
+/// position as `v x y z r g b`, the other lines of the file being copied as they are.
+///
+/// # Parameters
+/// - `source`: The path of the OBJ file.
+/// - `output`: The path of the OBJ file to write.
+/// - `colors`: The colors of the vertices, in the order of the file.
+pub(crate) fn write_vertex_colors(
+    source: &str,
+    output: &str,
+    colors: &[Color],
+) -> Result<(), RenderError> {
+    let text = std::fs::read_to_string(source).map_err(|e| RenderError::file(source, e))?;
+    let mut colors = colors.iter();
+    let mut lines = String::with_capacity(text.len());
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() == Some("v")
+            && let Some(color) = colors.next()
+        {
+            // A color already there is replaced
+            let position: Vec<&str> = tokens.take(3).collect();
+            let (r, g, b) = color.rgb();
+            lines.push_str(&format!("v {} {r} {g} {b}", position.join(" ")));
+        } else {
+            lines.push_str(line);
+        }
+        lines.push('\n');
+    }
+    std::fs::write(output, lines).map_err(|e| RenderError::file(output, e))
+}
+
+/// Writes a triangle mesh to an OBJ file with a color on each of its vertices.
+pub(crate) fn write_colored_mesh(
+    vertices: &[Point3],
+    indices: &[u32],
+    colors: &[Color],
+    output: &str,
+) -> Result<(), RenderError> {
+    let mut lines = String::new();
+    for (v, color) in vertices.iter().zip(colors) {
+        let (r, g, b) = color.rgb();
+        lines.push_str(&format!("v {} {} {} {r} {g} {b}\n", v.x(), v.y(), v.z()));
+    }
+    for triangle in indices.chunks_exact(3) {
+        // The indices of the OBJ files start at 1
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + 1);
+        lines.push_str(&format!("f {a} {b} {c}\n"));
+    }
+    std::fs::write(output, lines).map_err(|e| RenderError::file(output, e))
+}
+
+/// Fills the empty texels around the UV islands of a lightmap with the average of
+/// their filled neighbors, `PADDING` texels deep.
+pub(crate) fn dilate(values: &mut [Option<Color>], resolution: usize) {
+    for _ in 0..PADDING {
+        let previous = values.to_vec();
+        for (index, value) in values.iter_mut().enumerate() {
+            if value.is_some() {
+                continue;
+            }
+            let (x, y) = (index % resolution, index / resolution);
+            let mut sum = Color::zero();
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..=(y + 1).min(resolution - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(resolution - 1) {
+                    if let Some(neighbor) = previous[ny * resolution + nx] {
+                        sum += neighbor;
+                        count += 1;
+                    }
+                }
+            }
+            if count > 0 {
+                *value = Some(sum / count as Float);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::material::{Lambertian, MaterialType};
+    use std::path::Path;
+
+    fn corner(position: Point3, uv: (Float, Float)) -> Corner {
+        Corner {
+            position,
+            normal: None,
+            uv,
+        }
+    }
+
+    #[test]
+    fn bakes_the_top_of_the_uv_layout_into_the_top_rows() {
+        // A triangle over the top left corner of the UV layout, high in the furnace
+        let mesh = UvMesh {
+            triangles: vec![[
+                corner(Point3::new(0.0, 50.0, 0.0), (0.0, 1.0)),
+                corner(Point3::new(1.0, 50.0, 0.0), (0.5, 1.0)),
+                corner(Point3::new(0.0, 50.0, 1.0), (0.0, 0.5)),
+            ]],
+        };
+        let doc = Document::read(Path::new("builtin:furnace")).unwrap();
+        let renderer = doc.renderer(doc.settings().with_samples_per_pixel(4));
+        let material = MaterialType::Lambertian(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let mode = BakeMode::Occlusion { max_distance: 0.1 };
+        let lightmap = renderer.bake_lightmap(&mesh, material.get_material().as_ref(), 16, mode);
+        assert_eq!(lightmap.get_rgb(0, 0), (1.0, 1.0, 1.0));
+        assert_eq!(lightmap.get_rgb(0, 15), (0.0, 0.0, 0.0));
+        assert_eq!(lightmap.get_rgb(15, 0), (0.0, 0.0, 0.0));
+    }
+}
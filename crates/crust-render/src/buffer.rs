@@ -1,17 +1,124 @@
-use utils::Color;
+use crate::color::ColorSpace;
+use crate::cryptomatte::Cryptomatte;
+use crate::filter::Filter;
+use exr::block::lines::LineIndex;
+use exr::block::writer::SequentialBlocksCompressor;
+use exr::block::{UncompressedBlock, enumerate_ordered_header_block_indices};
+use exr::math::RoundingMode;
+use exr::meta::attribute::{
+    AttributeValue, ChannelDescription, LevelMode, LineOrder, SampleType, TileDescription,
+};
+use exr::meta::header::Header;
+use exr::meta::{BlockDescription, Headers};
+use exr::prelude::{
+    AnyChannel, AnyChannels, Compression, Encoding, FlatSamples, Image, ImageAttributes, Layer,
+    LayerAttributes, SmallVec, Text, Vec2, WritableImage, f16,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::Range;
+use utils::{Color, Float};
+
+/// The type of the samples written to the EXR files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExrPixelType {
+    /// 16-bit floats, halving the size of the files with ample precision for images.
+    Half,
+    /// 32-bit floats.
+    #[default]
+    Float,
+}
+
+/// The compression of the EXR files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExrCompression {
+    /// No compression, the fastest to read and write.
+    None,
+    /// Lossless run-length encoding, quick but only effective on flat areas.
+    #[default]
+    Rle,
+    /// Lossless zlib compression of blocks of 16 lines.
+    Zip,
+    /// Lossless wavelet compression, the most effective on noisy images.
+    Piz,
+    /// Lossy DCT compression of the color channels, the others being kept losslessly.
+    Dwaa {
+        /// The compression level, higher values giving smaller files of lower quality.
+        #[serde(default = "default_dwa_level")]
+        level: Float,
+    },
+}
+
+fn default_dwa_level() -> Float {
+    45.0
+}
+
+impl ExrCompression {
+    fn compression(self) -> Compression {
+        match self {
+            ExrCompression::None => Compression::Uncompressed,
+            ExrCompression::Rle => Compression::RLE,
+            ExrCompression::Zip => Compression::ZIP16,
+            ExrCompression::Piz => Compression::PIZ,
+            ExrCompression::Dwaa { level } => Compression::DWAA(Some(level as f32)),
+        }
+    }
+}
+
+/// The pixel type and compression of the EXR files, set in the render settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExrOptions {
+    #[serde(default)]
+    pub pixel_type: ExrPixelType,
+    #[serde(default)]
+    pub compression: ExrCompression,
+}
+
+impl ExrOptions {
+    /// The encoding of the beauty and AOV layers.
+    fn encoding(&self) -> Encoding {
+        Encoding {
+            compression: self.compression.compression(),
+            ..Encoding::FAST_LOSSLESS
+        }
+    }
+}
 
 /// The `Buffer` struct represents a 2D image buffer used to store pixel colors.
 /// It provides methods to set and retrieve pixel values, as well as access RGB data.
+///
+/// Besides the beauty image, a buffer is a film holding any number of named AOVs
+/// (arbitrary output variables), each one a buffer of the same size.
+///
+/// Each pixel accumulates a weighted sum of samples and the sum of their weights, their
+/// ratio being the pixel color, so that samples can be splatted through a
+/// reconstruction filter. For concurrent rendering, each thread splats into a tile of
+/// its own, a buffer covering a window of the image, merged into the image at the end.
+#[derive(Clone)]
 pub struct Buffer {
     /// The width of the buffer in pixels.
     width: usize,
     /// The height of the buffer in pixels.
     height: usize,
-    /// A flat vector storing the color data for each pixel.
+    /// The position of the buffer in the image, `(0, 0)` but for the tiles.
+    origin: (usize, usize),
+    /// A flat vector storing the weighted sum of the samples of each pixel.
     data: Vec<Color>,
+    /// The sum of the sample weights of each pixel.
+    weights: Vec<Float>,
+    /// The named AOVs, in the order they were added.
+    aovs: Vec<(String, Buffer)>,
+    /// The cryptomattes, in the order they were added.
+    cryptomattes: Vec<Cryptomatte>,
+    /// The metadata written to the EXR header, as names and values.
+    metadata: Vec<(String, String)>,
 }
 
 impl Buffer {
+    /// The memory of a pixel, its weighted sum and weight, in bytes.
+    pub(crate) const PIXEL_BYTES: usize = size_of::<Color>() + size_of::<Float>();
+
     /// Creates a new `Buffer` with the specified width and height.
     ///
     /// # Parameters
@@ -21,14 +128,39 @@ impl Buffer {
     /// # Returns
     /// - A new instance of `Buffer` initialized with black pixels.
     pub fn new(width: usize, height: usize) -> Self {
-        let data = vec![Color::new(0.0, 0.0, 0.0); width * height];
+        Self::tile((0, 0), width, height)
+    }
+
+    /// Creates an empty tile covering a window of the image, to splat samples into
+    /// before merging it into the image with `merge`.
+    ///
+    /// # Parameters
+    /// - `origin`: The position of the top-left pixel of the tile in the image.
+    /// - `width`: The width of the tile in pixels.
+    /// - `height`: The height of the tile in pixels.
+    pub fn tile(origin: (usize, usize), width: usize, height: usize) -> Self {
         Buffer {
             width,
             height,
-            data,
+            origin,
+            data: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weights: vec![0.0; width * height],
+            aovs: Vec::new(),
+            cryptomattes: Vec::new(),
+            metadata: Vec::new(),
         }
     }
 
+    /// The width of the buffer in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the buffer in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     /// Sets the color of a specific pixel in the buffer.
     ///
     /// # Parameters
@@ -37,9 +169,73 @@ impl Buffer {
     /// - `color`: The `Color` to set for the pixel.
     ///
     /// This method ensures that the coordinates are within bounds before setting the pixel.
+    /// The samples accumulated in the pixel are replaced by the color, with weight 1.
     pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
         if x < self.width && y < self.height {
             self.data[y * self.width + x] = color;
+            self.weights[y * self.width + x] = 1.0;
+        }
+    }
+
+    /// Accumulates a weighted sample into a pixel.
+    ///
+    /// # Parameters
+    /// - `x`: The x-coordinate of the pixel.
+    /// - `y`: The y-coordinate of the pixel.
+    /// - `color`: The color of the sample.
+    /// - `weight`: The weight of the sample in the pixel.
+    pub fn add_sample(&mut self, x: usize, y: usize, color: Color, weight: Float) {
+        if x < self.width && y < self.height {
+            self.data[y * self.width + x] += weight * color;
+            self.weights[y * self.width + x] += weight;
+        }
+    }
+
+    /// Splats a sample into the pixels within the radius of a reconstruction filter.
+    ///
+    /// # Parameters
+    /// - `(x, y)`: The position of the sample in the image, in pixels, the pixel
+    ///   `(i, j)` covering `[i, i + 1) x [j, j + 1)`. Pixels outside the buffer are
+    ///   left out, so a tile should extend beyond its samples by the filter radius.
+    /// - `color`: The color of the sample.
+    /// - `filter`: The reconstruction filter.
+    pub fn splat(&mut self, (x, y): (Float, Float), color: Color, filter: &Filter) {
+        let radius = filter.radius();
+        // Pixels whose center lies in (x - radius, x + radius], so that a sample on
+        // the border of two pixels goes to one of them only with the box filter
+        let range = |p: Float, origin: usize, size: usize| {
+            let start = ((p - 0.5 - radius).floor() + 1.0).max(origin as Float) as usize;
+            let end = ((p - 0.5 + radius).floor() + 1.0).clamp(0.0, (origin + size) as Float);
+            start..end as usize
+        };
+        for j in range(y, self.origin.1, self.height) {
+            for i in range(x, self.origin.0, self.width) {
+                let weight = filter.evaluate(x - i as Float - 0.5, y - j as Float - 0.5);
+                if weight != 0.0 {
+                    self.add_sample(i - self.origin.0, j - self.origin.1, color, weight);
+                }
+            }
+        }
+    }
+
+    /// Adds the samples of a tile, and of its AOVs, to the pixels it covers.
+    pub fn merge(&mut self, tile: &Buffer) {
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let (image_x, image_y) = (tile.origin.0 + x, tile.origin.1 + y);
+                if image_x < self.origin.0 || image_y < self.origin.1 {
+                    continue;
+                }
+                let (local_x, local_y) = (image_x - self.origin.0, image_y - self.origin.1);
+                if local_x < self.width && local_y < self.height {
+                    let (index, target) = (y * tile.width + x, local_y * self.width + local_x);
+                    self.data[target] += tile.data[index];
+                    self.weights[target] += tile.weights[index];
+                }
+            }
+        }
+        for (name, aov) in &tile.aovs {
+            self.aov_mut(name).merge(aov);
         }
     }
 
@@ -53,8 +249,8 @@ impl Buffer {
     /// - The `Color` of the pixel at the specified coordinates.
     /// - Returns black (`Color::new(0.0, 0.0, 0.0)`) if the coordinates are out of bounds.
     pub fn get_pixel(&self, x: usize, y: usize) -> Color {
-        if x < self.width && y < self.height {
-            self.data[y * self.width + x]
+        if x < self.width && y < self.height && self.weights[y * self.width + x] > 0.0 {
+            self.data[y * self.width + x] / self.weights[y * self.width + x]
         } else {
             Color::new(0.0, 0.0, 0.0)
         }
@@ -67,12 +263,375 @@ impl Buffer {
     /// - `y`: The y-coordinate of the pixel.
     ///
     /// # Returns
-    /// - A tuple `(f32, f32, f32)` representing the RGB values of the pixel.
+    /// - A tuple `(Float, Float, Float)` representing the RGB values of the pixel.
     ///
     /// This method flips the y-coordinate to match the image coordinate system
     /// and converts the pixel color to RGB format.
-    pub fn get_rgb(&self, x: usize, y: usize) -> (f32, f32, f32) {
+    pub fn get_rgb(&self, x: usize, y: usize) -> (Float, Float, Float) {
         let pixel: Color = self.get_pixel(x, self.height - 1 - y);
         pixel.rgb()
     }
+
+    /// Retrieves a named AOV.
+    ///
+    /// # Returns
+    /// - The AOV buffer, or `None` if it was not rendered.
+    pub fn aov(&self, name: &str) -> Option<&Buffer> {
+        self.aovs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, aov)| aov)
+    }
+
+    /// Retrieves a named AOV for writing, adding it in black if it does not exist yet.
+    pub fn aov_mut(&mut self, name: &str) -> &mut Buffer {
+        let index = match self.aovs.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                let aov = Buffer::tile(self.origin, self.width, self.height);
+                self.aovs.push((name.to_string(), aov));
+                self.aovs.len() - 1
+            }
+        };
+        &mut self.aovs[index].1
+    }
+
+    /// Iterates over the names and buffers of the AOVs.
+    pub fn aovs(&self) -> impl Iterator<Item = (&str, &Buffer)> {
+        self.aovs.iter().map(|(name, aov)| (name.as_str(), aov))
+    }
+
+    /// Adds a cryptomatte to the film, replacing any of the same name.
+    pub fn add_cryptomatte(&mut self, cryptomatte: Cryptomatte) {
+        self.cryptomattes.retain(|c| c.name() != cryptomatte.name());
+        self.cryptomattes.push(cryptomatte);
+    }
+
+    /// Iterates over the cryptomattes.
+    pub fn cryptomattes(&self) -> impl Iterator<Item = &Cryptomatte> {
+        self.cryptomattes.iter()
+    }
+
+    /// Sets a metadata entry of the film, replacing any of the same name.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the EXR header attribute, e.g. `crust/samplesPerPixel`.
+    /// - `value`: The value of the attribute, as text.
+    pub fn set_metadata(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.metadata.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.metadata.push((name.to_string(), value)),
+        }
+    }
+
+    /// Iterates over the names and values of the metadata.
+    pub fn metadata(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.metadata
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Writes the film to a multi-layer EXR file: the image in a first `beauty` layer,
+    /// then one layer per AOV and the RGBA layers of the cryptomattes, whose manifests
+    /// go in the header along with the metadata of the film.
+    ///
+    /// The AOVs are RGB, except the depth `Z`, written to a single `Z` channel as the
+    /// compositing packages expect, and the sample counts `samples`, written to a single
+    /// `Y` channel to show in gray.
+    ///
+    /// The pixel type and compression apply to the beauty and the AOVs. The cryptomattes
+    /// keep 32-bit floats, which their hashes need, and fall back to ZIP compression
+    /// when the selected one is lossy.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the EXR file.
+    /// - `options`: The pixel type and compression of the file.
+    /// - `space`: The working space of the film, whose chromaticities tag the file.
+    pub fn write_exr(
+        &self,
+        path: &str,
+        options: ExrOptions,
+        space: ColorSpace,
+    ) -> exr::error::Result<()> {
+        let size = Vec2(self.width, self.height);
+        let layer = |name: &str, buffer: &Buffer| {
+            let channel = |channel: &str, component: fn(&Color) -> Float| {
+                let samples = (0..self.height)
+                    .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+                    .map(|(x, y)| component(&buffer.get_pixel(x, self.height - 1 - y)) as f32);
+                let samples = match options.pixel_type {
+                    ExrPixelType::Half => FlatSamples::F16(samples.map(f16::from_f32).collect()),
+                    ExrPixelType::Float => FlatSamples::F32(samples.collect()),
+                };
+                AnyChannel::new(channel, samples)
+            };
+            let channels: SmallVec<[_; 4]> = match name {
+                "Z" => SmallVec::from_vec(vec![channel("Z", Color::r)]),
+                "samples" => SmallVec::from_vec(vec![channel("Y", Color::r)]),
+                _ => SmallVec::from_vec(vec![
+                    channel("R", Color::r),
+                    channel("G", Color::g),
+                    channel("B", Color::b),
+                ]),
+            };
+            Layer::new(
+                size,
+                LayerAttributes::named(name),
+                options.encoding(),
+                AnyChannels::sort(channels),
+            )
+        };
+        let cryptomatte_compression = match options.compression.compression() {
+            compression if compression.may_loose_data() => Compression::ZIP16,
+            compression => compression,
+        };
+        let layers: Vec<_> = std::iter::once(layer("beauty", self))
+            .chain(self.aovs().map(|(name, aov)| layer(name, aov)))
+            .chain(
+                self.cryptomattes
+                    .iter()
+                    .flat_map(|cryptomatte| cryptomatte.layers(cryptomatte_compression)),
+            )
+            .collect();
+        let mut attributes = ImageAttributes::with_size(size);
+        attributes.chromaticities = Some(space.chromaticities());
+        attributes
+            .other
+            .extend(self.cryptomattes.iter().flat_map(Cryptomatte::attributes));
+        attributes
+            .other
+            .extend(self.metadata.iter().filter_map(|(name, value)| {
+                Some((
+                    Text::new_or_none(name)?,
+                    AttributeValue::Text(Text::new_or_none(value)?),
+                ))
+            }));
+        Image::from_layers(attributes, layers).write().to_file(path)
+    }
+}
+
+/// Writes an image whose film is too large to be held in memory to an EXR file of
+/// square tiles, a row of tiles at a time from the top of the image down.
+///
+/// `rows` is handed the rows of the film a row of tiles covers, counted from the
+/// bottom as in the buffers, and returns a film covering them, e.g. a tile of the
+/// image. The film only holds the beauty, written to the single `beauty` layer in the
+/// pixel type and compression of the options.
+///
+/// # Parameters
+/// - `path`: The path of the EXR file.
+/// - `size`: The width and height of the image in pixels.
+/// - `tile_size`: The width and height of the tiles of the file.
+/// - `options`: The pixel type and compression of the file.
+/// - `space`: The working space of the film, whose chromaticities tag the file.
+/// - `metadata`: The metadata written to the header, as names and values.
+/// - `rows`: The film of the rows of a row of tiles.
+pub(crate) fn write_exr_rows(
+    path: &str,
+    (width, height): (usize, usize),
+    tile_size: usize,
+    options: ExrOptions,
+    space: ColorSpace,
+    metadata: &[(String, String)],
+    mut rows: impl FnMut(Range<usize>) -> Buffer,
+) -> exr::error::Result<()> {
+    let size = Vec2(width, height);
+    let sample_type = match options.pixel_type {
+        ExrPixelType::Half => SampleType::F16,
+        ExrPixelType::Float => SampleType::F32,
+    };
+    // The channels are sorted by name, as the format requires
+    let channels = ["B", "G", "R"].map(|name| ChannelDescription::named(name, sample_type));
+    let mut attributes = ImageAttributes::with_size(size);
+    attributes.chromaticities = Some(space.chromaticities());
+    attributes
+        .other
+        .extend(metadata.iter().filter_map(|(name, value)| {
+            Some((
+                Text::new_or_none(name)?,
+                AttributeValue::Text(Text::new_or_none(value)?),
+            ))
+        }));
+    let blocks = BlockDescription::Tiles(TileDescription {
+        tile_size: Vec2(tile_size, tile_size),
+        level_mode: LevelMode::Singular,
+        rounding_mode: RoundingMode::Down,
+    });
+    let header = Header::new(
+        Text::from("beauty"),
+        size,
+        SmallVec::from(channels.to_vec()),
+    )
+    .with_encoding(
+        options.compression.compression(),
+        blocks,
+        LineOrder::Increasing,
+    )
+    .with_shared_attributes(attributes);
+    let headers: Headers = SmallVec::from_vec(vec![header]);
+    let file = BufWriter::new(File::create(path)?);
+    exr::block::write(file, headers, true, |meta, chunks| {
+        let indices: Vec<_> = enumerate_ordered_header_block_indices(&meta.headers).collect();
+        let channels = &meta.headers[0].channels;
+        let mut compressor = SequentialBlocksCompressor::new(&meta, chunks);
+        // The film of the row of tiles being written, with the first row of the file
+        // it covers
+        let mut film: Option<(usize, Buffer)> = None;
+        for (index, block) in indices {
+            let y = block.pixel_position.y();
+            if film.as_ref().is_none_or(|(top, _)| *top != y) {
+                let bottom = height - y - block.pixel_size.y();
+                film = Some((y, rows(bottom..height - y)));
+            }
+            let (_, film) = film.as_ref().expect("the film of the row is set");
+            let block = UncompressedBlock::from_lines(channels, block, |line| {
+                let LineIndex {
+                    channel,
+                    position: Vec2(x, y),
+                    ..
+                } = line.location;
+                let component = [Color::b, Color::g, Color::r][channel];
+                let row = height - 1 - y - film.origin.1;
+                let sample = |i: usize| component(&film.get_pixel(x + i, row)) as f32;
+                match sample_type {
+                    SampleType::F16 => line.write_samples(|i| f16::from_f32(sample(i))),
+                    _ => line.write_samples(sample),
+                }
+                .expect("the line holds its samples")
+            });
+            compressor.compress_block(index, block)?;
+        }
+        Ok(())
+    })
+}
+
+/// The space of the shading normals of the `N` AOV, set in the render settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NormalSpace {
+    /// The axes of the scene.
+    #[default]
+    World,
+    /// The axes of the camera, see `Camera::to_camera_space`.
+    Camera,
+}
+
+/// The AOVs recorded for a pixel, averaged over its samples.
+#[derive(Debug, Default, Clone)]
+pub struct Aovs {
+    /// The name, sum and number of samples of each AOV.
+    values: Vec<(&'static str, Color, u32)>,
+}
+
+impl Aovs {
+    /// Creates an empty set of AOVs.
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    /// Adds the value of a sample to a named AOV.
+    pub fn add(&mut self, name: &'static str, value: Color) {
+        match self.values.iter_mut().find(|(n, _, _)| *n == name) {
+            Some((_, sum, samples)) => {
+                *sum += value;
+                *samples += 1;
+            }
+            None => self.values.push((name, value, 1)),
+        }
+    }
+
+    /// Sets a named AOV that must not be filtered, such as an ID: the value of the
+    /// first sample of the pixel is kept.
+    pub fn add_unfiltered(&mut self, name: &'static str, value: Color) {
+        if !self.values.iter().any(|(n, _, _)| *n == name) {
+            self.values.push((name, value, 0));
+        }
+    }
+
+    /// Iterates over the names and pixel values of the AOVs.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Color)> + '_ {
+        self.values.iter().map(|&(name, sum, samples)| {
+            if samples > 0 {
+                (name, sum / samples as Float)
+            } else {
+                (name, sum)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f64) -> Color {
+        Color::new(value as _, value as _, value as _)
+    }
+
+    #[test]
+    fn splats_a_box_sample_into_one_pixel() {
+        let mut film = Buffer::new(3, 1);
+        film.splat((1.5, 0.5), gray(1.0), &Filter::Box);
+        // On the border of two pixels, the sample goes to the right one only
+        film.splat((1.0, 0.5), gray(3.0), &Filter::Box);
+        assert_eq!(film.get_pixel(0, 0).r(), 0.0);
+        assert_eq!(film.get_pixel(1, 0).r(), 2.0);
+        assert_eq!(film.get_pixel(2, 0).r(), 0.0);
+    }
+
+    #[test]
+    fn weighs_the_samples_by_the_filter() {
+        let filter = Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        };
+        let mut film = Buffer::new(5, 5);
+        film.splat((2.5, 2.5), gray(1.0), &filter);
+        film.splat((3.5, 2.5), gray(0.0), &filter);
+        let (near, far) = (filter.evaluate(0.0, 0.0), filter.evaluate(-1.0, 0.0));
+        let expected = near / (near + far);
+        assert!((film.get_pixel(2, 2).r() - expected).abs() < 1e-5);
+        assert!((film.get_pixel(3, 2).r() - far / (near + far)).abs() < 1e-5);
+        // Beyond the radius of both samples
+        assert_eq!(film.get_pixel(0, 2).r(), 0.0);
+    }
+
+    #[test]
+    fn merges_padded_tiles_as_one_image() {
+        let filter = Filter::Gaussian {
+            radius: 1.0,
+            sigma: 0.5,
+        };
+        let samples: Vec<_> = (0..16)
+            .flat_map(|pixel| (0..4).map(move |s| (pixel, s)))
+            .map(|(pixel, s)| {
+                let x = (pixel % 4) as f64 + 0.25 + 0.5 * (s % 2) as f64;
+                let y = (pixel / 4) as f64 + 0.25 + 0.5 * (s / 2) as f64;
+                ((x as _, y as _), gray((pixel * 4 + s) as f64 / 64.0))
+            })
+            .collect();
+        let mut whole = Buffer::new(4, 4);
+        for &(position, color) in &samples {
+            whole.splat(position, color, &filter);
+        }
+        // Tiles of 2x2 pixels, padded by the radius of the filter within the image
+        let mut merged = Buffer::new(4, 4);
+        for (x0, y0) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+            let origin = (x0.max(1) - 1, y0.max(1) - 1);
+            let mut tile = Buffer::tile(origin, 3, 3);
+            for &(position, color) in &samples {
+                let (x, y) = (position.0 as usize, position.1 as usize);
+                if (x0..x0 + 2).contains(&x) && (y0..y0 + 2).contains(&y) {
+                    tile.splat(position, color, &filter);
+                }
+            }
+            merged.merge(&tile);
+        }
+        for y in 0..4 {
+            for x in 0..4 {
+                let (a, b) = (whole.get_pixel(x, y), merged.get_pixel(x, y));
+                assert!((a.r() - b.r()).abs() < 1e-5, "({x}, {y}): {a:?} {b:?}");
+            }
+        }
+    }
 }
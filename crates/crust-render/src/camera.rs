@@ -1,6 +1,7 @@
 use crate::ray::Ray;
+use crate::validate;
 use serde::{Deserialize, Serialize};
-use utils::{Point3, Vec3};
+use utils::{Float, Point3, Vec3};
 
 /// The `Camera` struct represents a virtual camera in the ray tracing system.
 /// It is responsible for generating rays that simulate the perspective view of a scene.
@@ -19,7 +20,7 @@ pub struct Camera {
     /// The camera's local vertical axis.
     v: Vec3,
     /// The radius of the camera's lens (used for depth of field).
-    lens_radius: f32,
+    lens_radius: Float,
 }
 
 impl Camera {
@@ -40,13 +41,13 @@ impl Camera {
         lookfrom: Point3,
         lookat: Point3,
         vup: Vec3,
-        vfov: f32, // Vertical field-of-view in degrees
-        aspect_ratio: f32,
-        aperture: f32,
-        focus_dist: f32,
+        vfov: Float, // Vertical field-of-view in degrees
+        aspect_ratio: Float,
+        aperture: Float,
+        focus_dist: Float,
     ) -> Camera {
         let theta = utils::degrees_to_radians(vfov);
-        let h = f32::tan(theta / 2.0);
+        let h = Float::tan(theta / 2.0);
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
         let w = utils::unit_vector(lookfrom - lookat);
@@ -71,6 +72,41 @@ impl Camera {
         }
     }
 
+    /// Scales the lengths of the camera, its position, viewport and lens, by `factor`,
+    /// e.g. to convert them to another unit. The field of view is kept.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.origin = factor * self.origin;
+        self.lower_left_corner = factor * self.lower_left_corner;
+        self.horizontal = factor * self.horizontal;
+        self.vertical = factor * self.vertical;
+        self.lens_radius *= factor;
+    }
+
+    /// The problems of the camera, e.g. NaNs from looking from the point it looks at,
+    /// for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let vectors = [
+            self.origin,
+            self.lower_left_corner,
+            self.horizontal,
+            self.vertical,
+            self.u,
+            self.v,
+        ];
+        if !vectors.into_iter().all(validate::is_finite) {
+            problems.push(
+                "the view is not finite, e.g. looking from the point it looks at".to_string(),
+            );
+        } else if self.horizontal.near_zero() || self.vertical.near_zero() {
+            problems.push("the viewport has no area".to_string());
+        }
+        if !(self.lens_radius.is_finite() && self.lens_radius >= 0.0) {
+            problems.push(format!("the lens radius {} is negative", self.lens_radius));
+        }
+        problems
+    }
+
     /// Generates a ray originating from the camera through the viewport.
     ///
     /// # Parameters
@@ -79,12 +115,173 @@ impl Camera {
     ///
     /// # Returns
     /// - A `Ray` that starts at the camera and passes through the specified point on the viewport.
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * utils::random_in_unit_disk();
+    pub fn get_ray(&self, s: Float, t: Float) -> Ray {
+        self.get_ray_lens(s, t, utils::random(), utils::random(), 0.0)
+    }
+
+    /// Generates a ray through the viewport using an explicit lens sample.
+    ///
+    /// # Parameters
+    /// - `s`: The horizontal coordinate on the viewport (normalized to [0, 1]).
+    /// - `t`: The vertical coordinate on the viewport (normalized to [0, 1]).
+    /// - `lens_u`, `lens_v`: A point of the unit square, mapped onto the lens disk.
+    /// - `time`: The time of the ray in the shutter interval, in [0, 1).
+    ///
+    /// # Returns
+    /// - A `Ray` that starts on the lens and passes through the specified point on the viewport.
+    pub fn get_ray_lens(
+        &self,
+        s: Float,
+        t: Float,
+        lens_u: Float,
+        lens_v: Float,
+        time: Float,
+    ) -> Ray {
+        let rd = self.lens_radius * utils::concentric_sample_disk(lens_u, lens_v);
         let offset = self.u * rd.x() + self.v * rd.y();
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
+        .with_time(time)
     }
+
+    /// Projects a point onto the viewport, ignoring the lens.
+    ///
+    /// # Returns
+    /// - The viewport coordinates `(s, t)` of the point, as taken by `get_ray`, or
+    ///   `None` for points behind the camera.
+    pub fn project(&self, p: Point3) -> Option<(Float, Float)> {
+        let forward = -utils::cross(self.u, self.v);
+        let center = self.lower_left_corner + 0.5 * self.horizontal + 0.5 * self.vertical;
+        let distance = utils::dot(p - self.origin, forward);
+        if distance <= 1e-6 {
+            return None;
+        }
+        let focus_dist = utils::dot(center - self.origin, forward);
+        let on_plane = self.origin + (p - self.origin) * (focus_dist / distance);
+        let offset = on_plane - self.lower_left_corner;
+        Some((
+            utils::dot(offset, self.horizontal) / self.horizontal.length_squared(),
+            utils::dot(offset, self.vertical) / self.vertical.length_squared(),
+        ))
+    }
+
+    /// Expresses a world direction in the space of the camera: `x` to the right, `y`
+    /// up and `z` towards the back, as the view space of OpenGL, the surfaces facing
+    /// the camera having normals towards `+z`.
+    pub fn to_camera_space(&self, direction: Vec3) -> Vec3 {
+        let backward = utils::cross(self.u, self.v);
+        Vec3::new(
+            utils::dot(direction, self.u),
+            utils::dot(direction, self.v),
+            utils::dot(direction, backward),
+        )
+    }
+
+    /// The center of the focus plane, around which the camera orbits and dollies.
+    fn pivot(&self) -> Point3 {
+        self.lower_left_corner + 0.5 * self.horizontal + 0.5 * self.vertical
+    }
+
+    /// Orbits the camera around the center of its focus plane.
+    ///
+    /// # Parameters
+    /// - `yaw`: The rotation around the world vertical axis, in radians.
+    /// - `pitch`: The rotation around the horizontal axis of the camera, in radians,
+    ///   positive to raise the camera. It stops short of the poles, where the camera
+    ///   would flip.
+    ///
+    /// # Returns
+    /// - The moved camera.
+    pub fn orbit(&self, yaw: Float, pitch: Float) -> Camera {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let forward = -utils::cross(self.u, self.v);
+        // Angle between the view direction and the vertical, kept within the poles
+        let polar = utils::dot(forward, up).clamp(-1.0, 1.0).acos();
+        let pitch = (polar + pitch).clamp(0.05, utils::consts::PI - 0.05) - polar;
+        let pivot = self.pivot();
+        let rotate = |v: Vec3| rotate(rotate(v, self.u, -pitch), up, yaw);
+        Camera {
+            origin: pivot + rotate(self.origin - pivot),
+            lower_left_corner: pivot + rotate(self.lower_left_corner - pivot),
+            horizontal: rotate(self.horizontal),
+            vertical: rotate(self.vertical),
+            u: rotate(self.u),
+            v: rotate(self.v),
+            lens_radius: self.lens_radius,
+        }
+    }
+
+    /// Moves the camera parallel to its viewport.
+    ///
+    /// # Parameters
+    /// - `dx`, `dy`: The move as fractions of the width and height of the viewport at the
+    ///   focus distance.
+    ///
+    /// # Returns
+    /// - The moved camera.
+    pub fn pan(&self, dx: Float, dy: Float) -> Camera {
+        let offset = dx * self.horizontal + dy * self.vertical;
+        Camera {
+            origin: self.origin + offset,
+            lower_left_corner: self.lower_left_corner + offset,
+            ..*self
+        }
+    }
+
+    /// Moves the camera towards the center of its focus plane, keeping it in focus.
+    ///
+    /// # Parameters
+    /// - `factor`: The ratio of the new distance to the focus plane to the current one,
+    ///   below 1 to move closer.
+    ///
+    /// # Returns
+    /// - The moved camera.
+    pub fn dolly(&self, factor: Float) -> Camera {
+        let pivot = self.pivot();
+        let origin = pivot + factor * (self.origin - pivot);
+        Camera {
+            origin,
+            lower_left_corner: origin + factor * (self.lower_left_corner - self.origin),
+            horizontal: factor * self.horizontal,
+            vertical: factor * self.vertical,
+            ..*self
+        }
+    }
+
+    /// Interpolates linearly between two cameras, e.g. keyframes of an animation.
+    ///
+    /// The axes are renormalized, the viewports being interpolated as they are: the
+    /// keys should not turn by more than a quarter turn from one to the next.
+    ///
+    /// # Parameters
+    /// - `other`: The camera at `t = 1`.
+    /// - `t`: The position between the cameras, from 0 for `self` to 1.
+    ///
+    /// # Returns
+    /// - The camera at `t`.
+    pub fn lerp(&self, other: &Camera, t: Float) -> Camera {
+        let lerp = |a: Vec3, b: Vec3| a + t * (b - a);
+        let origin = lerp(self.origin, other.origin);
+        Camera {
+            origin,
+            lower_left_corner: origin
+                + lerp(
+                    self.lower_left_corner - self.origin,
+                    other.lower_left_corner - other.origin,
+                ),
+            horizontal: lerp(self.horizontal, other.horizontal),
+            vertical: lerp(self.vertical, other.vertical),
+            u: utils::unit_vector(lerp(self.u, other.u)),
+            v: utils::unit_vector(lerp(self.v, other.v)),
+            lens_radius: self.lens_radius + t * (other.lens_radius - self.lens_radius),
+        }
+    }
+}
+
+/// Rotates a vector around a unit axis by `angle` radians, with Rodrigues' formula.
+fn rotate(v: Vec3, axis: Vec3, angle: Float) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    cos * v + sin * utils::cross(axis, v) + (1.0 - cos) * utils::dot(axis, v) * axis
 }
@@ -0,0 +1,188 @@
+use exr::meta::attribute::Chromaticities;
+use exr::prelude::Vec2;
+use serde::{Deserialize, Serialize};
+use utils::Float;
+
+/// The linear RGB space in which the scene colors are given and the image is rendered.
+///
+/// The renderer is agnostic of the primaries: the working space only tells how to read
+/// the image, through the chromaticities of the EXR output, and how to bring it to the
+/// display for the 8-bit outputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// Linear sRGB, with the Rec.709 primaries and a D65 white point.
+    #[default]
+    LinearSrgb,
+    /// ACEScg, with the wide AP1 primaries and a D60 white point.
+    AcesCg,
+}
+
+/// ACEScg to linear sRGB, with a Bradford adaptation from D60 to D65.
+const ACESCG_TO_LINEAR_SRGB: [[Float; 3]; 3] = [
+    [1.705_051, -0.621_792, -0.083_259],
+    [-0.130_257, 1.140_805, -0.010_548],
+    [-0.024_003, -0.128_969, 1.152_972],
+];
+
+/// CIE XYZ to linear sRGB.
+pub(crate) const XYZ_TO_LINEAR_SRGB: [[Float; 3]; 3] = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+/// Linear sRGB to CIE XYZ.
+const LINEAR_SRGB_TO_XYZ: [[Float; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175],
+    [0.019_333_9, 0.119_192, 0.950_304_1],
+];
+
+/// CIE XYZ to the cone responses of the Bradford chromatic adaptation.
+const BRADFORD: [[Float; 3]; 3] = [
+    [0.895_1, 0.266_4, -0.161_4],
+    [-0.750_2, 1.713_5, 0.036_7],
+    [0.038_9, -0.068_5, 1.029_6],
+];
+
+/// The Bradford cone responses to CIE XYZ.
+const BRADFORD_INVERSE: [[Float; 3]; 3] = [
+    [0.986_992_9, -0.147_054_3, 0.159_962_8],
+    [0.432_305_3, 0.518_360_3, 0.049_291_2],
+    [-0.008_528_5, 0.040_042_8, 0.968_486_7],
+];
+
+impl ColorSpace {
+    /// Converts a color of the space to linear sRGB, the primaries of the displays.
+    pub fn to_linear_srgb(self, color: [Float; 3]) -> [Float; 3] {
+        match self {
+            ColorSpace::LinearSrgb => color,
+            ColorSpace::AcesCg => ACESCG_TO_LINEAR_SRGB
+                .map(|row| row[0] * color[0] + row[1] * color[1] + row[2] * color[2]),
+        }
+    }
+
+    /// The CIE xy chromaticities of the primaries and white point of the space.
+    pub fn chromaticities(self) -> Chromaticities {
+        let [red, green, blue, white] = match self {
+            ColorSpace::LinearSrgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)],
+            ColorSpace::AcesCg => [
+                (0.713, 0.293),
+                (0.165, 0.830),
+                (0.128, 0.044),
+                (0.32168, 0.33767),
+            ],
+        }
+        .map(|(x, y)| Vec2(x, y));
+        Chromaticities {
+            red,
+            green,
+            blue,
+            white,
+        }
+    }
+}
+
+/// The transfer function of the display the 8-bit outputs are meant for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Display {
+    /// The piecewise sRGB curve of computer monitors.
+    #[default]
+    Srgb,
+    /// The BT.1886 gamma 2.4 of Rec.709 video displays.
+    Rec709,
+}
+
+impl Display {
+    /// Encodes a linear value of [0, 1] for the display.
+    pub fn encode(self, linear: Float) -> Float {
+        match self {
+            Display::Srgb => {
+                if linear <= 0.0031308 {
+                    12.92 * linear
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Display::Rec709 => linear.powf(1.0 / 2.4),
+        }
+    }
+}
+
+/// Color temperature of the D65 white of linear sRGB, in kelvins.
+const D65_TEMPERATURE: Float = 6504.0;
+
+/// The adaptation of linear sRGB showing the light of a black body at `kelvin` as
+/// white: a von Kries scaling of the Bradford cone responses, from the white of the
+/// black body to that of D65.
+///
+/// The temperature is clamped to [1667, 25000] K, the range of the fit of the
+/// Planckian locus by Kim et al. The cone responses of the whites stay positive over
+/// the whole range, even where the black body is out of the sRGB gamut.
+pub(crate) fn white_balance(kelvin: Float) -> [[Float; 3]; 3] {
+    let white = transform(&BRADFORD, planckian_xyz(kelvin));
+    let d65 = transform(&BRADFORD, planckian_xyz(D65_TEMPERATURE));
+    // The cone responses scaled from the white to D65, back in XYZ
+    let adapt = BRADFORD_INVERSE.map(|row| [0, 1, 2].map(|j| row[j] * d65[j] / white[j]));
+    let to_cones = compose(&BRADFORD, &LINEAR_SRGB_TO_XYZ);
+    compose(&XYZ_TO_LINEAR_SRGB, &compose(&adapt, &to_cones))
+}
+
+/// The CIE XYZ of a black body at `kelvin`, of luminance 1.
+fn planckian_xyz(kelvin: Float) -> [Float; 3] {
+    let t = kelvin.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179_910
+    } else {
+        -3.025_846_9e9 / t3 + 2.107_038e6 / t2 + 0.222_634_7e3 / t + 0.240_390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x3 - 1.348_110_2 * x2 + 2.185_558_3 * x - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x3 - 1.374_185_9 * x2 + 2.091_37 * x - 0.167_488_67
+    } else {
+        3.081_758 * x3 - 5.873_387 * x2 + 3.751_129_9 * x - 0.370_014_83
+    };
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+/// A color transformed by a matrix.
+fn transform(matrix: &[[Float; 3]; 3], color: [Float; 3]) -> [Float; 3] {
+    matrix.map(|row| row[0] * color[0] + row[1] * color[1] + row[2] * color[2])
+}
+
+/// The matrix applying `b`, then `a`.
+fn compose(a: &[[Float; 3]; 3], b: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    a.map(|row| [0, 1, 2].map(|j| row[0] * b[0][j] + row[1] * b[1][j] + row[2] * b[2][j]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_balances_the_black_bodies_to_d65() {
+        let d65 = transform(&XYZ_TO_LINEAR_SRGB, planckian_xyz(D65_TEMPERATURE));
+        for kelvin in [1667.0, 1900.0, 2700.0, 3200.0, 5000.0, 10000.0, 25000.0] {
+            let matrix = white_balance(kelvin);
+            let white = transform(&XYZ_TO_LINEAR_SRGB, planckian_xyz(kelvin));
+            let balanced = transform(&matrix, white);
+            for i in 0..3 {
+                assert!(
+                    (balanced[i] - d65[i]).abs() < 1e-3,
+                    "{kelvin} K: {balanced:?}"
+                );
+                assert!(matrix[i][i] > 0.0 && matrix[i][i].is_finite(), "{kelvin} K");
+            }
+        }
+        let identity = white_balance(D65_TEMPERATURE);
+        for (i, row) in identity.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-3, "{identity:?}");
+            }
+        }
+    }
+}
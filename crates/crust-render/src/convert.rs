@@ -1,69 +1,287 @@
-use exr::prelude as exrs;
-use exr::prelude::*;
-use image as png;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{error, info};
-
-fn unique_timestamp() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
-
-    format!(
-        "./test_images/{}{:03}.png",
-        now.as_secs(),
-        now.subsec_millis()
-    )
+use crate::buffer::Buffer;
+use crate::color::{self, ColorSpace, Display};
+use crate::error::RenderError;
+use crate::tracer::RenderSettings;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use tracing::trace_span;
+use utils::Float;
+
+/// Quality of the JPEG images, from 1 to 100.
+const JPEG_QUALITY: u8 = 90;
+
+/// The curve compressing the HDR image into the display range for the 8-bit outputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToneMapper {
+    /// The values are clipped to 1.
+    Linear,
+    /// `x / (1 + x)`, which never clips but flattens the highlights.
+    Reinhard,
+    /// The ACES filmic curve fitted by Narkowicz, rolling the highlights off with
+    /// some contrast.
+    #[default]
+    Aces,
+    /// An approximation of AgX, desaturating the bright colors towards white as film
+    /// does instead of skewing their hue.
+    AgX,
+}
+
+impl ToneMapper {
+    /// Maps a linear HDR color to a linear display color in [0, 1].
+    pub fn apply(self, color: [Float; 3]) -> [Float; 3] {
+        let color = color.map(|c| c.max(0.0));
+        let mapped = match self {
+            ToneMapper::Linear => color,
+            ToneMapper::Reinhard => color.map(|c| c / (1.0 + c)),
+            ToneMapper::Aces => {
+                color.map(|c| c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14))
+            }
+            ToneMapper::AgX => agx(color),
+        };
+        mapped.map(|c| c.clamp(0.0, 1.0))
+    }
 }
 
-/// compress any possible f32 into the range of [0,1].
-/// and then convert it to an unsigned byte.
-fn tone_map(linear: f32) -> u8 {
-    let clamped = linear.clamp(0.0, 1.0);
-    let srgb = if clamped <= 0.0031308 {
-        12.92 * clamped
+/// Input transform of AgX, mixing a little of each primary into the others.
+const AGX_INSET: [[Float; 3]; 3] = [
+    [0.842_479, 0.078_433_6, 0.079_223_75],
+    [0.042_328_24, 0.878_468_6, 0.079_166_13],
+    [0.042_375_65, 0.078_433_6, 0.879_143],
+];
+
+/// Output transform of AgX, the inverse of the inset.
+const AGX_OUTSET: [[Float; 3]; 3] = [
+    [1.196_879, -0.098_020_88, -0.099_029_74],
+    [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+    [-0.052_971_64, -0.098_043_45, 1.151_073_7],
+];
+
+/// Exposure range of AgX, in stops around middle gray.
+const AGX_MIN_EV: Float = -12.473_93;
+const AGX_MAX_EV: Float = 4.026_069;
+
+/// The minimal AgX of Wrensch: the color is encoded in log2 after the inset, shaped by
+/// a polynomial fit of the AgX sigmoid, then brought back to linear after the outset.
+fn agx(color: [Float; 3]) -> [Float; 3] {
+    let transform = |m: &[[Float; 3]; 3], c: [Float; 3]| {
+        m.map(|row| row[0] * c[0] + row[1] * c[1] + row[2] * c[2])
+    };
+    let encoded = transform(&AGX_INSET, color).map(|c| {
+        let x = (c.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV)
+            / (AGX_MAX_EV - AGX_MIN_EV);
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    transform(&AGX_OUTSET, encoded).map(|c| c.max(0.0).powf(2.2))
+}
+
+/// Writes a film to an image file, in the format given by the extension of `path`.
+///
+/// EXR files keep the scene-referred image with its AOVs, tagged with the chromaticities
+/// of the working space, whereas PNG and JPEG files hold the beauty alone, transformed
+/// for the display in 8 bits. With a depth range in the settings, the depth AOV `Z`
+/// of the film goes along them to `<name>_depth.png` or `.jpg`, see `depth_to_luma8`.
+///
+/// # Parameters
+/// - `buffer`: The film to write.
+/// - `path`: The path of the image, ending in `.exr`, `.png`, `.jpg` or `.jpeg`.
+/// - `settings`: The settings giving the EXR options and the color management.
+pub fn write_image(
+    buffer: &Buffer,
+    path: &str,
+    settings: &RenderSettings,
+) -> Result<(), RenderError> {
+    let _span = trace_span!("write_image", path).entered();
+    let format = output_format(path)?;
+    if format == ImageFormat::OpenExr {
+        return buffer
+            .write_exr(path, settings.exr(), settings.working_space())
+            .map_err(|e| at_path(path, e.into()));
+    }
+    write_8bit(
+        &DynamicImage::ImageRgb8(to_rgb8(buffer, settings)),
+        path,
+        format,
+    )?;
+    if let (Some(range), Some(depth)) = (settings.depth_range(), buffer.aov("Z")) {
+        let depth_path = depth_path(path);
+        write_8bit(
+            &DynamicImage::ImageLuma8(depth_to_luma8(depth, range)),
+            &depth_path,
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes an 8-bit image to a PNG or JPEG file.
+fn write_8bit(image: &DynamicImage, path: &str, format: ImageFormat) -> Result<(), RenderError> {
+    if format == ImageFormat::Jpeg {
+        let file = File::create(Path::new(path)).map_err(|e| RenderError::file(path, e))?;
+        image
+            .write_with_encoder(JpegEncoder::new_with_quality(
+                BufWriter::new(file),
+                JPEG_QUALITY,
+            ))
+            .map_err(|e| at_path(path, e.into()))
     } else {
-        1.055 * clamped.powf(1.0 / 2.4) - 0.055
+        image
+            .save_with_format(path, format)
+            .map_err(|e| at_path(path, e.into()))
+    }
+}
+
+/// The I/O errors of the encoders, as those of the file at `path`.
+fn at_path(path: &str, e: RenderError) -> RenderError {
+    match e {
+        RenderError::Image(image::ImageError::IoError(e))
+        | RenderError::Exr(exr::error::Error::Io(e)) => RenderError::file(path, e),
+        e => e,
+    }
+}
+
+/// Path of the depth image next to an image, e.g. `output_depth.png`.
+fn depth_path(path: &str) -> String {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    path.with_file_name(format!("{stem}_depth.{extension}"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Brings a depth AOV to an 8-bit grayscale image, top row first, white at the near
+/// distance and fading linearly to black at the far one and beyond, as the depth
+/// mattes of the compositing packages.
+pub(crate) fn depth_to_luma8(depth: &Buffer, (near, far): (Float, Float)) -> GrayImage {
+    GrayImage::from_fn(depth.width() as u32, depth.height() as u32, |x, y| {
+        let z = depth.get_rgb(x as usize, y as usize).0;
+        let t = ((far - z) / (far - near)).clamp(0.0, 1.0);
+        image::Luma([(t * 255.0 + 0.5).floor() as u8])
+    })
+}
+
+/// The format of an output image, given by the extension of `path`.
+///
+/// # Returns
+/// - The format, or an error if the renderer cannot write it.
+pub fn output_format(path: &str) -> Result<ImageFormat, RenderError> {
+    match ImageFormat::from_path(path)? {
+        format @ (ImageFormat::OpenExr | ImageFormat::Png | ImageFormat::Jpeg) => Ok(format),
+        format => Err(RenderError::UnsupportedFormat(format)),
+    }
+}
+
+/// Brings the beauty of a film to an 8-bit image for the display, top row first, see
+/// `DisplayTransform`.
+pub(crate) fn to_rgb8(buffer: &Buffer, settings: &RenderSettings) -> RgbImage {
+    let (width, height) = (buffer.width(), buffer.height());
+    let transform = DisplayTransform::new(settings, width, height);
+    let pixel = |x, y| {
+        let (r, g, b) = buffer.get_rgb(x, y);
+        [r, g, b]
     };
-    (srgb * 255.0 + 0.5).floor() as u8
+    RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        image::Rgb(transform.apply(x as usize, y as usize, pixel))
+    })
 }
 
-pub fn convert() {
-    // read from the exr file directly into a new `png::RgbaImage` image without intermediate buffers
-    let reader = exrs::read()
-        .no_deep_data()
-        .largest_resolution_level()
-        .rgba_channels(
-            |resolution, _channels: &RgbaChannels| -> png::RgbaImage {
-                png::ImageBuffer::new(resolution.width() as u32, resolution.height() as u32)
-            },
-            // set each pixel in the png buffer from the exr file
-            |png_pixels, position, (r, g, b, a): (f32, f32, f32, f32)| {
-                // TODO implicit argument types!
-                png_pixels.put_pixel(
-                    position.x() as u32,
-                    position.y() as u32,
-                    png::Rgba([tone_map(r), tone_map(g), tone_map(b), (a * 255.0) as u8]),
-                );
-            },
-        )
-        .first_valid_layer()
-        .all_attributes();
-
-    // an image that contains a single layer containing an png rgba buffer
-    let image: Image<Layer<SpecificChannels<png::RgbaImage, RgbaChannels>>> = reader
-        .from_file("output.exr")
-        .expect("run the `1_write_rgba` example to generate the required file");
-
-    // save the png buffer to a png file
-    let png_buffer = &image.layer_data.channel_data.pixels;
-    match png_buffer.save(unique_timestamp()) {
-        Ok(_) => {
-            info!("Image saved successfully in tmp rgb.png");
-        }
-        Err(e) => {
-            error!("Error saving image: {}", e);
-            std::process::exit(1);
+/// The transform of the pixels of an image to the 8-bit outputs.
+///
+/// The image is exposed by `2^exposure`, its red and blue channels scaled about the
+/// center by the chromatic aberration, converted from the working space to the
+/// display primaries, white balanced and darkened towards the corners by the
+/// vignette, compressed by the tone mapper, the view, and encoded with the transfer
+/// function of the display.
+pub struct DisplayTransform {
+    scale: Float,
+    white_balance: [[Float; 3]; 3],
+    vignette: Float,
+    chromatic_aberration: Float,
+    size: (usize, usize),
+    working_space: ColorSpace,
+    tone_mapper: ToneMapper,
+    display: Display,
+}
+
+impl DisplayTransform {
+    /// The transform of the settings for an image of `width` by `height` pixels.
+    pub fn new(settings: &RenderSettings, width: usize, height: usize) -> Self {
+        Self {
+            scale: settings.exposure().exp2(),
+            white_balance: settings.white_balance().map_or(
+                [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                color::white_balance,
+            ),
+            vignette: settings.vignette(),
+            chromatic_aberration: settings.chromatic_aberration(),
+            size: (width, height),
+            working_space: settings.working_space(),
+            tone_mapper: settings.tone_mapper(),
+            display: settings.display(),
         }
     }
+
+    /// Transforms the pixel `(x, y)` of an image.
+    ///
+    /// # Parameters
+    /// - `pixel`: The linear RGB of the pixels of the image, in the working space, the
+    ///   chromatic aberration reading the neighbors of `(x, y)`.
+    pub fn apply(&self, x: usize, y: usize, pixel: impl Fn(usize, usize) -> [Float; 3]) -> [u8; 3] {
+        let (cx, cy) = (self.size.0 as Float / 2.0, self.size.1 as Float / 2.0);
+        let (dx, dy) = (x as Float + 0.5 - cx, y as Float + 0.5 - cy);
+        let rgb = if self.chromatic_aberration == 0.0 {
+            pixel(x, y)
+        } else {
+            // Red is magnified and blue shrunk, fringing the edges towards the corners
+            let at = |magnification: Float| {
+                self.bilinear(cx + dx * magnification, cy + dy * magnification, &pixel)
+            };
+            [
+                at(1.0 + self.chromatic_aberration)[0],
+                pixel(x, y)[1],
+                at(1.0 - self.chromatic_aberration)[2],
+            ]
+        };
+        // The distance to the center, 1 in the corners
+        let r2 = (dx * dx + dy * dy) / (cx * cx + cy * cy);
+        let scale = self.scale * (1.0 - self.vignette * r2 * (2.0 - r2));
+        let linear = self.working_space.to_linear_srgb(rgb.map(|c| c * scale));
+        let balanced = self
+            .white_balance
+            .map(|row| row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]);
+        self.tone_mapper
+            .apply(balanced)
+            .map(|c| (self.display.encode(c) * 255.0 + 0.5).floor() as u8)
+    }
+
+    /// The image interpolated at a position in pixels, clamped to its edges.
+    fn bilinear(
+        &self,
+        x: Float,
+        y: Float,
+        pixel: &impl Fn(usize, usize) -> [Float; 3],
+    ) -> [Float; 3] {
+        let (width, height) = self.size;
+        let x = (x - 0.5).clamp(0.0, (width - 1) as Float);
+        let y = (y - 0.5).clamp(0.0, (height - 1) as Float);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+        let lerp =
+            |a: [Float; 3], b: [Float; 3], t: Float| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        lerp(
+            lerp(pixel(x0, y0), pixel(x1, y0), fx),
+            lerp(pixel(x0, y1), pixel(x1, y1), fx),
+            fy,
+        )
+    }
 }
@@ -0,0 +1,215 @@
+use exr::meta::attribute::{AttributeValue, Text};
+use exr::prelude::{
+    AnyChannel, AnyChannels, Compression, Encoding, FlatSamples, Layer, LayerAttributes, SmallVec,
+    Vec2,
+};
+use utils::Float;
+
+/// Number of IDs kept per pixel, two per RGBA layer.
+const RANKS: usize = 6;
+
+/// A cryptomatte: the names covering each pixel, ranked by coverage, from which the
+/// compositing packages build mattes by picking objects in the image.
+///
+/// Following the Cryptomatte specification, the names are hashed with MurmurHash3 and
+/// stored as floats next to their coverage, and a manifest in the EXR header maps the
+/// hashes back to the names.
+#[derive(Clone)]
+pub struct Cryptomatte {
+    /// The type name of the matte, e.g. `CryptoObject`, prefixing its layers.
+    name: String,
+    /// The names of the IDs, the ID `n` being at index `n - 1`.
+    names: Vec<String>,
+    /// The width of the matte in pixels.
+    width: usize,
+    /// The height of the matte in pixels.
+    height: usize,
+    /// The (hash, coverage) ranks of each pixel, by decreasing coverage.
+    ranks: Vec<[(f32, f32); RANKS]>,
+}
+
+impl Cryptomatte {
+    /// The memory of the ranks of a pixel, in bytes.
+    pub(crate) const PIXEL_BYTES: usize = RANKS * size_of::<(f32, f32)>();
+
+    /// Creates an empty cryptomatte.
+    ///
+    /// # Parameters
+    /// - `name`: The type name of the matte, e.g. `CryptoObject`.
+    /// - `names`: The names of the IDs, the ID `n` being at index `n - 1`.
+    pub fn new(name: &str, names: Vec<String>, width: usize, height: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            names,
+            width,
+            height,
+            ranks: vec![[(0.0, 0.0); RANKS]; width * height],
+        }
+    }
+
+    /// The type name of the matte.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the IDs covering a pixel.
+    ///
+    /// # Parameters
+    /// - `coverage`: The IDs seen by the samples of the pixel with the fraction of the
+    ///   samples seeing them. The background, ID 0, is left out of the matte.
+    pub fn set_pixel(&mut self, x: usize, y: usize, coverage: &[(u32, Float)]) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let mut sorted: Vec<(u32, Float)> = coverage
+            .iter()
+            .filter(|(id, _)| *id != 0)
+            .copied()
+            .collect();
+        sorted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mut ranks = [(0.0, 0.0); RANKS];
+        for (rank, (id, coverage)) in ranks.iter_mut().zip(sorted) {
+            *rank = (
+                f32::from_bits(hash_name(&self.id_name(id))),
+                coverage as f32,
+            );
+        }
+        self.ranks[y * self.width + x] = ranks;
+    }
+
+    /// The name of an ID, numbered when it is unknown.
+    fn id_name(&self, id: u32) -> String {
+        self.names
+            .get(id as usize - 1)
+            .cloned()
+            .unwrap_or_else(|| format!("id_{id}"))
+    }
+
+    /// The manifest mapping each name to its hash, as a JSON object.
+    pub fn manifest(&self) -> String {
+        let entries: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| format!("{}:\"{:08x}\"", json_string(name), hash_name(name)))
+            .collect();
+        format!("{{{}}}", entries.join(","))
+    }
+
+    /// The EXR header attributes describing the matte, keyed by the hash of its name.
+    pub(crate) fn attributes(&self) -> Vec<(Text, AttributeValue)> {
+        let key = &format!("{:08x}", murmur3_32(self.name.as_bytes()))[..7];
+        [
+            ("name", self.name.clone()),
+            ("hash", "MurmurHash3_32".to_string()),
+            ("conversion", "uint32_to_float32".to_string()),
+            ("manifest", self.manifest()),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| {
+            Some((
+                Text::new_or_none(format!("cryptomatte/{key}/{field}"))?,
+                AttributeValue::Text(Text::new_or_none(value)?),
+            ))
+        })
+        .collect()
+    }
+
+    /// The RGBA layers of the matte, `<name>00` to `<name>02`, each holding two ranks
+    /// as (hash, coverage) pairs, flipped to the image coordinate system.
+    ///
+    /// # Parameters
+    /// - `compression`: The compression of the layers, which must be lossless.
+    pub(crate) fn layers(&self, compression: Compression) -> Vec<Layer<AnyChannels<FlatSamples>>> {
+        let size = Vec2(self.width, self.height);
+        (0..RANKS / 2)
+            .map(|layer| {
+                let channel = |channel: &str, rank: usize, hash: bool| {
+                    let samples = (0..self.height)
+                        .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+                        .map(|(x, y)| {
+                            let (id, coverage) =
+                                self.ranks[(self.height - 1 - y) * self.width + x][rank];
+                            if hash { id } else { coverage }
+                        })
+                        .collect();
+                    AnyChannel::new(channel, FlatSamples::F32(samples))
+                };
+                let channels: SmallVec<[_; 4]> = SmallVec::from_vec(vec![
+                    channel("R", 2 * layer, true),
+                    channel("G", 2 * layer, false),
+                    channel("B", 2 * layer + 1, true),
+                    channel("A", 2 * layer + 1, false),
+                ]);
+                Layer::new(
+                    size,
+                    LayerAttributes::named(format!("{}{:02}", self.name, layer).as_str()),
+                    Encoding {
+                        compression,
+                        ..Encoding::FAST_LOSSLESS
+                    },
+                    AnyChannels::sort(channels),
+                )
+            })
+            .collect()
+    }
+}
+
+/// MurmurHash3 (x86, 32 bits) with a zero seed, as used by Cryptomatte.
+fn murmur3_32(bytes: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mut h: u32 = 0;
+    let chunks = bytes.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .rev()
+            .fold(0u32, |k, &byte| (k << 8) | byte as u32);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    h ^= bytes.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// Hashes a name for the matte, nudging the exponent of the hash seen as a float off
+/// the denormal and infinite/NaN ranges so that the value survives any float processing.
+fn hash_name(name: &str) -> u32 {
+    let hash = murmur3_32(name.as_bytes());
+    let exponent = (hash >> 23) & 0xff;
+    if exponent == 0 || exponent == 0xff {
+        hash ^ (1 << 23)
+    } else {
+        hash
+    }
+}
+
+/// Quotes a string for JSON, escaping everything outside printable ASCII so that it
+/// fits in an EXR text attribute.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            ' '..='~' => quoted.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    quoted.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,131 @@
+use crate::buffer::Buffer;
+use crate::denoise::Features;
+use rayon::prelude::*;
+use utils::{Color, Float};
+
+/// Weights of the B3 spline kernel, applied separably over a 5x5 footprint.
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+/// Albedo below which a channel is not demodulated.
+const MIN_ALBEDO: Float = 1e-3;
+
+/// Edge-avoiding à-trous wavelet denoiser (Dammertz et al. 2010).
+///
+/// The texture is first divided out of the image, using the albedo buffer, so that the
+/// filter only smooths the lighting. Each iteration then convolves the image with a
+/// 5x5 B3 spline kernel whose taps are spread twice as far apart as in the previous
+/// one, which covers a large footprint for a small cost. Every tap is weighted down
+/// by its difference in color, normal, depth and albedo with the center pixel, so the
+/// edges of the image are preserved. The color tolerance is halved at each iteration,
+/// as the noise has already been reduced by the previous ones.
+///
+/// # Parameters
+/// - `beauty`: The noisy HDR image.
+/// - `features`: The feature buffers, rendered at the resolution of the image.
+/// - `iterations`: The number of filtering passes.
+/// - `sigmas`: The color, normal, depth and albedo tolerances.
+///
+/// # Returns
+/// - The denoised image.
+pub fn denoise_atrous(
+    beauty: &Buffer,
+    features: &Features,
+    iterations: u32,
+    [sigma_color, sigma_normal, sigma_depth, sigma_albedo]: [Float; 4],
+) -> Buffer {
+    let (width, height) = (beauty.width(), beauty.height());
+    let pixel = |buffer: &Buffer, index: usize| buffer.get_pixel(index % width, index / width);
+    let albedo: Vec<Color> = (0..width * height)
+        .map(|i| pixel(&features.albedo, i))
+        .collect();
+    let normal: Vec<Color> = (0..width * height)
+        .map(|i| pixel(&features.normal, i))
+        .collect();
+    let depth: Vec<Float> = (0..width * height)
+        .map(|i| pixel(&features.depth, i).r())
+        .collect();
+    let mut image: Vec<Color> = (0..width * height)
+        .map(|i| demodulate(pixel(beauty, i), albedo[i]))
+        .collect();
+
+    for iteration in 0..iterations.min(16) {
+        let step = 1isize << iteration;
+        let sigma_color = sigma_color * Float::powi(0.5, iteration as i32);
+        let color_scale = 1.0 / (sigma_color * sigma_color).max(1e-8);
+        let normal_scale = 1.0 / (sigma_normal * sigma_normal).max(1e-8);
+        let albedo_scale = 1.0 / (sigma_albedo * sigma_albedo).max(1e-8);
+        image = (0..width * height)
+            .into_par_iter()
+            .map(|p| {
+                let (x, y) = ((p % width) as isize, (p / width) as isize);
+                let center = tone_map(image[p]);
+                let mut sum = Color::zero();
+                let mut total = 0.0;
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    let qy = y + (dy as isize - 2) * step;
+                    if qy < 0 || qy >= height as isize {
+                        continue;
+                    }
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (dx as isize - 2) * step;
+                        if qx < 0 || qx >= width as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let color_distance = (tone_map(image[q]) - center).length_squared();
+                        let normal_distance = (normal[q] - normal[p]).length_squared();
+                        let albedo_distance = (albedo[q] - albedo[p]).length_squared();
+                        let depth_distance = (depth[q] - depth[p]).abs()
+                            / (sigma_depth * depth[p] * step as Float).max(1e-6);
+                        let weight = kx
+                            * ky
+                            * (-color_distance * color_scale
+                                - normal_distance * normal_scale
+                                - albedo_distance * albedo_scale
+                                - depth_distance)
+                                .exp();
+                        sum += weight * image[q];
+                        total += weight;
+                    }
+                }
+                if total > 0.0 { sum / total } else { image[p] }
+            })
+            .collect();
+    }
+
+    let mut denoised = Buffer::new(width, height);
+    for (index, color) in image.into_iter().enumerate() {
+        denoised.set_pixel(
+            index % width,
+            index / width,
+            remodulate(color, albedo[index]),
+        );
+    }
+    denoised
+}
+
+/// Compresses HDR values so that the color tolerance does not depend on the exposure.
+fn tone_map(color: Color) -> Color {
+    Color::new(
+        color.r() / (1.0 + color.r()),
+        color.g() / (1.0 + color.g()),
+        color.b() / (1.0 + color.b()),
+    )
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    let divide = |c: Float, a: Float| if a > MIN_ALBEDO { c / a } else { c };
+    Color::new(
+        divide(color.r(), albedo.r()),
+        divide(color.g(), albedo.g()),
+        divide(color.b(), albedo.b()),
+    )
+}
+
+fn remodulate(color: Color, albedo: Color) -> Color {
+    let multiply = |c: Float, a: Float| if a > MIN_ALBEDO { c * a } else { c };
+    Color::new(
+        multiply(color.r(), albedo.r()),
+        multiply(color.g(), albedo.g()),
+        multiply(color.b(), albedo.b()),
+    )
+}
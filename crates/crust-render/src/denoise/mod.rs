@@ -0,0 +1,125 @@
+mod atrous;
+pub use atrous::denoise_atrous;
+#[cfg(feature = "oidn")]
+mod oidn;
+use crate::buffer::Buffer;
+#[cfg(feature = "oidn")]
+pub use oidn::denoise_oidn;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "oidn")]
+use tracing::error;
+use utils::Float;
+
+/// The auxiliary buffers guiding the denoisers, see `Renderer::render_features`.
+pub struct Features {
+    /// Albedo of the first non-specular surfaces.
+    pub albedo: Buffer,
+    /// Shading normals of the first hits.
+    pub normal: Buffer,
+    /// Distance from the camera to the first hits, in every channel.
+    pub depth: Buffer,
+}
+
+impl Features {
+    /// Takes the feature buffers from the `albedo`, `N` and `Z` AOVs of a film,
+    /// leaving black the ones it does not have.
+    pub fn from_film(film: &Buffer) -> Self {
+        let aov = |name: &str| {
+            film.aov(name)
+                .cloned()
+                .unwrap_or_else(|| Buffer::new(film.width(), film.height()))
+        };
+        Self {
+            albedo: aov("albedo"),
+            normal: aov("N"),
+            depth: aov("Z"),
+        }
+    }
+}
+
+/// The denoiser applied to the image after rendering, selectable in the render settings.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Denoiser {
+    /// The image is left noisy.
+    #[default]
+    None,
+    /// Edge-avoiding à-trous wavelet filter, guided by the feature buffers.
+    ATrous {
+        #[serde(default = "default_iterations")]
+        iterations: u32,
+        /// Tolerance to color differences, in tone-mapped units.
+        #[serde(default = "default_sigma_color")]
+        sigma_color: Float,
+        /// Tolerance to normal differences.
+        #[serde(default = "default_sigma_normal")]
+        sigma_normal: Float,
+        /// Tolerance to depth differences, relative to the depth.
+        #[serde(default = "default_sigma_depth")]
+        sigma_depth: Float,
+        /// Tolerance to albedo differences.
+        #[serde(default = "default_sigma_albedo")]
+        sigma_albedo: Float,
+    },
+    /// Intel Open Image Denoise, guided by the albedo and normal buffers, which needs
+    /// crust-render to be built with the `oidn` feature.
+    Oidn,
+}
+
+fn default_iterations() -> u32 {
+    5
+}
+fn default_sigma_color() -> Float {
+    0.4
+}
+fn default_sigma_normal() -> Float {
+    0.3
+}
+fn default_sigma_depth() -> Float {
+    0.05
+}
+fn default_sigma_albedo() -> Float {
+    0.1
+}
+
+impl Denoiser {
+    /// The problems of the denoiser, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        match self {
+            Denoiser::Oidn if !cfg!(feature = "oidn") => vec![
+                "the Oidn denoiser needs crust-render to be built with the `oidn` feature"
+                    .to_string(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Denoises an image with this denoiser.
+    ///
+    /// # Returns
+    /// - The denoised image, or `None` when no denoiser is selected, or when Open
+    ///   Image Denoise is not built in or fails, the error being logged.
+    pub fn apply(&self, beauty: &Buffer, features: &Features) -> Option<Buffer> {
+        match *self {
+            Denoiser::None => None,
+            Denoiser::ATrous {
+                iterations,
+                sigma_color,
+                sigma_normal,
+                sigma_depth,
+                sigma_albedo,
+            } => Some(denoise_atrous(
+                beauty,
+                features,
+                iterations,
+                [sigma_color, sigma_normal, sigma_depth, sigma_albedo],
+            )),
+            #[cfg(feature = "oidn")]
+            Denoiser::Oidn => denoise_oidn(beauty, features)
+                .map_err(|e| error!("Error denoising the image with Open Image Denoise: {}", e))
+                .ok(),
+            // Refused by the validation of the scene
+            #[cfg(not(feature = "oidn"))]
+            Denoiser::Oidn => None,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::buffer::Buffer;
+use crate::denoise::Features;
+use std::ffi::{CStr, c_char, c_void};
+use utils::{Color, Float};
+
+// Bindings to the C API of Intel Open Image Denoise 2, linked from the system library
+type OidnDevice = *mut c_void;
+type OidnFilter = *mut c_void;
+
+const OIDN_DEVICE_TYPE_DEFAULT: i32 = 0;
+const OIDN_ERROR_NONE: i32 = 0;
+const OIDN_FORMAT_FLOAT3: i32 = 3;
+
+#[link(name = "OpenImageDenoise")]
+unsafe extern "C" {
+    fn oidnNewDevice(device_type: i32) -> OidnDevice;
+    fn oidnCommitDevice(device: OidnDevice);
+    fn oidnReleaseDevice(device: OidnDevice);
+    fn oidnGetDeviceError(device: OidnDevice, message: *mut *const c_char) -> i32;
+    fn oidnNewFilter(device: OidnDevice, filter_type: *const c_char) -> OidnFilter;
+    #[allow(clippy::too_many_arguments)]
+    fn oidnSetSharedFilterImage(
+        filter: OidnFilter,
+        name: *const c_char,
+        data: *mut c_void,
+        format: i32,
+        width: usize,
+        height: usize,
+        byte_offset: usize,
+        pixel_byte_stride: usize,
+        row_byte_stride: usize,
+    );
+    fn oidnSetFilterBool(filter: OidnFilter, name: *const c_char, value: bool);
+    fn oidnCommitFilter(filter: OidnFilter);
+    fn oidnExecuteFilter(filter: OidnFilter);
+    fn oidnReleaseFilter(filter: OidnFilter);
+}
+
+/// Denoises a rendered image with the `RT` filter of Intel Open Image Denoise.
+///
+/// The albedo and normal buffers guide the filter so that texture and geometric
+/// detail survive the denoising; they should be rendered with the same pixel filter
+/// as the beauty, see `Renderer::render_features`.
+///
+/// # Parameters
+/// - `beauty`: The noisy HDR image.
+/// - `features`: The feature buffers, of which the albedo and normal are used.
+///
+/// # Returns
+/// - The denoised image, or the error reported by the device.
+pub fn denoise_oidn(beauty: &Buffer, features: &Features) -> std::io::Result<Buffer> {
+    let (width, height) = (beauty.width(), beauty.height());
+    let mut color = flatten(beauty);
+    let mut albedo = flatten(&features.albedo);
+    let mut normal = flatten(&features.normal);
+    let mut output = vec![0.0f32; width * height * 3];
+
+    // SAFETY: the images outlive the filter, which is released before returning, and
+    // all hold `width * height` tightly packed RGB triplets
+    unsafe {
+        let device = oidnNewDevice(OIDN_DEVICE_TYPE_DEFAULT);
+        oidnCommitDevice(device);
+        let filter = oidnNewFilter(device, c"RT".as_ptr());
+        for (name, image) in [
+            (c"color", &mut color),
+            (c"albedo", &mut albedo),
+            (c"normal", &mut normal),
+            (c"output", &mut output),
+        ] {
+            oidnSetSharedFilterImage(
+                filter,
+                name.as_ptr(),
+                image.as_mut_ptr().cast(),
+                OIDN_FORMAT_FLOAT3,
+                width,
+                height,
+                0,
+                0,
+                0,
+            );
+        }
+        oidnSetFilterBool(filter, c"hdr".as_ptr(), true);
+        oidnCommitFilter(filter);
+        oidnExecuteFilter(filter);
+
+        let mut message: *const c_char = std::ptr::null();
+        let error = oidnGetDeviceError(device, &mut message);
+        oidnReleaseFilter(filter);
+        if error != OIDN_ERROR_NONE {
+            let message = if message.is_null() {
+                "unknown error".to_string()
+            } else {
+                CStr::from_ptr(message).to_string_lossy().into_owned()
+            };
+            oidnReleaseDevice(device);
+            return Err(std::io::Error::other(format!("OIDN: {message}")));
+        }
+        oidnReleaseDevice(device);
+    }
+
+    let mut denoised = Buffer::new(width, height);
+    for (index, rgb) in output.chunks_exact(3).enumerate() {
+        denoised.set_pixel(
+            index % width,
+            index / width,
+            Color::new(rgb[0] as Float, rgb[1] as Float, rgb[2] as Float),
+        );
+    }
+    Ok(denoised)
+}
+
+/// Packs a buffer into interleaved RGB floats, row by row.
+fn flatten(buffer: &Buffer) -> Vec<f32> {
+    let mut data = Vec::with_capacity(buffer.width() * buffer.height() * 3);
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            let color = buffer.get_pixel(x, y);
+            let (r, g, b) = color.rgb();
+            data.extend([r as f32, g as f32, b as f32]);
+        }
+    }
+    data
+}
@@ -1,23 +1,62 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
-use crate::Material;
 use crate::MaterialType;
+use crate::SceneMaterial;
+use crate::animation::{Animation, ObjectAnimation};
+use crate::bake::{self, BakeMode, UvMesh};
+use crate::buffer::Buffer;
 use crate::camera::Camera;
+use crate::error::RenderError;
 use crate::hittable_list::HittableList;
+use crate::integrator::IntegratorType;
 use crate::light::{self, LightList};
-use crate::primitives::{Object, Primitive};
-use crate::tracer::RenderSettings;
+use crate::material::{Emissive, MaterialLibrary};
+use crate::medium::{Fog, Medium, MediumList};
+use crate::primitives::{
+    BVHNode, MeshBVH, Object, Primitive, load_scaled_obj_mesh, mesh_triangles,
+};
+use crate::scene::{Background, Scene};
+use crate::scene_file::Sources;
+use crate::tracer::{RenderSettings, Renderer};
+use crate::unit::Unit;
+use crate::validate::{self, Issue};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::sync::Arc;
-use tracing::error;
+use tracing::debug;
+use tracing::trace_span;
 use tracing::warn;
+use utils::{Color, Float, Vec3};
+use web_time::Instant;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Document {
     pub(crate) camera: Camera,
     pub(crate) object_list: ObjectList,
     pub(crate) settings: RenderSettings,
+    #[serde(default)]
+    pub(crate) media: Vec<Medium>,
+    /// Fog filling the whole scene, none by default.
+    #[serde(default)]
+    pub(crate) fog: Option<Fog>,
+    #[serde(default)]
+    pub(crate) animation: Animation,
+    /// Radiance of the rays escaping the scene, the sky by default.
+    #[serde(default)]
+    pub(crate) background: Background,
+    /// Path of the material library of the `Named` materials, relative to the scene.
+    #[serde(default)]
+    pub(crate) material_library: Option<String>,
+    /// Length unit of the scene, the scenes it includes being converted to it.
+    #[serde(default, skip_serializing_if = "Unit::is_meter")]
+    pub(crate) unit: Unit,
+    /// Path of the file holding the BVHs of the meshes, relative to the scene, written
+    /// by `write_bvh_cache` so that they are not built again at each render.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) bvh_cache: Option<String>,
 }
 
 impl Document {
@@ -26,13 +65,155 @@ impl Document {
             camera,
             object_list,
             settings,
+            media: Vec::new(),
+            fog: None,
+            animation: Animation::default(),
+            background: Background::default(),
+            material_library: None,
+            unit: Unit::Meter,
+            bvh_cache: None,
         }
     }
 
+    pub fn with_media(mut self, media: Vec<Medium>) -> Self {
+        self.media = media;
+        self
+    }
+
+    /// Fills the whole scene with a fog, rendered by the path tracer.
+    pub fn with_fog(mut self, fog: Fog) -> Self {
+        self.fog = Some(fog);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: RenderSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_animation(mut self, animation: Animation) -> Self {
+        self.animation = animation;
+        self
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Sets the material library the `Named` materials are read from when the scene
+    /// is, its path being relative to the scene.
+    pub fn with_material_library(mut self, path: &str) -> Self {
+        self.material_library = Some(path.to_string());
+        self
+    }
+
+    /// Sets the length unit of the scene, without converting its lengths.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Converts the lengths of the scene to `unit`: its camera, geometry, lights, media,
+    /// fog and the distances of its integrator.
+    pub fn convert(&mut self, unit: Unit) {
+        let factor = self.unit.to(unit);
+        self.unit = unit;
+        if factor == 1.0 {
+            return;
+        }
+        self.camera.rescale(factor);
+        self.object_list.rescale(factor);
+        self.settings.rescale(factor);
+        for medium in &mut self.media {
+            medium.rescale(factor);
+        }
+        if let Some(fog) = &mut self.fog {
+            fog.rescale(factor);
+        }
+        self.animation.rescale(factor);
+    }
+
+    /// Replaces the `Named` materials of the objects by those of the library.
+    pub fn resolve_materials(&mut self, library: &MaterialLibrary) -> Result<(), RenderError> {
+        self.resolve_found_materials(library);
+        for object in &self.object_list.objects {
+            if let MaterialType::Named(name) = &object.material {
+                return Err(RenderError::NotFound(format!(
+                    "material {name:?} of {} is not in the material library",
+                    object.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the `Named` materials found in the library, the others being left
+    /// for the validation to report.
+    fn resolve_found_materials(&mut self, library: &MaterialLibrary) {
+        for object in &mut self.object_list.objects {
+            if let MaterialType::Named(name) = &object.material
+                && let Some(material) = library.get(name)
+            {
+                object.material = material.clone();
+            }
+        }
+    }
+
+    /// The problems of the scene, e.g. a zero-radius sphere, a roughness out of
+    /// [0, 1] or a camera looking from the point it looks at, that would fail or
+    /// spoil its render.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut add = |section, problems: Vec<String>| {
+            issues.extend(problems.into_iter().map(|p| Issue::new(section, p)));
+        };
+        add("unit", self.unit.problems());
+        add("camera", self.camera.problems());
+        add("settings", self.settings.problems());
+        for (i, medium) in self.media.iter().enumerate() {
+            add(
+                "media",
+                medium
+                    .problems()
+                    .into_iter()
+                    .map(|p| format!("medium {i}: {p}"))
+                    .collect(),
+            );
+        }
+        if let Some(fog) = &self.fog {
+            add("fog", fog.problems());
+        }
+        add("animation", self.animation.problems());
+        for object in &self.object_list.objects {
+            let mut problems = object.object.problems();
+            problems.extend(object.material.problems());
+            validate::check_finite(&mut problems, "velocity", object.velocity);
+            if let Some(animation) = &object.animation {
+                problems.extend(animation.problems());
+            }
+            issues.extend(problems.into_iter().map(|p| Issue::object(&object.name, p)));
+        }
+        issues
+    }
+
     pub fn camera(&self) -> Camera {
         self.camera
     }
 
+    /// The camera at a frame of the animation.
+    pub fn camera_at(&self, frame: u32) -> Camera {
+        self.animation.camera_at(frame, self.camera)
+    }
+
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
     pub fn object_list(&self) -> &ObjectList {
         &self.object_list
     }
@@ -40,92 +221,722 @@ impl Document {
     pub fn settings(&self) -> RenderSettings {
         self.settings
     }
+    pub fn get_media(&self) -> MediumList {
+        let mut media = MediumList::new().with_fog(self.fog);
+        for medium in &self.media {
+            media.add(medium.clone());
+        }
+        media
+    }
     pub fn get_world(&self) -> (HittableList, LightList) {
-        let mut world = HittableList::new();
-        let mut lights = LightList::new();
-        for object in &self.object_list.objects {
-            let mat_type = object.material();
-            let material: Arc<dyn Material> = mat_type.get_material();
-            if mat_type.is_emissive() {
-                let emissive = match mat_type.get_emissive() {
-                    Some(emissive) => emissive,
-                    None => {
-                        warn!(
-                            "Emissive material is missing emissive value for object: {}",
-                            object.name
-                        );
-                        continue;
-                    }
-                };
-                let light: Arc<dyn light::Light> = Arc::new(emissive.clone());
-                lights.add(light);
+        self.get_world_at(*self.animation.frames().start())
+    }
+
+    /// The world at a frame of the animation, the objects having moved by their
+    /// velocity every frame since the first, and following their keyframes.
+    pub fn get_world_at(&self, frame: u32) -> (HittableList, LightList) {
+        self.object_list
+            .world(frame as Float, self.animation.elapsed(frame))
+    }
+
+    /// The scene to render, its camera and world at the first frame of its animation.
+    pub fn scene(&self) -> Scene {
+        let (world, lights) = self.get_world();
+        self.fill_scene(Scene::new(self.camera, world, lights))
+    }
+
+    /// The scene to render at a frame of the animation.
+    pub fn scene_at(&self, frame: u32) -> Scene {
+        let (world, lights) = self.get_world_at(frame);
+        self.fill_scene(Scene::new(self.camera_at(frame), world, lights))
+    }
+
+    /// Sets the media, the fog and the background of the document in a scene.
+    fn fill_scene(&self, scene: Scene) -> Scene {
+        scene
+            .with_media(self.get_media())
+            .with_background(self.background)
+    }
+
+    /// The renderer of the scene, its camera and world at the first frame of its
+    /// animation, with `settings`.
+    pub fn renderer(&self, settings: RenderSettings) -> Renderer {
+        Renderer::new(self.scene(), settings).with_names(self.object_names(), self.material_names())
+    }
+
+    /// The renderer of the scene at a frame of its animation, with `settings`.
+    pub fn renderer_at(&self, frame: u32, settings: RenderSettings) -> Renderer {
+        Renderer::new(self.scene_at(frame), settings)
+            .with_names(self.object_names(), self.material_names())
+    }
+
+    /// Bakes the lighting of an OBJ mesh of the scene into a lightmap over its UV
+    /// layout, at the first frame of the animation with the settings of the scene,
+    /// see `Renderer::bake_lightmap`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the object of the mesh.
+    /// - `resolution`: The width and height of the lightmap in texels.
+    /// - `mode`: What the lightmap holds.
+    ///
+    /// # Returns
+    /// - The lightmap, or an error if there is no such object, it is not an OBJ mesh
+    ///   or its file has no texture coordinates.
+    pub fn bake_lightmap(
+        &self,
+        name: &str,
+        resolution: usize,
+        mode: BakeMode,
+    ) -> Result<Buffer, RenderError> {
+        let object = self.object_named(name)?;
+        let Primitive::Obj { path, scale } = &object.object else {
+            return Err(RenderError::NotFound(format!(
+                "the object {name} is not an OBJ mesh, with texture coordinates to bake into"
+            )));
+        };
+        let frame = *self.animation.frames().start();
+        let mut mesh = UvMesh::read_obj(path, *scale)?;
+        if let Some(animation) = &object.animation {
+            mesh = mesh.transformed(&animation.transform_at(frame as Float));
+        }
+        let material = object.material.get_material();
+        Ok(self
+            .renderer(self.settings())
+            .bake_lightmap(&mesh, material.as_ref(), resolution, mode))
+    }
+
+    /// Bakes the lighting of a mesh of the scene at its vertices, at the first frame
+    /// of the animation with the settings of the scene, see `Renderer::bake_vertices`.
+    ///
+    /// The normals of the vertices are those of their triangles, averaged.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the object of the mesh, an OBJ file or an inline mesh.
+    /// - `mode`: What is baked.
+    ///
+    /// # Returns
+    /// - The value baked at each vertex, in the order of the mesh, or an error if
+    ///   there is no such object or it is not a mesh.
+    pub fn bake_vertices(&self, name: &str, mode: BakeMode) -> Result<Vec<Color>, RenderError> {
+        let object = self.object_named(name)?;
+        let (mut vertices, indices) = match &object.object {
+            Primitive::Obj { path, scale } => load_scaled_obj_mesh(path, *scale)?,
+            Primitive::Mesh { vertices, indices } => (vertices.clone(), indices.clone()),
+            _ => {
+                return Err(RenderError::NotFound(format!(
+                    "the object {name} is not a mesh, with vertices to bake at"
+                )));
             }
+        };
+        if let Some(animation) = &object.animation {
+            let frame = *self.animation.frames().start();
+            let transform = animation.transform_at(frame as Float);
+            for v in &mut vertices {
+                *v = transform.transform_point(*v);
+            }
+        }
+        let material = object.material.get_material();
+        Ok(self.renderer(self.settings()).bake_vertices(
+            &bake::vertex_normals(&vertices, &indices),
+            material.as_ref(),
+            mode,
+        ))
+    }
+
+    /// Writes a mesh of the scene to an OBJ file with colors on its vertices, e.g.
+    /// baked by `bake_vertices`: an OBJ file is copied with the colors after the
+    /// positions, an inline mesh written with them.
+    pub fn write_baked_vertices(
+        &self,
+        name: &str,
+        colors: &[Color],
+        output: &str,
+    ) -> Result<(), RenderError> {
+        match &self.object_named(name)?.object {
+            Primitive::Obj { path, .. } => bake::write_vertex_colors(path, output, colors),
+            Primitive::Mesh { vertices, indices } => {
+                bake::write_colored_mesh(vertices, indices, colors, output)
+            }
+            _ => Err(RenderError::NotFound(format!(
+                "the object {name} is not a mesh"
+            ))),
+        }
+    }
+
+    /// The object of the scene named `name`.
+    fn object_named(&self, name: &str) -> Result<&DocObject, RenderError> {
+        self.object_list
+            .objects
+            .iter()
+            .find(|object| object.name == name)
+            .ok_or_else(|| RenderError::NotFound(format!("no object is named {name}")))
+    }
+
+    /// The names of the objects, by object ID.
+    pub fn object_names(&self) -> Vec<String> {
+        self.object_list
+            .objects
+            .iter()
+            .map(|object| object.name.clone())
+            .collect()
+    }
+
+    /// The names of the materials, by material ID: the kind of material followed by
+    /// its ID, e.g. `Lambertian_1`.
+    pub fn material_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for (object, id) in self
+            .object_list
+            .objects
+            .iter()
+            .zip(self.object_list.material_ids())
+        {
+            if id as usize > names.len() {
+                names.push(format!("{}_{id}", object.material().kind()));
+            }
+        }
+        names
+    }
+
+    /// Statistics of the scene, its OBJ files being loaded to count their triangles.
+    pub fn info(&self) -> SceneInfo {
+        let objects = &self.object_list.objects;
+        let mut light_groups: Vec<&str> = objects
+            .iter()
+            .filter_map(|object| object.light_group.as_deref())
+            .collect();
+        light_groups.sort_unstable();
+        light_groups.dedup();
+        let mut info = SceneInfo {
+            objects: objects.len(),
+            spheres: 0,
+            triangles: 0,
+            meshes: 0,
+            materials: self
+                .object_list
+                .material_ids()
+                .into_iter()
+                .max()
+                .unwrap_or(0) as usize,
+            lights: objects
+                .iter()
+                .filter(|object| object.material().is_emissive())
+                .count(),
+            light_groups: light_groups.len(),
+            media: self.media.len(),
+            moving: objects
+                .iter()
+                .filter(|object| {
+                    object.velocity.length_squared() > 0.0 || object.animation.is_some()
+                })
+                .count(),
+            frames: self.animation.frames(),
+            dimensions: self.settings.get_dimensions(),
+            samples_per_pixel: self.settings.samples_per_pixel(),
+            integrator: self.settings.integrator(),
+            bvh_nodes: 0,
+            bvh_depth: 0,
+            geometry_memory: objects.len() * size_of::<Object>(),
+            film_memory: self.settings.film_memory(light_groups.len()),
+        };
+        // The spheres and triangles are in the BVH of the world, the meshes in their own
+        let mut bounded = 0;
+        for object in objects {
             match object.object() {
-                Primitive::Sphere { center, radius } => {
-                    let obj = Object::new_sphere(*center, *radius, material);
-                    world.add(Box::new(obj));
+                Primitive::Sphere { .. } => {
+                    info.spheres += 1;
+                    bounded += 1;
                 }
-                Primitive::Triangle { v0, v1, v2 } => {
-                    let obj = Object::new_triangle(*v0, *v1, *v2, material);
-                    world.add(Box::new(obj));
+                Primitive::Triangle { .. } => {
+                    info.triangles += 1;
+                    bounded += 1;
                 }
                 Primitive::Mesh { vertices, indices } => {
-                    let obj = Object::new_mesh(vertices.clone(), indices.clone(), material);
-                    world.add(Box::new(obj));
-                }
-                Primitive::Obj { path } => {
-                    let obj = Object::new_obj(path.clone(), material);
-                    world.add(Box::new(obj));
+                    info.add_mesh(vertices.len(), indices.len())
                 }
+                Primitive::Obj { path, scale } => match load_scaled_obj_mesh(path, *scale) {
+                    Ok((vertices, indices)) => info.add_mesh(vertices.len(), indices.len()),
+                    Err(e) => {
+                        info.meshes += 1;
+                        warn!("Failed to load OBJ file {}: {}", path, e);
+                    }
+                },
             }
         }
-        (world, lights)
+        info.add_bvh(MeshBVH::shape(bounded));
+        info
     }
-    pub fn write(&self, path: &Path) -> std::io::Result<()> {
-        let file = std::fs::File::create(path)?;
-        let mut writer = std::io::BufWriter::new(file);
-        let r = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
-            Ok(r) => r,
-            Err(e) => {
-                error!("Failed to serialize Document: {}", e);
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to serialize Document",
-                ));
+
+    /// Bakes the scene: the meshes of its OBJ files are embedded in it, so that it
+    /// renders without them and without parsing them.
+    pub fn baked(mut self) -> Result<Self, RenderError> {
+        for object in &mut self.object_list.objects {
+            if let Primitive::Obj { path, scale } = &object.object {
+                let (vertices, indices) = load_scaled_obj_mesh(path, *scale)?;
+                debug!(
+                    "Object {} embeds {} triangles from {}",
+                    object.name,
+                    indices.len() / 3,
+                    path
+                );
+                object.object = Primitive::new_mesh(vertices, indices);
             }
+        }
+        Ok(self)
+    }
+
+    /// Builds the BVHs of the meshes of the scene and writes them to a BVH cache, e.g.
+    /// after `baked`, which the scene then refers to so that its renders read them
+    /// instead of building them. The cache is written next to where the scene is to
+    /// be written, the scene referring to it by its file name.
+    ///
+    /// # Returns
+    /// - The number of BVHs written, none and no file for a scene without meshes.
+    pub fn write_bvh_cache(&mut self, path: &Path) -> Result<usize, RenderError> {
+        let count = self.object_list.write_bvh_cache(path)?;
+        if count > 0 {
+            self.bvh_cache = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+        }
+        Ok(count)
+    }
+
+    /// Writes the scene, as JSON for a `.json` path and as RON otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), RenderError> {
+        let r = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| e.to_string())
         };
-        writer.write_all(r.as_bytes())?;
-        writer.flush()?;
-        Ok(())
+        let r =
+            r.map_err(|e| RenderError::Serialize(format!("Failed to serialize Document: {e}")))?;
+        let file = std::fs::File::create(path).map_err(|e| RenderError::file(path, e))?;
+        let mut writer = std::io::BufWriter::new(file);
+        writer
+            .write_all(r.as_bytes())
+            .and_then(|()| writer.flush())
+            .map_err(|e| RenderError::file(path, e))
     }
-    pub fn read(path: &Path) -> std::io::Result<Self> {
-        let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-        let doc: Document = match ron::de::from_reader(reader) {
-            Ok(doc) => doc,
-            Err(e) => {
-                error!("Failed to deserialize Document: {}", e);
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to deserialize Document",
-                ));
+    /// Reads a scene, from JSON for a `.json` path, imported from pbrt for a `.pbrt`
+    /// path and from Mitsuba for a `.xml` path, and from RON otherwise. A
+    /// `builtin:<name>` path is one of the built-in scenes, e.g. `builtin:cornell-box`.
+    pub fn read(path: &Path) -> Result<Self, RenderError> {
+        if let Some(name) = path.to_str().and_then(|p| p.strip_prefix("builtin:")) {
+            return crate::world::builtin_scene(name).ok_or_else(|| {
+                RenderError::NotFound(format!(
+                    "no built-in scene {name:?}, the scenes are {}",
+                    crate::world::BUILTIN_SCENES.join(", ")
+                ))
+            });
+        }
+        let _span = trace_span!("read_scene", path = %path.display()).entered();
+        let start = Instant::now();
+        let (mut doc, sources) = if has_extension(path, "pbrt") {
+            (crate::pbrt::read(path)?, Sources::new(path))
+        } else if has_extension(path, "xml") {
+            (crate::mitsuba::read(path)?, Sources::new(path))
+        } else {
+            crate::scene_file::read(path)?
+        };
+        let library = match doc.material_library.take() {
+            Some(library) => {
+                debug!("Reading the material library: {:?}", library);
+                MaterialLibrary::read(Path::new(&library))?
             }
+            None => MaterialLibrary::new(),
         };
+        doc.resolve_found_materials(&library);
+        if let Some(cache) = doc.bvh_cache.take() {
+            debug!("Reading the BVH cache: {:?}", cache);
+            doc.object_list.read_bvh_cache(Path::new(&cache))?;
+        }
+        let issues = doc.validate();
+        if !issues.is_empty() {
+            return Err(sources.locate(issues).into());
+        }
+        debug!(
+            path = %path.display(),
+            objects = doc.object_list.objects.len(),
+            elapsed = ?start.elapsed(),
+            "Read the scene"
+        );
         Ok(doc)
     }
+
+    /// Sets a value of the scene from a `path=value` assignment, e.g.
+    /// `settings.samples_per_pixel=256`, `settings.integrator=Normal` or
+    /// `camera=@closeup.ron` for the camera of another scene.
+    pub fn set(&mut self, assignment: &str) -> Result<(), RenderError> {
+        let bvhs = std::mem::take(&mut self.object_list.bvhs);
+        let result = crate::scene_file::set(self, assignment, Path::new(""));
+        self.object_list.keep_bvhs(bvhs);
+        result
+    }
+}
+
+/// Whether a scene path is a JSON file, from its extension.
+pub(crate) fn is_json(path: &Path) -> bool {
+    has_extension(path, "json")
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Statistics of a scene, from `Document::info`.
+#[derive(Debug, Clone)]
+pub struct SceneInfo {
+    pub objects: usize,
+    pub spheres: usize,
+    /// Triangles of the scene, those of the meshes included.
+    pub triangles: usize,
+    /// Meshes, inline or loaded from OBJ files.
+    pub meshes: usize,
+    /// Distinct materials.
+    pub materials: usize,
+    /// Emissive objects.
+    pub lights: usize,
+    pub light_groups: usize,
+    pub media: usize,
+    /// Objects with a velocity, blurred or animated.
+    pub moving: usize,
+    pub frames: RangeInclusive<u32>,
+    pub dimensions: (usize, usize),
+    pub samples_per_pixel: u32,
+    pub integrator: IntegratorType,
+    /// Nodes of the BVH of the world and of those of the meshes.
+    pub bvh_nodes: usize,
+    /// Depth of the deepest BVH.
+    pub bvh_depth: usize,
+    /// Estimated memory of the objects, the meshes, their triangles and BVHs, in bytes.
+    pub geometry_memory: usize,
+    /// Estimated memory of the image, its AOVs and cryptomattes, in bytes.
+    pub film_memory: usize,
+}
+
+impl SceneInfo {
+    /// Estimated memory of the render, in bytes.
+    pub fn memory(&self) -> usize {
+        self.geometry_memory + self.film_memory
+    }
+
+    /// Counts a mesh, its vertices and indices, and the BVH of its triangles built
+    /// when it is first hit.
+    fn add_mesh(&mut self, vertices: usize, indices: usize) {
+        let triangles = indices / 3;
+        self.meshes += 1;
+        self.triangles += triangles;
+        self.add_bvh(MeshBVH::shape(triangles));
+        self.geometry_memory += vertices * size_of::<Vec3>()
+            + indices * size_of::<u32>()
+            + triangles * size_of::<[Vec3; 3]>();
+    }
+
+    /// Counts a BVH of `(nodes, depth)`, see `MeshBVH::shape`.
+    fn add_bvh(&mut self, (nodes, depth): (usize, usize)) {
+        self.bvh_nodes += nodes;
+        self.bvh_depth = self.bvh_depth.max(depth);
+        self.geometry_memory += nodes * size_of::<BVHNode>();
+    }
 }
+
+/// A number of bytes in the largest unit it is at least one of.
+fn bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+impl fmt::Display for SceneInfo {
+    /// A report of the scene, one statistic per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Scene statistics:")?;
+        writeln!(
+            f,
+            "  Objects:           {} ({} spheres, {} meshes)",
+            self.objects, self.spheres, self.meshes
+        )?;
+        writeln!(f, "  Triangles:         {}", self.triangles)?;
+        writeln!(f, "  Materials:         {}", self.materials)?;
+        writeln!(
+            f,
+            "  Lights:            {} ({} light groups)",
+            self.lights, self.light_groups
+        )?;
+        writeln!(f, "  Media:             {}", self.media)?;
+        writeln!(f, "  Moving objects:    {}", self.moving)?;
+        writeln!(
+            f,
+            "  Frames:            {}-{}",
+            self.frames.start(),
+            self.frames.end()
+        )?;
+        writeln!(
+            f,
+            "  Image size:        {}x{}",
+            self.dimensions.0, self.dimensions.1
+        )?;
+        writeln!(f, "  Samples per pixel: {}", self.samples_per_pixel)?;
+        writeln!(f, "  Integrator:        {:?}", self.integrator)?;
+        writeln!(
+            f,
+            "  BVH:               {} nodes, depth {}",
+            self.bvh_nodes, self.bvh_depth
+        )?;
+        writeln!(
+            f,
+            "  Estimated memory:  {} ({} geometry, {} film)",
+            bytes(self.memory()),
+            bytes(self.geometry_memory),
+            bytes(self.film_memory)
+        )
+    }
+}
+
+/// Magic and version of the BVH cache files.
+const BVH_CACHE_MAGIC: &[u8; 8] = b"CRBVH\0\0\x01";
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ObjectList {
     objects: Vec<DocObject>,
+    /// The BVHs of the meshes read from the BVH cache of the scene, by object.
+    #[serde(skip)]
+    bvhs: Vec<Option<Arc<MeshBVH>>>,
 }
 impl ObjectList {
     pub fn new(objects: Vec<DocObject>) -> Self {
-        Self { objects }
+        Self {
+            objects,
+            bvhs: Vec::new(),
+        }
+    }
+
+    /// Scales the objects, their lights and motion by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        for object in &mut self.objects {
+            object.object.rescale(factor);
+            object.material.rescale(factor);
+            object.velocity = factor * object.velocity;
+            if let Some(animation) = &mut object.animation {
+                animation.rescale(factor);
+            }
+        }
     }
 
     pub fn add(&mut self, object: DocObject) {
         self.objects.push(object);
     }
+
+    pub(crate) fn objects(&self) -> &[DocObject] {
+        &self.objects
+    }
+
+    /// Builds the BVHs of the inline meshes and writes them to a BVH cache: after its
+    /// magic, the number of BVHs, then the index of the object of each before the BVH,
+    /// see `MeshBVH::write`.
+    ///
+    /// # Returns
+    /// - The number of BVHs written, none and no file without meshes.
+    fn write_bvh_cache(&self, path: &Path) -> Result<usize, RenderError> {
+        let bvhs: Vec<(u32, MeshBVH)> = self
+            .objects
+            .par_iter()
+            .enumerate()
+            .filter_map(|(index, object)| {
+                let Primitive::Mesh { vertices, indices } = &object.object else {
+                    return None;
+                };
+                let triangles = mesh_triangles(vertices, indices);
+                // Built as on the first hit of the mesh, from a stream of its own
+                (!triangles.is_empty()).then(|| {
+                    let bvh = utils::with_random_stream(0, 0, || MeshBVH::build(triangles));
+                    (index as u32, bvh)
+                })
+            })
+            .collect();
+        if bvhs.is_empty() {
+            return Ok(0);
+        }
+        let file = std::fs::File::create(path).map_err(|e| RenderError::file(path, e))?;
+        let mut writer = std::io::BufWriter::new(file);
+        let mut write = || {
+            writer.write_all(BVH_CACHE_MAGIC)?;
+            writer.write_all(&(bvhs.len() as u32).to_le_bytes())?;
+            for (index, bvh) in &bvhs {
+                writer.write_all(&index.to_le_bytes())?;
+                bvh.write(&mut writer)?;
+            }
+            writer.flush()
+        };
+        write().map_err(|e| RenderError::file(path, e))?;
+        Ok(bvhs.len())
+    }
+
+    /// Takes the BVHs read from the BVH cache of the objects as they were, e.g. before
+    /// a value of the scene was set, for the meshes left unchanged.
+    fn keep_bvhs(&mut self, bvhs: Vec<Option<Arc<MeshBVH>>>) {
+        self.bvhs = bvhs
+            .into_iter()
+            .zip(&self.objects)
+            .map(|(bvh, object)| {
+                let Primitive::Mesh { vertices, indices } = &object.object else {
+                    return None;
+                };
+                bvh.filter(|bvh| bvh.is_of(&mesh_triangles(vertices, indices)))
+            })
+            .collect();
+    }
+
+    /// Reads the BVHs of the meshes from a BVH cache written by `write_bvh_cache`.
+    ///
+    /// The BVHs written for other triangles, e.g. of a mesh edited or converted to
+    /// another unit since, are left to be built again.
+    fn read_bvh_cache(&mut self, path: &Path) -> Result<(), RenderError> {
+        let file = std::fs::File::open(path).map_err(|e| RenderError::file(path, e))?;
+        let mut reader = std::io::BufReader::new(file);
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let mut bvhs = vec![None; self.objects.len()];
+        let mut read = || {
+            let mut magic = [0; 8];
+            reader.read_exact(&mut magic)?;
+            if &magic != BVH_CACHE_MAGIC {
+                return Err(invalid("not a BVH cache"));
+            }
+            let mut count = [0; 4];
+            reader.read_exact(&mut count)?;
+            for _ in 0..u32::from_le_bytes(count) {
+                let mut index = [0; 4];
+                reader.read_exact(&mut index)?;
+                let index = u32::from_le_bytes(index) as usize;
+                let Some(object) = self.objects.get(index) else {
+                    return Err(invalid("BVH of a missing object"));
+                };
+                let Primitive::Mesh { vertices, indices } = &object.object else {
+                    return Err(invalid("BVH of an object that is not a mesh"));
+                };
+                match MeshBVH::read(&mut reader, mesh_triangles(vertices, indices))? {
+                    Some(bvh) => bvhs[index] = Some(Arc::new(bvh)),
+                    None => warn!(
+                        "The cached BVH of {} is of another mesh, it is built again",
+                        object.name
+                    ),
+                }
+            }
+            Ok(())
+        };
+        read().map_err(|e| RenderError::file(path, e))?;
+        self.bvhs = bvhs;
+        Ok(())
+    }
+
+    /// Builds the world of the objects, e.g. those generated by `random_objects`, with
+    /// the emissive ones as its lights.
+    pub fn get_world(&self) -> (HittableList, LightList) {
+        self.world(0.0, 0.0)
+    }
+
+    /// Builds the world of the objects at `frame` of an animation, `elapsed` frames
+    /// after its first, the objects having moved by their velocity every frame and
+    /// following their keyframes.
+    pub(crate) fn world(&self, frame: Float, elapsed: Float) -> (HittableList, LightList) {
+        let mut world = HittableList::new();
+        let mut lights = LightList::new();
+        let material_ids = self.material_ids();
+        for (index, object) in self.objects.iter().enumerate() {
+            let mat_type = object.material();
+            let material_id = material_ids[index];
+            let object_id = index as u32 + 1;
+            debug!(
+                "Object {} has ID {} and material ID {}",
+                object.name, object_id, material_id
+            );
+            let material: Arc<SceneMaterial> = mat_type.get_material();
+            let translation = elapsed * object.velocity;
+            if mat_type.is_emissive() {
+                let emissive = match mat_type.get_emissive() {
+                    Some(emissive) => emissive,
+                    None => {
+                        warn!(
+                            "Emissive material is missing emissive value for object: {}",
+                            object.name
+                        );
+                        continue;
+                    }
+                };
+                // The light follows the keyframes at the shutter opening
+                let (position, radius) = match &object.animation {
+                    Some(animation) => {
+                        let transform = animation.transform_at(frame);
+                        (
+                            transform.transform_point(emissive.position()),
+                            emissive.radius() * transform.uniform_scale(),
+                        )
+                    }
+                    None => (emissive.position(), emissive.radius()),
+                };
+                let light: Arc<dyn light::Light> = Arc::new(Emissive::new(
+                    emissive.color(),
+                    position + translation,
+                    radius,
+                ));
+                match &object.light_group {
+                    Some(group) => lights.add_to_group(light, object_id, group),
+                    None => lights.add(light),
+                }
+            }
+            let obj = match object.object() {
+                Primitive::Sphere { center, radius } => {
+                    Object::new_sphere(*center, *radius, material)
+                }
+                Primitive::Triangle { v0, v1, v2 } => Object::new_triangle(*v0, *v1, *v2, material),
+                Primitive::Mesh { vertices, indices } => {
+                    Object::new_mesh(vertices.clone(), indices.clone(), material)
+                }
+                Primitive::Obj { path, scale } => Object::new_obj(path.clone(), *scale, material),
+            };
+            let mut obj = obj
+                .with_ids(object_id, material_id)
+                .with_velocity(object.velocity)
+                .with_translation(translation);
+            if let Some(Some(bvh)) = self.bvhs.get(index) {
+                obj = obj.with_bvh(bvh.clone());
+            }
+            if let Some(animation) = &object.animation {
+                obj = obj.with_keyframes(Arc::new(animation.clone()), frame);
+            }
+            world.add(obj);
+        }
+        (world, lights)
+    }
+    /// The material ID of each object: identical materials share their ID, numbered
+    /// from 1 in order of appearance.
+    pub(crate) fn material_ids(&self) -> Vec<u32> {
+        let mut materials: Vec<String> = Vec::new();
+        self.objects
+            .iter()
+            .map(|object| {
+                let key = ron::to_string(object.material()).unwrap_or_default();
+                (match materials.iter().position(|m| *m == key) {
+                    Some(position) => position + 1,
+                    None => {
+                        materials.push(key);
+                        materials.len()
+                    }
+                }) as u32
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -133,6 +944,16 @@ pub struct DocObject {
     name: String,
     object: Primitive,
     material: MaterialType,
+    /// Displacement of the object over the shutter interval, for motion blur.
+    #[serde(default)]
+    velocity: Vec3,
+    /// Light group of an emissive object, accumulated into its own AOV.
+    #[serde(default)]
+    light_group: Option<String>,
+    /// Keyframed transform of the object, for the frames of an animation and their
+    /// motion blur.
+    #[serde(default)]
+    animation: Option<ObjectAnimation>,
 }
 impl DocObject {
     pub fn new(name: String, object: Primitive, material: MaterialType) -> Self {
@@ -140,9 +961,27 @@ impl DocObject {
             name,
             object,
             material,
+            velocity: Vec3::zero(),
+            light_group: None,
+            animation: None,
         }
     }
 
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_light_group(mut self, group: &str) -> Self {
+        self.light_group = Some(group.to_string());
+        self
+    }
+
+    pub fn with_animation(mut self, animation: ObjectAnimation) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
     pub fn object(&self) -> &Primitive {
         &self.object
     }
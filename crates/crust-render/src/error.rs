@@ -0,0 +1,58 @@
+use crate::validate::SceneError;
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The errors of the library: reading the scenes and their meshes, and writing and
+/// merging the images.
+#[derive(Debug, Error)]
+pub enum RenderError {
+    /// A file that could not be opened, read or written.
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// An I/O error out of any file, e.g. on a stream.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A scene, material library or mesh that does not parse.
+    #[error("{0}")]
+    Parse(String),
+    /// A scene or material library that could not be serialized.
+    #[error("{0}")]
+    Serialize(String),
+    /// The problems of a scene found when it is validated.
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+    /// A built-in scene, a library material or a value of a scene that does not
+    /// exist.
+    #[error("{0}")]
+    NotFound(String),
+    /// A PNG or JPEG image that could not be encoded.
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    /// An EXR image that could not be written or read.
+    #[error(transparent)]
+    Exr(#[from] exr::error::Error),
+    /// An image format the renderer does not write.
+    #[error("unsupported output format: {0:?}")]
+    UnsupportedFormat(ImageFormat),
+    /// An integrator rendering the whole image at once, which cannot stream.
+    #[error("the {0} integrator renders the whole image at once and cannot stream")]
+    CannotStream(String),
+    /// Renders that cannot be merged.
+    #[error("{0}")]
+    Merge(String),
+}
+
+impl RenderError {
+    /// An I/O error on the file at `path`.
+    pub(crate) fn file(path: impl AsRef<Path>, source: std::io::Error) -> Self {
+        RenderError::File {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use utils::Float;
+use utils::consts::PI;
+
+/// The reconstruction filter weighting each sample into the pixels around it.
+///
+/// The filters are separable: the weight of a sample is the product of the 1D filter
+/// evaluated at its horizontal and vertical distances to the pixel center.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Filter {
+    /// Each sample counts fully in its own pixel only: the plain average of its samples.
+    #[default]
+    Box,
+    /// A Gaussian bell, shifted to reach 0 at the radius. Soft, without ringing.
+    Gaussian {
+        #[serde(default = "default_gaussian_radius")]
+        radius: Float,
+        #[serde(default = "default_sigma")]
+        sigma: Float,
+    },
+    /// The Mitchell-Netravali cubic, sharper than the Gaussian thanks to its negative
+    /// lobes, at the cost of slight ringing around edges.
+    Mitchell {
+        #[serde(default = "default_radius")]
+        radius: Float,
+        #[serde(default = "default_mitchell_b")]
+        b: Float,
+        #[serde(default = "default_mitchell_c")]
+        c: Float,
+    },
+    /// The Blackman-Harris window, a compromise between sharpness and aliasing.
+    BlackmanHarris {
+        #[serde(default = "default_radius")]
+        radius: Float,
+    },
+}
+
+fn default_gaussian_radius() -> Float {
+    1.5
+}
+fn default_sigma() -> Float {
+    0.5
+}
+fn default_radius() -> Float {
+    2.0
+}
+fn default_mitchell_b() -> Float {
+    1.0 / 3.0
+}
+fn default_mitchell_c() -> Float {
+    1.0 / 3.0
+}
+
+impl Filter {
+    /// The distance from the pixel center beyond which the filter is zero, in pixels.
+    pub fn radius(&self) -> Float {
+        match *self {
+            Filter::Box => 0.5,
+            Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::BlackmanHarris { radius } => radius,
+        }
+    }
+
+    /// Evaluates the filter at an offset from the pixel center, in pixels.
+    pub fn evaluate(&self, dx: Float, dy: Float) -> Float {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: Float) -> Float {
+        let x = x.abs();
+        match *self {
+            Filter::Box => 1.0,
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: Float| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                // The cubic spans [-2, 2], stretched to the radius
+                let t = 2.0 * x / radius;
+                let value = if t < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * t * t * t
+                        + (-18.0 + 12.0 * b + 6.0 * c) * t * t
+                        + (6.0 - 2.0 * b)
+                } else if t < 2.0 {
+                    (-b - 6.0 * c) * t * t * t
+                        + (6.0 * b + 30.0 * c) * t * t
+                        + (-12.0 * b - 48.0 * c) * t
+                        + (8.0 * b + 24.0 * c)
+                } else {
+                    0.0
+                };
+                value / 6.0
+            }
+            Filter::BlackmanHarris { radius } => {
+                if x >= radius {
+                    return 0.0;
+                }
+                let n = (x + radius) / (2.0 * radius);
+                0.35875 - 0.48829 * (2.0 * PI * n).cos() + 0.14128 * (4.0 * PI * n).cos()
+                    - 0.01168 * (6.0 * PI * n).cos()
+            }
+        }
+    }
+}
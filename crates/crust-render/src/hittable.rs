@@ -1,33 +1,63 @@
 use crate::ray::Ray;
-use std::sync::Arc;
-use utils::{Point3, Vec3};
+use utils::{Float, Point3, Vec3};
 
 use crate::aabb::AABB;
-use crate::material::Material;
+use crate::material::SceneMaterial;
 
 /// The `HitRecord` struct stores information about a ray-object intersection.
 /// It contains details such as the intersection point, surface normal, material, and more.
-#[derive(Clone, Default)]
-pub struct HitRecord {
+#[derive(Clone)]
+pub struct HitRecord<'a> {
     /// The point of intersection.
     pub p: Point3,
     /// The surface normal at the intersection point.
     pub normal: Vec3,
-    /// The material of the object at the intersection point.
-    pub mat: Option<Arc<dyn Material>>,
+    /// The material of the object at the intersection point, borrowed from the object
+    /// rather than shared, so that a hit counts no references.
+    pub mat: &'a SceneMaterial,
     /// The parameter `t` along the ray where the intersection occurs.
-    pub t: f32,
+    pub t: Float,
     /// Indicates whether the ray hit the front face of the surface.
     pub front_face: bool,
+    /// The ID of the object hit, 0 for objects without one.
+    pub object_id: u32,
+    /// The ID of the material hit, 0 for materials without one.
+    pub material_id: u32,
+    /// The displacement of the surface over the shutter interval.
+    pub velocity: Vec3,
+    /// The minimum roughness of the glossy lobes scattering the ray here, 0 by default.
+    ///
+    /// The path tracer raises it once a path went through a rough bounce, so the
+    /// near-specular lobes found afterwards are widened (path-space regularization):
+    /// the caustics they would produce are blurred instead of showing up as fireflies.
+    pub roughness_floor: Float,
 }
 
-impl HitRecord {
-    /// Creates a new, default `HitRecord`.
+impl<'a> HitRecord<'a> {
+    /// Creates the record of a hit of a ray with a surface.
+    ///
+    /// # Parameters
+    /// - `r`: The ray that intersects the surface.
+    /// - `t`: The parameter along the ray where the intersection occurs.
+    /// - `outward_normal`: The outward-facing normal of the surface.
+    /// - `mat`: The material of the surface.
     ///
     /// # Returns
-    /// - A new instance of `HitRecord` with default values.
-    pub fn new() -> HitRecord {
-        Default::default()
+    /// - A record of the hit, its normal facing the ray, without IDs or velocity.
+    pub fn new(r: &Ray, t: Float, outward_normal: Vec3, mat: &'a SceneMaterial) -> Self {
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: outward_normal,
+            mat,
+            t,
+            front_face: true,
+            object_id: 0,
+            material_id: 0,
+            velocity: Vec3::zero(),
+            roughness_floor: 0.0,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
     }
 
     /// Sets the surface normal and determines whether the ray hit the front face.
@@ -57,10 +87,10 @@ pub trait Hittable: Send + Sync {
     /// - `ray`: The ray to test for intersection.
     /// - `t_min`: The minimum value of the parameter `t` to consider.
     /// - `t_max`: The maximum value of the parameter `t` to consider.
-    /// - `rec`: A mutable reference to a `HitRecord` to store intersection details.
     ///
     /// # Returns
-    /// - `true` if the ray intersects the object, `false` otherwise.
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool;
+    /// - The closest intersection within `[t_min, t_max]`, or `None` if the ray misses
+    ///   the object.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Option<AABB>;
 }
@@ -1,13 +1,21 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
+use crate::primitives::{MeshBVH, Object};
+use crate::progress;
 use crate::ray::Ray;
+use std::sync::OnceLock;
+use tracing::debug;
+use utils::{Float, Vec3};
+use web_time::Instant;
 
 /// The `HittableList` struct represents a collection of objects that can be intersected by rays.
-/// It allows for managing multiple `Hittable` objects and testing for ray intersections with all of them.
+/// It allows for managing multiple objects and testing for ray intersections with all of them.
 #[derive(Default)]
 pub struct HittableList {
-    /// A vector of objects implementing the `Hittable` trait.
-    objects: Vec<Box<dyn Hittable>>,
+    /// The objects, stored in one pool rather than allocated one by one.
+    objects: Vec<Object>,
+    /// The BVH of the objects, built on the first hit.
+    bvh: OnceLock<ListBVH>,
 }
 
 impl HittableList {
@@ -19,12 +27,124 @@ impl HittableList {
         Default::default()
     }
 
-    /// Adds a `Hittable` object to the list.
+    /// Adds an object to the list.
     ///
     /// # Parameters
-    /// - `object`: A boxed object implementing the `Hittable` trait.
-    pub fn add(&mut self, object: Box<dyn Hittable>) {
+    /// - `object`: The object to add.
+    pub fn add(&mut self, object: Object) {
         self.objects.push(object);
+        self.bvh = OnceLock::new();
+    }
+
+    fn hit_child(
+        &self,
+        bvh: &ListBVH,
+        child: ListChild,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<HitRecord<'_>> {
+        match child {
+            ListChild::Object(index) => self.objects[index as usize].hit(ray, t_min, t_max),
+            ListChild::Node(index) => {
+                let node = &bvh.nodes[index as usize];
+                progress::count_bvh_node();
+                if !node.bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+
+                let left = self.hit_child(bvh, node.left, ray, t_min, t_max);
+                let closest_so_far = left.as_ref().map_or(t_max, |rec| rec.t);
+                self.hit_child(bvh, node.right, ray, t_min, closest_so_far)
+                    .or(left)
+            }
+        }
+    }
+}
+
+/// A child of a node of the BVH of a list: another node or an object, by index.
+#[derive(Clone, Copy)]
+enum ListChild {
+    Node(u32),
+    Object(u32),
+}
+
+struct ListNode {
+    bbox: AABB,
+    left: ListChild,
+    right: ListChild,
+}
+
+/// The BVH of the objects of a list that have a bounding box, as `MeshBVH` is of the
+/// triangles of a mesh. The meshes have none, their triangles being in BVHs of their
+/// own, and are tested one by one.
+struct ListBVH {
+    nodes: Vec<ListNode>,
+    root: Option<ListChild>,
+    unbounded: Vec<u32>,
+}
+
+impl ListBVH {
+    fn build(objects: &[Object]) -> Self {
+        let start = Instant::now();
+        let boxes: Vec<Option<AABB>> = objects.iter().map(Hittable::bounding_box).collect();
+        let (mut bounded, unbounded): (Vec<u32>, Vec<u32>) =
+            (0..objects.len() as u32).partition(|&index| boxes[index as usize].is_some());
+        // The boxes of the unbounded objects are left out of the BVH, never read
+        let boxes: Vec<AABB> = boxes
+            .into_iter()
+            .map(|bbox| bbox.unwrap_or(AABB::new(Vec3::zero(), Vec3::zero())))
+            .collect();
+        let mut bvh = ListBVH {
+            nodes: Vec::with_capacity(MeshBVH::shape(bounded.len()).0),
+            root: None,
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.root = Some(bvh.build_node(&boxes, &mut bounded));
+        }
+        debug!(
+            objects = objects.len(),
+            nodes = bvh.nodes.len(),
+            elapsed = ?start.elapsed(),
+            "Built the BVH of the objects"
+        );
+        bvh
+    }
+
+    /// Builds the subtree of the objects of `order`, sorted along the longest axis of
+    /// their box and split in halves, the shape of `MeshBVH` then.
+    fn build_node(&mut self, boxes: &[AABB], order: &mut [u32]) -> ListChild {
+        if order.len() == 1 {
+            return ListChild::Object(order[0]);
+        }
+        let bbox = order
+            .iter()
+            .map(|&index| boxes[index as usize])
+            .reduce(AABB::surrounding_box)
+            .expect("the subtrees are not empty");
+        let extent = bbox.maximum - bbox.minimum;
+        let comparator = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            AABB::compare_x
+        } else if extent.y() >= extent.z() {
+            AABB::compare_y
+        } else {
+            AABB::compare_z
+        };
+        order.sort_by(|&a, &b| comparator(boxes[a as usize], boxes[b as usize]));
+
+        // The node goes before its subtrees, filled in once they are built
+        let index = self.nodes.len();
+        self.nodes.push(ListNode {
+            bbox,
+            left: ListChild::Object(order[0]),
+            right: ListChild::Object(order[0]),
+        });
+        let mid = order.len() / 2;
+        let (first, second) = order.split_at_mut(mid);
+        self.nodes[index].left = self.build_node(boxes, first);
+        self.nodes[index].right = self.build_node(boxes, second);
+        ListChild::Node(index as u32)
     }
 }
 
@@ -35,27 +155,30 @@ impl Hittable for HittableList {
     /// - `ray`: The ray to test for intersections.
     /// - `t_min`: The minimum value of the parameter `t` to consider.
     /// - `t_max`: The maximum value of the parameter `t` to consider.
-    /// - `rec`: A mutable reference to a `HitRecord` to store intersection details.
     ///
     /// # Returns
-    /// - `true` if the ray intersects any object in the list, `false` otherwise.
+    /// - The closest intersection with the objects of the list, or `None` if the ray
+    ///   misses them all.
     ///
-    /// This method iterates through all objects in the list and checks for intersections.
-    /// If an intersection is found, it updates the `HitRecord` with the closest intersection.
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        let mut temp_rec = HitRecord::new();
-        let mut hit_anything = false;
-        let mut closest_so_far = t_max;
+    /// This method traverses the BVH of the objects with a bounding box, then checks the
+    /// others one by one, keeping the closest intersection.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Only the shadow rays look for a hit within a distance
+        progress::count_ray(t_max.is_finite());
+        // The other threads wait for the first to build it
+        let bvh = self.bvh.get_or_init(|| ListBVH::build(&self.objects));
+        let mut closest = bvh
+            .root
+            .and_then(|root| self.hit_child(bvh, root, ray, t_min, t_max));
 
-        for object in &self.objects {
-            if object.hit(ray, t_min, closest_so_far, &mut temp_rec) {
-                hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec.clone();
+        for &index in &bvh.unbounded {
+            let closest_so_far = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = self.objects[index as usize].hit(ray, t_min, closest_so_far) {
+                closest = Some(rec);
             }
         }
 
-        hit_anything
+        closest
     }
     fn bounding_box(&self) -> Option<AABB> {
         if self.objects.is_empty() {
@@ -0,0 +1,43 @@
+use crate::hittable::Hittable;
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use utils::{Color, Float};
+
+/// An integrator returning the fraction of the cosine-weighted hemisphere that is
+/// unoccluded within `max_distance` of the first hit.
+pub struct AmbientOcclusionIntegrator {
+    max_distance: Float,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(max_distance: Float) -> Self {
+        Self { max_distance }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let Some(rec) = scene.world.hit(ray, 0.001, Float::INFINITY) else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+
+        let (u1, u2) = sampler.get_2d();
+        let r = u2.sqrt();
+        let phi = 2.0 * utils::consts::PI * u1;
+        let local = utils::Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u2).sqrt());
+        let direction = utils::Onb::from_normal(rec.normal).to_world(local);
+
+        let occlusion_ray = ray.spawn(rec.p, direction);
+        if scene
+            .world
+            .hit(&occlusion_ray, 0.001, self.max_distance)
+            .is_some()
+        {
+            Color::zero()
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+}
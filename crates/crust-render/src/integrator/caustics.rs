@@ -0,0 +1,162 @@
+use crate::hittable::Hittable;
+use crate::integrator::scatter;
+use crate::integrator::sppm::cell_of;
+use crate::sampler::{IndependentSampler, PHOTON_STREAMS, stream_seed};
+use crate::scene::Scene;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use tracing::debug;
+use utils::{Color, Float, Point3, Vec3};
+use web_time::Instant;
+
+/// A photon landed on a non-specular surface after one or more specular bounces.
+struct Photon {
+    p: Point3,
+    normal: Vec3,
+    power: Color,
+}
+
+/// A photon map of the caustics of a scene: the light reaching its non-specular
+/// surfaces through mirrors and glass only, which the path tracer finds by chance.
+///
+/// The photons are shot from the lights once, before the render, and hashed into a
+/// uniform grid of cells twice the gather radius, so that a gather looks up 8 cells at
+/// most. The path tracer layered on it gathers them at its non-specular hits instead
+/// of tracing the paths reaching the lights through specular surfaces from there, see
+/// `PathIntegrator`.
+///
+/// Non-specular surfaces are approximated as Lambertian with the albedo of their
+/// material when the photons are gathered, as in `SppmIntegrator`.
+pub struct CausticMap {
+    photons: Vec<Photon>,
+    grid: HashMap<(i32, i32, i32), Vec<u32>>,
+    /// The cells of the grid holding photons, so that the points far from the
+    /// caustics skip the lookups.
+    cells: ((i32, i32, i32), (i32, i32, i32)),
+    radius: Float,
+    /// The number of photons shot, the stored ones and the others.
+    emitted: u32,
+}
+
+impl CausticMap {
+    /// Shoots the photons of the caustics of a scene.
+    ///
+    /// # Parameters
+    /// - `scene`: The scene, its geometry and its lights.
+    /// - `photons`: The number of photons shot from the lights, most of them missing
+    ///   the specular surfaces and not stored.
+    /// - `radius`: The radius within which the photons are gathered.
+    /// - `max_depth`: The maximum number of bounces of a photon.
+    /// - `seed`: The seed of the render, decorrelating the photons from those of the
+    ///   other seeds.
+    pub fn build(scene: &Scene, photons: u32, radius: Float, max_depth: u32, seed: u32) -> Self {
+        let start = Instant::now();
+        let lights = &scene.lights.lights;
+        let photons_shot = if lights.is_empty() { 0 } else { photons };
+        let stored: Vec<Photon> = (0..photons_shot)
+            .into_par_iter()
+            .filter_map(|photon| {
+                // Streams of their own, apart from those of the pixels
+                let stream = PHOTON_STREAMS + photon as u64;
+                utils::with_random_stream(stream_seed(seed, 0), stream, || {
+                    trace_photon(scene, max_depth)
+                })
+            })
+            .collect();
+        let mut grid: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+        let cell_size = 2.0 * radius;
+        let mut cells = (
+            (i32::MAX, i32::MAX, i32::MAX),
+            (i32::MIN, i32::MIN, i32::MIN),
+        );
+        for (index, photon) in stored.iter().enumerate() {
+            let cell = cell_of(photon.p, cell_size);
+            grid.entry(cell).or_default().push(index as u32);
+            cells.0 = (
+                cells.0.0.min(cell.0),
+                cells.0.1.min(cell.1),
+                cells.0.2.min(cell.2),
+            );
+            cells.1 = (
+                cells.1.0.max(cell.0),
+                cells.1.1.max(cell.1),
+                cells.1.2.max(cell.2),
+            );
+        }
+        debug!(
+            shot = photons_shot,
+            stored = stored.len(),
+            elapsed = ?start.elapsed(),
+            "Built the caustic photon map"
+        );
+        Self {
+            photons: stored,
+            grid,
+            cells,
+            radius,
+            emitted: photons_shot,
+        }
+    }
+
+    /// The radiance of the caustics leaving a non-specular surface, from the photons
+    /// within the radius of the point on the same side of the surface.
+    ///
+    /// # Parameters
+    /// - `p`: The point of the surface.
+    /// - `normal`: The normal of the surface, facing the ray that hit it.
+    /// - `albedo`: The albedo of the surface.
+    pub fn radiance(&self, p: Point3, normal: Vec3, albedo: Color) -> Color {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let cell_size = 2.0 * self.radius;
+        let (lo, hi) = (cell_of(p - r, cell_size), cell_of(p + r, cell_size));
+        let (min, max) = self.cells;
+        let mut flux = Color::zero();
+        for x in lo.0.max(min.0)..=hi.0.min(max.0) {
+            for y in lo.1.max(min.1)..=hi.1.min(max.1) {
+                for z in lo.2.max(min.2)..=hi.2.min(max.2) {
+                    for &index in self.grid.get(&(x, y, z)).into_iter().flatten() {
+                        let photon = &self.photons[index as usize];
+                        if (photon.p - p).length_squared() <= self.radius * self.radius
+                            && utils::dot(photon.normal, normal) > 0.0
+                        {
+                            flux += photon.power;
+                        }
+                    }
+                }
+            }
+        }
+        if flux.is_black() {
+            return Color::zero();
+        }
+        let area = utils::consts::PI * self.radius * self.radius;
+        flux * albedo / (utils::consts::PI * area * self.emitted as Float)
+    }
+}
+
+/// Traces a photon from a light through the specular surfaces.
+///
+/// # Returns
+/// - The photon where it lands on a non-specular surface, or `None` if it lands
+///   there without a specular bounce before, carrying no caustic.
+fn trace_photon(scene: &Scene, max_depth: u32) -> Option<Photon> {
+    let lights = &scene.lights.lights;
+    let light_index = ((utils::random() * lights.len() as Float) as usize).min(lights.len() - 1);
+    let (mut ray, power) =
+        lights[light_index].sample_emission(utils::random2(), utils::random2())?;
+    let mut power = power * lights.len() as Float;
+    for depth in 0..max_depth {
+        let rec = scene.world.hit(&ray, 0.001, Float::INFINITY)?;
+        let mat = rec.mat;
+        if !mat.is_specular() {
+            return (depth > 0).then_some(Photon {
+                p: rec.p,
+                normal: rec.normal,
+                power,
+            });
+        }
+        let (scattered, weight) = scatter(mat, &ray, &rec, &mut IndependentSampler)?;
+        power *= weight;
+        ray = scattered;
+    }
+    None
+}
@@ -0,0 +1,60 @@
+use crate::hittable::Hittable;
+use crate::integrator::{Integrator, bsdf_mis_weight, sample_lights};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use utils::{Color, Float};
+
+/// A fast preview integrator: light sampling at the first hit plus a single
+/// BRDF-sampled bounce that gathers emission and sky, without further recursion.
+///
+/// It misses all indirect illumination, but converges in a handful of samples,
+/// which makes it a good fit for layout and lookdev previews.
+pub struct DirectLightingIntegrator;
+
+impl Integrator for DirectLightingIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        self.li_groups(ray, scene, sampler, &mut [])
+    }
+
+    fn li_groups(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        groups: &mut [Color],
+    ) -> Color {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let Some(rec) = world.hit(ray, 0.001, Float::INFINITY) else {
+            return scene.background.radiance(ray);
+        };
+        let mat = rec.mat;
+
+        lights.record_emission(groups, rec.object_id, mat.emitted());
+        let mut radiance =
+            mat.emitted() + sample_lights(ray, &rec, world, lights, sampler, true, groups);
+
+        if let Some((scattered, brdf_value, brdf_pdf)) = mat.scatter_importance(ray, &rec, sampler)
+        {
+            let cosine = Float::max(
+                utils::dot(rec.normal, utils::unit_vector(scattered.direction())),
+                0.0,
+            );
+            let weight = brdf_value * cosine / brdf_pdf;
+
+            if let Some(bounce) = world.hit(&scattered, 0.001, Float::INFINITY) {
+                let emitted = bounce.mat.emitted();
+                if !emitted.is_black() {
+                    let contribution =
+                        emitted * weight * bsdf_mis_weight(lights, rec.p, bounce.p, brdf_pdf);
+                    lights.record_emission(groups, bounce.object_id, contribution);
+                    radiance += contribution;
+                }
+            } else {
+                radiance += weight * scene.background.radiance(&scattered);
+            }
+        }
+
+        radiance
+    }
+}
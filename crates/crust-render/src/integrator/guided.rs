@@ -0,0 +1,632 @@
+use crate::aabb::AABB;
+use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::integrator::{atomic_add, bsdf_mis_weight, scatter};
+use crate::progress;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerType, TRAINING_STREAMS, stream_seed};
+use crate::scene::Scene;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use utils::consts::PI;
+use utils::{Color, Float, Point3, Vec3};
+
+/// Samples a spatial leaf may collect in the first iteration before it is split;
+/// the threshold grows with the square root of the samples per pixel.
+const SPATIAL_THRESHOLD: Float = 12000.0;
+/// Maximum depth of the spatial binary tree.
+const MAX_SPATIAL_DEPTH: u32 = 24;
+/// Fraction of a directional tree's energy above which a quadrant is subdivided.
+const SUBDIVISION_THRESHOLD: Float = 0.01;
+/// Maximum depth of the directional quadtrees.
+const MAX_DIRECTIONAL_DEPTH: u32 = 20;
+
+/// Practical path guiding (Müller et al. 2017).
+///
+/// An SD-tree learns the incident radiance over the scene during a few training passes
+/// with doubling sample counts: a binary tree over space whose leaves hold quadtrees
+/// over the sphere of directions. Both trees are refined between passes where the
+/// energy concentrates. The final pass samples each bounce from a one-sample MIS mix
+/// of the learned distribution and cosine sampling, sending paths toward the bright
+/// directions that plain BRDF sampling rarely finds in indirectly lit scenes.
+///
+/// Diffuse surfaces are approximated as Lambertian with the material's albedo, so the
+/// BRDF and its density can be evaluated for the guided directions.
+pub struct GuidedPathIntegrator {
+    training_iterations: u32,
+    guiding_fraction: Float,
+    max_depth: u32,
+    seed: u32,
+}
+
+/// A directional quadtree node: the energy of its four quadrants and their children.
+struct QuadNode {
+    energy: [AtomicU32; 4],
+    /// Index of each quadrant's child node, 0 for leaves.
+    children: [u32; 4],
+}
+
+/// Distribution over the sphere of directions, in cylindrical coordinates.
+struct QuadTree {
+    nodes: Vec<QuadNode>,
+}
+
+/// The directional part of a spatial leaf.
+struct DTree {
+    /// Distribution learned in the previous pass, used for sampling.
+    sampling: QuadTree,
+    /// Distribution being learned in the current pass.
+    building: QuadTree,
+    samples: AtomicU32,
+}
+
+/// A spatial node, split in half along `axis`.
+struct SpatialNode {
+    axis: usize,
+    /// Children of an interior node, or `None` with the index of the leaf's `DTree`.
+    children: Option<[u32; 2]>,
+    dtree: usize,
+}
+
+/// Spatial binary tree with a directional quadtree at each leaf.
+struct SdTree {
+    bounds: AABB,
+    nodes: Vec<SpatialNode>,
+    dtrees: Vec<DTree>,
+}
+
+/// Scattering vertex of a path, kept to record the radiance it received.
+struct Vertex {
+    dtree: usize,
+    direction: Vec3,
+    /// Radiance of the path before the contributions found through `direction`.
+    radiance: Color,
+    /// Throughput of the path after the scattering.
+    throughput: Color,
+    pdf: Float,
+}
+
+impl QuadNode {
+    fn new(energy: [Float; 4]) -> Self {
+        Self {
+            energy: energy.map(|e| AtomicU32::new((e as f32).to_bits())),
+            children: [0; 4],
+        }
+    }
+
+    fn energies(&self) -> [Float; 4] {
+        [0, 1, 2, 3].map(|i| f32::from_bits(self.energy[i].load(Ordering::Relaxed)) as Float)
+    }
+}
+
+impl QuadTree {
+    fn new() -> Self {
+        Self {
+            nodes: vec![QuadNode::new([0.0; 4])],
+        }
+    }
+
+    fn total(&self) -> Float {
+        self.nodes[0].energies().iter().sum()
+    }
+
+    /// Deposits energy along the path from the root to the leaf containing `(u, v)`.
+    fn record(&self, (mut u, mut v): (Float, Float), energy: Float) {
+        let mut node = 0;
+        loop {
+            let i = quadrant(&mut u, &mut v);
+            atomic_add(&self.nodes[node].energy[i], energy);
+            match self.nodes[node].children[i] {
+                0 => return,
+                child => node = child as usize,
+            }
+        }
+    }
+
+    /// Samples a point of the unit square proportionally to the energy.
+    ///
+    /// # Returns
+    /// - The point and its density over the unit square.
+    fn sample(&self, (u, v): (Float, Float)) -> ((Float, Float), Float) {
+        let (mut origin, mut size, mut pdf) = ((0.0, 0.0), 1.0, 1.0);
+        let mut node = 0;
+        loop {
+            let energies = self.nodes[node].energies();
+            let total: Float = energies.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            let mut pick = utils::random() * total;
+            let mut i = 0;
+            while i < 3 && pick >= energies[i] {
+                pick -= energies[i];
+                i += 1;
+            }
+            pdf *= 4.0 * energies[i] / total;
+            size *= 0.5;
+            origin = (
+                origin.0 + size * (i & 1) as Float,
+                origin.1 + size * (i >> 1) as Float,
+            );
+            match self.nodes[node].children[i] {
+                0 => break,
+                child => node = child as usize,
+            }
+        }
+        ((origin.0 + size * u, origin.1 + size * v), pdf)
+    }
+
+    /// The density over the unit square at `(u, v)`.
+    fn pdf(&self, (mut u, mut v): (Float, Float)) -> Float {
+        let mut pdf = 1.0;
+        let mut node = 0;
+        loop {
+            let energies = self.nodes[node].energies();
+            let total: Float = energies.iter().sum();
+            if total <= 0.0 {
+                return pdf;
+            }
+            let i = quadrant(&mut u, &mut v);
+            pdf *= 4.0 * energies[i] / total;
+            match self.nodes[node].children[i] {
+                0 => return pdf,
+                child => node = child as usize,
+            }
+        }
+    }
+
+    /// A copy of the structure, subdivided where the energy concentrates, with no energy.
+    fn refined(&self) -> QuadTree {
+        let mut tree = QuadTree::new();
+        let total = self.total();
+        if total > 0.0 {
+            self.refine_node(Some(0), self.nodes[0].energies(), total, 1, 0, &mut tree);
+        }
+        for node in &tree.nodes {
+            for e in &node.energy {
+                e.store(0.0f32.to_bits(), Ordering::Relaxed);
+            }
+        }
+        tree
+    }
+
+    fn refine_node(
+        &self,
+        node: Option<usize>,
+        energies: [Float; 4],
+        total: Float,
+        depth: u32,
+        target: usize,
+        tree: &mut QuadTree,
+    ) {
+        for (i, &energy) in energies.iter().enumerate() {
+            if depth >= MAX_DIRECTIONAL_DEPTH || energy / total <= SUBDIVISION_THRESHOLD {
+                continue;
+            }
+            // Energy below a former leaf is assumed to be uniform
+            let child = node
+                .map(|n| self.nodes[n].children[i] as usize)
+                .filter(|&c| c != 0);
+            let child_energies = child.map_or([energy / 4.0; 4], |c| self.nodes[c].energies());
+            let index = tree.nodes.len();
+            tree.nodes.push(QuadNode::new([0.0; 4]));
+            tree.nodes[target].children[i] = index as u32;
+            self.refine_node(child, child_energies, total, depth + 1, index, tree);
+        }
+    }
+
+    fn clone_tree(&self) -> QuadTree {
+        QuadTree {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| QuadNode {
+                    energy: node
+                        .energies()
+                        .map(|e| AtomicU32::new((e as f32).to_bits())),
+                    children: node.children,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DTree {
+    fn new() -> Self {
+        Self {
+            sampling: QuadTree::new(),
+            building: QuadTree::new(),
+            samples: AtomicU32::new(0),
+        }
+    }
+
+    fn clone_tree(&self) -> DTree {
+        DTree {
+            sampling: self.sampling.clone_tree(),
+            building: self.building.clone_tree(),
+            samples: AtomicU32::new(self.samples.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn is_trained(&self) -> bool {
+        self.sampling.total() > 0.0
+    }
+
+    /// Samples a direction from the learned distribution.
+    fn sample(&self, u: (Float, Float)) -> (Vec3, Float) {
+        let (point, pdf) = self.sampling.sample(u);
+        (square_to_direction(point), pdf / (4.0 * PI))
+    }
+
+    /// The solid angle density of sampling `direction`.
+    fn pdf(&self, direction: Vec3) -> Float {
+        self.sampling.pdf(direction_to_square(direction)) / (4.0 * PI)
+    }
+
+    fn record(&self, direction: Vec3, energy: Float) {
+        self.building.record(direction_to_square(direction), energy);
+    }
+}
+
+impl SdTree {
+    fn new(bounds: AABB) -> Self {
+        Self {
+            bounds,
+            nodes: vec![SpatialNode {
+                axis: 0,
+                children: None,
+                dtree: 0,
+            }],
+            dtrees: vec![DTree::new()],
+        }
+    }
+
+    /// Index of the directional tree of the leaf containing `p`.
+    fn lookup(&self, p: Point3) -> usize {
+        let extent = self.bounds.maximum - self.bounds.minimum;
+        let mut q = [0, 1, 2]
+            .map(|a| ((p[a] - self.bounds.minimum[a]) / extent[a].max(1e-4)).clamp(0.0, 0.9999));
+        let mut node = 0;
+        loop {
+            let n = &self.nodes[node];
+            let Some(children) = n.children else {
+                return n.dtree;
+            };
+            let a = n.axis;
+            let side = (q[a] >= 0.5) as usize;
+            q[a] = 2.0 * q[a] - side as Float;
+            node = children[side] as usize;
+        }
+    }
+
+    /// Splits crowded leaves, then moves the learned distributions to sampling.
+    fn refine(&mut self, threshold: Float) {
+        let mut stack = vec![(0usize, 0u32)];
+        while let Some((node, depth)) = stack.pop() {
+            if let Some(children) = self.nodes[node].children {
+                stack.extend(children.iter().map(|&c| (c as usize, depth + 1)));
+                continue;
+            }
+            let dtree = self.nodes[node].dtree;
+            let samples = self.dtrees[dtree].samples.load(Ordering::Relaxed);
+            if depth >= MAX_SPATIAL_DEPTH || (samples as Float) < threshold {
+                continue;
+            }
+            // Both halves start from the parent's distribution and half its samples
+            let axis = self.nodes[node].axis;
+            let copy = self.dtrees[dtree].clone_tree();
+            copy.samples.store(samples / 2, Ordering::Relaxed);
+            self.dtrees[dtree]
+                .samples
+                .store(samples / 2, Ordering::Relaxed);
+            self.dtrees.push(copy);
+            let first = self.nodes.len() as u32;
+            for d in [dtree, self.dtrees.len() - 1] {
+                self.nodes.push(SpatialNode {
+                    axis: (axis + 1) % 3,
+                    children: None,
+                    dtree: d,
+                });
+            }
+            self.nodes[node].children = Some([first, first + 1]);
+            stack.extend([(first as usize, depth + 1), (first as usize + 1, depth + 1)]);
+        }
+
+        for dtree in &mut self.dtrees {
+            dtree.sampling = dtree.building.clone_tree();
+            dtree.building = dtree.sampling.refined();
+            dtree.samples.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl GuidedPathIntegrator {
+    pub fn new(training_iterations: u32, guiding_fraction: Float, max_depth: u32) -> Self {
+        Self {
+            training_iterations,
+            guiding_fraction: guiding_fraction.clamp(0.0, 1.0),
+            max_depth,
+            seed: 0,
+        }
+    }
+
+    /// Sets the seed of the render, decorrelating its samples from those of the other
+    /// seeds.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Trains the SD-tree, then renders the image with guided sampling.
+    ///
+    /// Training pass `k` takes `2^k` samples per pixel; the final pass takes
+    /// `samples_per_pixel` and is the only one kept in the image.
+    ///
+    /// # Parameters
+    /// - `scene`: The scene, seen through its camera.
+    /// - `sampler_type`: The sampler used for the camera rays.
+    /// - `width`, `height`: The film resolution.
+    ///
+    /// # Returns
+    /// - A `Buffer` holding the final image.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        sampler_type: SamplerType,
+        samples_per_pixel: u32,
+        width: usize,
+        height: usize,
+    ) -> Buffer {
+        let mut tree = SdTree::new(scene_bounds(&scene.camera, &scene.world));
+        let bar = progress::progress_bar(
+            u64::from(self.training_iterations),
+            "Guiding training passes",
+        );
+        for iteration in 0..self.training_iterations {
+            let spp = 1 << iteration.min(16);
+            self.render_pass(scene, sampler_type, spp, (width, height), &tree, true);
+            tree.refine(SPATIAL_THRESHOLD * (spp as Float).sqrt());
+            bar.inc(1);
+        }
+        bar.finish();
+        self.render_pass(
+            scene,
+            sampler_type,
+            samples_per_pixel.max(1),
+            (width, height),
+            &tree,
+            false,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        scene: &Scene,
+        sampler_type: SamplerType,
+        spp: u32,
+        (width, height): (usize, usize),
+        tree: &SdTree,
+        learn: bool,
+    ) -> Buffer {
+        let mut film = Buffer::new(width, height);
+        let rows: Vec<Vec<Color>> = (0..height)
+            .into_par_iter()
+            .map(|j| {
+                (0..width)
+                    .map(|i| {
+                        let first = if learn { TRAINING_STREAMS } else { 0 };
+                        utils::with_random_stream(
+                            stream_seed(self.seed, spp),
+                            first + (j * width + i) as u64,
+                            || {
+                                let mut sampler =
+                                    sampler_type.create_seeded((i, j), spp, self.seed);
+                                let mut sum = Color::zero();
+                                for s in 0..spp {
+                                    sampler.start_sample(s);
+                                    let (u_offset, v_offset) = sampler.get_2d();
+                                    let (lens_u, lens_v) = sampler.get_2d();
+                                    let u = (i as Float + u_offset) / (width - 1) as Float;
+                                    let v = (j as Float + v_offset) / (height - 1) as Float;
+                                    let ray = scene.camera.get_ray_lens(u, v, lens_u, lens_v, 0.0);
+                                    sum += self.li(&ray, scene, sampler.as_mut(), tree, learn);
+                                }
+                                sum / spp as Float
+                            },
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        for (j, row) in rows.into_iter().enumerate() {
+            for (i, color) in row.into_iter().enumerate() {
+                film.set_pixel(i, j, color);
+            }
+        }
+        film
+    }
+
+    /// Traces a path, sampling diffuse bounces from the mix of guiding and cosine
+    /// sampling, and records the radiance received by its vertices when `learn` is set.
+    fn li(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        tree: &SdTree,
+        learn: bool,
+    ) -> Color {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let mut radiance = Color::zero();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = r.spawn(r.origin(), r.direction());
+        let mut vertices: Vec<Vertex> = Vec::new();
+        // Diffuse sample that produced the current ray: (origin, throughput before it, weight, pdf)
+        let mut bsdf_sample: Option<(Point3, Color, Color, Float)> = None;
+
+        for bounce in 0..=self.max_depth {
+            let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+                if bounce < self.max_depth {
+                    radiance += throughput * scene.background.radiance(&ray);
+                }
+                break;
+            };
+            let mat = rec.mat;
+            let emitted = mat.emitted();
+            if !emitted.is_black() {
+                radiance += match bsdf_sample {
+                    Some((origin, prev_throughput, weight, pdf)) => {
+                        prev_throughput
+                            * emitted
+                            * weight
+                            * bsdf_mis_weight(lights, origin, rec.p, pdf)
+                    }
+                    None => throughput * emitted,
+                };
+            }
+            if bounce == self.max_depth {
+                break;
+            }
+
+            if mat.is_specular() {
+                let Some((scattered, weight)) = scatter(mat, &ray, &rec, sampler) else {
+                    break;
+                };
+                bsdf_sample = None;
+                throughput *= weight;
+                ray = scattered;
+                continue;
+            }
+            let brdf = mat.albedo() / PI;
+            if brdf.max_component() <= 0.0 {
+                break;
+            }
+            let dtree_index = tree.lookup(rec.p);
+            let dtree = &tree.dtrees[dtree_index];
+            let alpha = if dtree.is_trained() {
+                self.guiding_fraction
+            } else {
+                0.0
+            };
+            let mixture_pdf = |direction: Vec3, cosine: Float| {
+                let guided = if alpha > 0.0 {
+                    dtree.pdf(direction)
+                } else {
+                    0.0
+                };
+                alpha * guided + (1.0 - alpha) * cosine.max(0.0) / PI
+            };
+
+            // === 1. Direct lighting via light sampling ===
+            for light in lights.lights.iter() {
+                let light_point = light.sample_cmj(sampler.get_1d(), sampler.get_1d());
+                let offset = light_point - rec.p;
+                let distance = offset.length();
+                let direction = offset / distance;
+                let cosine = utils::dot(rec.normal, direction);
+                if cosine <= 0.0 {
+                    continue;
+                }
+                let shadow_ray = ray.spawn(rec.p, direction);
+                if world.hit(&shadow_ray, 0.001, distance - 0.001).is_some() {
+                    continue;
+                }
+                let light_pdf = light.pdf(rec.p, light_point);
+                let weight = utils::balance_heuristic(light_pdf, mixture_pdf(direction, cosine));
+                radiance += throughput * light.color() * brdf * cosine * weight / light_pdf;
+            }
+
+            // === 2. Indirect lighting via guided or cosine sampling ===
+            let direction = if sampler.get_1d() < alpha {
+                dtree.sample(sampler.get_2d()).0
+            } else {
+                let (u, v) = sampler.get_2d();
+                let (radius, phi) = (u.sqrt(), 2.0 * PI * v);
+                let local = Vec3::new(radius * phi.cos(), radius * phi.sin(), (1.0 - u).sqrt());
+                utils::Onb::from_normal(rec.normal).to_world(local)
+            };
+            let cosine = utils::dot(rec.normal, direction);
+            let pdf = mixture_pdf(direction, cosine);
+            if cosine <= 0.0 || pdf <= 0.0 {
+                break;
+            }
+            let weight = brdf * cosine / pdf;
+            bsdf_sample = Some((rec.p, throughput, weight, pdf));
+            throughput *= weight;
+            if learn {
+                vertices.push(Vertex {
+                    dtree: dtree_index,
+                    direction,
+                    radiance,
+                    throughput,
+                    pdf,
+                });
+            }
+            ray = ray.spawn(rec.p, direction);
+        }
+
+        // === Record the incident radiance found through each sampled direction ===
+        for vertex in &vertices {
+            let dtree = &tree.dtrees[vertex.dtree];
+            dtree.samples.fetch_add(1, Ordering::Relaxed);
+            let incident = (radiance - vertex.radiance).luminance() / vertex.throughput.luminance();
+            if incident.is_finite() && incident > 0.0 {
+                dtree.record(vertex.direction, incident / vertex.pdf);
+            }
+        }
+
+        radiance
+    }
+}
+
+/// Moves `(u, v)` into the quadrant it falls in, rescaled to the unit square.
+fn quadrant(u: &mut Float, v: &mut Float) -> usize {
+    let x = (*u >= 0.5) as usize;
+    let y = (*v >= 0.5) as usize;
+    *u = 2.0 * *u - x as Float;
+    *v = 2.0 * *v - y as Float;
+    x + 2 * y
+}
+
+/// Area-preserving map from the unit square to the sphere (cylindrical coordinates).
+fn square_to_direction((u, v): (Float, Float)) -> Vec3 {
+    let cos_theta = 2.0 * u - 1.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+fn direction_to_square(direction: Vec3) -> (Float, Float) {
+    let d = utils::unit_vector(direction);
+    let u = ((d.z() + 1.0) * 0.5).clamp(0.0, 0.9999);
+    let phi = d.y().atan2(d.x());
+    let v = (if phi < 0.0 { phi + 2.0 * PI } else { phi }) / (2.0 * PI);
+    (u, v.clamp(0.0, 0.9999))
+}
+
+/// Bounds of the scene for the spatial tree, probing it with camera rays when the
+/// geometry does not report its own bounding box (e.g. meshes).
+fn scene_bounds(camera: &Camera, world: &dyn Hittable) -> AABB {
+    if let Some(bounds) = world.bounding_box() {
+        return bounds;
+    }
+    const PROBES: usize = 64;
+    let origin = camera.get_ray_lens(0.5, 0.5, 0.5, 0.5, 0.0).origin();
+    let (mut minimum, mut maximum) = (origin, origin);
+    for j in 0..PROBES {
+        for i in 0..PROBES {
+            let u = (i as Float + 0.5) / PROBES as Float;
+            let v = (j as Float + 0.5) / PROBES as Float;
+            let ray = camera.get_ray_lens(u, v, 0.5, 0.5, 0.0);
+            if let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) {
+                for a in 0..3 {
+                    minimum[a] = minimum[a].min(rec.p[a]);
+                    maximum[a] = maximum[a].max(rec.p[a]);
+                }
+            }
+        }
+    }
+    AABB::new(minimum, maximum)
+}
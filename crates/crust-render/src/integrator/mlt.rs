@@ -0,0 +1,324 @@
+use crate::buffer::Buffer;
+use crate::integrator::Integrator;
+use crate::sampler::{Sampler, stream_seed};
+use crate::scene::Scene;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use utils::{Color, Float};
+
+/// Primary-sample-space Metropolis light transport (Kelemen et al. 2002).
+///
+/// A path is a deterministic function of an infinite vector of uniform numbers. Markov
+/// chains mutate that vector (small perturbations and occasional independent "large
+/// steps") and splat the resulting radiance wherever the path lands on the film, so the
+/// sample density follows image brightness. This resolves transport that unidirectional
+/// path tracing rarely finds, such as caustics seen through glass.
+///
+/// Every random decision of the wrapped integrator, including material sampling through
+/// `utils::random()`, is routed to the chain's primary sample vector.
+pub struct MltIntegrator {
+    /// Average number of mutations per pixel.
+    mutations_per_pixel: u32,
+    /// Probability of an independent large-step mutation.
+    large_step_probability: Float,
+    /// Standard deviation of the small-step perturbation.
+    sigma: Float,
+    /// Number of independent Markov chains.
+    chains: u32,
+    /// Number of paths used to estimate the image brightness and seed the chains.
+    bootstrap_samples: u32,
+    /// Seed of the render, decorrelating its paths and chains from those of the other
+    /// seeds.
+    seed: u32,
+}
+
+impl MltIntegrator {
+    pub fn new(
+        mutations_per_pixel: u32,
+        large_step_probability: Float,
+        sigma: Float,
+        chains: u32,
+        bootstrap_samples: u32,
+    ) -> Self {
+        Self {
+            mutations_per_pixel,
+            large_step_probability: large_step_probability.clamp(0.0, 1.0),
+            sigma,
+            chains: chains.max(1),
+            bootstrap_samples: bootstrap_samples.max(1),
+            seed: 0,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Renders the image with Metropolis sampling.
+    ///
+    /// # Parameters
+    /// - `scene`: The scene, seen through its camera.
+    /// - `integrator`: The integrator evaluating the radiance of a camera ray.
+    /// - `width`, `height`: The film resolution.
+    ///
+    /// # Returns
+    /// - A `Buffer` holding the final image.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        integrator: &dyn Integrator,
+        width: usize,
+        height: usize,
+    ) -> Buffer {
+        let evaluate = |sampler: &mut MltSampler| {
+            sampler.state.borrow_mut().start_iteration();
+            let state = sampler.state.clone();
+            utils::with_random_source(
+                move || state.borrow_mut().next(),
+                || {
+                    let (u, v) = sampler.get_2d();
+                    let (lens_u, lens_v) = sampler.get_2d();
+                    let px = u * width as Float;
+                    let py = v * height as Float;
+                    let ray = scene.camera.get_ray_lens(
+                        px / (width - 1) as Float,
+                        py / (height - 1) as Float,
+                        lens_u,
+                        lens_v,
+                        0.0,
+                    );
+                    let l = integrator.li(&ray, scene, sampler);
+                    let pixel = ((px as usize).min(width - 1), (py as usize).min(height - 1));
+                    (pixel, sanitize(l))
+                },
+            )
+        };
+
+        // === Bootstrap: estimate the normalization and the chains' starting points ===
+        let bootstrap_weights: Vec<Float> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map(|i| {
+                let mut sampler = self.sampler(i);
+                let (_, l) = evaluate(&mut sampler);
+                l.luminance()
+            })
+            .collect();
+        let total: Float = bootstrap_weights.iter().sum();
+        let b = total / self.bootstrap_samples as Float;
+        let mut film = Buffer::new(width, height);
+        if b <= 0.0 {
+            return film;
+        }
+
+        // === Run the chains ===
+        let total_mutations = self.mutations_per_pixel as u64 * (width * height) as u64;
+        let chains = self.chains as u64;
+        let accumulation = (0..chains)
+            .into_par_iter()
+            .fold(
+                || vec![Color::zero(); width * height],
+                |mut acc, chain| {
+                    let chain_seed = stream_seed(self.seed, chain as u32);
+                    let mut rng = StdRng::seed_from_u64(chain_seed ^ 0x6d6c_745f_6368_6169);
+                    let path = sample_discrete(&bootstrap_weights, total, rng.random());
+                    let mut sampler = self.sampler(path as u32);
+                    let (mut current_pixel, mut current_l) = evaluate(&mut sampler);
+                    sampler.state.borrow_mut().accept();
+
+                    let mutations =
+                        total_mutations / chains + u64::from(chain < total_mutations % chains);
+                    for _ in 0..mutations {
+                        let large_step = rng.random::<Float>() < self.large_step_probability;
+                        sampler.state.borrow_mut().large_step = large_step;
+                        let (proposed_pixel, proposed_l) = evaluate(&mut sampler);
+
+                        let current_y = current_l.luminance();
+                        let proposed_y = proposed_l.luminance();
+                        let accept = if current_y > 0.0 {
+                            (proposed_y / current_y).min(1.0)
+                        } else {
+                            1.0
+                        };
+                        if accept > 0.0 {
+                            acc[proposed_pixel.1 * width + proposed_pixel.0] +=
+                                proposed_l * (accept / proposed_y);
+                        }
+                        if current_y > 0.0 {
+                            acc[current_pixel.1 * width + current_pixel.0] +=
+                                current_l * ((1.0 - accept) / current_y);
+                        }
+
+                        if rng.random::<Float>() < accept {
+                            current_pixel = proposed_pixel;
+                            current_l = proposed_l;
+                            sampler.state.borrow_mut().accept();
+                        } else {
+                            sampler.state.borrow_mut().reject();
+                        }
+                    }
+                    acc
+                },
+            )
+            .reduce(
+                || vec![Color::zero(); width * height],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+
+        let scale = b / self.mutations_per_pixel.max(1) as Float;
+        for y in 0..height {
+            for x in 0..width {
+                film.set_pixel(x, y, accumulation[y * width + x] * scale);
+            }
+        }
+        film
+    }
+
+    /// The sampler of a chain, from the primary samples of a bootstrap path.
+    fn sampler(&self, path: u32) -> MltSampler {
+        let seed = stream_seed(self.seed, path);
+        MltSampler {
+            state: Rc::new(RefCell::new(PrimarySamples::new(seed, self.sigma))),
+        }
+    }
+}
+
+/// Sampler view over a chain's primary sample vector.
+struct MltSampler {
+    state: Rc<RefCell<PrimarySamples>>,
+}
+
+impl Sampler for MltSampler {
+    fn start_pixel(&mut self, _pixel: (usize, usize)) {}
+
+    fn start_sample(&mut self, _index: u32) {}
+
+    fn get_1d(&mut self) -> Float {
+        self.state.borrow_mut().next()
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let mut state = self.state.borrow_mut();
+        (state.next(), state.next())
+    }
+}
+
+/// One coordinate of the primary sample vector, mutated lazily.
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: Float,
+    last_modified: u64,
+    value_backup: Float,
+    modify_backup: u64,
+}
+
+/// The primary sample vector of a chain (PBRT's `MLTSampler`).
+///
+/// Coordinates are only mutated when the path actually consumes them, replaying the
+/// small steps they missed since their last use.
+struct PrimarySamples {
+    rng: StdRng,
+    sigma: Float,
+    samples: Vec<PrimarySample>,
+    iteration: u64,
+    large_step: bool,
+    last_large_step_iteration: u64,
+    index: usize,
+}
+
+impl PrimarySamples {
+    fn new(seed: u64, sigma: Float) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            samples: Vec::new(),
+            iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            index: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modified == self.iteration {
+                sample.value = sample.value_backup;
+                sample.last_modified = sample.modify_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn next(&mut self) -> Float {
+        let index = self.index;
+        self.index += 1;
+        if index >= self.samples.len() {
+            self.samples.resize(index + 1, PrimarySample::default());
+        }
+        let iteration = self.iteration;
+        let last_large_step = self.last_large_step_iteration;
+        let mut sample = self.samples[index];
+
+        // Bring coordinates untouched since the last accepted large step up to date
+        if sample.last_modified < last_large_step {
+            sample.value = self.rng.random();
+            sample.last_modified = last_large_step;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modify_backup = sample.last_modified;
+        if self.large_step {
+            sample.value = self.rng.random();
+        } else {
+            let n_small = (iteration - sample.last_modified) as Float;
+            let normal = standard_normal(&mut self.rng);
+            let effective_sigma = self.sigma * n_small.sqrt();
+            sample.value = (sample.value + normal * effective_sigma).rem_euclid(1.0);
+            if sample.value >= 1.0 {
+                sample.value = 0.0;
+            }
+        }
+        sample.last_modified = iteration;
+        self.samples[index] = sample;
+        sample.value
+    }
+}
+
+fn standard_normal(rng: &mut StdRng) -> Float {
+    // Box-Muller transform
+    let u1: Float = rng.random::<Float>().max(Float::MIN_POSITIVE);
+    let u2: Float = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * utils::consts::PI * u2).cos()
+}
+
+fn sample_discrete(weights: &[Float], total: Float, u: Float) -> usize {
+    let mut target = u * total;
+    for (i, w) in weights.iter().enumerate() {
+        if target < *w {
+            return i;
+        }
+        target -= w;
+    }
+    weights.len() - 1
+}
+
+fn sanitize(l: Color) -> Color {
+    if l.is_finite() { l } else { Color::zero() }
+}
@@ -0,0 +1,524 @@
+mod ambient_occlusion;
+pub use ambient_occlusion::AmbientOcclusionIntegrator;
+mod caustics;
+pub use caustics::CausticMap;
+mod direct;
+pub use direct::DirectLightingIntegrator;
+mod guided;
+pub use guided::GuidedPathIntegrator;
+mod mlt;
+pub use mlt::MltIntegrator;
+mod normal;
+pub use normal::NormalIntegrator;
+mod path;
+mod restir;
+mod sppm;
+mod traversal;
+use crate::buffer::Aovs;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::LightList;
+use crate::material::SceneMaterial;
+use crate::medium::{MediumList, phase_hg};
+use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::Scene;
+pub use path::PathIntegrator;
+pub use restir::RestirIntegrator;
+use serde::{Deserialize, Serialize};
+pub use sppm::SppmIntegrator;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+pub use traversal::TraversalIntegrator;
+use utils::{Color, Float, Point3};
+
+/// The `Integrator` trait defines how the radiance carried by a camera ray is estimated.
+///
+/// The renderer only deals with pixels and samples; everything that happens once a
+/// camera ray has been generated is the integrator's business.
+pub trait Integrator: Send + Sync {
+    /// Estimates the incoming radiance along a ray.
+    ///
+    /// # Parameters
+    /// - `ray`: The camera ray.
+    /// - `scene`: The scene, its geometry, its lights used for next-event estimation
+    ///   and its background.
+    /// - `sampler`: The sampler providing the sample values of the current pixel sample.
+    ///
+    /// # Returns
+    /// - A `Color` representing the estimated radiance.
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+
+    /// Estimates the incoming radiance along a ray as `li`, recording the part of it
+    /// each light group of the scene contributes.
+    ///
+    /// The default implementation records nothing, for the integrators that do not
+    /// follow the light to its sources.
+    ///
+    /// # Parameters
+    /// - `groups`: The radiance of each light group, to add the contributions to.
+    fn li_groups(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        groups: &mut [Color],
+    ) -> Color {
+        let _ = groups;
+        self.li(ray, scene, sampler)
+    }
+
+    /// Records the AOVs seen by a camera ray.
+    ///
+    /// The default implementation records the `albedo`, normal `N` and depth `Z` of the
+    /// first hit, see `first_hit_features`, and the `object_id` and `material_id` of
+    /// the first surface along with their color-hashed previews.
+    ///
+    /// # Parameters
+    /// - `ray`: The camera ray.
+    /// - `scene`: The scene.
+    /// - `aovs`: The AOVs of the pixel, to add the values to.
+    fn aovs(&self, ray: &Ray, scene: &Scene, aovs: &mut Aovs) {
+        let (albedo, normal, depth) = first_hit_features(ray, scene);
+        aovs.add("albedo", albedo);
+        aovs.add("N", normal);
+        aovs.add("Z", Color::new(depth, depth, depth));
+
+        // The IDs are 0 where no object is hit
+        let (object_id, material_id) = scene
+            .world
+            .hit(ray, 0.001, Float::INFINITY)
+            .map_or((0, 0), |rec| (rec.object_id, rec.material_id));
+        let (id, material) = (object_id as Float, material_id as Float);
+        aovs.add_unfiltered("object_id", Color::new(id, id, id));
+        aovs.add_unfiltered("material_id", Color::new(material, material, material));
+        aovs.add("object_id_preview", id_color(object_id));
+        aovs.add("material_id_preview", id_color(material_id));
+    }
+}
+
+/// A color hashed from an ID, to tell the IDs apart at a glance; black for 0.
+pub(crate) fn id_color(id: u32) -> Color {
+    if id == 0 {
+        return Color::zero();
+    }
+    // Finalizer of MurmurHash3, so that consecutive IDs get unrelated colors
+    let mut h = id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as Float / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+/// Specular bounces followed to find the albedo seen through mirrors and glass.
+const FEATURE_DEPTH: u32 = 8;
+/// Depth given to the sky in the feature buffers.
+const SKY_DEPTH: Float = 1.0e4;
+
+/// Albedo, shading normal and depth seen by a camera ray.
+///
+/// The normal and depth are the ones of the first hit. The albedo is the reflectance
+/// of the first non-specular surface, its specular lobes included as OIDN expects, see
+/// `Material::feature_albedo`, tinted by the mirrors and glass in front of it, and the
+/// hue of the background for the rays escaping the scene.
+pub(crate) fn first_hit_features(ray: &Ray, scene: &Scene) -> (Color, Color, Float) {
+    let mut ray = ray.spawn(ray.origin(), ray.direction());
+    let mut tint = Color::new(1.0, 1.0, 1.0);
+    let mut first_hit = None;
+    for _ in 0..FEATURE_DEPTH {
+        let Some(rec) = scene.world.hit(&ray, 0.001, Float::INFINITY) else {
+            let sky = scene.background.radiance(&ray);
+            let (normal, depth) = first_hit.unwrap_or((Color::zero(), SKY_DEPTH));
+            let hue = if sky.max_component() > 0.0 {
+                sky / sky.max_component()
+            } else {
+                Color::zero()
+            };
+            return (tint * hue, normal, depth);
+        };
+        let (normal, depth) =
+            *first_hit.get_or_insert((Color::from(rec.normal), rec.t * ray.direction().length()));
+        let mat = rec.mat;
+        let cosine = utils::dot(rec.normal, utils::unit_vector(ray.direction())).abs();
+        if !mat.is_specular() {
+            return (tint * mat.feature_albedo(cosine), normal, depth);
+        }
+        let Some((scattered, _)) = scatter(mat, &ray, &rec, &mut IndependentSampler) else {
+            return (Color::zero(), normal, depth);
+        };
+        // The reflectance rather than the weight of the scattering, which dispersion
+        // gathers in a single channel
+        tint *= mat.feature_albedo(cosine);
+        ray = scattered;
+    }
+    let (normal, depth) = first_hit.unwrap_or((Color::zero(), SKY_DEPTH));
+    (Color::zero(), normal, depth)
+}
+
+/// The integrators selectable in the render settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IntegratorType {
+    /// Unidirectional path tracing with light sampling and MIS.
+    #[default]
+    Path,
+    /// Direct lighting only: light sampling plus a single BRDF-sampled bounce.
+    DirectLighting,
+    /// Ambient occlusion within a maximum distance.
+    AmbientOcclusion { max_distance: Float },
+    /// Debug view of the first-hit shading normals.
+    Normal,
+    /// Debug heatmap of the BVH nodes visited and primitives tested to find the first
+    /// hit, red at `max_tests`.
+    Traversal {
+        #[serde(default = "default_max_tests")]
+        max_tests: u32,
+    },
+    /// Primary-sample-space Metropolis light transport over the path tracer.
+    Mlt {
+        mutations_per_pixel: u32,
+        #[serde(default = "default_large_step_probability")]
+        large_step_probability: Float,
+        #[serde(default = "default_mutation_sigma")]
+        sigma: Float,
+        #[serde(default = "default_chains")]
+        chains: u32,
+        #[serde(default = "default_bootstrap_samples")]
+        bootstrap_samples: u32,
+    },
+    /// Stochastic progressive photon mapping, for caustics.
+    Sppm {
+        iterations: u32,
+        photons_per_iteration: u32,
+        initial_radius: Float,
+    },
+    /// Direct lighting with spatiotemporal reservoir resampling (ReSTIR DI), for
+    /// scenes with many lights. Each sample per pixel is one frame.
+    Restir {
+        #[serde(default = "default_initial_candidates")]
+        initial_candidates: u32,
+        #[serde(default = "default_spatial_neighbors")]
+        spatial_neighbors: u32,
+        #[serde(default = "default_spatial_radius")]
+        spatial_radius: u32,
+        #[serde(default = "default_tile_size")]
+        tile_size: usize,
+    },
+    /// Path tracing guided by the incident radiance learned in an SD-tree during a few
+    /// training passes, for scenes lit mostly indirectly.
+    Guided {
+        #[serde(default = "default_training_iterations")]
+        training_iterations: u32,
+        #[serde(default = "default_guiding_fraction")]
+        guiding_fraction: Float,
+    },
+    /// Path tracing with the caustics, the light reaching the non-specular surfaces
+    /// through mirrors and glass, gathered from a photon map of `photons` shot from the
+    /// lights before the render, within `radius`.
+    Caustics {
+        #[serde(default = "default_caustic_photons")]
+        photons: u32,
+        radius: Float,
+    },
+}
+
+fn default_max_tests() -> u32 {
+    100
+}
+fn default_large_step_probability() -> Float {
+    0.3
+}
+fn default_mutation_sigma() -> Float {
+    0.01
+}
+fn default_chains() -> u32 {
+    1000
+}
+fn default_bootstrap_samples() -> u32 {
+    100_000
+}
+fn default_initial_candidates() -> u32 {
+    32
+}
+fn default_spatial_neighbors() -> u32 {
+    5
+}
+fn default_spatial_radius() -> u32 {
+    16
+}
+fn default_tile_size() -> usize {
+    64
+}
+fn default_training_iterations() -> u32 {
+    5
+}
+fn default_guiding_fraction() -> Float {
+    0.5
+}
+fn default_caustic_photons() -> u32 {
+    1_000_000
+}
+
+impl IntegratorType {
+    /// Scales the distances of the integrator, the occlusion distance and the photon
+    /// radii, by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        match self {
+            IntegratorType::AmbientOcclusion { max_distance } => *max_distance *= factor,
+            IntegratorType::Sppm { initial_radius, .. } => *initial_radius *= factor,
+            IntegratorType::Caustics { radius, .. } => *radius *= factor,
+            _ => {}
+        }
+    }
+
+    /// Creates the integrator described by this value.
+    ///
+    /// # Parameters
+    /// - `max_depth`: The maximum path depth, for integrators that follow paths.
+    /// - `scene`: The scene, its participating media for integrators that render
+    ///   volumes and its lights for the photons of the caustics.
+    /// - `regularization`: The minimum roughness after a non-specular bounce, for the
+    ///   path tracer.
+    /// - `seed`: The seed of the render, for the photons of the caustics.
+    pub fn create(
+        &self,
+        max_depth: u32,
+        scene: &Scene,
+        regularization: Float,
+        seed: u32,
+    ) -> Box<dyn Integrator> {
+        match *self {
+            IntegratorType::Path => Box::new(
+                PathIntegrator::new(max_depth)
+                    .with_media(scene.media.clone())
+                    .with_regularization(regularization),
+            ),
+            IntegratorType::Caustics { photons, radius } => Box::new(
+                PathIntegrator::new(max_depth)
+                    .with_media(scene.media.clone())
+                    .with_regularization(regularization)
+                    .with_caustics(Arc::new(CausticMap::build(
+                        scene, photons, radius, max_depth, seed,
+                    ))),
+            ),
+            IntegratorType::DirectLighting => Box::new(DirectLightingIntegrator),
+            IntegratorType::AmbientOcclusion { max_distance } => {
+                Box::new(AmbientOcclusionIntegrator::new(max_distance))
+            }
+            IntegratorType::Normal => Box::new(NormalIntegrator),
+            IntegratorType::Traversal { max_tests } => {
+                Box::new(TraversalIntegrator::new(max_tests))
+            }
+            // Metropolis drives the path tracer, SPPM, ReSTIR and guiding have their
+            // own passes, see `Renderer::render`
+            IntegratorType::Mlt { .. }
+            | IntegratorType::Sppm { .. }
+            | IntegratorType::Restir { .. }
+            | IntegratorType::Guided { .. } => {
+                Box::new(PathIntegrator::new(max_depth).with_regularization(regularization))
+            }
+        }
+    }
+}
+
+/// Estimates the direct lighting at a hit point by sampling a point on every light.
+///
+/// With `mis` set, each light contribution is weighted by the balance heuristic against
+/// BRDF sampling, so it must be combined with the MIS-weighted emission found by
+/// BRDF-sampled rays (see `bsdf_mis_weight`). Without it, the estimate stands alone.
+///
+/// # Parameters
+/// - `ray`: The ray that produced the hit.
+/// - `rec`: The hit record.
+/// - `mis`: Whether to apply the light-sampling MIS weight.
+/// - `groups`: The radiance of each light group, to record the contributions to.
+pub(crate) fn sample_lights(
+    ray: &Ray,
+    rec: &HitRecord,
+    world: &dyn Hittable,
+    lights: &LightList,
+    sampler: &mut dyn Sampler,
+    mis: bool,
+    groups: &mut [Color],
+) -> Color {
+    let no_media = MediumList::new();
+    sample_lights_through(
+        ray,
+        rec,
+        world,
+        lights,
+        &no_media,
+        sampler,
+        mis,
+        Color::new(1.0, 1.0, 1.0),
+        groups,
+    )
+}
+
+/// Same as `sample_lights`, with the shadow rays attenuated by participating media.
+///
+/// The estimate is scaled by the path `throughput`, so that each light contribution can
+/// be recorded to its light group as it reaches the camera.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_lights_through(
+    ray: &Ray,
+    rec: &HitRecord,
+    world: &dyn Hittable,
+    lights: &LightList,
+    media: &MediumList,
+    sampler: &mut dyn Sampler,
+    mis: bool,
+    throughput: Color,
+    groups: &mut [Color],
+) -> Color {
+    let mat = rec.mat;
+    let mut radiance = Color::zero();
+    for (index, light) in lights.lights.iter().enumerate() {
+        let (u, v) = sampler.get_2d();
+        let light_point = light.sample_cmj(u, v);
+        let light_dir_unit = utils::unit_vector(light_point - rec.p);
+
+        let transmittance = shadow_transmittance(world, media, ray, rec.p, light_point);
+        if transmittance > 0.0 {
+            let cosine = Float::max(utils::dot(rec.normal, light_dir_unit), 0.0);
+            let light_pdf = light.pdf(rec.p, light_point);
+
+            if let Some((_, brdf_value, brdf_pdf)) = mat.scatter_importance(ray, rec, sampler) {
+                let weight = if mis {
+                    utils::balance_heuristic(light_pdf, brdf_pdf)
+                } else {
+                    1.0
+                };
+                let contribution =
+                    throughput * light.color() * brdf_value * cosine * weight * transmittance
+                        / light_pdf;
+                lights.record(groups, index, contribution);
+                radiance += contribution;
+            }
+        }
+    }
+    radiance
+}
+
+/// Estimates the direct lighting at a scattering point inside a medium.
+///
+/// Each light contribution is weighted by the balance heuristic against phase function
+/// sampling, whose density is the phase function itself.
+///
+/// # Parameters
+/// - `ray`: The ray reaching the scattering point, whose time the shadow rays keep.
+/// - `p`: The scattering point.
+/// - `g`: The Henyey-Greenstein asymmetry of the medium.
+/// - `throughput`: The path throughput scaling the estimate, as in `sample_lights_through`.
+/// - `groups`: The radiance of each light group, to record the contributions to.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_lights_in_medium(
+    ray: &Ray,
+    p: Point3,
+    g: Float,
+    world: &dyn Hittable,
+    lights: &LightList,
+    media: &MediumList,
+    sampler: &mut dyn Sampler,
+    throughput: Color,
+    groups: &mut [Color],
+) -> Color {
+    let direction = utils::unit_vector(ray.direction());
+    let mut radiance = Color::zero();
+    for (index, light) in lights.lights.iter().enumerate() {
+        let (u, v) = sampler.get_2d();
+        let light_point = light.sample_cmj(u, v);
+        let light_dir_unit = utils::unit_vector(light_point - p);
+
+        let transmittance = shadow_transmittance(world, media, ray, p, light_point);
+        if transmittance > 0.0 {
+            let phase = phase_hg(utils::dot(direction, light_dir_unit), g);
+            let light_pdf = light.pdf(p, light_point);
+            let weight = utils::balance_heuristic(light_pdf, phase);
+            let contribution =
+                throughput * light.color() * phase * weight * transmittance / light_pdf;
+            lights.record(groups, index, contribution);
+            radiance += contribution;
+        }
+    }
+    radiance
+}
+
+/// Fraction of light travelling from `from` to `to`: zero when a surface blocks the
+/// segment, the transmittance of the media along it otherwise.
+///
+/// The shadow ray is traced at the time of `ray`, the ray of the path reaching `from`.
+fn shadow_transmittance(
+    world: &dyn Hittable,
+    media: &MediumList,
+    ray: &Ray,
+    from: Point3,
+    to: Point3,
+) -> Float {
+    let offset = to - from;
+    let distance = offset.length();
+    let shadow_ray = ray.spawn(from, offset / distance);
+    if world.hit(&shadow_ray, 0.001, distance - 0.001).is_some() {
+        return 0.0;
+    }
+    if media.is_empty() {
+        1.0
+    } else {
+        media.transmittance(&shadow_ray, distance)
+    }
+}
+
+/// MIS weight of an emitter reached from `origin` by BRDF sampling with density `brdf_pdf`.
+pub(crate) fn bsdf_mis_weight(
+    lights: &LightList,
+    origin: Point3,
+    light_point: Point3,
+    brdf_pdf: Float,
+) -> Float {
+    let light_pdf_sum: Float = lights
+        .lights
+        .iter()
+        .map(|light| light.pdf(origin, light_point))
+        .sum();
+    let light_pdf = (light_pdf_sum / lights.lights.len() as Float).max(1e-4);
+    utils::balance_heuristic(brdf_pdf, light_pdf)
+}
+
+/// Samples the next direction and its throughput weight.
+///
+/// Specular materials are followed through their delta lobe with `scatter`, whose
+/// attenuation already is the weight; the cosine-weighted estimate of
+/// `scatter_importance` would cancel the refracted directions.
+pub(crate) fn scatter(
+    mat: &SceneMaterial,
+    ray: &Ray,
+    rec: &HitRecord,
+    sampler: &mut dyn Sampler,
+) -> Option<(Ray, Color)> {
+    if mat.is_specular() {
+        let mut attenuation = Color::zero();
+        let mut scattered = Ray::default();
+        return mat
+            .scatter(ray, rec, sampler, &mut attenuation, &mut scattered)
+            .then_some((scattered, attenuation));
+    }
+    let (scattered, brdf_value, brdf_pdf) = mat.scatter_importance(ray, rec, sampler)?;
+    let cosine = Float::max(
+        utils::dot(rec.normal, utils::unit_vector(scattered.direction())),
+        0.0,
+    );
+    Some((scattered, brdf_value * cosine / brdf_pdf))
+}
+
+/// Adds `value` to an `f32` stored as bits in an atomic, for lock-free accumulation.
+pub(crate) fn atomic_add(target: &AtomicU32, value: Float) {
+    let mut current = target.load(Ordering::Relaxed);
+    loop {
+        let new = (f32::from_bits(current) + value as f32).to_bits();
+        match target.compare_exchange_weak(current, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(actual) => current = actual,
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use crate::hittable::Hittable;
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use utils::{Color, Float, Vec3};
+
+/// A debug integrator mapping the first-hit shading normal from [-1, 1] to [0, 1].
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Color {
+        let Some(rec) = scene.world.hit(ray, 0.001, Float::INFINITY) else {
+            return Color::zero();
+        };
+        Color::from(0.5 * (rec.normal + Vec3::new(1.0, 1.0, 1.0)))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::hittable::Hittable;
+use crate::integrator::{
+    CausticMap, Integrator, bsdf_mis_weight, sample_lights_in_medium, sample_lights_through,
+    scatter,
+};
+use crate::medium::{MediumList, sample_hg};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::sync::Arc;
+use utils::{Color, Float, Point3};
+
+/// Unidirectional path tracer combining light sampling and BRDF sampling with MIS.
+///
+/// The path is traced iteratively: `throughput` carries the product of the BRDF
+/// weights along the path and `radiance` accumulates the weighted contributions,
+/// so the stack stays flat whatever the depth and the hit records are reused.
+///
+/// Participating media are handled with delta tracking: each segment either reaches
+/// the next surface or stops at a scattering event inside a medium, where the path
+/// continues by sampling the phase function. Shadow rays are attenuated by the
+/// transmittance of the media, estimated with ratio tracking. The fog of the scene
+/// attenuates every segment and shadow ray in closed form.
+///
+/// With regularization, the glossy lobes met after the first non-specular bounce are
+/// widened to a minimum roughness, trading hard-to-sample caustics for a slight blur.
+///
+/// With a caustic map, the caustics are gathered from its photons at each non-specular
+/// hit, and the emission the path then reaches through specular surfaces only is not
+/// counted again, so that the caustics of glass and mirrors are not pure noise.
+pub struct PathIntegrator {
+    max_depth: u32,
+    media: Arc<MediumList>,
+    regularization: Float,
+    caustics: Option<Arc<CausticMap>>,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            media: Arc::new(MediumList::new()),
+            regularization: 0.0,
+            caustics: None,
+        }
+    }
+
+    pub fn with_media(mut self, media: Arc<MediumList>) -> Self {
+        self.media = media;
+        self
+    }
+
+    /// Sets the minimum roughness of the glossy lobes after a non-specular bounce,
+    /// 0 to disable the regularization.
+    pub fn with_regularization(mut self, regularization: Float) -> Self {
+        self.regularization = regularization;
+        self
+    }
+
+    /// Sets the photon map the caustics are gathered from.
+    pub fn with_caustics(mut self, caustics: Arc<CausticMap>) -> Self {
+        self.caustics = Some(caustics);
+        self
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, r: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        self.li_groups(r, scene, sampler, &mut [])
+    }
+
+    fn li_groups(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        groups: &mut [Color],
+    ) -> Color {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let depth = self.max_depth as i32;
+        let mut radiance = Color::zero();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = r.spawn(r.origin(), r.direction());
+        // BRDF sample that produced the current ray: (origin, throughput before it, weight, pdf)
+        let mut bsdf_sample: Option<(Point3, Color, Color, Float)> = None;
+        // Whether the caustic map was gathered at the last non-specular surface, and
+        // whether the path went through specular surfaces only since then
+        let (mut gathered, mut caustic) = (false, false);
+        // The minimum roughness of the glossy lobes, raised after a non-specular bounce
+        let mut roughness_floor = 0.0;
+
+        for bounce in 0..=depth {
+            let hit = world.hit(&ray, 0.001, Float::INFINITY);
+
+            // === Scattering inside a medium, before the surface is reached ===
+            let t_max = hit.as_ref().map_or(Float::INFINITY, |rec| rec.t);
+            let collision = self.media.sample_distance(&ray, t_max);
+
+            // === The fog over the segment, integrated in closed form ===
+            if let Some(fog) = &self.media.fog {
+                let (transmittance, fog_radiance) =
+                    fog.attenuation(&ray, collision.map_or(t_max, |(t, _)| t));
+                if bounce < depth {
+                    radiance += throughput * fog_radiance;
+                }
+                throughput *= transmittance;
+                if let Some((_, prev_throughput, ..)) = bsdf_sample.as_mut() {
+                    *prev_throughput *= transmittance;
+                }
+            }
+
+            if let Some((t, medium)) = collision {
+                if bounce == depth {
+                    break;
+                }
+                let p = ray.at(t);
+                let direction = utils::unit_vector(ray.direction());
+                throughput *= medium.albedo();
+
+                radiance += sample_lights_in_medium(
+                    &ray,
+                    p,
+                    medium.g(),
+                    world,
+                    lights,
+                    &self.media,
+                    sampler,
+                    throughput,
+                    groups,
+                );
+
+                // The phase function is sampled exactly, so the weight is one
+                let (scattered, phase_pdf) = sample_hg(direction, medium.g(), sampler.get_2d());
+                bsdf_sample = Some((p, throughput, Color::new(1.0, 1.0, 1.0), phase_pdf));
+                (gathered, caustic) = (false, false);
+                ray = ray.spawn(p, scattered);
+                continue;
+            }
+
+            let Some(mut rec) = hit else {
+                if bounce < depth {
+                    radiance += throughput * scene.background.radiance(&ray);
+                }
+                break;
+            };
+            rec.roughness_floor = roughness_floor;
+            let mat = rec.mat;
+            // The photons carried the light of the caustic paths
+            let emitted = if caustic {
+                Color::zero()
+            } else {
+                mat.emitted()
+            };
+
+            // === Light reached via BRDF sampling, weighted against light sampling ===
+            if let Some((origin, prev_throughput, weight, brdf_pdf)) =
+                bsdf_sample.filter(|_| !emitted.is_black())
+            {
+                let mis_weight = bsdf_mis_weight(lights, origin, rec.p, brdf_pdf);
+                let contribution = prev_throughput * emitted * weight * mis_weight;
+                lights.record_emission(groups, rec.object_id, contribution);
+                radiance += contribution;
+            }
+            if bounce == depth {
+                break;
+            }
+            lights.record_emission(groups, rec.object_id, throughput * emitted);
+            radiance += throughput * emitted;
+
+            // === Specular surfaces are followed through their delta lobe ===
+            // They cannot be light-sampled, so the emission found next is counted fully
+            if mat.is_specular() {
+                let Some((scattered, weight)) = scatter(mat, &ray, &rec, sampler) else {
+                    break;
+                };
+                bsdf_sample = None;
+                throughput *= weight;
+                ray = scattered;
+                caustic = gathered;
+                continue;
+            }
+
+            if let Some(caustics) = &self.caustics {
+                radiance += throughput * caustics.radiance(rec.p, rec.normal, mat.albedo());
+                (gathered, caustic) = (true, false);
+            }
+
+            // === 1. Direct Lighting via Light Sampling ===
+            radiance += sample_lights_through(
+                &ray,
+                &rec,
+                world,
+                lights,
+                &self.media,
+                sampler,
+                true,
+                throughput,
+                groups,
+            );
+
+            // === 2. Indirect Lighting via BRDF Sampling ===
+            let Some((scattered, brdf_value, brdf_pdf)) =
+                mat.scatter_importance(&ray, &rec, sampler)
+            else {
+                break;
+            };
+            let cosine = Float::max(
+                utils::dot(rec.normal, utils::unit_vector(scattered.direction())),
+                0.0,
+            );
+            let weight = brdf_value * cosine / brdf_pdf;
+            bsdf_sample = Some((rec.p, throughput, weight, brdf_pdf));
+            throughput *= weight;
+            ray = scattered;
+            roughness_floor = self.regularization;
+        }
+
+        radiance
+    }
+}
@@ -0,0 +1,395 @@
+use crate::buffer::Buffer;
+use crate::hittable::Hittable;
+use crate::integrator::scatter;
+use crate::light::LightList;
+use crate::progress;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerType, stream_seed};
+use crate::scene::Scene;
+use rayon::prelude::*;
+use utils::{Color, Float, Point3, Vec3};
+
+/// Cap of the temporal history, in multiples of the initial candidate count.
+const MAX_HISTORY: Float = 20.0;
+/// Minimum cosine between the normals of two pixels sharing their reservoirs.
+const NORMAL_THRESHOLD: Float = 0.9;
+/// Maximum relative difference between the depths of two pixels sharing their reservoirs.
+const DEPTH_THRESHOLD: Float = 0.1;
+
+/// Reservoir-based spatiotemporal importance resampling for direct lighting
+/// (ReSTIR DI, Bitterli et al. 2020).
+///
+/// Every frame, each pixel picks a light sample out of a stream of cheap candidates
+/// by resampled importance sampling, merges it with its reservoir from the previous
+/// frame and then with the reservoirs of a few neighboring pixels. Only the surviving
+/// sample is shadow-tested, so the cost stays flat as the number of lights grows.
+///
+/// The image is processed tile by tile: tiles render in parallel, and spatial reuse
+/// never reaches across a tile border. The frames are the samples of the pixel.
+///
+/// Camera paths go through specular surfaces to their first diffuse hit, where the
+/// surface is approximated as Lambertian with the material's albedo. Indirect
+/// lighting is not computed, except for the sky gathered with one BRDF-sampled ray.
+pub struct RestirIntegrator {
+    initial_candidates: u32,
+    spatial_neighbors: u32,
+    spatial_radius: u32,
+    tile_size: usize,
+    max_depth: u32,
+    seed: u32,
+}
+
+/// A weighted reservoir holding one light sample.
+#[derive(Clone, Copy)]
+struct Reservoir {
+    light: usize,
+    point: Point3,
+    /// Sum of the resampling weights seen so far.
+    w_sum: Float,
+    /// Number of candidates seen so far.
+    m: Float,
+    /// Unbiased contribution weight of the selected sample.
+    weight: Float,
+}
+
+/// First diffuse hit of a camera path.
+#[derive(Clone, Copy)]
+struct Surface {
+    p: Point3,
+    normal: Vec3,
+    beta: Color,
+    brdf: Color,
+    depth: Float,
+}
+
+impl Reservoir {
+    fn new() -> Self {
+        Self {
+            light: 0,
+            point: Point3::default(),
+            w_sum: 0.0,
+            m: 0.0,
+            weight: 0.0,
+        }
+    }
+
+    /// Streams a candidate into the reservoir, keeping it with probability `w / w_sum`.
+    fn update(&mut self, light: usize, point: Point3, w: Float, m: Float) {
+        self.w_sum += w;
+        self.m += m;
+        if w > 0.0 && utils::random() * self.w_sum < w {
+            self.light = light;
+            self.point = point;
+        }
+    }
+
+    /// Streams another reservoir in, given the target density of its sample here.
+    fn merge(&mut self, other: &Reservoir, p_hat: Float) {
+        self.update(
+            other.light,
+            other.point,
+            p_hat * other.weight * other.m,
+            other.m,
+        );
+    }
+
+    /// Computes the contribution weight once all candidates have been streamed.
+    fn finalize(&mut self, p_hat: Float) {
+        self.weight = if p_hat > 0.0 && self.m > 0.0 {
+            self.w_sum / (self.m * p_hat)
+        } else {
+            0.0
+        };
+    }
+}
+
+impl Surface {
+    /// Whether the reservoir of `other` is a good fit for this surface.
+    fn is_similar(&self, other: &Surface) -> bool {
+        utils::dot(self.normal, other.normal) >= NORMAL_THRESHOLD
+            && (self.depth - other.depth).abs() <= DEPTH_THRESHOLD * self.depth
+    }
+
+    /// The contribution of a light sample, ignoring visibility.
+    ///
+    /// `Light::pdf` is the density of the light's own point sampling, so the
+    /// contribution is expressed against the same measure for every pixel and the
+    /// samples can be exchanged between pixels.
+    fn unshadowed(&self, lights: &LightList, light: usize, point: Point3) -> Color {
+        let light = &lights.lights[light];
+        let cosine = utils::dot(self.normal, utils::unit_vector(point - self.p));
+        if cosine <= 0.0 {
+            return Color::zero();
+        }
+        light.color() * self.brdf * cosine / light.pdf(self.p, point)
+    }
+
+    /// The target density of a light sample: the luminance of its contribution.
+    fn p_hat(&self, lights: &LightList, r: &Reservoir) -> Float {
+        self.unshadowed(lights, r.light, r.point).luminance()
+    }
+
+    fn is_visible(&self, world: &dyn Hittable, point: Point3) -> bool {
+        let to_light = point - self.p;
+        let distance = to_light.length();
+        let shadow_ray = Ray::new(self.p, to_light / distance);
+        world.hit(&shadow_ray, 0.001, distance - 0.001).is_none()
+    }
+}
+
+impl RestirIntegrator {
+    pub fn new(
+        initial_candidates: u32,
+        spatial_neighbors: u32,
+        spatial_radius: u32,
+        tile_size: usize,
+        max_depth: u32,
+    ) -> Self {
+        Self {
+            initial_candidates: initial_candidates.max(1),
+            spatial_neighbors,
+            spatial_radius,
+            tile_size: tile_size.max(1),
+            max_depth,
+            seed: 0,
+        }
+    }
+
+    /// Sets the seed of the render, decorrelating its samples from those of the other
+    /// seeds.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Renders the image tile by tile with spatiotemporal reservoir resampling.
+    ///
+    /// # Parameters
+    /// - `scene`: The scene, seen through its camera.
+    /// - `sampler_type`: The sampler used for the camera rays.
+    /// - `frames`: The number of frames, i.e. samples, per pixel.
+    /// - `width`, `height`: The film resolution.
+    ///
+    /// # Returns
+    /// - A `Buffer` holding the final image.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        sampler_type: SamplerType,
+        frames: u32,
+        width: usize,
+        height: usize,
+    ) -> Buffer {
+        let frames = frames.max(1);
+        let tiles: Vec<(usize, usize)> = (0..height)
+            .step_by(self.tile_size)
+            .flat_map(|y| (0..width).step_by(self.tile_size).map(move |x| (x, y)))
+            .collect();
+        let bar = progress::progress_bar(tiles.len() as u64, "ReSTIR tiles");
+
+        let rendered: Vec<_> = tiles
+            .par_iter()
+            .map(|&(x0, y0)| {
+                let tile_width = self.tile_size.min(width - x0);
+                let tile_height = self.tile_size.min(height - y0);
+                // The stream of the tile, its pixels being rendered together
+                let stream = (y0 * width + x0) as u64;
+                let colors = utils::with_random_stream(stream_seed(self.seed, 0), stream, || {
+                    self.render_tile(
+                        scene,
+                        sampler_type,
+                        frames,
+                        (x0, y0, tile_width, tile_height),
+                        (width, height),
+                    )
+                });
+                bar.inc(1);
+                (x0, y0, tile_width, colors)
+            })
+            .collect();
+        bar.finish();
+
+        let mut film = Buffer::new(width, height);
+        for (x0, y0, tile_width, colors) in rendered {
+            for (index, color) in colors.into_iter().enumerate() {
+                film.set_pixel(x0 + index % tile_width, y0 + index / tile_width, color);
+            }
+        }
+        film
+    }
+
+    /// Renders every frame of a tile and returns the averaged pixels, row by row.
+    fn render_tile(
+        &self,
+        scene: &Scene,
+        sampler_type: SamplerType,
+        frames: u32,
+        (x0, y0, tile_width, tile_height): (usize, usize, usize, usize),
+        (width, height): (usize, usize),
+    ) -> Vec<Color> {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let count = tile_width * tile_height;
+        let mut samplers: Vec<_> = (0..count)
+            .map(|k| {
+                let pixel = (x0 + k % tile_width, y0 + k / tile_width);
+                sampler_type.create_seeded(pixel, frames, self.seed)
+            })
+            .collect();
+        let mut sum = vec![Color::zero(); count];
+        let mut surfaces: Vec<Option<Surface>> = vec![None; count];
+        let mut reservoirs = vec![Reservoir::new(); count];
+        let mut history: Vec<Option<(Surface, Reservoir)>> = vec![None; count];
+
+        for frame in 0..frames {
+            // === 1. Camera rays and initial candidates, merged with the history ===
+            for k in 0..count {
+                let (i, j) = (x0 + k % tile_width, y0 + k / tile_width);
+                let sampler = samplers[k].as_mut();
+                sampler.start_sample(frame);
+                let (u_offset, v_offset) = sampler.get_2d();
+                let (lens_u, lens_v) = sampler.get_2d();
+                let u = (i as Float + u_offset) / (width - 1) as Float;
+                let v = (j as Float + v_offset) / (height - 1) as Float;
+                let ray = scene.camera.get_ray_lens(u, v, lens_u, lens_v, 0.0);
+
+                let (radiance, surface) = self.trace_camera_ray(ray, scene, sampler);
+                sum[k] += radiance;
+                surfaces[k] = surface;
+                let Some(surface) = surface.filter(|_| !lights.lights.is_empty()) else {
+                    history[k] = None;
+                    continue;
+                };
+
+                let mut reservoir = self.initial_reservoir(&surface, lights);
+                if !surface.is_visible(world, reservoir.point) {
+                    reservoir.weight = 0.0;
+                }
+
+                if let Some((previous_surface, mut previous)) = history[k]
+                    && surface.is_similar(&previous_surface)
+                {
+                    previous.m = previous.m.min(MAX_HISTORY * reservoir.m);
+                    let mut temporal = Reservoir::new();
+                    temporal.merge(&reservoir, surface.p_hat(lights, &reservoir));
+                    temporal.merge(&previous, surface.p_hat(lights, &previous));
+                    temporal.finalize(surface.p_hat(lights, &temporal));
+                    reservoir = temporal;
+                }
+                // The history skips the spatial pass, so the samples of a pixel do not
+                // bleed over the whole tile across frames
+                history[k] = Some((surface, reservoir));
+                reservoirs[k] = reservoir;
+            }
+
+            // === 2. Spatial reuse within the tile, then shading ===
+            for k in 0..count {
+                let Some(surface) = surfaces[k].filter(|_| !lights.lights.is_empty()) else {
+                    continue;
+                };
+                let (x, y) = (k % tile_width, k / tile_width);
+                let mut spatial = Reservoir::new();
+                spatial.merge(&reservoirs[k], surface.p_hat(lights, &reservoirs[k]));
+                for _ in 0..self.spatial_neighbors {
+                    let n = neighbor(x, y, self.spatial_radius, tile_width, tile_height);
+                    if n == k {
+                        continue;
+                    }
+                    let Some(neighbor_surface) = surfaces[n] else {
+                        continue;
+                    };
+                    if surface.is_similar(&neighbor_surface) {
+                        spatial.merge(&reservoirs[n], surface.p_hat(lights, &reservoirs[n]));
+                    }
+                }
+                spatial.finalize(surface.p_hat(lights, &spatial));
+
+                if spatial.weight > 0.0 && surface.is_visible(world, spatial.point) {
+                    sum[k] += surface.beta
+                        * surface.unshadowed(lights, spatial.light, spatial.point)
+                        * spatial.weight;
+                }
+            }
+        }
+
+        sum.into_iter()
+            .map(|color| color / frames as Float)
+            .collect()
+    }
+
+    /// Follows a camera ray through specular surfaces to its first diffuse hit.
+    ///
+    /// # Returns
+    /// - The emission and sky radiance found along the way.
+    /// - The diffuse surface to light with reservoirs, if one was reached.
+    fn trace_camera_ray(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Surface>) {
+        let world = &scene.world;
+        let mut radiance = Color::zero();
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        let mut depth = 0.0;
+        for _ in 0..self.max_depth {
+            let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+                radiance += beta * scene.background.radiance(&ray);
+                break;
+            };
+            depth += rec.t * ray.direction().length();
+            let mat = rec.mat;
+            radiance += beta * mat.emitted();
+            let scattered = scatter(mat, &ray, &rec, sampler);
+            if !mat.is_specular() {
+                // Emitters are covered by the reservoirs, the bounce only gathers the sky
+                if let Some((bounce, weight)) = scattered
+                    && world.hit(&bounce, 0.001, Float::INFINITY).is_none()
+                {
+                    radiance += beta * weight * scene.background.radiance(&bounce);
+                }
+                let brdf = mat.albedo() / utils::consts::PI;
+                let surface = (brdf.max_component() > 0.0).then_some(Surface {
+                    p: rec.p,
+                    normal: rec.normal,
+                    beta,
+                    brdf,
+                    depth,
+                });
+                return (radiance, surface);
+            }
+            let Some((next, weight)) = scattered else {
+                break;
+            };
+            beta *= weight;
+            ray = next;
+        }
+        (radiance, None)
+    }
+
+    /// Resamples one light sample out of uniformly chosen candidates.
+    fn initial_reservoir(&self, surface: &Surface, lights: &LightList) -> Reservoir {
+        let light_count = lights.lights.len();
+        let mut reservoir = Reservoir::new();
+        for _ in 0..self.initial_candidates {
+            let light = ((utils::random() * light_count as Float) as usize).min(light_count - 1);
+            let (u, v) = utils::random2();
+            let point = lights.lights[light].sample_cmj(u, v);
+            // The source density is 1 / light_count against the measure of `unshadowed`
+            let p_hat = surface.unshadowed(lights, light, point).luminance();
+            reservoir.update(light, point, p_hat * light_count as Float, 1.0);
+        }
+        let p_hat = surface.p_hat(lights, &reservoir);
+        reservoir.finalize(p_hat);
+        reservoir
+    }
+}
+
+/// Picks a random pixel within `radius` of `(x, y)`, clamped to the tile.
+fn neighbor(x: usize, y: usize, radius: u32, tile_width: usize, tile_height: usize) -> usize {
+    let offset = |c: usize, size: usize| {
+        let r = radius as Float;
+        let c = c as Float + ((2.0 * utils::random() - 1.0) * r).round();
+        (c.max(0.0) as usize).min(size - 1)
+    };
+    offset(y, tile_height) * tile_width + offset(x, tile_width)
+}
@@ -0,0 +1,311 @@
+use crate::buffer::Buffer;
+use crate::hittable::Hittable;
+use crate::integrator::{atomic_add, sample_lights, scatter};
+use crate::light::LightList;
+use crate::progress;
+use crate::sampler::{IndependentSampler, PHOTON_STREAMS, SamplerType, stream_seed};
+use crate::scene::Scene;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use utils::{Color, Float, Point3, Vec3};
+
+/// Fraction of the new photons kept when shrinking the gather radius.
+const ALPHA: Float = 2.0 / 3.0;
+
+/// Stochastic progressive photon mapping (Hachisuka & Jensen 2009).
+///
+/// Each iteration traces one camera path per pixel through specular surfaces to its
+/// first diffuse hit (the visible point), then shoots a batch of photons from the
+/// lights and accumulates those landing within each visible point's radius. The radii
+/// shrink over iterations, so the estimate converges while resolving caustics seen
+/// through `Dielectric` objects, which path tracing can only find by chance.
+///
+/// Diffuse surfaces are approximated as Lambertian with the material's albedo when
+/// photons are gathered.
+pub struct SppmIntegrator {
+    iterations: u32,
+    photons_per_iteration: u32,
+    initial_radius: Float,
+    max_depth: u32,
+    seed: u32,
+}
+
+/// Per-pixel SPPM state.
+struct SppmPixel {
+    radius: Float,
+    /// Direct lighting, emission and sky summed over iterations.
+    ld: Color,
+    /// Flux gathered in previous iterations, scaled to the current radius.
+    tau: Color,
+    /// Accumulated photon count (fractional because of the radius reduction).
+    n: Float,
+    visible_point: Option<VisiblePoint>,
+    phi: [AtomicU32; 3],
+    m: AtomicU32,
+}
+
+/// First diffuse hit of a camera path.
+#[derive(Clone, Copy)]
+struct VisiblePoint {
+    p: Point3,
+    normal: Vec3,
+    beta: Color,
+    albedo: Color,
+}
+
+impl SppmIntegrator {
+    pub fn new(
+        iterations: u32,
+        photons_per_iteration: u32,
+        initial_radius: Float,
+        max_depth: u32,
+    ) -> Self {
+        Self {
+            iterations: iterations.max(1),
+            photons_per_iteration: photons_per_iteration.max(1),
+            initial_radius,
+            max_depth,
+            seed: 0,
+        }
+    }
+
+    /// Sets the seed of the render, decorrelating its samples and photons from those
+    /// of the other seeds.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Renders the image with progressive photon mapping.
+    ///
+    /// # Parameters
+    /// - `scene`: The scene, seen through its camera.
+    /// - `sampler_type`: The sampler used for the camera paths.
+    /// - `width`, `height`: The film resolution.
+    ///
+    /// # Returns
+    /// - A `Buffer` holding the final image.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        sampler_type: SamplerType,
+        width: usize,
+        height: usize,
+    ) -> Buffer {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let mut pixels: Vec<SppmPixel> = (0..width * height)
+            .map(|_| SppmPixel {
+                radius: self.initial_radius,
+                ld: Color::zero(),
+                tau: Color::zero(),
+                n: 0.0,
+                visible_point: None,
+                phi: Default::default(),
+                m: AtomicU32::new(0),
+            })
+            .collect();
+
+        let bar = progress::progress_bar(u64::from(self.iterations), "SPPM iterations");
+        for iteration in 0..self.iterations {
+            // === 1. Camera pass: find the visible points ===
+            pixels
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(index, pixel)| {
+                    let seed = stream_seed(self.seed, iteration);
+                    utils::with_random_stream(seed, index as u64, || {
+                        let (i, j) = (index % width, index / width);
+                        let mut sampler =
+                            sampler_type.create_seeded((i, j), self.iterations, self.seed);
+                        sampler.start_sample(iteration);
+                        let (u_offset, v_offset) = sampler.get_2d();
+                        let (lens_u, lens_v) = sampler.get_2d();
+                        let u = (i as Float + u_offset) / (width - 1) as Float;
+                        let v = (j as Float + v_offset) / (height - 1) as Float;
+                        let mut ray = scene.camera.get_ray_lens(u, v, lens_u, lens_v, 0.0);
+
+                        // Every vertex before the visible point is specular, so emission is
+                        // always counted: there is no light sampling to weigh it against.
+                        let mut beta = Color::new(1.0, 1.0, 1.0);
+                        for _ in 0..self.max_depth {
+                            let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+                                pixel.ld += beta * scene.background.radiance(&ray);
+                                break;
+                            };
+                            let mat = rec.mat;
+                            pixel.ld += beta * mat.emitted();
+                            if !mat.is_specular() {
+                                pixel.ld += beta
+                                    * sample_lights(
+                                        &ray,
+                                        &rec,
+                                        world,
+                                        lights,
+                                        sampler.as_mut(),
+                                        false,
+                                        &mut [],
+                                    );
+                                pixel.visible_point = Some(VisiblePoint {
+                                    p: rec.p,
+                                    normal: rec.normal,
+                                    beta,
+                                    albedo: mat.albedo(),
+                                });
+                                break;
+                            }
+                            let Some((scattered, weight)) =
+                                scatter(mat, &ray, &rec, sampler.as_mut())
+                            else {
+                                break;
+                            };
+                            beta *= weight;
+                            ray = scattered;
+                        }
+                    })
+                });
+
+            // === 2. Hash the visible points into a uniform grid ===
+            let max_radius = pixels
+                .iter()
+                .filter(|p| p.visible_point.is_some())
+                .map(|p| p.radius)
+                .fold(0.0, Float::max);
+            if max_radius <= 0.0 {
+                continue;
+            }
+            let cell_size = 2.0 * max_radius;
+            let mut grid: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+            for (index, pixel) in pixels.iter().enumerate() {
+                if let Some(vp) = pixel.visible_point {
+                    let r = Vec3::new(pixel.radius, pixel.radius, pixel.radius);
+                    let lo = cell_of(vp.p - r, cell_size);
+                    let hi = cell_of(vp.p + r, cell_size);
+                    for x in lo.0..=hi.0 {
+                        for y in lo.1..=hi.1 {
+                            for z in lo.2..=hi.2 {
+                                grid.entry((x, y, z)).or_default().push(index as u32);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // === 3. Photon pass: deposit flux at nearby visible points ===
+            if !lights.lights.is_empty() {
+                (0..self.photons_per_iteration)
+                    .into_par_iter()
+                    .for_each(|photon| {
+                        // Streams of their own, apart from those of the pixels
+                        let (seed, stream) = (
+                            stream_seed(self.seed, iteration),
+                            PHOTON_STREAMS + photon as u64,
+                        );
+                        utils::with_random_stream(seed, stream, || {
+                            self.trace_photon(world, lights, &pixels, &grid, cell_size)
+                        })
+                    });
+            }
+
+            // === 4. Progressive radius and flux update ===
+            pixels.par_iter_mut().for_each(|pixel| {
+                let m = pixel.m.swap(0, Ordering::Relaxed) as Float;
+                let phi = Color::new(
+                    f32::from_bits(pixel.phi[0].swap(0, Ordering::Relaxed)) as Float,
+                    f32::from_bits(pixel.phi[1].swap(0, Ordering::Relaxed)) as Float,
+                    f32::from_bits(pixel.phi[2].swap(0, Ordering::Relaxed)) as Float,
+                );
+                if let Some(vp) = pixel.visible_point.take().filter(|_| m > 0.0) {
+                    let n_new = pixel.n + ALPHA * m;
+                    let r_new = pixel.radius * (n_new / (pixel.n + m)).sqrt();
+                    let shrink = (r_new * r_new) / (pixel.radius * pixel.radius);
+                    pixel.tau = (pixel.tau + vp.beta * phi) * shrink;
+                    pixel.n = n_new;
+                    pixel.radius = r_new;
+                }
+            });
+            bar.inc(1);
+        }
+        bar.finish();
+
+        let mut film = Buffer::new(width, height);
+        let iterations = self.iterations as Float;
+        let photons = iterations * self.photons_per_iteration as Float;
+        for (index, pixel) in pixels.iter().enumerate() {
+            let area = utils::consts::PI * pixel.radius * pixel.radius;
+            let color = pixel.ld / iterations + pixel.tau / (photons * area);
+            film.set_pixel(index % width, index / width, color);
+        }
+        film
+    }
+
+    fn trace_photon(
+        &self,
+        world: &dyn Hittable,
+        lights: &LightList,
+        pixels: &[SppmPixel],
+        grid: &HashMap<(i32, i32, i32), Vec<u32>>,
+        cell_size: Float,
+    ) {
+        let light_count = lights.lights.len();
+        let light_index = ((utils::random() * light_count as Float) as usize).min(light_count - 1);
+        let Some((mut ray, power)) =
+            lights.lights[light_index].sample_emission(utils::random2(), utils::random2())
+        else {
+            return;
+        };
+        let mut beta = power * light_count as Float;
+
+        for depth in 0..self.max_depth {
+            let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+                break;
+            };
+            let mat = rec.mat;
+
+            // Direct lighting is estimated at the visible points, only deposit indirect flux
+            let candidates = if depth > 0 && !mat.is_specular() {
+                grid.get(&cell_of(rec.p, cell_size))
+            } else {
+                None
+            };
+            for &index in candidates.into_iter().flatten() {
+                let pixel = &pixels[index as usize];
+                let Some(vp) = pixel.visible_point else {
+                    continue;
+                };
+                if (vp.p - rec.p).length_squared() > pixel.radius * pixel.radius
+                    || utils::dot(vp.normal, rec.normal) <= 0.0
+                {
+                    continue;
+                }
+                let flux = beta * vp.albedo / utils::consts::PI;
+                for c in 0..3 {
+                    atomic_add(&pixel.phi[c], flux[c]);
+                }
+                pixel.m.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let Some((scattered, weight)) = scatter(mat, &ray, &rec, &mut IndependentSampler)
+            else {
+                break;
+            };
+            let new_beta = beta * weight;
+
+            // Russian roulette on the throughput change
+            let q = (1.0 - new_beta.luminance() / beta.luminance().max(1e-8)).max(0.0);
+            if utils::random() < q {
+                break;
+            }
+            beta = new_beta / (1.0 - q);
+            ray = scattered;
+        }
+    }
+}
+
+pub(crate) fn cell_of(p: Point3, cell_size: Float) -> (i32, i32, i32) {
+    (
+        (p.x() / cell_size).floor() as i32,
+        (p.y() / cell_size).floor() as i32,
+        (p.z() / cell_size).floor() as i32,
+    )
+}
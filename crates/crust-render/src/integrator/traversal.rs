@@ -0,0 +1,54 @@
+use crate::hittable::Hittable;
+use crate::integrator::Integrator;
+use crate::progress;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use utils::{Color, Float};
+
+/// A debug integrator coloring the pixels by the work of finding the first hit: the
+/// BVH nodes visited plus the primitives tested, from blue for none through green and
+/// yellow to red at `max_tests`.
+///
+/// Hot spots show the geometry the acceleration structure handles poorly, such as
+/// long thin triangles or meshes intersected without a BVH.
+pub struct TraversalIntegrator {
+    max_tests: u32,
+}
+
+impl TraversalIntegrator {
+    pub fn new(max_tests: u32) -> Self {
+        Self {
+            max_tests: max_tests.max(1),
+        }
+    }
+}
+
+/// The stops of the heat ramp, evenly spaced.
+const HEAT: [[Float; 3]; 5] = [
+    [0.0, 0.0, 1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [1.0, 0.0, 0.0],
+];
+
+/// Maps a value of [0, 1] to the heat ramp, clamping the others.
+fn heat(value: Float) -> Color {
+    let position = value.clamp(0.0, 1.0) * (HEAT.len() - 1) as Float;
+    let index = (position as usize).min(HEAT.len() - 2);
+    let t = position - index as Float;
+    let [a, b] = [HEAT[index], HEAT[index + 1]].map(|[r, g, b]| Color::new(r, g, b));
+    a + t * (b - a)
+}
+
+impl Integrator for TraversalIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Color {
+        let before = progress::ray_counts();
+        scene.world.hit(ray, 0.001, Float::INFINITY);
+        let after = progress::ray_counts();
+        let tests =
+            (after.bvh_nodes - before.bvh_nodes) + (after.primitive_tests - before.primitive_tests);
+        heat(tests as Float / self.max_tests as Float)
+    }
+}
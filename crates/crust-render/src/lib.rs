@@ -1,28 +1,90 @@
+// Casts between `Float` and the `f32` of the files are needed whichever its precision
+#![allow(clippy::unnecessary_cast)]
+
 mod aabb;
+mod animation;
+mod bake;
 mod buffer;
 mod camera;
+mod color;
 mod convert;
+mod cryptomatte;
+mod denoise;
 mod document;
+mod error;
+mod filter;
 mod hittable;
 mod hittable_list;
+mod integrator;
 mod light;
 mod material;
+mod medium;
+mod merge;
+mod mitsuba;
+mod pbrt;
+mod ply;
 mod primitives;
+mod progress;
 mod ray;
 mod sampler;
+mod scene;
+mod scene_file;
+mod server;
+mod spectrum;
+mod tile;
 mod tracer;
+mod unit;
+mod validate;
+#[cfg(feature = "preview")]
+mod window;
 mod world;
 
+pub use aabb::AABB;
+pub use animation::{Animation, Interpolation, ObjectAnimation, ObjectKey, frame_path};
+pub use bake::{BakeMode, UvMesh};
+pub use buffer::{Aovs, Buffer, ExrCompression, ExrOptions, ExrPixelType, NormalSpace};
 pub use camera::Camera;
-pub use convert::convert;
-pub use document::{DocObject, Document, ObjectList};
+pub use color::{ColorSpace, Display};
+pub use convert::{DisplayTransform, ToneMapper, output_format, write_image};
+pub use cryptomatte::Cryptomatte;
+#[cfg(feature = "oidn")]
+pub use denoise::denoise_oidn;
+pub use denoise::{Denoiser, Features, denoise_atrous};
+pub use document::{DocObject, Document, ObjectList, SceneInfo};
+pub use error::RenderError;
+pub use filter::Filter;
+pub use hittable::{HitRecord, Hittable};
 pub use hittable_list::HittableList;
+pub use integrator::{
+    AmbientOcclusionIntegrator, CausticMap, DirectLightingIntegrator, GuidedPathIntegrator,
+    Integrator, IntegratorType, MltIntegrator, NormalIntegrator, PathIntegrator, RestirIntegrator,
+    SppmIntegrator, TraversalIntegrator,
+};
 pub use light::{Light, LightList};
 pub use material::MaterialType;
 pub use material::*;
-pub use primitives::Primitive;
+pub use medium::{Density, Fog, Medium, MediumList};
+pub use merge::merge_renders;
+pub use primitives::{Object, Primitive};
 pub use primitives::{UVSphere, UVTorus};
+pub use progress::{Progress, Stats};
 pub use ray::Ray;
-pub use sampler::generate_cmj_2d;
-pub use tracer::{RenderSettings, Renderer};
-pub use world::simple_scene;
+pub use sampler::{
+    BlueNoiseSampler, IndependentSampler, Sampler, SamplerType, SobolSampler, StratifiedSampler,
+    blue_noise_mask, blue_noise_value, generate_cmj_2d, generate_stratified_2d,
+};
+pub use scene::{Background, Scene};
+pub use server::{JobLimits, serve, serve_jobs};
+pub use spectrum::{SampledWavelength, Wavelength, cie_xyz};
+pub use tile::{TileOrder, tiles};
+pub use tracer::{
+    PassCallback, Passes, Preview, ProgressCallback, RenderSettings, Renderer, render,
+};
+pub use unit::Unit;
+pub use validate::{Issue, SceneError};
+#[cfg(feature = "preview")]
+pub use window::render_in_window;
+pub use world::{
+    BUILTIN_SCENES, RandomScene, builtin_scene, random_objects, random_scene, simple_objects,
+    simple_scene,
+};
@@ -1,6 +1,7 @@
+use crate::ray::Ray;
 use std::sync::Arc;
-use utils::Color;
 use utils::Point3;
+use utils::{Color, Float};
 
 /// The `Light` trait defines the behavior of light sources in the ray tracing system.
 /// Lights are responsible for illuminating the scene and providing sampling methods.
@@ -22,7 +23,7 @@ pub trait Light: Send + Sync {
     /// # Returns
     /// - A `Point3` representing a sampled point on the light.
     #[allow(unused_variables)]
-    fn sample_cmj(&self, u: f32, v: f32) -> Point3 {
+    fn sample_cmj(&self, u: Float, v: Float) -> Point3 {
         self.sample() // fallback if not overridden
     }
 
@@ -33,14 +34,32 @@ pub trait Light: Send + Sync {
     /// - `light_point`: The sampled point on the light source.
     ///
     /// # Returns
-    /// - A `f32` representing the PDF value for the given sample.
-    fn pdf(&self, hit_point: Point3, light_point: Point3) -> f32;
+    /// - A `Float` representing the PDF value for the given sample.
+    fn pdf(&self, hit_point: Point3, light_point: Point3) -> Float;
 
     /// Returns the color of the light source.
     ///
     /// # Returns
     /// - A `Color` representing the light's color.
     fn color(&self) -> Color;
+
+    /// Samples a ray leaving the light source, as used by light tracing and photon mapping.
+    ///
+    /// # Parameters
+    /// - `u_pos`: A 2D sample choosing the emission point.
+    /// - `u_dir`: A 2D sample choosing the emission direction.
+    ///
+    /// # Returns
+    /// - `Some((ray, power))` with the power carried by the ray (radiance over pdf).
+    /// - `None` if the light cannot emit rays.
+    #[allow(unused_variables)]
+    fn sample_emission(
+        &self,
+        u_pos: (Float, Float),
+        u_dir: (Float, Float),
+    ) -> Option<(Ray, Color)> {
+        None
+    }
 }
 
 /// The `LightList` struct manages a collection of light sources in the scene.
@@ -48,6 +67,12 @@ pub trait Light: Send + Sync {
 pub struct LightList {
     /// A vector of light sources stored as `Arc<dyn Light>` for shared ownership.
     pub lights: Vec<Arc<dyn Light>>,
+    /// The names of the light groups, in order of first use.
+    pub groups: Vec<String>,
+    /// The group of each light, parallel to `lights`.
+    light_groups: Vec<Option<usize>>,
+    /// The group of the emissive objects, by object ID, for the emission found by BSDF sampling.
+    object_groups: Vec<(u32, usize)>,
 }
 
 impl Default for LightList {
@@ -63,7 +88,12 @@ impl LightList {
     /// # Returns
     /// - A new instance of `LightList`.
     pub fn new() -> Self {
-        Self { lights: Vec::new() }
+        Self {
+            lights: Vec::new(),
+            groups: Vec::new(),
+            light_groups: Vec::new(),
+            object_groups: Vec::new(),
+        }
     }
 
     /// Adds a light source to the `LightList`.
@@ -72,6 +102,46 @@ impl LightList {
     /// - `light`: An `Arc<dyn Light>` representing the light source to add.
     pub fn add(&mut self, light: Arc<dyn Light>) {
         self.lights.push(light);
+        self.light_groups.push(None);
+    }
+
+    /// Adds the light of an emissive object to a named light group.
+    ///
+    /// # Parameters
+    /// - `light`: The light source to add.
+    /// - `object_id`: The ID of the emissive object, see `HitRecord::object_id`.
+    /// - `group`: The name of the group, created on first use.
+    pub fn add_to_group(&mut self, light: Arc<dyn Light>, object_id: u32, group: &str) {
+        let index = match self.groups.iter().position(|name| name == group) {
+            Some(index) => index,
+            None => {
+                self.groups.push(group.to_string());
+                self.groups.len() - 1
+            }
+        };
+        self.lights.push(light);
+        self.light_groups.push(Some(index));
+        self.object_groups.push((object_id, index));
+    }
+
+    /// Records the contribution of the light at `index` to its group, if any.
+    ///
+    /// # Parameters
+    /// - `groups`: The radiance of each light group, empty when they are not recorded.
+    pub(crate) fn record(&self, groups: &mut [Color], index: usize, radiance: Color) {
+        if let Some(group) = self.light_groups[index].and_then(|group| groups.get_mut(group)) {
+            *group += radiance;
+        }
+    }
+
+    /// Records the emission of the object hit by a path to the group of its light, if
+    /// any, as `record`.
+    pub(crate) fn record_emission(&self, groups: &mut [Color], object_id: u32, radiance: Color) {
+        if let Some(&(_, group)) = self.object_groups.iter().find(|(id, _)| *id == object_id)
+            && let Some(group) = groups.get_mut(group)
+        {
+            *group += radiance;
+        }
     }
 
     /// Randomly samples a light source from the `LightList`.
@@ -83,7 +153,7 @@ impl LightList {
         if self.lights.is_empty() {
             None
         } else {
-            let i = (utils::random() * self.lights.len() as f32) as usize;
+            let i = (utils::random() * self.lights.len() as Float) as usize;
             self.lights.get(i)
         }
     }
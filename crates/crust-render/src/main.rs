@@ -1,10 +1,25 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use crust_render::Animation;
+use crust_render::BakeMode;
+use crust_render::Buffer;
 use crust_render::Document;
-use crust_render::Renderer;
-use crust_render::convert;
-use exr::prelude::*;
-use std::time::{Duration, Instant};
-use tracing::{Level, debug, error, info};
+use crust_render::JobLimits;
+use crust_render::RandomScene;
+use crust_render::{RenderSettings, Renderer};
+use crust_render::{frame_path, merge_renders, output_format, write_image};
+use image::ImageFormat;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{Level, debug, error, info, warn};
+use tracing_chrome::ChromeLayerBuilder;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use utils::Float;
+
+/// Period of the polling of the watched scene for changes.
+const WATCH_PERIOD: Duration = Duration::from_millis(250);
 
 #[derive(clap::ValueEnum, Clone, Debug, Copy)]
 enum LoggerLevel {
@@ -15,20 +30,278 @@ enum LoggerLevel {
     Trace,
 }
 
+#[derive(clap::ValueEnum, Clone, Debug, Copy)]
+enum LightmapMode {
+    Irradiance,
+    Lighting,
+    Occlusion,
+}
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Input Scene path should be a .ron file
-    #[arg(short, long)]
-    input: String,
+    /// Verbose level
+    #[arg(short, long, default_value = "info", global = true)]
+    level: LoggerLevel,
+    /// Logs more, one level past the verbose level for each -v: -v for the scene
+    /// reading, BVH building and pass timings, -vv for everything
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Logs less, one level below the verbose level for each -q: -q for the warnings
+    /// and errors only, -qq for the errors, -qqq for nothing
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    quiet: u8,
+    /// Writes the spans of the rendering of the tiles, the building of the BVHs and the
+    /// writing of the files to this file, e.g. trace.json, for chrome://tracing or
+    /// Perfetto
+    #[arg(long, global = true)]
+    trace: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Renders a scene to an image, or to an image sequence
+    Render {
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Renders these frames of the animation, e.g. 1-48, to an image sequence whose
+        /// output path has a printf-style frame number, e.g. frame_%04d.exr
+        /// Default is the frame range of the scene, when the output has a frame number
+        #[arg(short, long, value_parser = parse_frames)]
+        frames: Option<(u32, u32)>,
+        /// Renders the scene again each time it is saved, cancelling the render in
+        /// progress, until Ctrl-C
+        #[arg(short, long)]
+        watch: bool,
+    },
+    /// Renders a scene in a window showing the image as it renders, then writes it
+    Preview {
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Renders the scene again each time it is saved, cancelling the render in
+        /// progress, the window opening again on the new version
+        #[arg(short, long)]
+        watch: bool,
+    },
+    /// Prints the statistics of a scene: its objects, triangles, materials and lights
+    Info {
+        #[command(flatten)]
+        scene: SceneArg,
+    },
+    /// Bakes a scene into a scene embedding the meshes of its OBJ files, which loads
+    /// without them, and a BVH cache of its meshes next to it, <output>.bvh, which
+    /// its renders read instead of building the BVHs
+    Bake {
+        #[command(flatten)]
+        scene: SceneArg,
+        /// Baked scene path, a .ron or .json file
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Bakes the lighting of an OBJ mesh of a scene into a lightmap over its texture
+    /// coordinates, for real-time engines, tracing the samples per pixel over the
+    /// hemisphere of each texel
+    Lightmap {
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Name of the object of the mesh
+        #[arg(long)]
+        object: String,
+        /// Width and height of the lightmap in texels, replacing those of the image
+        #[arg(long, default_value_t = 512)]
+        resolution: usize,
+        /// What the lightmap holds: the irradiance, the lighting shaded by the albedo
+        /// of the mesh, or the ambient occlusion, a ray per sample per pixel
+        #[arg(long, default_value = "irradiance")]
+        mode: LightmapMode,
+        /// Distance within which the hits occlude, for the occlusion
+        /// Default is the whole scene
+        #[arg(long)]
+        max_distance: Option<Float>,
+        /// Bakes at the vertices of the mesh instead, an OBJ file or an inline mesh,
+        /// writing an OBJ file of the mesh with the baked colors on its vertices
+        #[arg(long)]
+        per_vertex: bool,
+    },
+    /// Writes a random scene of spheres on a grid around three large ones, e.g. to
+    /// benchmark and stress test the accelerators with larger scenes
+    Generate {
+        /// Scene path, a .ron or .json file
+        #[arg(short, long)]
+        output: String,
+        /// Half the side of the grid of small spheres, one per unit cell
+        #[arg(long, default_value_t = 11)]
+        grid: u32,
+        /// Probability of a cell of the grid to have a sphere
+        #[arg(long, default_value_t = 1.0)]
+        density: Float,
+        /// Relative probabilities of the diffuse, Cook-Torrance, metal and glass spheres
+        #[arg(long, default_value = "0.3,0.5,0.15,0.05", value_parser = parse_materials)]
+        materials: [Float; 4],
+        /// Seed of the random numbers, the same seed generating the same scene
+        #[arg(long)]
+        seed: Option<u32>,
+    },
+    /// Renders a turntable to an image sequence: the camera orbits around the point it
+    /// looks at and is focused on, replacing the camera animation of the scene
+    Turntable {
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Number of frames of the orbit
+        #[arg(long, default_value_t = 36)]
+        frames: u32,
+        /// Angle of the orbit in degrees, a full turn looping seamlessly
+        #[arg(short, long, default_value_t = 360.0)]
+        degrees: Float,
+    },
+    /// Merges EXR renders of a scene made with different seeds into the output, each
+    /// pixel weighted by the samples it took, for a lower noise
+    Merge {
+        /// EXR files of the renders
+        #[arg(required = true)]
+        renders: Vec<String>,
+        /// Output image path
+        #[arg(short, long, default_value = "output.exr")]
+        output: String,
+    },
+    /// Serves a queue of render jobs over HTTP until stopped: scenes are submitted
+    /// with POST /jobs, followed with GET /jobs/<id> and their images fetched with
+    /// GET /jobs/<id>/output
+    Serve {
+        /// Address to listen to
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: String,
+        /// Directory the scenes and images of the jobs are written to
+        #[arg(short, long, default_value = "jobs")]
+        directory: String,
+        /// Number of rendering threads
+        /// Default is one per logical core
+        #[arg(short, long)]
+        threads: Option<usize>,
+        /// Largest image accepted, in pixels, its width times its height
+        #[arg(long, default_value_t = JobLimits::default().max_pixels)]
+        max_pixels: usize,
+        /// Most samples per pixel accepted
+        #[arg(long, default_value_t = JobLimits::default().max_samples_per_pixel)]
+        max_spp: u32,
+    },
+}
+
+/// The scene a command reads, given as its first argument or with -i or --scene.
+#[derive(Args)]
+struct SceneArg {
+    /// Input Scene path, a .ron or .json file, possibly including others, or a .pbrt or Mitsuba .xml file to import
+    /// A built-in scene for builtin:<name>: builtin:cornell-box, builtin:random-spheres,
+    /// builtin:material-test-spheres or builtin:furnace
+    #[arg(value_name = "SCENE", required_unless_present = "input")]
+    scene: Option<String>,
+    /// Input Scene path, as the first argument
+    #[arg(
+        short,
+        long,
+        visible_alias = "scene",
+        value_name = "SCENE",
+        conflicts_with = "scene"
+    )]
+    input: Option<String>,
+}
+
+impl SceneArg {
+    /// The path of the scene, whichever way it was given.
+    fn path(&self) -> &str {
+        self.scene
+            .as_deref()
+            .or(self.input.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// The arguments of the commands rendering a scene.
+#[derive(Args)]
+struct RenderArgs {
+    #[command(flatten)]
+    scene: SceneArg,
     /// Output image path
     /// Default is output.exr
-    /// If you want to use a different name, please specify it here
+    /// The format follows the extension: .exr keeps the HDR image and its AOVs,
+    /// .png and .jpg write a tonemapped 8-bit image
     #[arg(short, long, default_value = "output.exr")]
     output: String,
-    /// Verbose level
-    #[arg(short, long, default_value = "info")]
-    level: LoggerLevel,
+    /// Writes the EXR output a band of rows at a time as it renders, without holding
+    /// the image in memory, for very large resolutions
+    /// Only the beauty is written, and each band takes all its samples at once
+    #[arg(long)]
+    stream: bool,
+    /// Serves the progress of the render over HTTP at this address, e.g. 127.0.0.1:8080
+    #[arg(long)]
+    serve: Option<String>,
+    /// Writes the statistics of the render as JSON to this path
+    #[arg(long)]
+    stats: Option<String>,
+    /// Seed of the samples, overriding the one of the scene, so that renders with
+    /// different seeds can be merged
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Image width in pixels, overriding the scene
+    /// The height follows the aspect ratio of the scene unless it is set too
+    #[arg(long)]
+    width: Option<usize>,
+    /// Image height in pixels, overriding the scene
+    /// The width follows the aspect ratio of the scene unless it is set too
+    #[arg(long)]
+    height: Option<usize>,
+    /// Samples per pixel, overriding the scene
+    #[arg(long)]
+    spp: Option<u32>,
+    /// Sets a value of the scene, e.g. settings.samples_per_pixel=256,
+    /// settings.integrator=Normal or camera=@closeup.ron for the camera of another scene
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    set: Vec<String>,
+    /// Number of rendering threads
+    /// Default is one per logical core
+    #[arg(short, long)]
+    threads: Option<usize>,
+}
+
+/// Parses a frame range, `first-last` or a single frame.
+fn parse_frames(range: &str) -> Result<(u32, u32), String> {
+    let parse = |frame: &str| {
+        frame
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| format!("invalid frame {frame:?}: {e}"))
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (parse(first)?, parse(last)?),
+        None => (parse(range)?, parse(range)?),
+    };
+    if first > last {
+        return Err(format!("the first frame {first} is after the last {last}"));
+    }
+    Ok((first, last))
+}
+
+/// Parses the probabilities of the materials, e.g. 0.3,0.5,0.15,0.05.
+fn parse_materials(probabilities: &str) -> Result<[Float; 4], String> {
+    let probabilities = probabilities
+        .split(',')
+        .map(|p| {
+            p.trim()
+                .parse::<Float>()
+                .map_err(|e| format!("invalid probability {p:?}: {e}"))
+        })
+        .collect::<Result<Vec<Float>, String>>()?;
+    let probabilities: [Float; 4] = probabilities
+        .try_into()
+        .map_err(|p: Vec<Float>| format!("{} probabilities instead of 4", p.len()))?;
+    if probabilities.iter().any(|&p| p.is_nan() || p < 0.0)
+        || probabilities.iter().sum::<Float>() <= 0.0
+    {
+        return Err("the probabilities are negative or all 0".to_string());
+    }
+    Ok(probabilities)
 }
 
 fn get_logger_level(level: LoggerLevel) -> Level {
@@ -41,37 +314,678 @@ fn get_logger_level(level: LoggerLevel) -> Level {
     }
 }
 
+/// The level of the logs, the verbose level raised by each -v and lowered by each -q,
+/// the logs being off below the errors.
+fn log_filter(cli: &Cli) -> LevelFilter {
+    let levels = [
+        Level::ERROR,
+        Level::WARN,
+        Level::INFO,
+        Level::DEBUG,
+        Level::TRACE,
+    ];
+    let level = get_logger_level(cli.level);
+    let base = levels.iter().position(|&l| l == level).unwrap_or(2) as i32;
+    match base + i32::from(cli.verbose) - i32::from(cli.quiet) {
+        index if index < 0 => LevelFilter::OFF,
+        index => LevelFilter::from_level(levels[(index as usize).min(levels.len() - 1)]),
+    }
+}
+
 fn main() {
     // CLI
     let cli = Cli::parse();
-    // Add tracing
-    tracing_subscriber::fmt()
-        .with_max_level(get_logger_level(cli.level))
+    // Add tracing, the spans being traced only to the profile
+    let filter = log_filter(&cli);
+    let (profile, _guard) = match &cli.trace {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+        .with(profile)
         .init();
-    let input = cli.input;
-    let input_path = std::path::Path::new(&input);
-    let output = cli.output;
-    let doc: Document = Document::read(input_path).expect("Failed to read document");
-    debug!("Document loaded at path: {:?}", input_path);
-    debug!("Render Settings: {:#?}", doc.settings());
+    match cli.command {
+        Command::Render {
+            render,
+            frames,
+            watch: watching,
+        } => {
+            let sequence = frame_path(&render.output, 0).is_some();
+            if frames.is_some() && !sequence {
+                error!(
+                    "Rendering frames needs a frame number in the output path, e.g. frame_%04d.exr"
+                );
+                std::process::exit(1);
+            }
+            if watching {
+                watch(&render, frames, false);
+                return;
+            }
+            let doc = load(&render);
+            let frames = match frames {
+                Some((first, last)) => first..=last,
+                None => doc.animation().frames(),
+            };
+            run(&doc, &render, frames, false);
+        }
+        Command::Preview {
+            render,
+            watch: watching,
+        } => {
+            if frame_path(&render.output, 0).is_some() {
+                error!("The preview window renders single images, not sequences");
+                std::process::exit(1);
+            }
+            if watching {
+                watch(&render, None, true);
+                return;
+            }
+            let doc = load(&render);
+            let frames = doc.animation().frames();
+            run(&doc, &render, frames, true);
+        }
+        Command::Info { scene } => {
+            let doc = read(scene.path());
+            println!("{}", doc.info());
+        }
+        Command::Bake { scene, output } => bake(scene.path(), &output),
+        Command::Lightmap {
+            render,
+            object,
+            resolution,
+            mode,
+            max_distance,
+            per_vertex,
+        } => {
+            let mode = match mode {
+                LightmapMode::Irradiance => BakeMode::Irradiance,
+                LightmapMode::Lighting => BakeMode::Lighting,
+                LightmapMode::Occlusion => BakeMode::Occlusion {
+                    max_distance: max_distance.unwrap_or(Float::INFINITY),
+                },
+            };
+            let problems = mode.problems();
+            if !problems.is_empty() {
+                error!("Invalid bake: {}", problems.join(", "));
+                std::process::exit(1);
+            }
+            if per_vertex {
+                if !render.output.ends_with(".obj") {
+                    error!(
+                        "A bake at the vertices is written to OBJ, not {:?}",
+                        render.output
+                    );
+                    std::process::exit(1);
+                }
+                vertex_colors(&setup(&render), &render.output, &object, mode);
+            } else {
+                lightmap(&load(&render), &render.output, &object, resolution, mode);
+            }
+        }
+        Command::Generate {
+            output,
+            grid,
+            density,
+            materials,
+            seed,
+        } => {
+            let mut generator = RandomScene::default()
+                .with_grid(grid)
+                .with_density(density)
+                .with_materials(materials[0], materials[1], materials[2], materials[3]);
+            if let Some(seed) = seed {
+                generator = generator.with_seed(seed);
+            }
+            let doc = generator.document();
+            match doc.write(std::path::Path::new(&output)) {
+                Ok(_) => info!(
+                    "Random scene of {} objects written to: {:?}",
+                    doc.info().objects,
+                    output
+                ),
+                Err(e) => {
+                    error!("Error writing the random scene: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Turntable {
+            render,
+            frames,
+            degrees,
+        } => {
+            if frame_path(&render.output, 0).is_none() {
+                error!(
+                    "Rendering frames needs a frame number in the output path, e.g. frame_%04d.exr"
+                );
+                std::process::exit(1);
+            }
+            let mut doc = load(&render);
+            let turntable = Animation::turntable(doc.camera(), frames, degrees);
+            doc = doc.with_animation(turntable);
+            let frames = doc.animation().frames();
+            run(&doc, &render, frames, false);
+        }
+        Command::Merge { renders, output } => merge(&renders, &output),
+        Command::Serve {
+            address,
+            directory,
+            threads,
+            max_pixels,
+            max_spp,
+        } => serve_jobs(
+            &address,
+            &directory,
+            threads,
+            JobLimits {
+                max_pixels,
+                max_samples_per_pixel: max_spp,
+            },
+        ),
+    }
+}
+
+/// Reads a scene, exiting if it cannot be.
+fn read(input: &str) -> Document {
+    match Document::read(std::path::Path::new(input)) {
+        Ok(doc) => doc,
+        Err(e) => {
+            error!("Error reading the scene {:?}: {}", input, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the scene to render with the settings set on the command line, and sets up
+/// the rendering threads.
+fn load(args: &RenderArgs) -> Document {
+    if let Err(e) = output_format(&args.output) {
+        error!("Invalid output path {:?}: {}", args.output, e);
+        std::process::exit(1);
+    }
+    if args.stream && !matches!(output_format(&args.output), Ok(ImageFormat::OpenExr)) {
+        error!("A streamed render is written to EXR, not {:?}", args.output);
+        std::process::exit(1);
+    }
+    setup(args)
+}
+
+/// Reads the scene with the settings set on the command line, and sets up the
+/// rendering threads, whatever the output.
+fn setup(args: &RenderArgs) -> Document {
+    let doc = match scene(args) {
+        Ok(doc) => doc,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(threads) = args.threads
+        && let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+    {
+        warn!("Failed to set the number of threads: {}", e);
+    }
+    doc
+}
+
+/// Reads the scene to render with the values and settings set on the command line.
+fn scene(args: &RenderArgs) -> Result<Document, String> {
+    let mut doc = Document::read(std::path::Path::new(args.scene.path()))
+        .map_err(|e| format!("Error reading the scene {:?}: {}", args.scene.path(), e))?;
+    for assignment in &args.set {
+        doc.set(assignment)
+            .map_err(|e| format!("Failed to set {:?}: {}", assignment, e))?;
+    }
+    let issues = doc.validate();
+    if !issues.is_empty() {
+        let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+        return Err(format!(
+            "Invalid scene {:?}: {}",
+            args.scene.path(),
+            issues.join(", ")
+        ));
+    }
+    let settings = override_settings(args, doc.settings());
+    debug!("Render Settings: {:#?}", settings);
+    Ok(doc.with_settings(settings))
+}
+
+/// Raises `cancel` on a first Ctrl-C, which stops the render and writes what has
+/// been rendered, and quits right away on a second one.
+///
+/// # Returns
+/// - Whether Ctrl-C was pressed.
+fn interrupt(cancel: Arc<AtomicBool>) -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = interrupted.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_interrupted.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        cancel.store(true, Ordering::Relaxed);
+        warn!("Stopping the render, press Ctrl-C again to quit without writing");
+    }) {
+        warn!("Failed to install the Ctrl-C handler: {}", e);
+    }
+    interrupted
+}
+
+/// The renderer of the scene, at the first frame of a sequence.
+fn scene_renderer(doc: &Document, output: &str, frames: &RangeInclusive<u32>) -> Renderer {
+    if frame_path(output, 0).is_some() {
+        doc.renderer_at(*frames.start(), doc.settings())
+    } else {
+        doc.renderer(doc.settings())
+    }
+}
+
+/// Serves the progress of the render when asked to on the command line.
+fn serve(args: &RenderArgs, renderer: &mut Renderer) {
+    if let Some(address) = &args.serve {
+        match crust_render::serve(address, renderer) {
+            Ok(address) => info!("Serving the progress at http://{}", address),
+            Err(e) => {
+                error!("Error serving the progress at {:?}: {}", address, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Renders the scene to the output, or the frames to the image sequence when the
+/// output has a frame number.
+fn run(doc: &Document, args: &RenderArgs, frames: RangeInclusive<u32>, preview: bool) {
+    let cancel = Arc::new(AtomicBool::new(false));
+    interrupt(cancel.clone());
+    let mut renderer = scene_renderer(doc, &args.output, &frames).with_cancel(cancel);
+    serve(args, &mut renderer);
+    render_frames(&mut renderer, doc, args, frames, preview);
+}
+
+/// Renders the image or the frames of the sequence with a renderer set up for the
+/// first one.
+fn render_frames(
+    renderer: &mut Renderer,
+    doc: &Document,
+    args: &RenderArgs,
+    frames: RangeInclusive<u32>,
+    preview: bool,
+) {
+    let output = &args.output;
+    if frame_path(output, 0).is_none() {
+        render_image(renderer, output, args, doc, preview);
+        return;
+    }
+    let first = *frames.start();
+    for frame in frames {
+        let output = frame_path(output, frame).expect("the output has a frame number");
+        info!("Rendering frame {} to {:?}", frame, output);
+        if frame != first {
+            renderer.scene = doc.scene_at(frame);
+        }
+        render_image(renderer, &output, args, doc, preview);
+        if renderer.is_cancelled() {
+            warn!("Sequence stopped at frame {}", frame);
+            return;
+        }
+    }
+}
+
+/// The time the scene was last saved at, `None` while it cannot be read.
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Renders the scene, then renders it again each time it is saved, cancelling the
+/// render of the previous version, until Ctrl-C. A version that fails to read is
+/// reported and skipped.
+///
+/// # Parameters
+/// - `frames`: The frames to render, those of the animation of each version by
+///   default.
+fn watch(args: &RenderArgs, frames: Option<(u32, u32)>, preview: bool) {
+    let frames_of = |doc: &Document| match frames {
+        Some((first, last)) => first..=last,
+        None => doc.animation().frames(),
+    };
+    let mut saved = modified(args.scene.path());
+    let mut doc = load(args);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let interrupted = interrupt(cancel.clone());
+    let mut renderer =
+        scene_renderer(&doc, &args.output, &frames_of(&doc)).with_cancel(cancel.clone());
+    serve(args, &mut renderer);
+    loop {
+        let rendered = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !rendered.load(Ordering::Relaxed) {
+                    std::thread::sleep(WATCH_PERIOD);
+                    if modified(args.scene.path()) != saved {
+                        info!("The scene changed, cancelling the render");
+                        cancel.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            });
+            render_frames(&mut renderer, &doc, args, frames_of(&doc), preview);
+            rendered.store(true, Ordering::Relaxed);
+        });
+        if interrupted.load(Ordering::Relaxed) {
+            return;
+        }
+        info!(
+            "Watching {:?} for changes, press Ctrl-C to quit",
+            args.scene.path()
+        );
+        doc = loop {
+            if interrupted.load(Ordering::Relaxed) {
+                return;
+            }
+            let now = modified(args.scene.path());
+            if now.is_none() || now == saved {
+                std::thread::sleep(WATCH_PERIOD);
+                continue;
+            }
+            saved = now;
+            match scene(args) {
+                Ok(doc) => break doc,
+                Err(e) => error!("{}", e),
+            }
+        };
+        info!("Rendering the new version of {:?}", args.scene.path());
+        let next = scene_renderer(&doc, &args.output, &frames_of(&doc));
+        renderer.scene = next.scene;
+        renderer.settings = next.settings;
+        renderer.integrator = next.integrator;
+        renderer.names = next.names;
+        cancel.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Renders an image, then writes it with its statistics and denoised version.
+fn render_image(
+    renderer: &mut Renderer,
+    output: &str,
+    args: &RenderArgs,
+    doc: &Document,
+    preview: bool,
+) {
+    if args.stream {
+        if !preview {
+            render_streamed(renderer, output, args);
+            return;
+        }
+        warn!("The preview window needs the whole image, the render is not streamed");
+    }
     // Timer
     let start = Instant::now();
-    // World
-    let (world, lights) = doc.get_world();
-    // Camera
-    let renderer = Renderer::new(doc.camera(), world, lights, doc.settings());
-    let buffer = renderer.render();
+    let buffer = if preview {
+        render_in_window(renderer)
+    } else {
+        let preview_output = output.to_string();
+        let settings = doc.settings();
+        // Replaces the preview of the previous frame of a sequence
+        renderer.pass_callbacks.clear();
+        renderer.on_pass_complete(move |pass, passes, film| {
+            if pass == passes {
+                return;
+            }
+            // The preview goes to the output itself, leaving a usable image if the
+            // render is stopped
+            match write_image(film, &preview_output, &settings) {
+                Ok(_) => debug!("Preview written to: {:?}", preview_output),
+                Err(e) => error!("Error writing preview: {}", e),
+            }
+        });
+        renderer.render()
+    };
+    if renderer.is_cancelled() {
+        warn!("Render cancelled, writing the partial image");
+    }
     // Close Timer
-    let duration: Duration = start.elapsed();
-    info!("Time elapsed in rendering() is: {:?}", duration);
+    report(renderer, args, start.elapsed());
     // Render
-    let (img_width, img_height) = doc.settings().get_dimensions();
-    match write_rgb_file(&output, img_width, img_height, |x, y| buffer.get_rgb(x, y)) {
+    match write_image(&buffer, output, &doc.settings()) {
         Ok(_) => info!("Image written to: {:?}", output),
         Err(e) => {
             error!("Error writing image: {}", e);
             std::process::exit(1);
         }
     }
-    convert();
+    if renderer.is_cancelled() {
+        return;
+    }
+    if let Some(mut denoised) = renderer.denoise(&buffer) {
+        for (name, value) in buffer.metadata() {
+            denoised.set_metadata(name, value);
+        }
+        let denoised_output = denoised_path(output);
+        match write_image(&denoised, &denoised_output, &doc.settings()) {
+            Ok(_) => info!("Denoised image written to: {:?}", denoised_output),
+            Err(e) => {
+                error!("Error writing denoised image: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Renders the image straight to the EXR output, a band of rows at a time.
+fn render_streamed(renderer: &Renderer, output: &str, args: &RenderArgs) {
+    let start = Instant::now();
+    let result = renderer.render_to_exr(output);
+    if renderer.is_cancelled() {
+        warn!("Render cancelled, the rows not rendered are left black");
+    }
+    report(renderer, args, start.elapsed());
+    match result {
+        Ok(_) => info!("Image written to: {:?}", output),
+        Err(e) => {
+            error!("Error rendering to {:?}: {}", output, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reports the time and statistics of a render, writing them to the statistics file
+/// when asked to.
+fn report(renderer: &Renderer, args: &RenderArgs, duration: Duration) {
+    info!("Time elapsed in rendering() is: {:?}", duration);
+    let stats = renderer.progress.stats();
+    // The integrators rendering without tiles do not report statistics
+    if stats.render > 0 {
+        info!("{}", stats);
+    }
+    if let Some(path) = &args.stats {
+        match std::fs::write(path, stats.to_json() + "\n") {
+            Ok(_) => info!("Statistics written to: {:?}", path),
+            Err(e) => error!("Error writing statistics: {}", e),
+        }
+    }
+}
+
+/// The render settings of the scene with those set on the command line.
+fn override_settings(args: &RenderArgs, mut settings: RenderSettings) -> RenderSettings {
+    if let Some(seed) = args.seed {
+        settings = settings.with_seed(seed);
+    }
+    if let Some(spp) = args.spp {
+        settings = settings.with_samples_per_pixel(spp);
+    }
+    let (width, height) = settings.get_dimensions();
+    let aspect_ratio = width as Float / height as Float;
+    let dimensions = match (args.width, args.height) {
+        (Some(width), Some(height)) => {
+            if ((width as Float / height as Float) / aspect_ratio - 1.0).abs() > 0.01 {
+                warn!(
+                    "The image size {}x{} does not match the aspect ratio of the camera, the image is stretched",
+                    width, height
+                );
+            }
+            Some((width, height))
+        }
+        (Some(width), None) => Some((width, (width as Float / aspect_ratio).round() as usize)),
+        (None, Some(height)) => Some(((height as Float * aspect_ratio).round() as usize, height)),
+        (None, None) => None,
+    };
+    if let Some((width, height)) = dimensions {
+        settings = settings.with_dimensions(width.max(1), height.max(1));
+    }
+    settings
+}
+
+/// Bakes the scene into the output.
+fn bake(input: &str, output: &str) {
+    let mut doc = match read(input).baked() {
+        Ok(doc) => doc,
+        Err(e) => {
+            error!("Error baking the scene: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let cache = std::path::Path::new(output).with_extension("bvh");
+    match doc.write_bvh_cache(&cache) {
+        Ok(0) => {}
+        Ok(count) => info!("BVHs of {} meshes written to: {:?}", count, cache),
+        Err(e) => {
+            error!("Error writing the BVH cache: {}", e);
+            std::process::exit(1);
+        }
+    }
+    match doc.write(std::path::Path::new(output)) {
+        Ok(_) => info!("Baked scene written to: {:?}", output),
+        Err(e) => {
+            error!("Error writing baked scene: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Bakes a lightmap of an object of the scene into the output.
+fn lightmap(doc: &Document, output: &str, object: &str, resolution: usize, mode: BakeMode) {
+    let start = Instant::now();
+    let lightmap = match doc.bake_lightmap(object, resolution.max(1), mode) {
+        Ok(lightmap) => lightmap,
+        Err(e) => {
+            error!("Error baking the lightmap of {:?}: {}", object, e);
+            std::process::exit(1);
+        }
+    };
+    match write_image(&lightmap, output, &doc.settings().for_bake()) {
+        Ok(_) => info!(
+            "Lightmap of {:?} baked in {:?}, written to: {:?}",
+            object,
+            start.elapsed(),
+            output
+        ),
+        Err(e) => {
+            error!("Error writing the lightmap: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Bakes an object of the scene at its vertices, into an OBJ file with their colors.
+fn vertex_colors(doc: &Document, output: &str, object: &str, mode: BakeMode) {
+    let start = Instant::now();
+    let result = doc
+        .bake_vertices(object, mode)
+        .and_then(|colors| doc.write_baked_vertices(object, &colors, output));
+    match result {
+        Ok(_) => info!(
+            "Vertices of {:?} baked in {:?}, written to: {:?}",
+            object,
+            start.elapsed(),
+            output
+        ),
+        Err(e) => {
+            error!("Error baking the vertices of {:?}: {}", object, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Merges renders into the output.
+fn merge(renders: &[String], output: &str) {
+    if let Err(e) = output_format(output) {
+        error!("Invalid output path {:?}: {}", output, e);
+        std::process::exit(1);
+    }
+    let (film, settings) = match merge_renders(renders) {
+        Ok(merged) => merged,
+        Err(e) => {
+            error!("Error merging the renders: {}", e);
+            std::process::exit(1);
+        }
+    };
+    match write_image(&film, output, &settings) {
+        Ok(_) => info!("{} renders merged into: {:?}", renders.len(), output),
+        Err(e) => {
+            error!("Error writing merged image: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Serves the render jobs until the process is stopped.
+fn serve_jobs(address: &str, directory: &str, threads: Option<usize>, limits: JobLimits) {
+    if let Some(threads) = threads
+        && let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+    {
+        warn!("Failed to set the number of threads: {}", e);
+    }
+    match crust_render::serve_jobs(address, std::path::Path::new(directory), limits) {
+        Ok(address) => info!("Serving the render jobs at http://{}/jobs", address),
+        Err(e) => {
+            error!("Error serving the render jobs at {:?}: {}", address, e);
+            std::process::exit(1);
+        }
+    }
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Renders in the preview window.
+#[cfg(feature = "preview")]
+fn render_in_window(renderer: &mut Renderer) -> Buffer {
+    match crust_render::render_in_window(renderer) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            error!("Error opening the preview window: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The preview window is not built in.
+#[cfg(not(feature = "preview"))]
+fn render_in_window(_renderer: &mut Renderer) -> Buffer {
+    error!("The preview window needs crust-render to be built with the `preview` feature");
+    std::process::exit(1);
+}
+
+/// Path of the denoised image next to the noisy one, e.g. `output_denoised.exr`.
+fn denoised_path(output: &str) -> String {
+    let path = std::path::Path::new(output);
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("exr");
+    path.with_file_name(format!("{stem}_denoised.{extension}"))
+        .to_string_lossy()
+        .into_owned()
 }
@@ -1,19 +1,21 @@
 use crate::hittable::HitRecord;
 use crate::material::Material;
 use crate::ray::Ray;
-use utils::Color;
+use crate::sampler::Sampler;
+use crate::validate;
+use utils::{Color, Float};
 
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BlinnPhong {
     pub diffuse: Color,
     pub specular: Color,
-    pub shininess: f32,
+    pub shininess: Float,
     pub light_dir: utils::Vec3, // Assume one directional light for now
 }
 
 impl BlinnPhong {
-    pub fn new(diffuse: Color, specular: Color, shininess: f32, light_dir: utils::Vec3) -> Self {
+    pub fn new(diffuse: Color, specular: Color, shininess: Float, light_dir: utils::Vec3) -> Self {
         BlinnPhong {
             diffuse,
             specular,
@@ -21,6 +23,18 @@ impl BlinnPhong {
             light_dir: utils::unit_vector(light_dir),
         }
     }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "diffuse color", self.diffuse);
+        validate::check_color(&mut problems, "specular color", self.specular);
+        if !(self.shininess.is_finite() && self.shininess >= 0.0) {
+            problems.push(format!("the shininess {} is negative", self.shininess));
+        }
+        validate::check_finite(&mut problems, "light direction", self.light_dir);
+        problems
+    }
 }
 
 impl Material for BlinnPhong {
@@ -28,6 +42,7 @@ impl Material for BlinnPhong {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
@@ -37,14 +52,22 @@ impl Material for BlinnPhong {
 
         let halfway = utils::unit_vector(view_dir + light_dir);
 
-        let diff = f32::max(utils::dot(normal, light_dir), 0.0);
-        let spec = f32::powf(f32::max(utils::dot(normal, halfway), 0.0), self.shininess);
+        let diff = Float::max(utils::dot(normal, light_dir), 0.0);
+        let spec = Float::powf(Float::max(utils::dot(normal, halfway), 0.0), self.shininess);
 
         let color = self.diffuse * diff + self.specular * spec;
 
         *attenuation = color;
-        *scattered = Ray::new(rec.p, light_dir); // Optional: or bounce randomly for realism
+        *scattered = r_in.spawn(rec.p, light_dir); // Optional: or bounce randomly for realism
 
         true
     }
+
+    fn albedo(&self) -> Color {
+        self.diffuse
+    }
+
+    fn feature_albedo(&self, _cosine: Float) -> Color {
+        (self.diffuse + self.specular).clamp(0.0, 1.0)
+    }
 }
@@ -1,18 +1,73 @@
-use std::f32::consts::PI;
-use utils::Color;
 use utils::Vec3;
-use utils::random2;
+use utils::consts::PI;
+use utils::{Color, Float};
 
-pub fn fresnel_schlick(cos_theta: f32, f0: Color) -> Color {
-    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * f32::powf(1.0 - cos_theta, 5.0)
+/// Applies the roughness floor of a hit, see `HitRecord::roughness_floor`, to a
+/// roughness.
+pub(crate) fn regularize(roughness: Float, floor: Float) -> Float {
+    roughness.max(floor.clamp(0.0, 1.0))
 }
 
-pub fn geometry_schlick_ggx(n_dot: f32, roughness: f32) -> f32 {
+pub fn fresnel_schlick(cos_theta: Float, f0: Color) -> Color {
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * Float::powf(1.0 - cos_theta, 5.0)
+}
+
+pub fn geometry_schlick_ggx(n_dot: Float, roughness: Float) -> Float {
     let k = (roughness + 1.0).powi(2) / 8.0;
     n_dot / (n_dot * (1.0 - k) + k)
 }
 
-pub fn sample_vndf_ggx(view: Vec3, roughness: f32) -> Vec3 {
+/// The GGX normal distribution of `CookTorrance`, its alpha being the square of the
+/// roughness.
+pub fn ggx_distribution(n_dot_h: Float, roughness: Float) -> Float {
+    let a2 = roughness.powi(4);
+    let denom = (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2);
+    a2 / (PI * denom)
+}
+
+/// The number of lanes of the batched terms, the 8 `f32` of an AVX register.
+pub const LANES: usize = 8;
+
+/// `fresnel_schlick` for 8 cosines at once, e.g. the hits of a batch of rays on the
+/// same material.
+pub fn fresnel_schlick_x8(cos_theta: [Float; LANES], f0: Color) -> [Color; LANES] {
+    let mut weight = [0.0; LANES];
+    for (weight, cos_theta) in weight.iter_mut().zip(cos_theta) {
+        let m = 1.0 - cos_theta;
+        let m2 = m * m;
+        *weight = m2 * m2 * m;
+    }
+    let one_minus_f0 = Color::new(1.0, 1.0, 1.0) - f0;
+    weight.map(|weight| f0 + one_minus_f0 * weight)
+}
+
+/// `ggx_distribution` for 8 cosines at once.
+pub fn ggx_distribution_x8(n_dot_h: [Float; LANES], roughness: Float) -> [Float; LANES] {
+    let a2 = roughness.powi(4);
+    let mut d = [0.0; LANES];
+    for (d, n_dot_h) in d.iter_mut().zip(n_dot_h) {
+        let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+        *d = a2 / (PI * denom * denom);
+    }
+    d
+}
+
+/// The Smith geometry term of `CookTorrance`, the product of the `geometry_schlick_ggx`
+/// of the view and of the light, for 8 pairs of cosines at once.
+pub fn geometry_smith_x8(
+    n_dot_v: [Float; LANES],
+    n_dot_l: [Float; LANES],
+    roughness: Float,
+) -> [Float; LANES] {
+    let k = (roughness + 1.0).powi(2) / 8.0;
+    let mut g = [0.0; LANES];
+    for ((g, n_dot_v), n_dot_l) in g.iter_mut().zip(n_dot_v).zip(n_dot_l) {
+        *g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+    }
+    g
+}
+
+pub fn sample_vndf_ggx(view: Vec3, roughness: Float, (u1, u2): (Float, Float)) -> Vec3 {
     // Transform view direction to hemisphere aligned with normal (Z+)
     let v = utils::unit_vector(Vec3::new(
         roughness * view.x(),
@@ -20,9 +75,6 @@ pub fn sample_vndf_ggx(view: Vec3, roughness: f32) -> Vec3 {
         view.z(),
     ));
 
-    // Generate 2D random numbers
-    let (u1, u2) = random2();
-
     // Construct orthonormal basis
     let lensq = v.x() * v.x() + v.y() * v.y();
     let (t1, t2) = if lensq > 0.0 {
@@ -42,7 +94,7 @@ pub fn sample_vndf_ggx(view: Vec3, roughness: f32) -> Vec3 {
 
     // Sample point on hemisphere
     let r = u1.sqrt();
-    let phi = 2.0 * std::f32::consts::PI * u2;
+    let phi = 2.0 * utils::consts::PI * u2;
     let t1_coeff = r * phi.cos();
     let t2_coeff = r * phi.sin();
     let _s = 0.5 * (1.0 + v.z());
@@ -56,23 +108,23 @@ pub fn sample_vndf_ggx(view: Vec3, roughness: f32) -> Vec3 {
     ))
 }
 
-pub fn pdf_vndf_ggx(view: Vec3, half: Vec3, normal: Vec3, roughness: f32) -> f32 {
+pub fn pdf_vndf_ggx(view: Vec3, half: Vec3, normal: Vec3, roughness: Float) -> Float {
     let a2 = roughness * roughness;
     let n_dot_h = utils::dot(normal, half).max(1e-6);
     let v_dot_h = utils::dot(view, half).max(1e-6);
 
-    let d = a2 / (std::f32::consts::PI * (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2));
+    let d = a2 / (utils::consts::PI * (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2));
     d * n_dot_h / (4.0 * v_dot_h)
 }
 
-pub fn schlick_weight(cos_theta: f32) -> f32 {
+pub fn schlick_weight(cos_theta: Float) -> Float {
     (1.0 - cos_theta).powf(5.0)
 }
 
 // Disney Diffuse
 pub fn disney_diffuse(
     base_color: Color,
-    roughness: f32,
+    roughness: Float,
     n: Vec3,
     v: Vec3,
     l: Vec3,
@@ -93,13 +145,62 @@ pub fn disney_diffuse(
 }
 
 // GTR1 distribution for clearcoat
-pub fn gtr1(n_dot_h: f32, alpha: f32) -> f32 {
+pub fn gtr1(n_dot_h: Float, alpha: Float) -> Float {
     let a2 = alpha * alpha;
     let denom = PI * ((n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2));
     (a2 - 1.0) / denom.max(1e-4)
 }
 
 // Clearcoat Fresnel approx
-pub fn fresnel_schlick_scalar(cos_theta: f32, f0: f32) -> f32 {
+pub fn fresnel_schlick_scalar(cos_theta: Float, f0: Float) -> Float {
     f0 + (1.0 - f0) * (1.0 - cos_theta).powf(5.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COSINES: [Float; LANES] = [0.0, 0.05, 0.2, 0.35, 0.5, 0.7, 0.9, 1.0];
+
+    fn assert_close(batched: Float, scalar: Float) {
+        assert!(
+            (batched - scalar).abs() <= 1e-5 * scalar.abs().max(1.0),
+            "{batched} != {scalar}"
+        );
+    }
+
+    #[test]
+    fn batches_the_fresnel_term() {
+        let f0 = Color::new(0.04, 0.5, 0.95);
+        for (batched, cos_theta) in fresnel_schlick_x8(COSINES, f0).iter().zip(COSINES) {
+            let scalar = fresnel_schlick(cos_theta, f0);
+            assert_close(batched.r(), scalar.r());
+            assert_close(batched.g(), scalar.g());
+            assert_close(batched.b(), scalar.b());
+        }
+    }
+
+    #[test]
+    fn batches_the_distribution_term() {
+        for roughness in [0.05, 0.3, 0.7, 1.0] {
+            let batched = ggx_distribution_x8(COSINES, roughness);
+            for (batched, n_dot_h) in batched.iter().zip(COSINES) {
+                assert_close(*batched, ggx_distribution(n_dot_h, roughness));
+            }
+        }
+    }
+
+    #[test]
+    fn batches_the_geometry_term() {
+        let mut n_dot_l = COSINES;
+        n_dot_l.reverse();
+        for roughness in [0.05, 0.3, 0.7, 1.0] {
+            let batched = geometry_smith_x8(COSINES, n_dot_l, roughness);
+            for ((batched, n_dot_v), n_dot_l) in batched.iter().zip(COSINES).zip(n_dot_l) {
+                let scalar = geometry_schlick_ggx(n_dot_v, roughness)
+                    * geometry_schlick_ggx(n_dot_l, roughness);
+                assert_close(*batched, scalar);
+            }
+        }
+    }
+}
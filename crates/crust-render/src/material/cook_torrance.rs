@@ -2,21 +2,25 @@ use crate::hittable::HitRecord;
 use crate::material::Material;
 use crate::material::fresnel_schlick;
 use crate::material::geometry_schlick_ggx;
+use crate::material::ggx_distribution;
 use crate::material::pdf_vndf_ggx;
+use crate::material::regularize;
 use crate::material::sample_vndf_ggx;
 use crate::ray::Ray;
-use utils::Color;
+use crate::sampler::Sampler;
+use crate::validate;
+use utils::{Color, Float};
 
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CookTorrance {
     pub albedo: Color,
-    pub roughness: f32,
-    pub metallic: f32,
+    pub roughness: Float,
+    pub metallic: Float,
 }
 
 impl CookTorrance {
-    pub fn new(albedo: Color, roughness: f32, metallic: f32) -> Self {
+    pub fn new(albedo: Color, roughness: Float, metallic: Float) -> Self {
         Self {
             albedo,
             roughness: roughness.clamp(0.05, 1.0),
@@ -24,32 +28,41 @@ impl CookTorrance {
         }
     }
 
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "albedo", self.albedo);
+        validate::check_unit(&mut problems, "roughness", self.roughness);
+        validate::check_unit(&mut problems, "metallic", self.metallic);
+        problems
+    }
+
     // GGX sample (based on spherical coordinates)
     #[allow(dead_code)]
-    fn sample_ggx(normal: utils::Vec3, roughness: f32) -> utils::Vec3 {
+    fn sample_ggx(normal: utils::Vec3, roughness: Float) -> utils::Vec3 {
         let u1 = utils::random();
         let u2 = utils::random();
 
         let a = roughness * roughness;
 
-        let theta = f32::acos(f32::sqrt((1.0 - u1) / (1.0 + (a * a - 1.0) * u1)));
-        let phi = 2.0 * std::f32::consts::PI * u2;
+        let theta = Float::acos(Float::sqrt((1.0 - u1) / (1.0 + (a * a - 1.0) * u1)));
+        let phi = 2.0 * utils::consts::PI * u2;
 
-        let sin_theta = f32::sin(theta);
-        let x = sin_theta * f32::cos(phi);
-        let y = sin_theta * f32::sin(phi);
-        let z = f32::cos(theta);
+        let sin_theta = Float::sin(theta);
+        let x = sin_theta * Float::cos(phi);
+        let y = sin_theta * Float::sin(phi);
+        let z = Float::cos(theta);
 
         let h_local = utils::Vec3::new(x, y, z);
-        utils::align_to_normal(h_local, normal)
+        utils::Onb::from_normal(normal).to_world(h_local)
     }
     #[allow(dead_code)]
-    fn pdf_ggx(normal: utils::Vec3, h: utils::Vec3, roughness: f32) -> f32 {
+    fn pdf_ggx(normal: utils::Vec3, h: utils::Vec3, roughness: Float) -> Float {
         let a = roughness * roughness;
         let a2 = a * a;
-        let n_dot_h = f32::max(utils::dot(normal, h), 0.0);
+        let n_dot_h = Float::max(utils::dot(normal, h), 0.0);
         let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
-        let d = a2 / (std::f32::consts::PI * denom * denom);
+        let d = a2 / (utils::consts::PI * denom * denom);
         d * n_dot_h / (4.0 * utils::dot(h, utils::unit_vector(h)).abs())
     }
 }
@@ -59,14 +72,21 @@ impl Material for CookTorrance {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
         let n = rec.normal;
         let v = -utils::unit_vector(r_in.direction());
-
-        // Sample a halfway vector using VNDF
-        let h = sample_vndf_ggx(v, self.roughness);
+        let roughness = regularize(self.roughness, rec.roughness_floor);
+
+        // Sample a halfway vector using VNDF, in the basis of the normal
+        let onb = utils::Onb::from_normal(n);
+        let h = onb.to_world(sample_vndf_ggx(
+            onb.to_local(v),
+            roughness,
+            sampler.get_2d(),
+        ));
         let l = utils::reflect(-v, h);
         if utils::dot(l, n) <= 0.0 {
             return false;
@@ -80,92 +100,88 @@ impl Material for CookTorrance {
         let f0 = Color::new(0.04, 0.04, 0.04).lerp(self.albedo, self.metallic);
         let f = fresnel_schlick(v_dot_h, f0);
 
-        let a = self.roughness * self.roughness;
-        let a2 = a * a;
-        let denom = (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2);
-        let d = a2 / (std::f32::consts::PI * denom);
+        let d = ggx_distribution(n_dot_h, roughness);
 
-        let g = geometry_schlick_ggx(n_dot_v, self.roughness)
-            * geometry_schlick_ggx(n_dot_l, self.roughness);
+        let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
         let specular = (f * d * g) / (4.0 * n_dot_v * n_dot_l + 1e-4);
         let kd = (Color::new(1.0, 1.0, 1.0) - f) * (1.0 - self.metallic);
-        let diffuse = self.albedo / std::f32::consts::PI;
+        let diffuse = self.albedo / utils::consts::PI;
 
         *attenuation = kd * diffuse + specular;
-        *scattered = Ray::new(rec.p, l);
+        *scattered = r_in.spawn(rec.p, l);
 
         true
     }
 
-    fn scatter_importance(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f32)> {
+    fn scatter_importance(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
         let n = rec.normal;
         let v = -utils::unit_vector(r_in.direction());
+        let roughness = regularize(self.roughness, rec.roughness_floor);
 
-        let sample_specular = utils::random() < 0.5;
+        let onb = utils::Onb::from_normal(n);
+        let sample_specular = sampler.get_1d() < 0.5;
+        let (u1, u2) = sampler.get_2d();
 
         let (l, pdf_specular, pdf_diffuse, brdf) = if sample_specular {
             // === Sample GGX specular ===
-            let h = sample_vndf_ggx(v, self.roughness);
+            let h = onb.to_world(sample_vndf_ggx(onb.to_local(v), roughness, (u1, u2)));
             let l = utils::reflect(-v, h);
             if utils::dot(l, n) <= 0.0 {
                 return None;
             }
 
             // PDFs
-            let pdf_ggx = pdf_vndf_ggx(v, h, n, self.roughness);
+            let pdf_ggx = pdf_vndf_ggx(v, h, n, roughness);
             let cosine = utils::dot(n, l).max(1e-4);
-            let pdf_cosine = cosine / std::f32::consts::PI;
+            let pdf_cosine = cosine / utils::consts::PI;
 
             // Fresnel term
             let f0 = Color::new(0.04, 0.04, 0.04).lerp(self.albedo, self.metallic);
             let f = fresnel_schlick(utils::dot(v, h), f0);
 
             // NDF
-            let a = self.roughness * self.roughness;
-            let a2 = a * a;
-            let n_dot_h = utils::dot(n, h).max(1e-4);
-            let denom = (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2);
-            let d = a2 / (std::f32::consts::PI * denom);
+            let d = ggx_distribution(utils::dot(n, h).max(1e-4), roughness);
 
             // Geometry term
-            let g = geometry_schlick_ggx(utils::dot(n, v), self.roughness)
-                * geometry_schlick_ggx(utils::dot(n, l), self.roughness);
+            let g = geometry_schlick_ggx(utils::dot(n, v), roughness)
+                * geometry_schlick_ggx(utils::dot(n, l), roughness);
 
             let spec = (f * d * g) / (4.0 * utils::dot(n, v) * utils::dot(n, l) + 1e-4);
 
             let kd = (Color::new(1.0, 1.0, 1.0) - f) * (1.0 - self.metallic);
-            let diffuse = self.albedo / std::f32::consts::PI;
+            let diffuse = self.albedo / utils::consts::PI;
 
             let brdf = kd * diffuse + spec;
 
             (l, pdf_ggx * 0.5, pdf_cosine * 0.5, brdf)
         } else {
             // === Sample cosine-weighted hemisphere (diffuse) ===
-            let l_local = utils::random_cosine_direction();
-            let l = utils::align_to_normal(l_local, n);
+            let l_local = utils::cosine_direction(u1, u2);
+            let l = onb.to_world(l_local);
             if utils::dot(l, n) <= 0.0 {
                 return None;
             }
 
             let h = utils::unit_vector(v + l);
-            let pdf_cosine = utils::dot(n, l).max(1e-4) / std::f32::consts::PI;
-            let pdf_ggx = pdf_vndf_ggx(v, h, n, self.roughness);
+            let pdf_cosine = utils::dot(n, l).max(1e-4) / utils::consts::PI;
+            let pdf_ggx = pdf_vndf_ggx(v, h, n, roughness);
 
             let f0 = Color::new(0.04, 0.04, 0.04).lerp(self.albedo, self.metallic);
             let f = fresnel_schlick(utils::dot(v, h), f0);
 
-            let a = self.roughness * self.roughness;
-            let a2 = a * a;
-            let n_dot_h = utils::dot(n, h).max(1e-4);
-            let denom = (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2);
-            let d = a2 / (std::f32::consts::PI * denom);
+            let d = ggx_distribution(utils::dot(n, h).max(1e-4), roughness);
 
-            let g = geometry_schlick_ggx(utils::dot(n, v), self.roughness)
-                * geometry_schlick_ggx(utils::dot(n, l), self.roughness);
+            let g = geometry_schlick_ggx(utils::dot(n, v), roughness)
+                * geometry_schlick_ggx(utils::dot(n, l), roughness);
 
             let spec = (f * d * g) / (4.0 * utils::dot(n, v) * utils::dot(n, l) + 1e-4);
             let kd = (Color::new(1.0, 1.0, 1.0) - f) * (1.0 - self.metallic);
-            let diffuse = self.albedo / std::f32::consts::PI;
+            let diffuse = self.albedo / utils::consts::PI;
 
             let brdf = kd * diffuse + spec;
 
@@ -176,7 +192,17 @@ impl Material for CookTorrance {
         let final_pdf = pdf_specular + pdf_diffuse;
         let n_dot_l = utils::dot(n, l).max(1e-4);
 
-        let scattered = Ray::new(rec.p, l);
+        let scattered = r_in.spawn(rec.p, l);
         Some((scattered, brdf * n_dot_l * weight, final_pdf.max(1e-4)))
     }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
+
+    fn feature_albedo(&self, cosine: Float) -> Color {
+        let f0 = Color::new(0.04, 0.04, 0.04).lerp(self.albedo, self.metallic);
+        let f = fresnel_schlick(cosine.clamp(0.0, 1.0), f0);
+        self.albedo * (1.0 - self.metallic) * (Color::new(1.0, 1.0, 1.0) - f) + f
+    }
 }
@@ -2,26 +2,92 @@ use crate::hittable::HitRecord;
 use crate::material::Material;
 use crate::material::brdf;
 use crate::ray::Ray;
-use utils::Color;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelength;
+use crate::validate;
+use utils::{Color, Float};
 
 use serde::{Deserialize, Serialize};
+
+/// Representative wavelengths of the red, green and blue channels, in nanometers, as
+/// for the conductors.
+const RGB_WAVELENGTHS: [Float; 3] = [630.0, 532.0, 465.0];
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Dielectric {
-    ir: f32, // Index of refraction
+    ir: Float, // Index of refraction
+    // Abbe number, enables dispersion (lower disperses more)
+    #[serde(default)]
+    abbe_number: Option<Float>,
 }
 
 impl Dielectric {
-    pub fn new(index_of_refraction: f32) -> Dielectric {
+    pub fn new(index_of_refraction: Float) -> Dielectric {
         Dielectric {
             ir: index_of_refraction,
+            abbe_number: None,
+        }
+    }
+
+    pub fn with_abbe_number(mut self, abbe_number: Float) -> Dielectric {
+        self.abbe_number = Some(abbe_number);
+        self
+    }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_positive(&mut problems, "index of refraction", self.ir);
+        if let Some(abbe_number) = self.abbe_number {
+            validate::check_positive(&mut problems, "Abbe number", abbe_number);
         }
+        problems
     }
 
-    fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
+    /// Index of refraction of a scattered ray, the weight of its channels and the
+    /// wavelength of its path.
+    ///
+    /// With dispersion, spectral paths refract at their sampled wavelength. RGB paths
+    /// refract at the representative wavelength of a channel: the first dispersion
+    /// picks it with `u`, weighting it by 3, and the path carries it alone from then
+    /// on, see `Wavelength::Channel`.
+    fn sample_index_of_refraction(&self, r_in: &Ray, u: Float) -> (Float, Color, Wavelength) {
+        let wavelength = r_in.wavelength();
+        let Some(abbe) = self.abbe_number else {
+            return (self.ir, Color::splat(1.0), wavelength);
+        };
+        let (channel, channel_weight) = match wavelength {
+            Wavelength::Sampled(lambda) => {
+                return (self.cauchy(abbe, lambda), Color::splat(1.0), wavelength);
+            }
+            Wavelength::Channel(channel) => (channel, 1.0),
+            Wavelength::Rgb => (((3.0 * u) as usize).min(2), 3.0),
+        };
+        let mut weight = Color::zero();
+        weight[channel] = channel_weight;
+        (
+            self.cauchy(abbe, RGB_WAVELENGTHS[channel]),
+            weight,
+            Wavelength::Channel(channel),
+        )
+    }
+
+    /// Index of refraction at a wavelength, from Cauchy's equation fitted to the
+    /// index at the sodium d line and the Abbe number.
+    fn cauchy(&self, abbe: Float, lambda: Float) -> Float {
+        const LAMBDA_D: Float = 587.6;
+        const LAMBDA_F: Float = 486.1;
+        const LAMBDA_C: Float = 656.3;
+        let inv_sq = |l: Float| 1.0 / (l * l);
+        let b = (self.ir - 1.0) / (abbe * (inv_sq(LAMBDA_F) - inv_sq(LAMBDA_C)));
+        self.ir + b * (inv_sq(lambda) - inv_sq(LAMBDA_D))
+    }
+
+    fn reflectance(cosine: Float, ref_idx: Float) -> Float {
         // Use Schlick's approximation for reflectance
         let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
         r0 = r0 * r0;
-        r0 + (1.0 - r0) * f32::powf(1.0 - cosine, 5.0)
+        r0 + (1.0 - r0) * Float::powf(1.0 - cosine, 5.0)
     }
 }
 
@@ -30,42 +96,49 @@ impl Material for Dielectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let (ir, weight, wavelength) = self.sample_index_of_refraction(r_in, sampler.get_1d());
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = utils::unit_vector(r_in.direction());
-        let cos_theta = f32::min(utils::dot(-unit_direction, rec.normal), 1.0);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+        let cos_theta = Float::min(utils::dot(-unit_direction, rec.normal), 1.0);
+        let sin_theta = Float::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > utils::random() {
-                utils::reflect(unit_direction, rec.normal)
-            } else {
-                utils::refract(unit_direction, rec.normal, refraction_ratio)
-            };
+        let direction = if cannot_refract
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            utils::reflect(unit_direction, rec.normal)
+        } else {
+            utils::refract(unit_direction, rec.normal, refraction_ratio)
+        };
+
+        *attenuation = weight;
+        *scattered = r_in.spawn(rec.p, direction).with_wavelength(wavelength);
+        true
+    }
 
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        *scattered = Ray::new(rec.p, direction);
+    fn is_specular(&self) -> bool {
         true
     }
+
+    fn albedo(&self) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
 
 pub struct ComplexDielectric {
-    pub ior: f32,
-    pub roughness: f32,
+    pub ior: Float,
+    pub roughness: Float,
     pub absorption: Option<Color>,
     pub thin: bool,
 }
 
 impl ComplexDielectric {
-    pub fn new(ior: f32, roughness: f32, absorption: Option<Color>, thin: bool) -> Self {
+    pub fn new(ior: Float, roughness: Float, absorption: Option<Color>, thin: bool) -> Self {
         Self {
             ior,
             roughness,
@@ -80,6 +153,7 @@ impl Material for ComplexDielectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
@@ -87,8 +161,13 @@ impl Material for ComplexDielectric {
         let view = -utils::unit_vector(r_in.direction());
         let n = if rec.front_face { normal } else { -normal };
 
-        // Sample half vector from GGX VNDF
-        let h = brdf::sample_vndf_ggx(view, self.roughness);
+        // Sample half vector from GGX VNDF, in the basis of the normal facing the ray
+        let onb = utils::Onb::from_normal(n);
+        let h = onb.to_world(brdf::sample_vndf_ggx(
+            onb.to_local(view),
+            self.roughness,
+            sampler.get_2d(),
+        ));
         let h = if utils::dot(h, n) < 0.0 { -h } else { h };
 
         let cos_theta = utils::dot(view, h).max(0.0);
@@ -96,7 +175,7 @@ impl Material for ComplexDielectric {
         let fresnel = brdf::fresnel_schlick(cos_theta, f0);
 
         // Decide between reflection and refraction
-        let reflect = utils::random() < fresnel.x();
+        let reflect = sampler.get_1d() < fresnel.r();
 
         let direction = if reflect {
             utils::reflect(view, h)
@@ -109,22 +188,26 @@ impl Material for ComplexDielectric {
             utils::refract(view, h, eta)
         };
 
-        *scattered = Ray::new(rec.p, direction);
+        *scattered = r_in.spawn(rec.p, direction);
 
         // Attenuation for transmission (Beer’s Law)
         if reflect || self.thin {
             *attenuation = Color::new(1.0, 1.0, 1.0);
         } else if let Some(abs) = self.absorption {
             let distance = 1.0; // Or distance inside medium, if available
-            *attenuation = Color::new(
-                (-abs.x() * distance).exp(),
-                (-abs.y() * distance).exp(),
-                (-abs.z() * distance).exp(),
-            );
+            *attenuation = (abs * -distance).exp();
         } else {
             *attenuation = Color::new(1.0, 1.0, 1.0);
         }
 
         true
     }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
@@ -2,35 +2,38 @@ use crate::hittable::HitRecord;
 use crate::material::Material;
 use crate::material::brdf::*;
 use crate::ray::Ray;
-use std::f32::consts::PI;
-use utils::Color;
+use crate::sampler::Sampler;
+use crate::validate;
+use utils::consts::PI;
+use utils::{Color, Float};
 use utils::{Lerp, dot, unit_vector};
 
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Disney {
     pub base_color: Color,
-    pub metallic: f32,
-    pub roughness: f32,
-    pub specular: f32,
-    pub specular_tint: f32,
-    pub sheen: f32,
-    pub sheen_tint: f32,
-    pub clearcoat: f32,
-    pub clearcoat_gloss: f32,
+    pub metallic: Float,
+    pub roughness: Float,
+    pub specular: Float,
+    pub specular_tint: Float,
+    pub sheen: Float,
+    pub sheen_tint: Float,
+    pub clearcoat: Float,
+    pub clearcoat_gloss: Float,
 }
 
 impl Disney {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         base_color: Color,
-        metallic: f32,
-        roughness: f32,
-        specular: f32,
-        specular_tint: f32,
-        sheen: f32,
-        sheen_tint: f32,
-        clearcoat: f32,
-        clearcoat_gloss: f32,
+        metallic: Float,
+        roughness: Float,
+        specular: Float,
+        specular_tint: Float,
+        sheen: Float,
+        sheen_tint: Float,
+        clearcoat: Float,
+        clearcoat_gloss: Float,
     ) -> Self {
         Disney {
             base_color,
@@ -44,14 +47,39 @@ impl Disney {
             clearcoat_gloss,
         }
     }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "base color", self.base_color);
+        for (name, value) in [
+            ("metallic", self.metallic),
+            ("roughness", self.roughness),
+            ("specular", self.specular),
+            ("specular tint", self.specular_tint),
+            ("sheen", self.sheen),
+            ("sheen tint", self.sheen_tint),
+            ("clearcoat", self.clearcoat),
+            ("clearcoat gloss", self.clearcoat_gloss),
+        ] {
+            validate::check_unit(&mut problems, name, value);
+        }
+        problems
+    }
 }
 
 impl Material for Disney {
-    fn scatter_importance(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f32)> {
+    fn scatter_importance(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
         let n = rec.normal;
         let v = -unit_vector(r_in.direction());
-        let l_local = utils::random_cosine_direction();
-        let l = utils::align_to_normal(l_local, n);
+        let (u1, u2) = sampler.get_2d();
+        let l_local = utils::cosine_direction(u1, u2);
+        let l = utils::Onb::from_normal(n).to_world(l_local);
 
         let h = unit_vector(v + l);
         let n_dot_l = dot(n, l).max(0.0);
@@ -79,7 +107,8 @@ impl Material for Disney {
         let sheen = sheen_color * schlick_weight(l_dot_h) * self.sheen;
 
         // Specular lobe
-        let a = self.roughness * self.roughness;
+        let roughness = regularize(self.roughness, rec.roughness_floor);
+        let a = roughness * roughness;
         let a2 = a * a;
         let denom = (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2);
         #[allow(non_snake_case)]
@@ -103,17 +132,42 @@ impl Material for Disney {
 
         let total = kd * diffuse + specular + sheen + Color::new(clearcoat, clearcoat, clearcoat);
 
-        let scattered = Ray::new(rec.p, l);
+        let scattered = r_in.spawn(rec.p, l);
         let pdf = n_dot_l / PI;
 
         Some((scattered, total * n_dot_l, pdf.max(1e-4)))
     }
 
-    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut Color, _: &mut Ray) -> bool {
+    fn scatter(
+        &self,
+        _: &Ray,
+        _: &HitRecord,
+        _: &mut dyn Sampler,
+        _: &mut Color,
+        _: &mut Ray,
+    ) -> bool {
         false // Only importance sampling supported
     }
 
     fn emitted(&self) -> Color {
         Color::zero()
     }
+
+    fn albedo(&self) -> Color {
+        self.base_color
+    }
+
+    fn feature_albedo(&self, cosine: Float) -> Color {
+        let tint = if self.base_color.max_component() > 0.0 {
+            self.base_color / self.base_color.max_component()
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+        let f0 = Color::new(0.04, 0.04, 0.04).lerp(tint, self.specular_tint) * self.specular;
+        let f = fresnel_schlick(
+            cosine.clamp(0.0, 1.0),
+            f0.lerp(self.base_color, self.metallic),
+        );
+        self.base_color * (1.0 - self.metallic) * (Color::new(1.0, 1.0, 1.0) - f) + f
+    }
 }
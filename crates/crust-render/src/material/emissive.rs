@@ -2,19 +2,21 @@ use crate::hittable::HitRecord;
 use crate::light::Light;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::validate;
 use serde::{Deserialize, Serialize};
-use utils::Color;
+use utils::{Color, Float};
 use utils::{Point3, Vec3};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Emissive {
     color: Color,
     position: Point3,
-    radius: f32,
+    radius: Float,
 }
 
 impl Emissive {
-    pub fn new(color: Color, position: Point3, radius: f32) -> Self {
+    pub fn new(color: Color, position: Point3, radius: Float) -> Self {
         Emissive {
             color,
             position,
@@ -27,9 +29,46 @@ impl Emissive {
     pub fn position(&self) -> Point3 {
         self.position
     }
-    pub fn radius(&self) -> f32 {
+    pub fn radius(&self) -> Float {
         self.radius
     }
+
+    /// Scales the sphere the light is sampled on by `factor`, its radiance being kept.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.position = factor * self.position;
+        self.radius *= factor;
+    }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "color", self.color);
+        validate::check_finite(&mut problems, "position", self.position);
+        if !(self.radius.is_finite() && self.radius >= 0.0) {
+            problems.push(format!("the radius {} is negative", self.radius));
+        }
+        problems
+    }
+
+    /// The light of an emissive mesh, sampled as a sphere of the area of the mesh at
+    /// its center.
+    pub(crate) fn for_mesh(color: Color, vertices: &[Point3], indices: &[u32]) -> Self {
+        let mut area = 0.0;
+        let mut center = Point3::zero();
+        for t in indices.chunks_exact(3) {
+            let (v0, v1, v2) = (
+                vertices[t[0] as usize],
+                vertices[t[1] as usize],
+                vertices[t[2] as usize],
+            );
+            let triangle = 0.5 * utils::cross(v1 - v0, v2 - v0).length();
+            area += triangle;
+            center += triangle * (v0 + v1 + v2) / 3.0;
+        }
+        let center = if area > 0.0 { center / area } else { center };
+        let radius = (area / (4.0 * utils::consts::PI)).sqrt();
+        Emissive::new(color, center, radius)
+    }
 }
 
 impl Material for Emissive {
@@ -37,6 +76,7 @@ impl Material for Emissive {
         &self,
         _r_in: &Ray,
         _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
         _attenuation: &mut Color,
         _scattered: &mut Ray,
     ) -> bool {
@@ -47,7 +87,12 @@ impl Material for Emissive {
         self.color
     }
 
-    fn scatter_importance(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color, f32)> {
+    fn scatter_importance(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
         None
     }
 }
@@ -56,9 +101,9 @@ impl Light for Emissive {
     fn sample(&self) -> Point3 {
         self.position + self.radius * utils::random_unit_vector()
     }
-    fn sample_cmj(&self, u: f32, v: f32) -> Point3 {
+    fn sample_cmj(&self, u: Float, v: Float) -> Point3 {
         // Map (u, v) on a sphere (uniform sphere sampling)
-        let theta = 2.0 * std::f32::consts::PI * u;
+        let theta = 2.0 * utils::consts::PI * u;
         let phi = (1.0 - 2.0 * v).acos();
         let x = phi.sin() * theta.cos();
         let y = phi.sin() * theta.sin();
@@ -67,19 +112,39 @@ impl Light for Emissive {
         self.position + self.radius * Vec3::new(x, y, z)
     }
 
-    fn pdf(&self, hit_point: Point3, light_point: Point3) -> f32 {
+    fn pdf(&self, hit_point: Point3, light_point: Point3) -> Float {
         let direction = light_point - hit_point;
         let distance_squared = direction.length_squared();
         let normal = utils::unit_vector(direction);
-        let cosine = f32::max(
+        let cosine = Float::max(
             utils::dot(normal, utils::unit_vector(light_point - hit_point)),
             0.0,
         );
-        let area = 4.0 * std::f32::consts::PI * self.radius * self.radius;
+        let area = 4.0 * utils::consts::PI * self.radius * self.radius;
         distance_squared / (cosine * area + 1e-4)
     }
 
     fn color(&self) -> Color {
         self.color
     }
+
+    fn sample_emission(
+        &self,
+        u_pos: (Float, Float),
+        u_dir: (Float, Float),
+    ) -> Option<(Ray, Color)> {
+        let point = self.sample_cmj(u_pos.0, u_pos.1);
+        let normal = (point - self.position) / self.radius;
+
+        // Cosine-weighted direction around the outward normal
+        let r = u_dir.1.sqrt();
+        let phi = 2.0 * utils::consts::PI * u_dir.0;
+        let local = Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u_dir.1).sqrt());
+        let direction = utils::Onb::from_normal(normal).to_world(local);
+
+        // Le * cos / (pdf_area * pdf_dir) with pdf_area = 1 / area and pdf_dir = cos / pi
+        let area = 4.0 * utils::consts::PI * self.radius * self.radius;
+        let power = self.color * area * utils::consts::PI;
+        Some((Ray::new(point + 1e-3 * normal, direction), power))
+    }
 }
@@ -0,0 +1,121 @@
+use crate::hittable::HitRecord;
+use crate::material::{
+    BlinnPhong, CookTorrance, Dielectric, Disney, Emissive, Lambertian, Material, Metal,
+};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use utils::{Color, Float};
+
+/// A material of any kind, dispatched by a `match` instead of a virtual call.
+///
+/// With the `enum-dispatch` feature, the objects hold their material as a `MaterialKind`
+/// and the integrators call it statically in the inner loop, see `SceneMaterial`.
+#[derive(Debug, Clone)]
+pub enum MaterialKind {
+    Lambertian(Lambertian),
+    Metal(Metal),
+    Dielectric(Dielectric),
+    BlinnPhong(BlinnPhong),
+    CookTorrance(CookTorrance),
+    Emissive(Emissive),
+    Disney(Disney),
+}
+
+/// Calls a method of the `Material` trait on the material of any kind.
+macro_rules! dispatch {
+    ($kind:expr, $m:ident => $call:expr) => {
+        match $kind {
+            MaterialKind::Lambertian($m) => $call,
+            MaterialKind::Metal($m) => $call,
+            MaterialKind::Dielectric($m) => $call,
+            MaterialKind::BlinnPhong($m) => $call,
+            MaterialKind::CookTorrance($m) => $call,
+            MaterialKind::Emissive($m) => $call,
+            MaterialKind::Disney($m) => $call,
+        }
+    };
+}
+
+/// The methods of `Material`, dispatched statically and callable without the trait in
+/// scope, as on the trait objects they replace.
+impl MaterialKind {
+    #[inline]
+    pub fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        dispatch!(self, m => m.scatter(r_in, rec, sampler, attenuation, scattered))
+    }
+
+    #[inline]
+    pub fn scatter_importance(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
+        dispatch!(self, m => m.scatter_importance(r_in, rec, sampler))
+    }
+
+    #[inline]
+    pub fn emitted(&self) -> Color {
+        dispatch!(self, m => m.emitted())
+    }
+
+    #[inline]
+    pub fn is_specular(&self) -> bool {
+        dispatch!(self, m => m.is_specular())
+    }
+
+    #[inline]
+    pub fn albedo(&self) -> Color {
+        dispatch!(self, m => m.albedo())
+    }
+
+    #[inline]
+    pub fn feature_albedo(&self, cosine: Float) -> Color {
+        dispatch!(self, m => m.feature_albedo(cosine))
+    }
+}
+
+impl Material for MaterialKind {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        MaterialKind::scatter(self, r_in, rec, sampler, attenuation, scattered)
+    }
+
+    fn scatter_importance(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
+        MaterialKind::scatter_importance(self, r_in, rec, sampler)
+    }
+
+    fn emitted(&self) -> Color {
+        MaterialKind::emitted(self)
+    }
+
+    fn is_specular(&self) -> bool {
+        MaterialKind::is_specular(self)
+    }
+
+    fn albedo(&self) -> Color {
+        MaterialKind::albedo(self)
+    }
+
+    fn feature_albedo(&self, cosine: Float) -> Color {
+        MaterialKind::feature_albedo(self, cosine)
+    }
+}
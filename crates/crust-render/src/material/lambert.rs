@@ -1,6 +1,8 @@
 use crate::hittable::HitRecord;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::validate;
 use serde::{Deserialize, Serialize};
 use utils::Color;
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -12,17 +14,26 @@ impl Lambertian {
     pub fn new(a: Color) -> Lambertian {
         Lambertian { albedo: a }
     }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "albedo", self.albedo);
+        problems
+    }
 }
 
 impl Material for Lambertian {
     fn scatter(
         &self,
-        _r_in: &Ray,
+        r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let mut scatter_direction = rec.normal + utils::random_unit_vector();
+        let (u, v) = sampler.get_2d();
+        let mut scatter_direction = rec.normal + utils::sphere_direction(u, v);
 
         // Catch degenerate scatter direction
         if scatter_direction.near_zero() {
@@ -30,7 +41,11 @@ impl Material for Lambertian {
         }
 
         *attenuation = self.albedo;
-        *scattered = Ray::new(rec.p, scatter_direction);
+        *scattered = r_in.spawn(rec.p, scatter_direction);
         true
     }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
 }
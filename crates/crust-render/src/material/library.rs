@@ -0,0 +1,66 @@
+use crate::error::RenderError;
+use crate::material::MaterialType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use tracing::error;
+
+/// A library of named materials, e.g. `materials.ron`, shared by the scenes whose
+/// objects reference them with `Named("name")`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct MaterialLibrary {
+    materials: BTreeMap<String, MaterialType>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, material: MaterialType) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MaterialType> {
+        self.materials.get(name)
+    }
+
+    /// Writes the library, as JSON for a `.json` path and as RON otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), RenderError> {
+        let r = if crate::document::is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| e.to_string())
+        };
+        let r = r.map_err(|e| {
+            RenderError::Serialize(format!("Failed to serialize MaterialLibrary: {e}"))
+        })?;
+        let file = std::fs::File::create(path).map_err(|e| RenderError::file(path, e))?;
+        let mut writer = std::io::BufWriter::new(file);
+        writer
+            .write_all(r.as_bytes())
+            .and_then(|()| writer.flush())
+            .map_err(|e| RenderError::file(path, e))
+    }
+
+    /// Reads a library, from JSON for a `.json` path and from RON otherwise.
+    pub fn read(path: &Path) -> Result<Self, RenderError> {
+        let file = std::fs::File::open(path).map_err(|e| RenderError::file(path, e))?;
+        let reader = std::io::BufReader::new(file);
+        let library = if crate::document::is_json(path) {
+            serde_json::from_reader(reader).map_err(|e| e.to_string())
+        } else {
+            ron::de::from_reader(reader).map_err(|e| e.to_string())
+        };
+        library.map_err(|e| {
+            error!("Failed to deserialize MaterialLibrary: {}", e);
+            RenderError::Parse(format!(
+                "Failed to deserialize MaterialLibrary {}: {e}",
+                path.display()
+            ))
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
-use utils::Color;
+use crate::sampler::Sampler;
+use utils::{Color, Float};
 
 /// The `Material` trait defines the behavior of materials in the ray tracing system.
 /// Materials determine how rays interact with surfaces, including scattering and emission.
@@ -10,6 +11,7 @@ pub trait Material: Send + Sync {
     /// # Parameters
     /// - `r_in`: The incoming ray.
     /// - `rec`: The hit record containing information about the intersection.
+    /// - `sampler`: The sampler providing the sample values of the scattered direction.
     /// - `attenuation`: A mutable reference to the color attenuation (output).
     /// - `scattered`: A mutable reference to the scattered ray (output).
     ///
@@ -19,6 +21,7 @@ pub trait Material: Send + Sync {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool;
@@ -32,16 +35,22 @@ pub trait Material: Send + Sync {
     /// # Parameters
     /// - `r_in`: The incoming ray.
     /// - `rec`: The hit record containing information about the intersection.
+    /// - `sampler`: The sampler providing the sample values of the scattered direction.
     ///
     /// # Returns
     /// - `Some((scattered_ray, attenuation, pdf))` if importance sampling is supported.
     /// - `None` if the material does not scatter the ray.
-    fn scatter_importance(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f32)> {
+    fn scatter_importance(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Float)> {
         // Default fallback for materials that don't support importance sampling
         let mut attenuation = Color::default();
         let mut scattered = Ray::default();
-        if self.scatter(r_in, rec, &mut attenuation, &mut scattered) {
-            let cosine = f32::max(
+        if self.scatter(r_in, rec, sampler, &mut attenuation, &mut scattered) {
+            let cosine = Float::max(
                 utils::dot(rec.normal, utils::unit_vector(scattered.direction())),
                 0.0,
             );
@@ -61,4 +70,42 @@ pub trait Material: Send + Sync {
     fn emitted(&self) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+
+    /// Indicates whether the material only scatters in discrete directions (mirror, glass).
+    ///
+    /// Such surfaces cannot be evaluated for an arbitrary pair of directions, so
+    /// integrators gathering light at surfaces (e.g. photon mapping) trace through them.
+    ///
+    /// # Returns
+    /// - `true` for perfectly specular materials, `false` otherwise.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Returns the overall reflectance color of the material.
+    ///
+    /// This is used wherever a diffuse approximation of the surface is needed.
+    /// By default, it returns black.
+    ///
+    /// # Returns
+    /// - A `Color` representing the albedo.
+    fn albedo(&self) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Returns the reflectance of the surface seen from a direction, its diffuse and
+    /// specular lobes together, for the albedo AOV guiding the denoisers.
+    ///
+    /// The denoisers divide the radiance by it, so that the highlights of metals and
+    /// coatings are not taken for noise once the texture is removed.
+    /// By default, it returns the `albedo`.
+    ///
+    /// # Parameters
+    /// - `cosine`: The cosine between the shading normal and the direction to the viewer.
+    ///
+    /// # Returns
+    /// - A `Color` in [0, 1] per channel.
+    fn feature_albedo(&self, _cosine: Float) -> Color {
+        self.albedo()
+    }
 }
@@ -1,39 +1,183 @@
 use crate::hittable::HitRecord;
-use crate::material::Material;
+use crate::material::{Material, regularize};
 use crate::ray::Ray;
-use utils::Color;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelength;
+use crate::validate;
+use utils::{Color, Float};
 
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Metal {
     albedo: Color,
-    fuzz: f32,
+    fuzz: Float,
+    // Measured conductor replacing the albedo by its Fresnel reflectance
+    #[serde(default)]
+    conductor: Option<Conductor>,
 }
 
 impl Metal {
-    pub fn new(a: Color, f: f32) -> Metal {
+    pub fn new(a: Color, f: Float) -> Metal {
         Metal {
             albedo: a,
             fuzz: if f < 1.0 { f } else { 1.0 },
+            conductor: None,
+        }
+    }
+
+    pub fn with_conductor(mut self, conductor: Conductor) -> Metal {
+        self.conductor = Some(conductor);
+        self
+    }
+
+    /// The problems of the parameters, for the validation of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "albedo", self.albedo);
+        validate::check_unit(&mut problems, "fuzz", self.fuzz);
+        problems
+    }
+
+    /// Reflectance for a given incident cosine.
+    ///
+    /// Conductors are evaluated at the sampled wavelength of spectral paths, and at
+    /// representative red, green and blue wavelengths otherwise.
+    fn reflectance(&self, cosine: Float, wavelength: Wavelength) -> Color {
+        let Some(conductor) = self.conductor else {
+            return self.albedo;
+        };
+        match wavelength {
+            Wavelength::Sampled(lambda) => {
+                let r = conductor.reflectance(cosine, lambda);
+                Color::new(r, r, r)
+            }
+            Wavelength::Rgb | Wavelength::Channel(_) => conductor.rgb_reflectance(cosine),
         }
     }
 }
 
+/// Metals with measured complex indices of refraction.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Conductor {
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+}
+
+impl Conductor {
+    /// Complex index of refraction (eta, k) from 400 to 700 nm, every 50 nm.
+    fn table(&self) -> [(Float, Float); 7] {
+        match self {
+            Conductor::Gold => [
+                (1.66, 1.96),
+                (1.50, 1.88),
+                (0.97, 1.87),
+                (0.43, 2.45),
+                (0.23, 2.98),
+                (0.17, 3.15),
+                (0.16, 3.80),
+            ],
+            Conductor::Silver => [
+                (0.17, 1.95),
+                (0.14, 2.57),
+                (0.13, 3.01),
+                (0.12, 3.35),
+                (0.12, 3.73),
+                (0.14, 4.15),
+                (0.14, 4.52),
+            ],
+            Conductor::Copper => [
+                (1.18, 2.21),
+                (1.13, 2.56),
+                (1.12, 2.60),
+                (1.02, 2.58),
+                (0.27, 3.35),
+                (0.21, 3.67),
+                (0.21, 4.05),
+            ],
+            Conductor::Aluminium => [
+                (0.49, 4.86),
+                (0.62, 5.47),
+                (0.77, 6.08),
+                (0.96, 6.69),
+                (1.20, 7.26),
+                (1.47, 7.79),
+                (1.83, 8.31),
+            ],
+        }
+    }
+
+    /// Fresnel reflectance at representative red, green and blue wavelengths.
+    pub fn rgb_reflectance(&self, cosine: Float) -> Color {
+        Color::new(
+            self.reflectance(cosine, 630.0),
+            self.reflectance(cosine, 532.0),
+            self.reflectance(cosine, 465.0),
+        )
+    }
+
+    /// Unpolarized Fresnel reflectance of the conductor at a wavelength in nanometers.
+    pub fn reflectance(&self, cosine: Float, lambda: Float) -> Float {
+        let table = self.table();
+        let x = ((lambda - 400.0) / 50.0).clamp(0.0, (table.len() - 1) as Float);
+        let i = (x as usize).min(table.len() - 2);
+        let t = x - i as Float;
+        let eta = table[i].0 + t * (table[i + 1].0 - table[i].0);
+        let k = table[i].1 + t * (table[i + 1].1 - table[i].1);
+
+        let cos2 = cosine.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    }
+}
+
 impl Material for Metal {
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        sampler: &mut dyn Sampler,
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let reflected = utils::reflect(utils::unit_vector(r_in.direction()), rec.normal);
+        let unit_direction = utils::unit_vector(r_in.direction());
+        let reflected = utils::reflect(unit_direction, rec.normal);
+
+        // A point of the unit ball, its radius the cube root of a uniform value
+        let (u, v) = sampler.get_2d();
+        let fuzz = sampler.get_1d().cbrt() * utils::sphere_direction(u, v);
 
-        *attenuation = self.albedo;
-        *scattered = Ray::new(
+        *attenuation = self.reflectance(-utils::dot(unit_direction, rec.normal), r_in.wavelength());
+        *scattered = r_in.spawn(
             rec.p,
-            reflected + self.fuzz * utils::random_in_unit_sphere(),
+            reflected + regularize(self.fuzz, rec.roughness_floor) * fuzz,
         );
         utils::dot(scattered.direction(), rec.normal) > 0.0
     }
+
+    fn is_specular(&self) -> bool {
+        // A mirror widened by the roughness floor is still followed through its lobe
+        self.fuzz == 0.0
+    }
+
+    fn albedo(&self) -> Color {
+        self.conductor
+            .map_or(self.albedo, |conductor| conductor.rgb_reflectance(1.0))
+    }
+
+    fn feature_albedo(&self, cosine: Float) -> Color {
+        self.conductor.map_or(self.albedo, |conductor| {
+            conductor.rgb_reflectance(cosine.clamp(0.0, 1.0))
+        })
+    }
 }
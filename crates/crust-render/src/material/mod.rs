@@ -1,9 +1,10 @@
+#[allow(clippy::module_inception)]
 mod material;
 pub use material::Material;
 mod lambert;
 pub use lambert::Lambertian;
 mod metal;
-pub use metal::Metal;
+pub use metal::{Conductor, Metal};
 mod dielectric;
 pub use dielectric::{ComplexDielectric, Dielectric};
 mod blinn_phong;
@@ -13,10 +14,20 @@ pub use cook_torrance::CookTorrance;
 mod emissive;
 pub use emissive::Emissive;
 mod brdf;
-pub use brdf::{fresnel_schlick, geometry_schlick_ggx, pdf_vndf_ggx, sample_vndf_ggx};
+pub(crate) use brdf::regularize;
+pub use brdf::{
+    LANES, fresnel_schlick, fresnel_schlick_x8, geometry_schlick_ggx, geometry_smith_x8,
+    ggx_distribution, ggx_distribution_x8, pdf_vndf_ggx, sample_vndf_ggx,
+};
 mod disney;
 pub use disney::Disney;
+mod library;
+pub use library::MaterialLibrary;
+mod kind;
+pub use kind::MaterialKind;
 use serde::{Deserialize, Serialize};
+use tracing::error;
+use utils::{Color, Float};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum MaterialType {
@@ -27,19 +38,93 @@ pub enum MaterialType {
     CookTorrance(CookTorrance),
     Emissive(Emissive),
     Disney(Disney),
+    /// A material of the material library of the scene, by name, resolved when the
+    /// scene is read.
+    Named(String),
 }
 use std::sync::Arc;
 
+/// The material held by the objects and borrowed by their hits: a `MaterialKind`,
+/// dispatched statically, with the `enum-dispatch` feature, a trait object otherwise.
+#[cfg(feature = "enum-dispatch")]
+pub type SceneMaterial = MaterialKind;
+/// The material held by the objects and borrowed by their hits: a `MaterialKind`,
+/// dispatched statically, with the `enum-dispatch` feature, a trait object otherwise.
+#[cfg(not(feature = "enum-dispatch"))]
+pub type SceneMaterial = dyn Material;
+
 impl MaterialType {
-    pub fn get_material(&self) -> Arc<dyn Material> {
+    #[cfg(feature = "enum-dispatch")]
+    pub fn get_material(&self) -> Arc<SceneMaterial> {
+        Arc::new(self.material_kind())
+    }
+    #[cfg(not(feature = "enum-dispatch"))]
+    pub fn get_material(&self) -> Arc<SceneMaterial> {
+        match self.material_kind() {
+            MaterialKind::Lambertian(m) => Arc::new(m),
+            MaterialKind::Metal(m) => Arc::new(m),
+            MaterialKind::Dielectric(m) => Arc::new(m),
+            MaterialKind::BlinnPhong(m) => Arc::new(m),
+            MaterialKind::CookTorrance(m) => Arc::new(m),
+            MaterialKind::Emissive(m) => Arc::new(m),
+            MaterialKind::Disney(m) => Arc::new(m),
+        }
+    }
+    /// The material, of its kind, a named one not resolved being a grey Lambertian.
+    pub fn material_kind(&self) -> MaterialKind {
+        match self {
+            MaterialType::Lambertian(m) => MaterialKind::Lambertian(m.clone()),
+            MaterialType::Metal(m) => MaterialKind::Metal(m.clone()),
+            MaterialType::Dielectric(m) => MaterialKind::Dielectric(m.clone()),
+            MaterialType::BlinnPhong(m) => MaterialKind::BlinnPhong(m.clone()),
+            MaterialType::CookTorrance(m) => MaterialKind::CookTorrance(m.clone()),
+            MaterialType::Emissive(m) => MaterialKind::Emissive(m.clone()),
+            MaterialType::Disney(m) => MaterialKind::Disney(m.clone()),
+            MaterialType::Named(name) => {
+                error!(
+                    "Material {:?} is not resolved from a material library",
+                    name
+                );
+                MaterialKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+            }
+        }
+    }
+    /// The name of the kind of material, e.g. `Lambertian`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MaterialType::Lambertian(_) => "Lambertian",
+            MaterialType::Metal(_) => "Metal",
+            MaterialType::Dielectric(_) => "Dielectric",
+            MaterialType::BlinnPhong(_) => "BlinnPhong",
+            MaterialType::CookTorrance(_) => "CookTorrance",
+            MaterialType::Emissive(_) => "Emissive",
+            MaterialType::Disney(_) => "Disney",
+            MaterialType::Named(_) => "Named",
+        }
+    }
+    /// Scales the lengths of the material, the light of an emissive one, by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        if let MaterialType::Emissive(emissive) = self {
+            emissive.rescale(factor);
+        }
+    }
+
+    /// The problems of the parameters, e.g. a roughness out of [0, 1], for the
+    /// validation of the scene.
+    pub fn problems(&self) -> Vec<String> {
         match self {
-            MaterialType::Lambertian(m) => Arc::new((*m).clone()),
-            MaterialType::Metal(m) => Arc::new((*m).clone()),
-            MaterialType::Dielectric(m) => Arc::new((*m).clone()),
-            MaterialType::BlinnPhong(m) => Arc::new((*m).clone()),
-            MaterialType::CookTorrance(m) => Arc::new((*m).clone()),
-            MaterialType::Emissive(m) => Arc::new((*m).clone()),
-            MaterialType::Disney(m) => Arc::new((*m).clone()),
+            MaterialType::Lambertian(m) => m.problems(),
+            MaterialType::Metal(m) => m.problems(),
+            MaterialType::Dielectric(m) => m.problems(),
+            MaterialType::BlinnPhong(m) => m.problems(),
+            MaterialType::CookTorrance(m) => m.problems(),
+            MaterialType::Emissive(m) => m.problems(),
+            MaterialType::Disney(m) => m.problems(),
+            MaterialType::Named(name) => {
+                vec![format!(
+                    "the material {name:?} is not in the material library"
+                )]
+            }
         }
     }
     pub fn is_emissive(&self) -> bool {
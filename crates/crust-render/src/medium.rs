@@ -0,0 +1,533 @@
+use crate::ray::Ray;
+use crate::validate;
+use serde::{Deserialize, Serialize};
+use utils::{Color, Float, Point3, Vec3};
+
+/// The spatial variation of a medium's density, with values in [0, 1].
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Density {
+    /// Constant density everywhere inside the bounds.
+    #[default]
+    Homogeneous,
+    /// Ground fog, thinning out exponentially above the `base` height.
+    HeightFog { base: Float, falloff: Float },
+    /// Fractal value noise with its mid-range stretched to carve out wisps of smoke.
+    Noise { scale: Float, octaves: u32 },
+    /// Cumulus clouds: fractal value noise above `1 - coverage` shapes them, and
+    /// Worley noise `erosion` billows their edges. Inside a medium, they thin out
+    /// towards the bottom and top of its bounds.
+    Cloud {
+        scale: Float,
+        octaves: u32,
+        coverage: Float,
+        erosion: Float,
+    },
+}
+
+impl Density {
+    /// Evaluates the density at a point.
+    pub fn eval(&self, p: Point3) -> Float {
+        match *self {
+            Density::Homogeneous => 1.0,
+            Density::HeightFog { base, falloff } => (-(p.y() - base).max(0.0) * falloff).exp(),
+            Density::Noise { scale, octaves } => {
+                let n = ((fbm(p / scale.max(1e-4), octaves.max(1)) - 0.3) / 0.4).clamp(0.0, 1.0);
+                n * n * (3.0 - 2.0 * n)
+            }
+            Density::Cloud {
+                scale,
+                octaves,
+                coverage,
+                erosion,
+            } => {
+                let p = p / scale.max(1e-4);
+                let coverage = coverage.clamp(1e-4, 1.0);
+                // The noise, mostly in [0.3, 0.7], stretched to [0, 1] as for the smoke
+                let n = ((fbm(p, octaves.max(1)) - 0.3) / 0.4).clamp(0.0, 1.0);
+                let shape = (n - (1.0 - coverage)) / coverage;
+                if shape <= 0.0 {
+                    return 0.0;
+                }
+                // Higher frequencies of cells, eating into the thin parts of the shape
+                let billows =
+                    0.625 * worley(4.0 * p) + 0.25 * worley(8.0 * p) + 0.125 * worley(16.0 * p);
+                let floor = erosion.clamp(0.0, 0.99) * (1.0 - billows);
+                ((shape - floor) / (1.0 - floor)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// A participating medium filling an axis-aligned box, such as fog or smoke.
+///
+/// The extinction is a scalar scaled by the density, so a single majorant bounds the
+/// whole box; the single-scattering albedo gives the medium its color. Light scatters
+/// following the Henyey-Greenstein phase function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Medium {
+    minimum: Point3,
+    maximum: Point3,
+    /// Extinction coefficient where the density is 1.
+    sigma_t: Float,
+    /// Ratio of scattering to extinction.
+    albedo: Color,
+    /// Henyey-Greenstein asymmetry, from -1 (backward) to 1 (forward scattering).
+    #[serde(default)]
+    g: Float,
+    #[serde(default)]
+    density: Density,
+}
+
+impl Medium {
+    pub fn new(minimum: Point3, maximum: Point3, sigma_t: Float, albedo: Color) -> Self {
+        Self {
+            minimum,
+            maximum,
+            sigma_t,
+            albedo,
+            g: 0.0,
+            density: Density::Homogeneous,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, g: Float) -> Self {
+        self.g = g.clamp(-0.99, 0.99);
+        self
+    }
+
+    pub fn with_density(mut self, density: Density) -> Self {
+        self.density = density;
+        self
+    }
+
+    /// Scales the lengths of the medium by `factor`: its bounds and density, and its
+    /// extinction, per length, inversely.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.minimum = factor * self.minimum;
+        self.maximum = factor * self.maximum;
+        self.sigma_t /= factor;
+        self.density = match self.density {
+            Density::Homogeneous => Density::Homogeneous,
+            Density::HeightFog { base, falloff } => Density::HeightFog {
+                base: factor * base,
+                falloff: falloff / factor,
+            },
+            Density::Noise { scale, octaves } => Density::Noise {
+                scale: factor * scale,
+                octaves,
+            },
+            Density::Cloud {
+                scale,
+                octaves,
+                coverage,
+                erosion,
+            } => Density::Cloud {
+                scale: factor * scale,
+                octaves,
+                coverage,
+                erosion,
+            },
+        };
+    }
+
+    /// The problems of the medium, e.g. inverted bounds, for the validation of the
+    /// scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_finite(&mut problems, "minimum", self.minimum);
+        validate::check_finite(&mut problems, "maximum", self.maximum);
+        let extent = self.maximum - self.minimum;
+        if extent.x() < 0.0 || extent.y() < 0.0 || extent.z() < 0.0 {
+            problems.push("the minimum exceeds the maximum".to_string());
+        }
+        if !(self.sigma_t.is_finite() && self.sigma_t >= 0.0) {
+            problems.push(format!("the extinction {} is negative", self.sigma_t));
+        }
+        validate::check_color(&mut problems, "albedo", self.albedo);
+        if !(-1.0..=1.0).contains(&self.g) {
+            problems.push(format!("the anisotropy {} is out of [-1, 1]", self.g));
+        }
+        if let Density::Cloud {
+            coverage, erosion, ..
+        } = self.density
+        {
+            if !(0.0..=1.0).contains(&coverage) {
+                problems.push(format!("the cloud coverage {coverage} is out of [0, 1]"));
+            }
+            if !(0.0..=1.0).contains(&erosion) {
+                problems.push(format!("the cloud erosion {erosion} is out of [0, 1]"));
+            }
+        }
+        problems
+    }
+
+    pub fn albedo(&self) -> Color {
+        self.albedo
+    }
+
+    pub fn g(&self) -> Float {
+        self.g.clamp(-0.99, 0.99)
+    }
+
+    /// The density at a point, the clouds rounded off at the bottom and top of the
+    /// bounds.
+    fn density_at(&self, p: Point3) -> Float {
+        let density = self.density.eval(p);
+        if let Density::Cloud { .. } = self.density {
+            let h = ((p.y() - self.minimum.y()) / (self.maximum.y() - self.minimum.y()))
+                .clamp(0.0, 1.0);
+            density * (4.0 * h * (1.0 - h)).min(1.0)
+        } else {
+            density
+        }
+    }
+
+    /// Clips the segment `[0, t_max]` of a unit-direction ray against the bounds.
+    fn overlap(&self, origin: Point3, direction: Vec3, t_max: Float) -> Option<(Float, Float)> {
+        let (mut t0, mut t1): (Float, Float) = (0.0, t_max);
+        for a in 0..3 {
+            let inv_d = 1.0 / direction[a];
+            let mut near = (self.minimum[a] - origin[a]) * inv_d;
+            let mut far = (self.maximum[a] - origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    /// Samples the distance to the first real collision by delta tracking.
+    ///
+    /// # Returns
+    /// - `Some(t)` with the distance of the collision, `None` if the ray passes through.
+    fn sample_distance(&self, origin: Point3, direction: Vec3, t_max: Float) -> Option<Float> {
+        if self.sigma_t <= 0.0 {
+            return None;
+        }
+        let (t0, t1) = self.overlap(origin, direction, t_max)?;
+        let mut t = t0;
+        loop {
+            t -= (1.0 - utils::random()).ln() / self.sigma_t;
+            if t >= t1 {
+                return None;
+            }
+            // Collisions with the fictitious null density are rejected
+            if matches!(self.density, Density::Homogeneous)
+                || utils::random() < self.density_at(origin + t * direction)
+            {
+                return Some(t);
+            }
+        }
+    }
+
+    /// Estimates the transmittance of a segment by ratio tracking.
+    fn transmittance(&self, origin: Point3, direction: Vec3, t_max: Float) -> Float {
+        if self.sigma_t <= 0.0 {
+            return 1.0;
+        }
+        let Some((t0, t1)) = self.overlap(origin, direction, t_max) else {
+            return 1.0;
+        };
+        if matches!(self.density, Density::Homogeneous) {
+            return (-self.sigma_t * (t1 - t0)).exp();
+        }
+        let mut transmittance = 1.0;
+        let mut t = t0;
+        loop {
+            t -= (1.0 - utils::random()).ln() / self.sigma_t;
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(origin + t * direction);
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+        }
+    }
+}
+
+/// A fog filling the whole scene, for atmosphere without bounding a medium.
+///
+/// Its extinction is `density` at the `height`, thinning out exponentially above it
+/// by `falloff` per unit of height, and thickening below, uniform with a falloff of
+/// 0. Rather than scattering the paths, the fog is integrated in closed form along
+/// each segment: the radiance is attenuated by its transmittance and the fog `color`
+/// blended in as it thickens.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Fog {
+    /// Radiance of the fog where it is opaque.
+    color: Color,
+    /// Extinction coefficient at the `height`.
+    density: Float,
+    #[serde(default)]
+    height: Float,
+    #[serde(default)]
+    falloff: Float,
+}
+
+impl Fog {
+    /// A uniform fog of the extinction `density`.
+    pub fn new(color: Color, density: Float) -> Self {
+        Self {
+            color,
+            density,
+            height: 0.0,
+            falloff: 0.0,
+        }
+    }
+
+    /// Thins the fog out above `height`, its density dividing by `e` every
+    /// `1 / falloff` of height.
+    pub fn with_height_falloff(mut self, height: Float, falloff: Float) -> Self {
+        self.height = height;
+        self.falloff = falloff;
+        self
+    }
+
+    /// Scales the lengths of the fog by `factor`, as `Medium::rescale`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.density /= factor;
+        self.height *= factor;
+        self.falloff /= factor;
+    }
+
+    /// The problems of the fog, e.g. a negative density, for the validation of the
+    /// scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate::check_color(&mut problems, "color", self.color);
+        if !(self.density.is_finite() && self.density >= 0.0) {
+            problems.push(format!("the density {} is negative", self.density));
+        }
+        if !self.height.is_finite() {
+            problems.push(format!("the height {} is not finite", self.height));
+        }
+        if !(self.falloff.is_finite() && self.falloff >= 0.0) {
+            problems.push(format!("the falloff {} is negative", self.falloff));
+        }
+        problems
+    }
+
+    /// The optical depth of the segment `[0, distance]` of a unit-direction ray.
+    fn optical_depth(&self, origin: Point3, direction: Vec3, distance: Float) -> Float {
+        // Keeps the products finite for the rays escaping the scene
+        let distance = distance.min(Float::MAX);
+        let at_origin = self.density * (-self.falloff * (origin.y() - self.height)).exp();
+        // No fog, or none left this high, not to take 0 times the infinite growth of
+        // the exponential down the escaping rays for a NaN
+        if at_origin == 0.0 || distance == 0.0 {
+            return 0.0;
+        }
+        let k = self.falloff * direction.y();
+        if k.abs() < 1e-6 {
+            at_origin * distance
+        } else {
+            at_origin * (1.0 - (-k * distance).exp()) / k
+        }
+    }
+
+    /// The transmittance of the fog along a ray up to the parameter `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_max: Float) -> Float {
+        let length = ray.direction().length();
+        let depth = self.optical_depth(ray.origin(), ray.direction() / length, t_max * length);
+        (-depth).exp()
+    }
+
+    /// The transmittance of the fog along a ray up to the parameter `t_max`, and
+    /// the radiance of the fog seen along it.
+    pub fn attenuation(&self, ray: &Ray, t_max: Float) -> (Float, Color) {
+        let transmittance = self.transmittance(ray, t_max);
+        (transmittance, (1.0 - transmittance) * self.color)
+    }
+}
+
+/// The `MediumList` struct holds the participating media of the scene, and its fog.
+///
+/// Media may overlap: their extinctions add up along a ray.
+#[derive(Default)]
+pub struct MediumList {
+    pub media: Vec<Medium>,
+    pub fog: Option<Fog>,
+}
+
+impl MediumList {
+    /// Creates a new, empty `MediumList`.
+    pub fn new() -> Self {
+        Self {
+            media: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Adds a medium to the list.
+    pub fn add(&mut self, medium: Medium) {
+        self.media.push(medium);
+    }
+
+    pub fn with_fog(mut self, fog: Option<Fog>) -> Self {
+        self.fog = fog;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.media.is_empty() && self.fog.is_none()
+    }
+
+    /// Samples the first real collision along a ray, before the parameter `t_max`.
+    ///
+    /// # Returns
+    /// - `Some((t, medium))` with the ray parameter of the collision and the medium hit.
+    /// - `None` if the ray reaches `t_max` without colliding.
+    pub fn sample_distance(&self, ray: &Ray, t_max: Float) -> Option<(Float, &Medium)> {
+        let length = ray.direction().length();
+        let direction = ray.direction() / length;
+        // The free flight through overlapping media is the nearest of their free flights
+        self.media
+            .iter()
+            .filter_map(|medium| {
+                medium
+                    .sample_distance(ray.origin(), direction, t_max * length)
+                    .map(|t| (t / length, medium))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// Estimates the transmittance along a ray up to the parameter `t_max`, the fog
+    /// included.
+    pub fn transmittance(&self, ray: &Ray, t_max: Float) -> Float {
+        let length = ray.direction().length();
+        let direction = ray.direction() / length;
+        let fog = self.fog.map_or(1.0, |fog| fog.transmittance(ray, t_max));
+        fog * self
+            .media
+            .iter()
+            .map(|medium| medium.transmittance(ray.origin(), direction, t_max * length))
+            .product::<Float>()
+    }
+}
+
+/// The Henyey-Greenstein phase function.
+///
+/// # Parameters
+/// - `cos_theta`: The cosine between the propagation directions before and after scattering.
+/// - `g`: The asymmetry parameter.
+pub(crate) fn phase_hg(cos_theta: Float, g: Float) -> Float {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * utils::consts::PI * denom * denom.sqrt())
+}
+
+/// Samples a scattered direction proportionally to the Henyey-Greenstein phase function.
+///
+/// # Parameters
+/// - `direction`: The unit propagation direction before scattering.
+/// - `(u, v)`: A 2D sample.
+///
+/// # Returns
+/// - The scattered direction and its density, equal to the phase function value.
+pub(crate) fn sample_hg(direction: Vec3, g: Float, (u, v): (Float, Float)) -> (Vec3, Float) {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let sqr = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - sqr * sqr) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * utils::consts::PI * v;
+    let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    (
+        utils::Onb::from_normal(direction).to_world(local),
+        phase_hg(cos_theta, g),
+    )
+}
+
+/// Fractal sum of value noise octaves, normalized to [0, 1].
+fn fbm(p: Point3, octaves: u32) -> Float {
+    let (mut sum, mut amplitude, mut total, mut frequency) = (0.0, 1.0, 0.0, 1.0);
+    for _ in 0..octaves {
+        sum += amplitude * value_noise(p * frequency);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+/// Trilinearly interpolated noise over a lattice of hashed values.
+fn value_noise(p: Point3) -> Float {
+    let cell = [p.x().floor(), p.y().floor(), p.z().floor()];
+    let smooth = |t: Float| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (
+        smooth(p.x() - cell[0]),
+        smooth(p.y() - cell[1]),
+        smooth(p.z() - cell[2]),
+    );
+    let (x, y, z) = (cell[0] as i32, cell[1] as i32, cell[2] as i32);
+    let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+    let plane = |dz: i32| {
+        let row = |dy: i32| lerp(hash(x, y + dy, z + dz), hash(x + 1, y + dy, z + dz), fx);
+        lerp(row(0), row(1), fy)
+    };
+    lerp(plane(0), plane(1), fz)
+}
+
+/// Cellular noise, 1 at the jittered feature point of each cell falling to 0 a cell
+/// away from the nearest one.
+fn worley(p: Point3) -> Float {
+    let (x, y, z) = (
+        p.x().floor() as i32,
+        p.y().floor() as i32,
+        p.z().floor() as i32,
+    );
+    let mut nearest: Float = 1.0;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (cx, cy, cz) = (x + dx, y + dy, z + dz);
+                let feature = Vec3::new(
+                    cx as Float + hash(cx, cy, cz),
+                    cy as Float + hash(cy, cz, cx),
+                    cz as Float + hash(cz, cx, cy),
+                );
+                nearest = nearest.min((feature - p).length_squared());
+            }
+        }
+    }
+    1.0 - nearest.sqrt()
+}
+
+fn hash(x: i32, y: i32, z: i32) -> Float {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    h as Float / u32::MAX as Float
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lets_the_rays_through_where_there_is_no_fog() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let down = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let fog = Fog::new(white, 0.0).with_height_falloff(0.0, 1.0);
+        assert_eq!(fog.transmittance(&down, Float::INFINITY), 1.0);
+        // So high that the density underflows
+        let high = Ray::new(Point3::new(0.0, 1e5, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let fog = Fog::new(white, 1.0).with_height_falloff(0.0, 1.0);
+        let transmittance = fog.transmittance(&high, Float::INFINITY);
+        assert!(!transmittance.is_nan());
+        let up = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let transmittance = fog.transmittance(&up, Float::INFINITY);
+        assert!((transmittance - (-1.0 as Float).exp()).abs() < 1e-4);
+        assert_eq!(fog.transmittance(&down, 0.0), 1.0);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::buffer::Buffer;
+use crate::error::RenderError;
+use crate::tracer::RenderSettings;
+use exr::meta::attribute::AttributeValue;
+use exr::prelude::read_all_flat_layers_from_file;
+use std::collections::HashMap;
+use tracing::warn;
+use utils::{Color, Float};
+
+/// A render read back from an EXR file.
+struct Render {
+    width: usize,
+    height: usize,
+    /// The beauty and AOV layers, in film coordinates, in the order of the file.
+    layers: Vec<(String, Vec<Color>)>,
+    /// The text attributes of the header.
+    metadata: HashMap<String, String>,
+    /// The samples per pixel of the render.
+    samples_per_pixel: u32,
+}
+
+impl Render {
+    /// Reads a render written by `Buffer::write_exr`, with its metadata. The layers of
+    /// the cryptomattes are left out.
+    fn read(path: &str) -> Result<Self, RenderError> {
+        let image = read_all_flat_layers_from_file(path)?;
+        let mut metadata = HashMap::new();
+        let attributes = image.layer_data.iter().map(|layer| &layer.attributes.other);
+        for (name, value) in std::iter::once(&image.attributes.other)
+            .chain(attributes)
+            .flatten()
+        {
+            if let AttributeValue::Text(value) = value {
+                metadata.insert(name.to_string(), value.to_string());
+            }
+        }
+        let samples_per_pixel = metadata
+            .get("crust/samplesPerPixel")
+            .ok_or("the render has no metadata, it was not written by crust-render")
+            .and_then(|samples| samples.parse().map_err(|_| "invalid samples per pixel"))
+            .map_err(|e| RenderError::Merge(e.to_string()))?;
+        let cryptomattes: Vec<&String> = metadata
+            .iter()
+            .filter(|(name, _)| name.starts_with("cryptomatte/") && name.ends_with("/name"))
+            .map(|(_, value)| value)
+            .collect();
+        let (width, height) = (
+            image.attributes.display_window.size.0,
+            image.attributes.display_window.size.1,
+        );
+        let mut layers = Vec::new();
+        for layer in &image.layer_data {
+            let name = layer
+                .attributes
+                .layer_name
+                .as_ref()
+                .map_or("beauty".to_string(), |name| name.to_string());
+            let is_cryptomatte = cryptomattes.iter().any(|matte| {
+                name.strip_prefix(matte.as_str())
+                    .is_some_and(|rank| rank.len() == 2 && rank.chars().all(|c| c.is_ascii_digit()))
+            });
+            if is_cryptomatte {
+                continue;
+            }
+            let channels = &layer.channel_data.list;
+            let channel = |names: &[&str]| {
+                channels
+                    .iter()
+                    .find(|channel| names.contains(&channel.name.to_string().as_str()))
+                    .map(|channel| &channel.sample_data)
+            };
+            // The single-channel layers, the depth and the sample counts, turn gray
+            let (r, g, b) = match (channel(&["R"]), channel(&["G"]), channel(&["B"])) {
+                (Some(r), Some(g), Some(b)) => (r, g, b),
+                _ => {
+                    let gray = channel(&["Y", "Z"]).ok_or_else(|| {
+                        RenderError::Merge("a layer has no known channel".to_string())
+                    })?;
+                    (gray, gray, gray)
+                }
+            };
+            let pixels = (0..width * height)
+                .map(|index| {
+                    // The file is flipped from the film, see `Buffer::write_exr`
+                    let (x, y) = (index % width, height - 1 - index / width);
+                    let flat = y * width + x;
+                    Color::new(
+                        r.value_by_flat_index(flat).to_f32() as Float,
+                        g.value_by_flat_index(flat).to_f32() as Float,
+                        b.value_by_flat_index(flat).to_f32() as Float,
+                    )
+                })
+                .collect();
+            layers.push((name, pixels));
+        }
+        Ok(Render {
+            width,
+            height,
+            layers,
+            metadata,
+            samples_per_pixel,
+        })
+    }
+
+    fn layer(&self, name: &str) -> Option<&[Color]> {
+        self.layers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    /// The samples taken by a pixel, from the `samples` AOV when the render has one.
+    fn samples(&self, index: usize) -> Float {
+        let samples_per_pixel = self.samples_per_pixel as Float;
+        self.layer("samples").map_or(samples_per_pixel, |samples| {
+            samples[index].r() * samples_per_pixel
+        })
+    }
+}
+
+/// Merges renders of a scene into one image of lower noise, e.g. renders made on
+/// several machines with different seeds.
+///
+/// Each pixel of the beauty and of the AOVs is the average of the renders weighted by
+/// the samples they took in it, from their `samples` AOV or their samples per pixel.
+/// The `samples` and `standard_error` AOVs are combined to describe the merged pixels,
+/// while the cryptomattes, which cannot be averaged, are left out.
+///
+/// # Parameters
+/// - `paths`: The EXR files of the renders, written by the renderer with the same
+///   settings but for their seeds and samples.
+///
+/// # Returns
+/// - The merged film, whose metadata sums the samples and render times, with the
+///   render settings of the first render, or an error if a render could not be read or
+///   does not match the first one.
+pub fn merge_renders(paths: &[String]) -> Result<(Buffer, RenderSettings), RenderError> {
+    let renders = paths
+        .iter()
+        .map(|path| Render::read(path).map_err(|e| RenderError::Merge(format!("{path}: {e}"))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = renders
+        .first()
+        .ok_or_else(|| RenderError::Merge("no render to merge".to_string()))?;
+    let settings: RenderSettings = first
+        .metadata
+        .get("crust/settings")
+        .ok_or_else(|| RenderError::Merge("the render has no settings in its metadata".into()))
+        .and_then(|settings| {
+            ron::from_str(settings).map_err(|e| RenderError::Merge(format!("{}: {e}", paths[0])))
+        })?;
+    let (width, height) = (first.width, first.height);
+    if let Some(path) = paths.iter().zip(&renders).find_map(|(path, render)| {
+        ((render.width, render.height) != (width, height)).then_some(path)
+    }) {
+        return Err(RenderError::Merge(format!(
+            "{path}: the size differs from the one of {}",
+            paths[0]
+        )));
+    }
+    let seeds: Vec<&str> = renders
+        .iter()
+        .map(|render| render.metadata.get("crust/seed").map_or("", String::as_str))
+        .collect();
+    if (1..seeds.len()).any(|i| seeds[..i].contains(&seeds[i])) {
+        warn!("Some renders share their seed, merging them does not reduce the noise as much");
+    }
+
+    let mut film = Buffer::new(width, height);
+    // The sums of the samples and of the squared standard errors weighted by them
+    let mut samples = vec![0.0; width * height];
+    let mut errors = vec![Color::zero(); width * height];
+    for render in &renders {
+        for index in 0..width * height {
+            let (x, y) = (index % width, index / width);
+            let weight = render.samples(index);
+            samples[index] += weight;
+            if let Some(error) = render.layer("standard_error") {
+                errors[index] += weight * weight * error[index] * error[index];
+            }
+            for (name, pixels) in &render.layers {
+                match name.as_str() {
+                    "beauty" => film.add_sample(x, y, pixels[index], weight),
+                    "samples" | "standard_error" => {}
+                    name => film.aov_mut(name).add_sample(x, y, pixels[index], weight),
+                }
+            }
+        }
+    }
+    let samples_per_pixel: u32 = renders.iter().map(|render| render.samples_per_pixel).sum();
+    for index in 0..width * height {
+        let (x, y) = (index % width, index / width);
+        if first.layer("samples").is_some() {
+            let share = samples[index] / samples_per_pixel as Float;
+            film.aov_mut("samples")
+                .set_pixel(x, y, Color::new(share, share, share));
+        }
+        if first.layer("standard_error").is_some() && samples[index] > 0.0 {
+            let error = errors[index];
+            let error = error.sqrt();
+            film.aov_mut("standard_error")
+                .set_pixel(x, y, error / samples[index]);
+        }
+    }
+
+    let mut metadata: Vec<(&String, &String)> = first
+        .metadata
+        .iter()
+        .filter(|(name, _)| name.starts_with("crust/"))
+        .collect();
+    metadata.sort();
+    for (name, value) in metadata {
+        film.set_metadata(name, value.as_str());
+    }
+    let render_time: f64 = renders
+        .iter()
+        .filter_map(|render| render.metadata.get("crust/renderTime")?.parse::<f64>().ok())
+        .sum();
+    film.set_metadata("crust/samplesPerPixel", samples_per_pixel.to_string());
+    film.set_metadata("crust/renderTime", format!("{render_time:.3}"));
+    film.set_metadata("crust/seed", seeds.join(","));
+    film.set_metadata("crust/merged", renders.len().to_string());
+    Ok((film, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a render of two pixels, their colors and sample shares, to an EXR file.
+    fn write_render(name: &str, seed: u32, colors: [Float; 2], shares: [Float; 2]) -> String {
+        let settings = RenderSettings::default().with_samples_per_pixel(4);
+        let mut film = Buffer::new(2, 1);
+        for x in 0..2 {
+            film.set_pixel(x, 0, Color::new(colors[x], colors[x], colors[x]));
+            let share = Color::new(shares[x], shares[x], shares[x]);
+            film.aov_mut("samples").set_pixel(x, 0, share);
+        }
+        film.set_metadata("crust/settings", ron::to_string(&settings).unwrap());
+        film.set_metadata("crust/samplesPerPixel", "4");
+        film.set_metadata("crust/seed", seed.to_string());
+        let path = std::env::temp_dir()
+            .join(format!(
+                "crust-render-merge-{name}-{}.exr",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+        film.write_exr(&path, settings.exr(), settings.working_space())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn weights_the_renders_by_their_samples() {
+        let paths = [
+            write_render("first", 1, [1.0, 1.0], [1.0, 0.25]),
+            write_render("second", 2, [2.0, 2.0], [1.0, 1.0]),
+        ];
+        let (film, settings) = merge_renders(&paths).unwrap();
+        paths
+            .iter()
+            .for_each(|path| std::fs::remove_file(path).unwrap());
+
+        assert_eq!(settings.samples_per_pixel(), 4);
+        // 4 samples of each in the first pixel, 1 of the first render in the second
+        assert!((film.get_pixel(0, 0).r() - 1.5).abs() < 1e-3);
+        assert!((film.get_pixel(1, 0).r() - 1.8).abs() < 1e-3);
+        let samples = film.aov("samples").unwrap();
+        assert!((samples.get_pixel(0, 0).r() - 1.0).abs() < 1e-3);
+        assert!((samples.get_pixel(1, 0).r() - 0.625).abs() < 1e-3);
+        let metadata: HashMap<&str, &str> = film.metadata().collect();
+        assert_eq!(metadata["crust/samplesPerPixel"], "8");
+        assert_eq!(metadata["crust/seed"], "1,2");
+        assert_eq!(metadata["crust/merged"], "2");
+    }
+
+    #[test]
+    fn refuses_renders_of_other_sizes() {
+        let first = write_render("size", 1, [1.0, 1.0], [1.0, 1.0]);
+        let other = std::env::temp_dir()
+            .join(format!(
+                "crust-render-merge-other-{}.exr",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+        let mut film = Buffer::new(1, 1);
+        film.set_metadata("crust/samplesPerPixel", "4");
+        film.write_exr(&other, Default::default(), Default::default())
+            .unwrap();
+        let result = merge_renders(&[first.clone(), other.clone()]);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(other).unwrap();
+        assert!(matches!(result, Err(RenderError::Merge(_))));
+    }
+}
@@ -0,0 +1,942 @@
+use crate::camera::Camera;
+use crate::document::{DocObject, Document, ObjectList};
+use crate::error::RenderError;
+use crate::integrator::IntegratorType;
+use crate::material::MaterialType;
+use crate::material::{Conductor, CookTorrance, Dielectric, Disney, Emissive, Lambertian, Metal};
+use crate::ply::load_ply_mesh;
+use crate::primitives::{Primitive, load_obj_mesh};
+use crate::tracer::RenderSettings;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use utils::{Color, Float, Mat4, Point3, Vec3};
+
+fn invalid(message: String) -> RenderError {
+    RenderError::Parse(message)
+}
+
+/// An element of an XML file. Mitsuba writes everything in the attributes, so the
+/// text of the elements is left out.
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The child setting the parameter `name`, e.g. `<float name="fov" value="45"/>`.
+    fn param(&self, name: &str) -> Option<&Element> {
+        self.children
+            .iter()
+            .find(|child| child.attribute("name") == Some(name))
+    }
+
+    fn float(&self, name: &str, default: Float) -> Float {
+        self.param(name)
+            .and_then(|param| param.attribute("value"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.param(name)?.attribute("value")
+    }
+
+    /// A point parameter, written with `x`, `y` and `z` attributes or as a `value`.
+    fn point(&self, name: &str) -> Option<Point3> {
+        point(self.param(name)?)
+    }
+}
+
+/// Parses the elements of an XML document.
+fn parse_xml(text: &str) -> Result<Vec<Element>, RenderError> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment
+                .find("-->")
+                .ok_or_else(|| invalid("unterminated comment".to_string()))?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest
+                .find('>')
+                .ok_or_else(|| invalid("unterminated declaration".to_string()))?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let end = tag_end(rest).ok_or_else(|| invalid("unterminated tag".to_string()))?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = stack.pop().filter(|element| element.name == name.trim());
+            let (Some(element), Some(parent)) = (element, stack.last_mut()) else {
+                return Err(invalid(format!("unexpected </{}>", name.trim())));
+            };
+            parent.children.push(element);
+            continue;
+        }
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let element = parse_tag(tag)?;
+        if empty {
+            stack
+                .last_mut()
+                .expect("the root is never popped")
+                .children
+                .push(element);
+        } else {
+            stack.push(element);
+        }
+    }
+    match stack.pop() {
+        Some(root) if stack.is_empty() => Ok(root.children),
+        _ => Err(invalid("unclosed element".to_string())),
+    }
+}
+
+/// The position of the `>` closing the tag starting `text`, outside the quotes.
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses the name and the attributes of a tag.
+fn parse_tag(tag: &str) -> Result<Element, RenderError> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let mut element = Element {
+        name: tag[..name_end].to_string(),
+        ..Element::default()
+    };
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let equal = rest
+            .find('=')
+            .ok_or_else(|| invalid(format!("invalid attribute in <{}>", element.name)))?;
+        let key = rest[..equal].trim().to_string();
+        let value = rest[equal + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| invalid(format!("unquoted attribute {key:?}")))?;
+        let value = &value[1..];
+        let end = value
+            .find(quote)
+            .ok_or_else(|| invalid(format!("unterminated attribute {key:?}")))?;
+        element.attributes.push((key, unescape(&value[..end])));
+        rest = value[end + 1..].trim_start();
+    }
+    Ok(element)
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The numbers of a list, separated by commas or spaces.
+fn numbers(value: &str) -> Vec<Float> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+fn point(element: &Element) -> Option<Point3> {
+    if let Some(value) = element.attribute("value") {
+        return match numbers(value).as_slice() {
+            [x, y, z] => Some(Point3::new(*x, *y, *z)),
+            _ => None,
+        };
+    }
+    let coordinate = |axis| {
+        element
+            .attribute(axis)
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0.0)
+    };
+    Some(Point3::new(
+        coordinate("x"),
+        coordinate("y"),
+        coordinate("z"),
+    ))
+}
+
+/// Reads a Mitsuba 3 scene: its shapes, BSDFs, emitters and sensor.
+///
+/// The unsupported elements are skipped with a warning: textures are replaced by the
+/// default value of the parameter they drive, and the environment emitters are left
+/// out.
+pub(crate) fn read(path: &Path) -> Result<Document, RenderError> {
+    let text = std::fs::read_to_string(path).map_err(|e| RenderError::file(path, e))?;
+    let mut importer = Importer::new(path.parent().map(Path::to_path_buf).unwrap_or_default());
+    importer.read(&text)?;
+    Ok(importer.document())
+}
+
+struct Importer {
+    /// Directory of the scene, which the included files and the meshes are relative to.
+    directory: PathBuf,
+    /// The values of the `<default>` parameters, substituted for `$name`.
+    defaults: HashMap<String, String>,
+    /// The BSDFs declared with an `id`, `None` for the `null` BSDF.
+    bsdfs: HashMap<String, Option<MaterialType>>,
+    sensor: Option<Element>,
+    integrator: Option<Element>,
+    objects: Vec<DocObject>,
+    /// The warnings already given, each given once.
+    warnings: HashSet<String>,
+}
+
+impl Importer {
+    /// An importer of the scenes whose included files and meshes are relative to
+    /// `directory`.
+    fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            defaults: HashMap::new(),
+            bsdfs: HashMap::new(),
+            sensor: None,
+            integrator: None,
+            objects: Vec::new(),
+            warnings: HashSet::new(),
+        }
+    }
+
+    /// Imports the `<scene>` element of the text of a scene.
+    fn read(&mut self, text: &str) -> Result<(), RenderError> {
+        let root = parse_xml(text)?;
+        let Some(scene) = root.into_iter().find(|element| element.name == "scene") else {
+            return Err(invalid("the <scene> element is missing".to_string()));
+        };
+        self.scene(&scene)
+    }
+
+    fn warn_once(&mut self, message: String) {
+        if !self.warnings.contains(&message) {
+            warn!("Mitsuba: {}", message);
+            self.warnings.insert(message);
+        }
+    }
+
+    /// The element with its `$name` attributes replaced by their default values.
+    fn substitute(&self, element: &Element) -> Element {
+        Element {
+            name: element.name.clone(),
+            attributes: element
+                .attributes
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value.strip_prefix('$') {
+                        Some(name) => self.defaults.get(name).cloned().unwrap_or(value.clone()),
+                        None => value.clone(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            children: element
+                .children
+                .iter()
+                .map(|child| self.substitute(child))
+                .collect(),
+        }
+    }
+
+    fn scene(&mut self, scene: &Element) -> Result<(), RenderError> {
+        for element in &scene.children {
+            if element.name == "default" {
+                if let (Some(name), Some(value)) =
+                    (element.attribute("name"), element.attribute("value"))
+                {
+                    self.defaults
+                        .entry(name.to_string())
+                        .or_insert(value.to_string());
+                }
+                continue;
+            }
+            let element = self.substitute(element);
+            match element.name.as_str() {
+                "sensor" => self.sensor = Some(element),
+                "integrator" => self.integrator = Some(element),
+                "bsdf" => {
+                    let material = self.bsdf(&element);
+                    match element.attribute("id") {
+                        Some(id) => {
+                            self.bsdfs.insert(id.to_string(), material);
+                        }
+                        None => self.warn_once("BSDF without an id left out".to_string()),
+                    }
+                }
+                "shape" => self.shape(&element),
+                "emitter" => self.emitter(&element),
+                "include" => {
+                    let file = self
+                        .directory
+                        .join(element.attribute("filename").unwrap_or_default());
+                    let text =
+                        std::fs::read_to_string(&file).map_err(|e| RenderError::file(&file, e))?;
+                    for included in parse_xml(&text)? {
+                        self.scene(&included)?;
+                    }
+                }
+                name => self.warn_once(format!("<{name}> is not supported")),
+            }
+        }
+        Ok(())
+    }
+
+    /// A color parameter, `<rgb>` or a uniform or sampled `<spectrum>`, the textures
+    /// being replaced by `default`.
+    fn color(&mut self, element: &Element, name: &str, default: Color) -> Color {
+        let Some(param) = element.param(name) else {
+            return default;
+        };
+        let values = numbers(param.attribute("value").unwrap_or_default());
+        match (param.name.as_str(), values.as_slice()) {
+            ("rgb", [r, g, b]) => Color::new(*r, *g, *b),
+            ("rgb" | "spectrum" | "float", [value]) => Color::new(*value, *value, *value),
+            ("spectrum", _) => {
+                // A sampled spectrum, `wavelength:value` pairs, averaged to gray
+                let samples: Vec<Float> = param
+                    .attribute("value")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(|pair| pair.split(':').nth(1)?.trim().parse().ok())
+                    .collect();
+                if samples.is_empty() {
+                    return default;
+                }
+                let value = samples.iter().sum::<Float>() / samples.len() as Float;
+                Color::new(value, value, value)
+            }
+            (kind, _) => {
+                self.warn_once(format!("{kind} parameter {name:?} replaced by its default"));
+                default
+            }
+        }
+    }
+
+    /// The material of a BSDF, `None` for the `null` BSDF of the medium boundaries.
+    fn bsdf(&mut self, element: &Element) -> Option<MaterialType> {
+        let kind = element.attribute("type").unwrap_or_default();
+        let gray = Color::new(0.5, 0.5, 0.5);
+        // The Beckmann and GGX roughness is the square of the perceptual one
+        let roughness = element.float("alpha", 0.0).max(0.0).sqrt();
+        let material = match kind {
+            "null" => return None,
+            "twosided" | "bumpmap" | "normalmap" | "mask" | "blendbsdf" => {
+                if kind != "twosided" {
+                    self.warn_once(format!("{kind} BSDF replaced by its nested BSDF"));
+                }
+                let nested = element.children.iter().find(|child| child.name == "bsdf");
+                return match nested {
+                    Some(nested) => self.bsdf(nested),
+                    None => Some(MaterialType::Lambertian(Lambertian::new(gray))),
+                };
+            }
+            "diffuse" => {
+                MaterialType::Lambertian(Lambertian::new(self.color(element, "reflectance", gray)))
+            }
+            "plastic" | "roughplastic" => MaterialType::CookTorrance(CookTorrance::new(
+                self.color(element, "diffuse_reflectance", gray),
+                roughness,
+                0.0,
+            )),
+            "conductor" | "roughconductor" => {
+                let metal = Metal::new(
+                    self.color(element, "specular_reflectance", Color::new(1.0, 1.0, 1.0)),
+                    roughness,
+                );
+                let conductor = match element.string("material").unwrap_or("none") {
+                    "none" => None,
+                    "Au" => Some(Conductor::Gold),
+                    "Ag" => Some(Conductor::Silver),
+                    "Al" => Some(Conductor::Aluminium),
+                    "Cu" | "Cu2O" => Some(Conductor::Copper),
+                    material => {
+                        self.warn_once(format!("conductor {material:?} rendered as a mirror"));
+                        None
+                    }
+                };
+                MaterialType::Metal(match conductor {
+                    Some(conductor) => metal.with_conductor(conductor),
+                    None => metal,
+                })
+            }
+            "dielectric" | "roughdielectric" | "thindielectric" => {
+                let interior = self.ior(element, "int_ior", 1.5046);
+                let exterior = self.ior(element, "ext_ior", 1.000277);
+                MaterialType::Dielectric(Dielectric::new(interior / exterior))
+            }
+            "principled" => MaterialType::Disney(Disney::new(
+                self.color(element, "base_color", gray),
+                element.float("metallic", 0.0),
+                element.float("roughness", 0.5),
+                element.float("specular", 0.5),
+                element.float("spec_tint", 0.0),
+                element.float("sheen", 0.0),
+                element.float("sheen_tint", 0.0),
+                element.float("clearcoat", 0.0),
+                element.float("clearcoat_gloss", 0.0),
+            )),
+            _ => {
+                self.warn_once(format!("{kind} BSDF rendered as a diffuse one"));
+                MaterialType::Lambertian(Lambertian::new(gray))
+            }
+        };
+        Some(material)
+    }
+
+    /// An index of refraction, a number or the name of a material.
+    fn ior(&mut self, element: &Element, name: &str, default: Float) -> Float {
+        let Some(value) = element
+            .param(name)
+            .and_then(|param| param.attribute("value"))
+        else {
+            return default;
+        };
+        if let Ok(ior) = value.trim().parse() {
+            return ior;
+        }
+        match value {
+            "vacuum" => 1.0,
+            "air" => 1.000277,
+            "water" => 1.333,
+            "acetone" => 1.36,
+            "ethanol" => 1.361,
+            "fused quartz" => 1.458,
+            "pyrex" => 1.47,
+            "acrylic glass" | "polypropylene" => 1.49,
+            "bk7" => 1.5046,
+            "sodium chloride" => 1.544,
+            "amber" => 1.55,
+            "pet" => 1.575,
+            "diamond" => 2.419,
+            _ => {
+                self.warn_once(format!("unknown index of refraction {value:?}"));
+                default
+            }
+        }
+    }
+
+    /// The transform of a `<transform>` parameter, its operations applied in order.
+    fn transform(&mut self, element: &Element, name: &str) -> Mat4 {
+        let Some(param) = element
+            .children
+            .iter()
+            .find(|child| child.name == "transform" && child.attribute("name") == Some(name))
+        else {
+            return Mat4::identity();
+        };
+        let mut transform = Mat4::identity();
+        for operation in &param.children {
+            let vector = || point(operation).unwrap_or_default();
+            let step = match operation.name.as_str() {
+                "translate" => Mat4::translate(vector()),
+                "scale" => match operation.attribute("value").map(numbers).as_deref() {
+                    Some([s]) => Mat4::scale(*s, *s, *s),
+                    Some([x, y, z]) => Mat4::scale(*x, *y, *z),
+                    _ => {
+                        // The axes left out are not scaled
+                        let axis = |a| {
+                            operation
+                                .attribute(a)
+                                .and_then(|v| v.trim().parse().ok())
+                                .unwrap_or(1.0)
+                        };
+                        Mat4::scale(axis("x"), axis("y"), axis("z"))
+                    }
+                },
+                "rotate" => {
+                    let angle = operation
+                        .attribute("angle")
+                        .and_then(|angle| angle.trim().parse().ok())
+                        .unwrap_or(0.0);
+                    Mat4::rotate(angle, vector())
+                }
+                "matrix" => {
+                    let values = numbers(operation.attribute("value").unwrap_or_default());
+                    if values.len() == 16 {
+                        let mut m = [[0.0; 4]; 4];
+                        for (i, row) in m.iter_mut().enumerate() {
+                            row.copy_from_slice(&values[i * 4..i * 4 + 4]);
+                        }
+                        Mat4::from_rows(m)
+                    } else {
+                        self.warn_once("matrix without 16 values ignored".to_string());
+                        Mat4::identity()
+                    }
+                }
+                "lookat" => {
+                    let attribute = |a| {
+                        let values = numbers(operation.attribute(a).unwrap_or_default());
+                        match values.as_slice() {
+                            [x, y, z] => Vec3::new(*x, *y, *z),
+                            _ => Vec3::zero(),
+                        }
+                    };
+                    Mat4::look_at(attribute("origin"), attribute("target"), attribute("up"))
+                }
+                name => {
+                    self.warn_once(format!("<{name}> transform ignored"));
+                    Mat4::identity()
+                }
+            };
+            transform = step.compose(&transform);
+        }
+        transform
+    }
+
+    /// The material of a shape: its nested BSDF, or the one it references.
+    fn shape_material(&mut self, shape: &Element) -> Option<MaterialType> {
+        for child in &shape.children {
+            match child.name.as_str() {
+                "bsdf" => return self.bsdf(child),
+                "ref" => {
+                    let id = child.attribute("id").unwrap_or_default();
+                    match self.bsdfs.get(id) {
+                        Some(material) => return material.clone(),
+                        None => self.warn_once(format!("unknown BSDF {id:?}")),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(MaterialType::Lambertian(Lambertian::new(Color::new(
+            0.5, 0.5, 0.5,
+        ))))
+    }
+
+    fn shape(&mut self, element: &Element) {
+        let kind = element.attribute("type").unwrap_or_default();
+        let name = element
+            .attribute("id")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{kind}_{}", self.objects.len() + 1));
+        let Some(material) = self.shape_material(element) else {
+            debug!("Mitsuba: {} with a null BSDF skipped", name);
+            return;
+        };
+        let radiance = element
+            .children
+            .iter()
+            .find(|child| child.name == "emitter")
+            .map(|emitter| {
+                let kind = emitter.attribute("type").unwrap_or_default();
+                if kind != "area" {
+                    self.warn_once(format!("{kind} emitter of a shape rendered as an area one"));
+                }
+                self.color(emitter, "radiance", Color::new(1.0, 1.0, 1.0))
+            });
+        let transform = self.transform(element, "to_world");
+        let (primitive, light) = if kind == "sphere" {
+            let center = transform.transform_point(element.point("center").unwrap_or_default());
+            let radius = element.float("radius", 1.0) * transform.uniform_scale();
+            let light = radiance.map(|radiance| Emissive::new(radiance, center, radius));
+            (Primitive::new_sphere(center, radius), light)
+        } else {
+            let Some((vertices, indices)) = self.mesh(kind, element) else {
+                return;
+            };
+            if indices.iter().any(|&i| i as usize >= vertices.len()) {
+                warn!("Mitsuba: {} has vertex indices out of range, skipped", name);
+                return;
+            }
+            let vertices: Vec<Point3> = vertices
+                .iter()
+                .map(|&p| transform.transform_point(p))
+                .collect();
+            let light = radiance.map(|radiance| Emissive::for_mesh(radiance, &vertices, &indices));
+            (Primitive::new_mesh(vertices, indices), light)
+        };
+        let material = match light {
+            Some(light) => MaterialType::Emissive(light),
+            None => material,
+        };
+        self.objects.push(DocObject::new(name, primitive, material));
+    }
+
+    /// The vertices and triangle indices of a mesh shape, in object space.
+    fn mesh(&mut self, kind: &str, element: &Element) -> Option<(Vec<Point3>, Vec<u32>)> {
+        let corners = |points: &[[Float; 3]]| -> Vec<Point3> {
+            points
+                .iter()
+                .map(|p| Point3::new(p[0], p[1], p[2]))
+                .collect()
+        };
+        match kind {
+            "rectangle" => Some((
+                corners(&[
+                    [-1.0, -1.0, 0.0],
+                    [1.0, -1.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [-1.0, 1.0, 0.0],
+                ]),
+                vec![0, 1, 2, 0, 2, 3],
+            )),
+            "cube" => {
+                let vertices = (0..8)
+                    .map(|i| {
+                        let coordinate = |bit: u32| if i & bit == 0 { -1.0 } else { 1.0 };
+                        Point3::new(coordinate(1), coordinate(2), coordinate(4))
+                    })
+                    .collect();
+                let indices = vec![
+                    0, 2, 3, 0, 3, 1, // -z
+                    4, 5, 7, 4, 7, 6, // +z
+                    0, 1, 5, 0, 5, 4, // -y
+                    2, 6, 7, 2, 7, 3, // +y
+                    0, 4, 6, 0, 6, 2, // -x
+                    1, 3, 7, 1, 7, 5, // +x
+                ];
+                Some((vertices, indices))
+            }
+            "disk" => {
+                const SEGMENTS: u32 = 64;
+                let mut vertices = vec![Point3::zero()];
+                let mut indices = Vec::new();
+                for i in 0..SEGMENTS {
+                    let phi = 2.0 * utils::consts::PI * i as Float / SEGMENTS as Float;
+                    vertices.push(Point3::new(phi.cos(), phi.sin(), 0.0));
+                    indices.extend([0, i + 1, (i + 1) % SEGMENTS + 1]);
+                }
+                Some((vertices, indices))
+            }
+            "obj" | "ply" => {
+                let file = self
+                    .directory
+                    .join(element.string("filename").unwrap_or_default());
+                let mesh = if kind == "obj" {
+                    load_obj_mesh(&file.to_string_lossy())
+                } else {
+                    load_ply_mesh(&file)
+                };
+                match mesh {
+                    Ok(mesh) => Some(mesh),
+                    Err(e) => {
+                        warn!("Mitsuba: failed to load {}: {}", file.display(), e);
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.warn_once(format!("{kind} shapes are not supported"));
+                None
+            }
+        }
+    }
+
+    /// Adds the point emitters as small emissive spheres, the other emitters being
+    /// left out.
+    fn emitter(&mut self, element: &Element) {
+        match element.attribute("type").unwrap_or_default() {
+            "point" => {
+                const RADIUS: Float = 0.01;
+                // Radiance of the sphere giving the intensity of the point light
+                let intensity = self.color(element, "intensity", Color::new(1.0, 1.0, 1.0));
+                let radiance = intensity / (utils::consts::PI * RADIUS * RADIUS);
+                let center = match element.point("position") {
+                    Some(position) => position,
+                    None => self
+                        .transform(element, "to_world")
+                        .transform_point(Point3::zero()),
+                };
+                let name = element
+                    .attribute("id")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("pointlight_{}", self.objects.len() + 1));
+                self.objects.push(DocObject::new(
+                    name,
+                    Primitive::new_sphere(center, RADIUS),
+                    MaterialType::Emissive(Emissive::new(radiance, center, RADIUS)),
+                ));
+            }
+            kind => self.warn_once(format!("{kind} emitters are not supported")),
+        }
+    }
+
+    /// The document of the imported scene.
+    fn document(mut self) -> Document {
+        let sensor = self.sensor.take().unwrap_or_default();
+        if let Some(kind) = sensor.attribute("type")
+            && kind != "perspective"
+            && kind != "thinlens"
+        {
+            self.warn_once(format!("{kind} sensor rendered as a perspective one"));
+        }
+        let child = |name: &str| {
+            sensor
+                .children
+                .iter()
+                .find(|child| child.name == name)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let (film, sampler) = (child("film"), child("sampler"));
+        let width = film.float("width", 768.0).max(1.0) as usize;
+        let height = film.float("height", 576.0).max(1.0) as usize;
+        let aspect_ratio = width as Float / height as Float;
+
+        let to_world = self.transform(&sensor, "to_world");
+        let eye = to_world.transform_point(Point3::zero());
+        let look = to_world.transform_point(Point3::new(0.0, 0.0, 1.0));
+        let up = to_world.transform_direction(Vec3::new(0.0, 1.0, 0.0));
+        // The vertical field of view, from that along the axis of the sensor
+        let tan = (utils::degrees_to_radians(sensor.float("fov", 39.3077)) / 2.0).tan();
+        let diagonal = (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let tan = match sensor.string("fov_axis").unwrap_or("x") {
+            "y" => tan,
+            "diagonal" => tan / diagonal,
+            "smaller" if aspect_ratio < 1.0 => tan / aspect_ratio,
+            "smaller" => tan,
+            "larger" if aspect_ratio < 1.0 => tan,
+            _ => tan / aspect_ratio,
+        };
+        let vfov = 2.0 * tan.atan().to_degrees();
+        let aperture = 2.0 * sensor.float("aperture_radius", 0.0);
+        let focus_distance = if aperture > 0.0 {
+            sensor.float("focus_distance", 0.0)
+        } else {
+            1.0
+        };
+        let camera = Camera::new(eye, look, up, vfov, aspect_ratio, aperture, focus_distance);
+
+        let samples_per_pixel = sampler.float("sample_count", 4.0).max(1.0) as u32;
+        let integrator = self.integrator.take().unwrap_or_default();
+        let kind = integrator.attribute("type").unwrap_or("path");
+        // A depth of -1 is unbounded
+        let max_depth = match integrator.float("max_depth", -1.0) {
+            depth if depth < 0.0 => 64,
+            depth => depth as u32,
+        };
+        let integrator_type = match kind {
+            "path" | "volpath" | "volpathmis" => IntegratorType::Path,
+            "direct" => IntegratorType::DirectLighting,
+            _ => {
+                warn!("Mitsuba: {} integrator replaced by the path tracer", kind);
+                IntegratorType::Path
+            }
+        };
+        // Every pixel takes all its samples, as Mitsuba does
+        let settings = RenderSettings::new(
+            samples_per_pixel,
+            max_depth,
+            width,
+            height,
+            samples_per_pixel,
+            0.0,
+        )
+        .with_integrator(integrator_type);
+        debug!("Mitsuba: {} objects", self.objects.len());
+        Document::new(camera, ObjectList::new(self.objects), settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    /// The importer of a scene, with the warnings it gave.
+    fn import(text: &str) -> Importer {
+        let mut importer = Importer::new(PathBuf::new());
+        importer.read(text).unwrap();
+        importer
+    }
+
+    /// The center and radius of the spheres of a scene, in order.
+    fn spheres(doc: &Document) -> Vec<(Point3, Float)> {
+        doc.object_list
+            .objects()
+            .iter()
+            .filter_map(|object| match object.object() {
+                Primitive::Sphere { center, radius } => Some((*center, *radius)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_elements_and_attributes() {
+        let root = parse_xml(
+            r#"<?xml version="1.0"?>
+            <!-- A comment with a <tag> -->
+            <scene version='3.0.0'>
+                <float name="fov" value="45"/>
+                <string name="label" value="a &lt;b&gt; &amp; c"/>
+            </scene>"#,
+        )
+        .unwrap();
+        assert_eq!(root.len(), 1);
+        let scene = &root[0];
+        assert_eq!(scene.attribute("version"), Some("3.0.0"));
+        assert_eq!(scene.float("fov", 0.0), 45.0);
+        assert_eq!(scene.string("label"), Some("a <b> & c"));
+        assert!(parse_xml("<scene><shape></scene>").is_err());
+        assert!(parse_xml("<scene>").is_err());
+        assert!(parse_xml(r#"<scene version="3.0.0></scene>"#).is_err());
+    }
+
+    #[test]
+    fn resolves_referenced_bsdfs() {
+        let doc = import(
+            r#"<scene version="3.0.0">
+                <bsdf type="conductor" id="gold">
+                    <string name="material" value="Au"/>
+                </bsdf>
+                <bsdf type="twosided" id="red">
+                    <bsdf type="diffuse">
+                        <rgb name="reflectance" value="0.8, 0.1, 0.1"/>
+                    </bsdf>
+                </bsdf>
+                <bsdf type="null" id="boundary"/>
+                <shape type="sphere"><ref id="gold"/></shape>
+                <shape type="sphere"><ref id="red"/></shape>
+                <shape type="sphere"><ref id="boundary"/></shape>
+                <shape type="sphere"><ref id="missing"/></shape>
+            </scene>"#,
+        )
+        .document();
+        let materials: Vec<&MaterialType> = doc
+            .object_list
+            .objects()
+            .iter()
+            .map(|object| object.material())
+            .collect();
+        // The shape with the null BSDF is left out
+        assert_eq!(materials.len(), 3);
+        assert!(matches!(materials[0], MaterialType::Metal(_)));
+        let albedo = |material: &MaterialType| match material {
+            MaterialType::Lambertian(lambertian) => lambertian.albedo().rgb(),
+            _ => panic!("expected a diffuse material"),
+        };
+        assert_eq!(albedo(materials[1]), (0.8, 0.1, 0.1));
+        // An unknown reference falls back to a gray diffuse material
+        assert_eq!(albedo(materials[2]), (0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn substitutes_default_parameters() {
+        let importer = import(
+            r#"<scene version="3.0.0">
+                <default name="radius" value="2"/>
+                <default name="radius" value="3"/>
+                <default name="spp" value="16"/>
+                <sensor type="perspective">
+                    <sampler type="independent">
+                        <integer name="sample_count" value="$spp"/>
+                    </sampler>
+                </sensor>
+                <shape type="sphere"><float name="radius" value="$radius"/></shape>
+                <shape type="sphere"><float name="radius" value="$unknown"/></shape>
+            </scene>"#,
+        );
+        assert_eq!(importer.defaults["radius"], "2");
+        let doc = importer.document();
+        assert_eq!(doc.settings.samples_per_pixel(), 16);
+        let radii: Vec<Float> = spheres(&doc).iter().map(|(_, radius)| *radius).collect();
+        // A parameter without a default keeps the default value of the attribute
+        assert_eq!(radii, [2.0, 1.0]);
+    }
+
+    #[test]
+    fn applies_to_world_transforms() {
+        let doc = import(
+            r#"<scene version="3.0.0">
+                <shape type="sphere">
+                    <point name="center" x="1" y="0" z="0"/>
+                    <float name="radius" value="0.5"/>
+                    <transform name="to_world">
+                        <scale value="2"/>
+                        <translate x="1" y="2" z="3"/>
+                    </transform>
+                </shape>
+                <shape type="rectangle">
+                    <transform name="to_world">
+                        <rotate x="1" angle="90"/>
+                        <translate value="0 1 0"/>
+                    </transform>
+                </shape>
+            </scene>"#,
+        )
+        .document();
+        // Scaled first, then translated
+        let (center, radius) = spheres(&doc)[0];
+        assert_eq!([center.x(), center.y(), center.z()], [3.0, 2.0, 3.0]);
+        assert_eq!(radius, 1.0);
+        let Primitive::Mesh { vertices, .. } = doc.object_list.objects()[1].object() else {
+            panic!("the rectangle is a mesh");
+        };
+        // The rectangle is turned from the xy plane to the xz one, then raised
+        for vertex in vertices {
+            assert!((vertex.y() - 1.0).abs() < 1e-6);
+            assert_eq!(vertex.x().abs(), 1.0);
+            assert!((vertex.z().abs() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn skips_unknown_plugins() {
+        let importer = import(
+            r#"<scene version="3.0.0">
+                <texture type="bitmap" id="wood"/>
+                <emitter type="envmap"/>
+                <shape type="cylinder"/>
+                <shape type="sphere">
+                    <bsdf type="hair"/>
+                </shape>
+            </scene>"#,
+        );
+        for warning in [
+            "<texture> is not supported",
+            "envmap emitters are not supported",
+            "cylinder shapes are not supported",
+            "hair BSDF rendered as a diffuse one",
+        ] {
+            assert!(importer.warnings.contains(warning), "{warning}");
+        }
+        let doc = importer.document();
+        assert_eq!(doc.object_list.objects().len(), 1);
+        assert!(matches!(
+            doc.object_list.objects()[0].material(),
+            MaterialType::Lambertian(_)
+        ));
+    }
+
+    #[test]
+    fn requires_a_scene() {
+        let mut importer = Importer::new(PathBuf::new());
+        match importer.read(r#"<shape type="sphere"/>"#) {
+            Err(RenderError::Parse(message)) => assert!(message.contains("<scene>")),
+            _ => panic!("expected a parse error"),
+        }
+    }
+}
@@ -0,0 +1,932 @@
+use crate::camera::Camera;
+use crate::document::{DocObject, Document, ObjectList};
+use crate::error::RenderError;
+use crate::integrator::IntegratorType;
+use crate::material::MaterialType;
+use crate::material::{Conductor, CookTorrance, Dielectric, Emissive, Lambertian, Metal};
+use crate::ply::load_ply_mesh;
+use crate::primitives::Primitive;
+use crate::tracer::RenderSettings;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use utils::{Color, Float, Mat4, Point3, Vec3};
+
+/// A token of a pbrt file.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A directive, e.g. `Shape`, or a bare keyword argument.
+    Word(String),
+    Str(String),
+    Num(Float),
+    Bool(bool),
+    Open,
+    Close,
+}
+
+fn invalid(message: String) -> RenderError {
+    RenderError::Parse(message)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, RenderError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '[' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ']' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => return Err(invalid("unterminated string".to_string())),
+                    }
+                }
+                tokens.push(match string.as_str() {
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => Token::Str(string),
+                });
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]\"#".contains(*c)) {
+                    word.push(c);
+                }
+                tokens.push(match word.as_str() {
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => match word.parse::<Float>() {
+                        Ok(number) => Token::Num(number),
+                        Err(_) => Token::Word(word),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A parameter of a directive, e.g. `"rgb reflectance" [0.5 0.5 0.5]`.
+#[derive(Debug, Clone)]
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<Token>,
+}
+
+/// The parameter list of a directive.
+#[derive(Debug, Clone, Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn floats(&self, name: &str) -> Option<Vec<Float>> {
+        let param = self.get(name)?;
+        Some(
+            param
+                .values
+                .iter()
+                .filter_map(|value| match value {
+                    Token::Num(number) => Some(*number),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    fn float(&self, name: &str, default: Float) -> Float {
+        self.floats(name)
+            .and_then(|values| values.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn int(&self, name: &str, default: u32) -> u32 {
+        self.float(name, default as Float).max(0.0) as u32
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.values.first()? {
+            Token::Str(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn points(&self, name: &str) -> Vec<Point3> {
+        self.floats(name)
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .map(|p| Point3::new(p[0], p[1], p[2]))
+            .collect()
+    }
+
+    fn indices(&self, name: &str) -> Option<Vec<u32>> {
+        self.floats(name)
+            .map(|values| values.iter().map(|&i| i as u32).collect())
+    }
+}
+
+/// The attributes set by the directives and scoped by `AttributeBegin`/`AttributeEnd`.
+#[derive(Debug, Clone)]
+struct GraphicsState {
+    transform: Mat4,
+    /// The material of the shapes, `None` for the `interface` material of the medium
+    /// boundaries, which are not rendered.
+    material: Option<MaterialType>,
+    /// The radiance emitted by the shapes, from `AreaLightSource`.
+    area_light: Option<Color>,
+}
+
+/// A shape of an object definition, emitted by each of its instances.
+#[derive(Debug, Clone)]
+struct Shape {
+    kind: String,
+    params: Params,
+    state: GraphicsState,
+}
+
+/// Reads a pbrt scene, the practical subset of the pbrt-v4 format made of the shapes,
+/// the materials, the lights, the camera and the film.
+///
+/// The unsupported directives and parameters are skipped with a warning: textures are
+/// replaced by the default value of the parameter they drive, and the infinite and
+/// distant lights are left out.
+pub(crate) fn read(path: &Path) -> Result<Document, RenderError> {
+    let text = std::fs::read_to_string(path).map_err(|e| RenderError::file(path, e))?;
+    read_text(
+        &text,
+        path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    )
+}
+
+/// Reads the text of a pbrt scene, its included files and meshes being relative to
+/// `directory`.
+fn read_text(text: &str, directory: PathBuf) -> Result<Document, RenderError> {
+    let mut parser = Parser::new(tokenize(text)?, directory);
+    parser.parse()?;
+    parser.document()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Directory of the scene, which the included files and the meshes are relative to.
+    directory: PathBuf,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    named_materials: HashMap<String, Option<MaterialType>>,
+    coordinate_systems: HashMap<String, Mat4>,
+    /// The shapes of the object definitions, by name.
+    definitions: HashMap<String, Vec<Shape>>,
+    /// The object being defined, between `ObjectBegin` and `ObjectEnd`.
+    definition: Option<(String, Vec<Shape>)>,
+    /// The camera-to-world transform and the parameters of the camera.
+    camera: Option<(Mat4, Params)>,
+    film: Params,
+    sampler: Params,
+    integrator: (String, Params),
+    objects: Vec<DocObject>,
+    /// The warnings already given, each given once.
+    warnings: HashSet<String>,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>, directory: PathBuf) -> Self {
+        Parser {
+            tokens,
+            position: 0,
+            directory,
+            state: GraphicsState {
+                transform: Mat4::identity(),
+                material: Some(MaterialType::Lambertian(Lambertian::new(Color::new(
+                    0.5, 0.5, 0.5,
+                )))),
+                area_light: None,
+            },
+            stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            definitions: HashMap::new(),
+            definition: None,
+            camera: None,
+            film: Params::default(),
+            sampler: Params::default(),
+            integrator: ("path".to_string(), Params::default()),
+            objects: Vec::new(),
+            warnings: HashSet::new(),
+        }
+    }
+
+    fn warn_once(&mut self, message: String) {
+        if !self.warnings.contains(&message) {
+            warn!("pbrt: {}", message);
+            self.warnings.insert(message);
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn string(&mut self, directive: &str) -> Result<String, RenderError> {
+        match self.next() {
+            Some(Token::Str(string)) => Ok(string),
+            token => Err(invalid(format!(
+                "{directive} expects a string, not {token:?}"
+            ))),
+        }
+    }
+
+    /// Reads `count` numbers, bracketed or not.
+    fn numbers(&mut self, directive: &str, count: usize) -> Result<Vec<Float>, RenderError> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.next();
+        }
+        let mut numbers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.next() {
+                Some(Token::Num(number)) => numbers.push(number),
+                token => {
+                    return Err(invalid(format!(
+                        "{directive} expects {count} numbers, not {token:?}"
+                    )));
+                }
+            }
+        }
+        if bracketed && self.next() != Some(Token::Close) {
+            return Err(invalid(format!("{directive} expects {count} numbers")));
+        }
+        Ok(numbers)
+    }
+
+    /// Reads the parameter list following the arguments of a directive.
+    fn params(&mut self) -> Result<Params, RenderError> {
+        let mut params = Vec::new();
+        while let Some(Token::Str(declaration)) = self.peek() {
+            let mut words = declaration.split_whitespace();
+            let (Some(kind), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                return Err(invalid(format!("invalid parameter {declaration:?}")));
+            };
+            let (kind, name) = (kind.to_string(), name.to_string());
+            self.next();
+            let values = match self.next() {
+                Some(Token::Open) => {
+                    let mut values = Vec::new();
+                    loop {
+                        match self.next() {
+                            Some(Token::Close) => break,
+                            Some(Token::Open) | Some(Token::Word(_)) | None => {
+                                return Err(invalid(format!("unterminated parameter {name:?}")));
+                            }
+                            Some(value) => values.push(value),
+                        }
+                    }
+                    values
+                }
+                Some(Token::Close) | Some(Token::Word(_)) | None => {
+                    return Err(invalid(format!("parameter {name:?} has no value")));
+                }
+                Some(value) => vec![value],
+            };
+            params.push(Param { kind, name, values });
+        }
+        Ok(Params(params))
+    }
+
+    /// Skips the arguments of an unsupported directive.
+    fn skip(&mut self) {
+        while !matches!(self.peek(), Some(Token::Word(_)) | None) {
+            self.next();
+        }
+    }
+
+    fn concat(&mut self, transform: Mat4) {
+        self.state.transform = self.state.transform.compose(&transform);
+    }
+
+    fn parse(&mut self) -> Result<(), RenderError> {
+        while let Some(token) = self.next() {
+            let Token::Word(directive) = token else {
+                return Err(invalid(format!("expected a directive, not {token:?}")));
+            };
+            let d = directive.as_str();
+            match d {
+                "WorldBegin" => {
+                    self.state.transform = Mat4::identity();
+                    self.coordinate_systems
+                        .insert("world".to_string(), Mat4::identity());
+                }
+                "WorldEnd" => {}
+                "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+                "AttributeEnd" | "TransformEnd" => {
+                    let Some(state) = self.stack.pop() else {
+                        return Err(invalid(format!("unmatched {directive}")));
+                    };
+                    if d == "TransformEnd" {
+                        self.state.transform = state.transform;
+                    } else {
+                        self.state = state;
+                    }
+                }
+                "Identity" => self.state.transform = Mat4::identity(),
+                "Translate" => {
+                    let v = self.numbers(d, 3)?;
+                    self.concat(Mat4::translate(Vec3::new(v[0], v[1], v[2])));
+                }
+                "Scale" => {
+                    let v = self.numbers(d, 3)?;
+                    self.concat(Mat4::scale(v[0], v[1], v[2]));
+                }
+                "Rotate" => {
+                    let v = self.numbers(d, 4)?;
+                    self.concat(Mat4::rotate(v[0], Vec3::new(v[1], v[2], v[3])));
+                }
+                "LookAt" => {
+                    let v = self.numbers(d, 9)?;
+                    let camera_to_world = Mat4::look_at(
+                        Point3::new(v[0], v[1], v[2]),
+                        Point3::new(v[3], v[4], v[5]),
+                        Vec3::new(v[6], v[7], v[8]),
+                    );
+                    self.concat(camera_to_world.inverse().unwrap_or_default());
+                }
+                "Transform" | "ConcatTransform" => {
+                    let v = self.numbers(d, 16)?;
+                    let matrix = Mat4::from_columns(&v.try_into().expect("16 numbers"));
+                    if d == "Transform" {
+                        self.state.transform = matrix;
+                    } else {
+                        self.concat(matrix);
+                    }
+                }
+                "CoordinateSystem" => {
+                    let name = self.string(d)?;
+                    self.coordinate_systems.insert(name, self.state.transform);
+                }
+                "CoordSysTransform" => {
+                    let name = self.string(d)?;
+                    match self.coordinate_systems.get(&name) {
+                        Some(transform) => self.state.transform = *transform,
+                        None => self.warn_once(format!("unknown coordinate system {name:?}")),
+                    }
+                }
+                "ActiveTransform" => {
+                    self.next();
+                    self.warn_once("motion blur is not supported".to_string());
+                }
+                "Camera" => {
+                    let kind = self.string(d)?;
+                    let params = self.params()?;
+                    if kind != "perspective" {
+                        self.warn_once(format!("{kind} camera rendered as a perspective one"));
+                    }
+                    let Some(camera_to_world) = self.state.transform.inverse() else {
+                        return Err(invalid("the camera transform is singular".to_string()));
+                    };
+                    self.coordinate_systems
+                        .insert("camera".to_string(), camera_to_world);
+                    self.camera = Some((camera_to_world, params));
+                }
+                "Film" => {
+                    self.string(d)?;
+                    self.film = self.params()?;
+                }
+                "Sampler" => {
+                    self.string(d)?;
+                    self.sampler = self.params()?;
+                }
+                "Integrator" => {
+                    let kind = self.string(d)?;
+                    self.integrator = (kind, self.params()?);
+                }
+                "Material" => {
+                    let kind = self.string(d)?;
+                    let params = self.params()?;
+                    self.state.material = self.material(&kind, &params);
+                }
+                "MakeNamedMaterial" => {
+                    let name = self.string(d)?;
+                    let params = self.params()?;
+                    let kind = params.string("type").unwrap_or("diffuse").to_string();
+                    let material = self.material(&kind, &params);
+                    self.named_materials.insert(name, material);
+                }
+                "NamedMaterial" => {
+                    let name = self.string(d)?;
+                    match self.named_materials.get(&name) {
+                        Some(material) => self.state.material = material.clone(),
+                        None => self.warn_once(format!("unknown material {name:?}")),
+                    }
+                }
+                "AreaLightSource" => {
+                    let kind = self.string(d)?;
+                    let params = self.params()?;
+                    if kind != "diffuse" {
+                        self.warn_once(format!("unknown area light {kind:?}"));
+                    }
+                    let radiance = self.color(&params, "L", Color::new(1.0, 1.0, 1.0));
+                    self.state.area_light = Some(params.float("scale", 1.0) * radiance);
+                }
+                "LightSource" => {
+                    let kind = self.string(d)?;
+                    let params = self.params()?;
+                    self.light(&kind, &params);
+                }
+                "Shape" => {
+                    let kind = self.string(d)?;
+                    let params = self.params()?;
+                    let shape = Shape {
+                        kind,
+                        params,
+                        state: self.state.clone(),
+                    };
+                    match &mut self.definition {
+                        Some((_, shapes)) => shapes.push(shape),
+                        None => self.shape(&shape, &Mat4::identity()),
+                    }
+                }
+                "ObjectBegin" => {
+                    let name = self.string(d)?;
+                    self.stack.push(self.state.clone());
+                    self.definition = Some((name, Vec::new()));
+                }
+                "ObjectEnd" => {
+                    if let Some((name, shapes)) = self.definition.take() {
+                        self.definitions.insert(name, shapes);
+                    }
+                    if let Some(state) = self.stack.pop() {
+                        self.state = state;
+                    }
+                }
+                "ObjectInstance" => {
+                    let name = self.string(d)?;
+                    let Some(shapes) = self.definitions.get(&name).cloned() else {
+                        self.warn_once(format!("unknown object {name:?}"));
+                        continue;
+                    };
+                    let instance = self.state.transform;
+                    for shape in &shapes {
+                        self.shape(shape, &instance);
+                    }
+                }
+                "Include" | "Import" => {
+                    let file = self.string(d)?;
+                    let file = self.directory.join(file);
+                    let text =
+                        std::fs::read_to_string(&file).map_err(|e| RenderError::file(&file, e))?;
+                    let included = tokenize(&text)?;
+                    self.tokens.splice(self.position..self.position, included);
+                }
+                "ReverseOrientation" => {}
+                _ => {
+                    self.warn_once(format!("{directive} is not supported"));
+                    self.skip();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// An RGB parameter, the other spectra being replaced by `default`.
+    fn color(&mut self, params: &Params, name: &str, default: Color) -> Color {
+        let Some(param) = params.get(name) else {
+            return default;
+        };
+        match (param.kind.as_str(), params.floats(name).as_deref()) {
+            ("rgb", Some([r, g, b])) => Color::new(*r, *g, *b),
+            ("blackbody", Some([_])) => {
+                self.warn_once("blackbody spectra rendered white".to_string());
+                Color::new(1.0, 1.0, 1.0)
+            }
+            (kind, _) => {
+                self.warn_once(format!("{kind} parameter {name:?} replaced by its default"));
+                default
+            }
+        }
+    }
+
+    /// The material of a `Material` or `MakeNamedMaterial` directive, `None` for the
+    /// `interface` material.
+    fn material(&mut self, kind: &str, params: &Params) -> Option<MaterialType> {
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let roughness = params.floats("roughness").map_or_else(
+            || (params.float("uroughness", 0.0) + params.float("vroughness", 0.0)) / 2.0,
+            |values| values.first().copied().unwrap_or(0.0),
+        );
+        let material = match kind {
+            "interface" => return None,
+            "diffuse" => {
+                MaterialType::Lambertian(Lambertian::new(self.color(params, "reflectance", gray)))
+            }
+            "coateddiffuse" => MaterialType::CookTorrance(CookTorrance::new(
+                self.color(params, "reflectance", gray),
+                roughness,
+                0.0,
+            )),
+            "coatedconductor" => MaterialType::CookTorrance(CookTorrance::new(
+                self.color(params, "reflectance", Color::new(0.9, 0.9, 0.9)),
+                params.float("conductor.roughness", roughness),
+                1.0,
+            )),
+            "conductor" => match params.get("reflectance") {
+                Some(_) => MaterialType::Metal(Metal::new(
+                    self.color(params, "reflectance", gray),
+                    roughness,
+                )),
+                None => {
+                    let eta = params.string("eta").unwrap_or("metal-Cu-eta");
+                    let conductor = match eta {
+                        "metal-Au-eta" => Conductor::Gold,
+                        "metal-Ag-eta" => Conductor::Silver,
+                        "metal-Al-eta" => Conductor::Aluminium,
+                        "metal-Cu-eta" => Conductor::Copper,
+                        _ => {
+                            self.warn_once(format!("conductor {eta:?} rendered as copper"));
+                            Conductor::Copper
+                        }
+                    };
+                    MaterialType::Metal(
+                        Metal::new(Color::new(1.0, 1.0, 1.0), roughness).with_conductor(conductor),
+                    )
+                }
+            },
+            "dielectric" | "thindielectric" => {
+                MaterialType::Dielectric(Dielectric::new(params.float("eta", 1.5)))
+            }
+            _ => {
+                self.warn_once(format!("{kind} material rendered as a diffuse one"));
+                MaterialType::Lambertian(Lambertian::new(gray))
+            }
+        };
+        Some(material)
+    }
+
+    /// Adds the point lights as small emissive spheres, the other lights being left out.
+    fn light(&mut self, kind: &str, params: &Params) {
+        match kind {
+            "point" | "spot" => {
+                if kind == "spot" {
+                    self.warn_once("spot lights rendered as point lights".to_string());
+                }
+                const RADIUS: Float = 0.01;
+                // Radiance of the sphere giving the intensity of the point light
+                let intensity =
+                    params.float("scale", 1.0) * self.color(params, "I", Color::new(1.0, 1.0, 1.0));
+                let radiance = intensity / (utils::consts::PI * RADIUS * RADIUS);
+                let from = params.points("from").first().copied().unwrap_or_default();
+                let center = world(&self.state.transform).transform_point(from);
+                let name = format!("{kind}light_{}", self.objects.len() + 1);
+                self.objects.push(DocObject::new(
+                    name,
+                    Primitive::new_sphere(center, RADIUS),
+                    MaterialType::Emissive(Emissive::new(radiance, center, RADIUS)),
+                ));
+            }
+            _ => self.warn_once(format!("{kind} lights are not supported")),
+        }
+    }
+
+    /// Adds a shape, placed by `instance` when it is part of an object definition.
+    fn shape(&mut self, shape: &Shape, instance: &Mat4) {
+        let Some(material) = shape.state.material.clone() else {
+            debug!("pbrt: {} with an interface material skipped", shape.kind);
+            return;
+        };
+        let transform = world(&instance.compose(&shape.state.transform));
+        let params = &shape.params;
+        let name = format!("{}_{}", shape.kind, self.objects.len() + 1);
+        let (primitive, light) = match shape.kind.as_str() {
+            "sphere" => {
+                let center = transform.transform_point(Point3::zero());
+                let radius = params.float("radius", 1.0) * transform.uniform_scale();
+                let light = shape
+                    .state
+                    .area_light
+                    .map(|radiance| Emissive::new(radiance, center, radius));
+                (Primitive::new_sphere(center, radius), light)
+            }
+            kind => {
+                let Some((vertices, indices)) = self.mesh(kind, params) else {
+                    return;
+                };
+                if indices.iter().any(|&i| i as usize >= vertices.len()) {
+                    warn!("pbrt: {} has vertex indices out of range, skipped", name);
+                    return;
+                }
+                let vertices: Vec<Point3> = vertices
+                    .iter()
+                    .map(|&p| transform.transform_point(p))
+                    .collect();
+                let light = shape
+                    .state
+                    .area_light
+                    .map(|radiance| Emissive::for_mesh(radiance, &vertices, &indices));
+                (Primitive::new_mesh(vertices, indices), light)
+            }
+        };
+        let material = match light {
+            Some(light) => MaterialType::Emissive(light),
+            None => material,
+        };
+        self.objects.push(DocObject::new(name, primitive, material));
+    }
+
+    /// The vertices and triangle indices of a mesh shape, in object space.
+    fn mesh(&mut self, kind: &str, params: &Params) -> Option<(Vec<Point3>, Vec<u32>)> {
+        match kind {
+            "trianglemesh" | "loopsubdiv" => {
+                if kind == "loopsubdiv" {
+                    self.warn_once("subdivision surfaces rendered as their cage".to_string());
+                }
+                let vertices = params.points("P");
+                let indices = params
+                    .indices("indices")
+                    .unwrap_or_else(|| (0..vertices.len() as u32).collect());
+                Some((vertices, indices))
+            }
+            "bilinearmesh" => {
+                let vertices = params.points("P");
+                let quads = params
+                    .indices("indices")
+                    .unwrap_or_else(|| (0..vertices.len() as u32).collect());
+                // The patches are split along their p00-p11 diagonal
+                let indices = quads
+                    .chunks_exact(4)
+                    .flat_map(|q| [q[0], q[1], q[3], q[0], q[3], q[2]])
+                    .collect();
+                Some((vertices, indices))
+            }
+            "disk" => {
+                const SEGMENTS: u32 = 64;
+                let radius = params.float("radius", 1.0);
+                let height = params.float("height", 0.0);
+                let mut vertices = vec![Point3::new(0.0, 0.0, height)];
+                let mut indices = Vec::new();
+                for i in 0..SEGMENTS {
+                    let phi = 2.0 * utils::consts::PI * i as Float / SEGMENTS as Float;
+                    vertices.push(Point3::new(radius * phi.cos(), radius * phi.sin(), height));
+                    indices.extend([0, i + 1, (i + 1) % SEGMENTS + 1]);
+                }
+                Some((vertices, indices))
+            }
+            "plymesh" => {
+                let file = self
+                    .directory
+                    .join(params.string("filename").unwrap_or_default());
+                match load_ply_mesh(&file) {
+                    Ok(mesh) => Some(mesh),
+                    Err(e) => {
+                        warn!("pbrt: failed to load {}: {}", file.display(), e);
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.warn_once(format!("{kind} shapes are not supported"));
+                None
+            }
+        }
+    }
+
+    /// The document of the parsed scene.
+    fn document(self) -> Result<Document, RenderError> {
+        let width = self.film.int("xresolution", 1280).max(1) as usize;
+        let height = self.film.int("yresolution", 720).max(1) as usize;
+        let aspect_ratio = width as Float / height as Float;
+        let (camera_to_world, params) = self.camera.unwrap_or_default();
+        let camera_to_world = world(&camera_to_world);
+        let eye = camera_to_world.transform_point(Point3::zero());
+        let look = camera_to_world.transform_point(Point3::new(0.0, 0.0, 1.0));
+        let up = camera_to_world.transform_direction(Vec3::new(0.0, 1.0, 0.0));
+        // The field of view is that of the shorter side of the image
+        let fov = params.float("fov", 90.0);
+        let vfov = if aspect_ratio >= 1.0 {
+            fov
+        } else {
+            let half = utils::degrees_to_radians(fov) / 2.0;
+            2.0 * (half.tan() / aspect_ratio).atan().to_degrees()
+        };
+        let lens_radius = params.float("lensradius", 0.0);
+        let focus_distance = if lens_radius > 0.0 {
+            params.float("focaldistance", 1e6)
+        } else {
+            1.0
+        };
+        let camera = Camera::new(
+            eye,
+            look,
+            up,
+            vfov,
+            aspect_ratio,
+            2.0 * lens_radius,
+            focus_distance,
+        );
+
+        let samples_per_pixel = self.sampler.int("pixelsamples", 16).max(1);
+        let (kind, integrator) = &self.integrator;
+        let max_depth = integrator.int("maxdepth", 5);
+        let integrator_type = match kind.as_str() {
+            "ambientocclusion" => IntegratorType::AmbientOcclusion {
+                max_distance: integrator.float("maxdistance", Float::MAX),
+            },
+            "path" | "volpath" => IntegratorType::Path,
+            _ => {
+                warn!("pbrt: {} integrator replaced by the path tracer", kind);
+                IntegratorType::Path
+            }
+        };
+        // Every pixel takes all its samples, as pbrt does
+        let settings = RenderSettings::new(
+            samples_per_pixel,
+            max_depth,
+            width,
+            height,
+            samples_per_pixel,
+            0.0,
+        )
+        .with_integrator(integrator_type);
+        debug!("pbrt: {} objects", self.objects.len());
+        Ok(Document::new(
+            camera,
+            ObjectList::new(self.objects),
+            settings,
+        ))
+    }
+}
+
+/// The transform from the space of the pbrt scene to ours.
+///
+/// pbrt is left-handed: the same camera sees the mirror image of the scene it sees
+/// here. The scene and the camera are mirrored along x, so that the images match.
+fn world(transform: &Mat4) -> Mat4 {
+    Mat4::scale(-1.0, 1.0, 1.0).compose(transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_scene(text: &str) -> Result<Document, RenderError> {
+        read_text(text, PathBuf::new())
+    }
+
+    fn parse_error(text: &str) -> String {
+        match read_scene(text) {
+            Err(RenderError::Parse(message)) => message,
+            Err(e) => panic!("expected a parse error, got {e}"),
+            Ok(_) => panic!("expected a parse error for {text:?}"),
+        }
+    }
+
+    /// The center and radius of the spheres of a scene, in order.
+    fn spheres(doc: &Document) -> Vec<(Point3, Float)> {
+        doc.object_list
+            .objects()
+            .iter()
+            .filter_map(|object| match object.object() {
+                Primitive::Sphere { center, radius } => Some((*center, *radius)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_quoted_strings() {
+        let tokens = tokenize(r#"Shape "sphere" "with \"quotes\"" "true" false"#).unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Word("Shape".to_string()),
+                Token::Str("sphere".to_string()),
+                Token::Str("with \"quotes\"".to_string()),
+                Token::Bool(true),
+                Token::Bool(false),
+            ]
+        );
+        assert!(tokenize(r#"Shape "sphere"#).is_err());
+    }
+
+    #[test]
+    fn skips_comments() {
+        let text = "# A comment\nTranslate 1 2 3 # Another one\n#Shape \"sphere\"\n[4]";
+        assert_eq!(
+            tokenize(text).unwrap(),
+            [
+                Token::Word("Translate".to_string()),
+                Token::Num(1.0),
+                Token::Num(2.0),
+                Token::Num(3.0),
+                Token::Open,
+                Token::Num(4.0),
+                Token::Close,
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_typed_parameter_arrays() {
+        let text = r#""rgb reflectance" [0.1 0.2 0.3] "float radius" 2 "string type" ["diffuse"]
+            "point3 P" [0 0 0 1 0 0 0 1 0] "integer indices" [0 1 2] "bool flip" true"#;
+        let mut parser = Parser::new(tokenize(text).unwrap(), PathBuf::new());
+        let params = parser.params().unwrap();
+        assert_eq!(params.get("reflectance").unwrap().kind, "rgb");
+        assert_eq!(params.floats("reflectance").unwrap(), [0.1, 0.2, 0.3]);
+        assert_eq!(params.float("radius", 1.0), 2.0);
+        assert_eq!(params.float("missing", 1.0), 1.0);
+        assert_eq!(params.string("type"), Some("diffuse"));
+        assert_eq!(params.points("P").len(), 3);
+        assert_eq!(params.points("P")[1].x(), 1.0);
+        assert_eq!(params.indices("indices").unwrap(), [0, 1, 2]);
+        assert_eq!(params.get("flip").unwrap().values, [Token::Bool(true)]);
+        assert!(parser.peek().is_none());
+    }
+
+    #[test]
+    fn scopes_attributes() {
+        let doc = read_scene(
+            r#"WorldBegin
+            AttributeBegin
+                Translate 1 0 0
+                Material "conductor" "rgb reflectance" [0.9 0.9 0.9]
+                Shape "sphere" "float radius" 2
+            AttributeEnd
+            Shape "sphere"
+            TransformBegin
+                Translate 0 3 0
+                Material "dielectric"
+            TransformEnd
+            Shape "sphere"
+            WorldEnd"#,
+        )
+        .unwrap();
+        let spheres = spheres(&doc);
+        assert_eq!(spheres.len(), 3);
+        // The scene is mirrored along x, see `world`
+        let (center, radius) = spheres[0];
+        assert_eq!((center.x(), center.y(), radius), (-1.0, 0.0, 2.0));
+        let (center, radius) = spheres[1];
+        assert_eq!((center.x(), center.y(), radius), (0.0, 0.0, 1.0));
+        // TransformEnd restores the transform only, not the material
+        let (center, _) = spheres[2];
+        assert_eq!((center.x(), center.y()), (0.0, 0.0));
+        let materials: Vec<&MaterialType> = doc
+            .object_list
+            .objects()
+            .iter()
+            .map(|object| object.material())
+            .collect();
+        assert!(matches!(materials[0], MaterialType::Metal(_)));
+        assert!(matches!(materials[1], MaterialType::Lambertian(_)));
+        assert!(matches!(materials[2], MaterialType::Dielectric(_)));
+    }
+
+    #[test]
+    fn skips_unsupported_directives() {
+        let doc = read_scene(
+            r#"MakeNamedMedium "fog" "string type" "homogeneous" "rgb sigma_a" [1 1 1]
+            WorldBegin
+            Shape "curve" "point3 P" [0 0 0 1 1 1 2 2 2 3 3 3]
+            LightSource "infinite" "rgb L" [1 1 1]
+            Shape "sphere" "float radius" 0.5"#,
+        )
+        .unwrap();
+        assert_eq!(doc.object_list.objects().len(), 1);
+        assert_eq!(spheres(&doc)[0].1, 0.5);
+    }
+
+    #[test]
+    fn reports_malformed_directives() {
+        assert!(parse_error("AttributeEnd").contains("unmatched AttributeEnd"));
+        assert!(parse_error("WorldBegin 1 2").contains("expected a directive"));
+        assert!(parse_error(r#"Shape "sphere" "radius" 1"#).contains("invalid parameter"));
+        assert!(parse_error(r#"Shape "sphere" "float radius" [1"#).contains("unterminated"));
+        assert!(parse_error(r#"Shape "sphere" "float radius" Shape"#).contains("has no value"));
+        assert!(parse_error("Translate 1 2").contains("Translate expects 3 numbers"));
+        assert!(parse_error("Camera perspective").contains("Camera expects a string"));
+        assert!(parse_error("Scale 0 0 0 Camera \"perspective\"").contains("singular"));
+    }
+}
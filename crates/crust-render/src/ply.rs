@@ -0,0 +1,222 @@
+use crate::error::RenderError;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use utils::{Float, Point3};
+
+/// The encoding of the body of a PLY file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// A scalar type of the PLY properties.
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, RenderError> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid(format!("unknown property type {name:?}"))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+/// A property of a PLY element: a scalar, or a list of scalars after its length.
+#[derive(Debug)]
+struct Property {
+    name: String,
+    count: Option<Scalar>,
+    value: Scalar,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of a PLY body, whatever its encoding.
+struct Values<R: BufRead> {
+    reader: R,
+    format: Format,
+    line: std::vec::IntoIter<String>,
+}
+
+impl<R: BufRead> Values<R> {
+    fn next(&mut self, scalar: Scalar) -> Result<f64, RenderError> {
+        if self.format == Format::Ascii {
+            loop {
+                if let Some(token) = self.line.next() {
+                    return token
+                        .parse()
+                        .map_err(|e| invalid(format!("invalid value {token:?}: {e}")));
+                }
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(invalid("unexpected end of file".to_string()));
+                }
+                self.line = line
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+            }
+        }
+        let mut raw = [0u8; 8];
+        let size = scalar.size();
+        self.reader.read_exact(&mut raw[..size])?;
+        if self.format == Format::BigEndian {
+            raw[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = raw;
+        Ok(match scalar {
+            Scalar::I8 => b0 as i8 as f64,
+            Scalar::U8 => b0 as f64,
+            Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F64 => f64::from_le_bytes(raw),
+        })
+    }
+}
+
+fn invalid(message: String) -> RenderError {
+    RenderError::Parse(message)
+}
+
+/// Loads the vertices and triangle indices of a PLY mesh, the polygons being split
+/// into fans of triangles. The other properties, e.g. the normals and the UVs, are
+/// ignored.
+pub(crate) fn load_ply_mesh(path: &Path) -> Result<(Vec<Point3>, Vec<u32>), RenderError> {
+    let file = std::fs::File::open(path).map_err(|e| RenderError::file(path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("the header does not end".to_string()));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", encoding, _] => {
+                format = Some(match *encoding {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(invalid(format!("unknown format {encoding:?}"))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|e| invalid(format!("invalid element count: {e}")))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, value, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("property outside an element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    count: Some(Scalar::parse(count)?),
+                    value: Scalar::parse(value)?,
+                });
+            }
+            ["property", value, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("property outside an element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    count: None,
+                    value: Scalar::parse(value)?,
+                });
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| invalid("the format is missing".to_string()))?;
+    let mut values = Values {
+        reader,
+        format,
+        line: Vec::new().into_iter(),
+    };
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position: [Float; 3] = [0.0; 3];
+            let mut polygon: Vec<u32> = Vec::new();
+            for property in &element.properties {
+                let Some(count) = property.count else {
+                    let value = values.next(property.value)? as Float;
+                    match property.name.as_str() {
+                        "x" => position[0] = value,
+                        "y" => position[1] = value,
+                        "z" => position[2] = value,
+                        _ => {}
+                    }
+                    continue;
+                };
+                let count = values.next(count)? as usize;
+                let face = element.name == "face"
+                    && matches!(property.name.as_str(), "vertex_indices" | "vertex_index");
+                for _ in 0..count {
+                    let value = values.next(property.value)?;
+                    if face {
+                        polygon.push(value as u32);
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                vertices.push(Point3::new(position[0], position[1], position[2]));
+            }
+            for i in 2..polygon.len() {
+                indices.extend([polygon[0], polygon[i - 1], polygon[i]]);
+            }
+        }
+    }
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        return Err(invalid(format!("vertex index {index} out of range")));
+    }
+    Ok((vertices, indices))
+}
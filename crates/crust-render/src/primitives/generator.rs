@@ -2,13 +2,13 @@ use crate::MaterialType;
 use crate::document::DocObject;
 use crate::primitives::Primitive;
 
-use std::f32::consts::PI;
-use utils::Vec3;
+use utils::consts::PI;
+use utils::{Float, Vec3};
 
 type Triangles = (
     Vec<Vec3>,
     Vec<Vec3>,
-    Vec<(f32, f32)>,
+    Vec<(Float, Float)>,
     Vec<(usize, usize, usize)>,
 );
 
@@ -35,7 +35,7 @@ trait Triangulable {
 }
 
 pub struct UVSphere {
-    radius: f32,
+    radius: Float,
     stacks: usize,
     sectors: usize,
 }
@@ -47,12 +47,12 @@ impl Triangulable for UVSphere {
         let mut indices = Vec::new();
 
         for i in 0..=self.stacks {
-            let stack_angle = PI / 2.0 - i as f32 * PI / self.stacks as f32; // from +pi/2 to -pi/2
+            let stack_angle = PI / 2.0 - i as Float * PI / self.stacks as Float; // from +pi/2 to -pi/2
             let xy = self.radius * stack_angle.cos();
             let z = self.radius * stack_angle.sin();
 
             for j in 0..=self.sectors {
-                let sector_angle = j as f32 * 2.0 * PI / self.sectors as f32; // from 0 to 2pi
+                let sector_angle = j as Float * 2.0 * PI / self.sectors as Float; // from 0 to 2pi
 
                 let x = xy * sector_angle.cos();
                 let y = xy * sector_angle.sin();
@@ -60,8 +60,8 @@ impl Triangulable for UVSphere {
                 vertices.push(position);
                 normals.push(position.unit_vector());
 
-                let u = j as f32 / self.sectors as f32;
-                let v = i as f32 / self.stacks as f32;
+                let u = j as Float / self.sectors as Float;
+                let v = i as Float / self.stacks as Float;
                 uvs.push((u, v));
             }
         }
@@ -81,7 +81,7 @@ impl Triangulable for UVSphere {
     }
 }
 impl UVSphere {
-    pub fn new(radius: f32, stacks: usize, sectors: usize) -> Self {
+    pub fn new(radius: Float, stacks: usize, sectors: usize) -> Self {
         UVSphere {
             radius,
             stacks,
@@ -94,8 +94,8 @@ impl UVSphere {
 }
 pub struct UVTorus {
     pub position: Vec3,
-    pub major_radius: f32,
-    pub minor_radius: f32,
+    pub major_radius: Float,
+    pub minor_radius: Float,
     pub segments: usize,
     pub sides: usize,
 }
@@ -107,12 +107,12 @@ impl Triangulable for UVTorus {
         let mut indices = Vec::new();
 
         for i in 0..=self.segments {
-            let seg_angle = i as f32 / self.segments as f32 * 2.0 * PI;
+            let seg_angle = i as Float / self.segments as Float * 2.0 * PI;
             let cos_seg = seg_angle.cos();
             let sin_seg = seg_angle.sin();
 
             for j in 0..=self.sides {
-                let side_angle = j as f32 / self.sides as f32 * 2.0 * PI;
+                let side_angle = j as Float / self.sides as Float * 2.0 * PI;
                 let cos_side = side_angle.cos();
                 let sin_side = side_angle.sin();
 
@@ -128,8 +128,8 @@ impl Triangulable for UVTorus {
                 let center = Vec3::new(cx, cy, self.position.z());
                 normals.push((position - center).unit_vector());
 
-                let u = i as f32 / self.segments as f32;
-                let v = j as f32 / self.sides as f32;
+                let u = i as Float / self.segments as Float;
+                let v = j as Float / self.sides as Float;
                 uvs.push((u, v));
             }
         }
@@ -150,8 +150,8 @@ impl Triangulable for UVTorus {
 impl UVTorus {
     pub fn new(
         position: Vec3,
-        major_radius: f32,
-        minor_radius: f32,
+        major_radius: Float,
+        minor_radius: Float,
         segments: usize,
         sides: usize,
     ) -> Self {
@@ -3,3 +3,5 @@ mod prim;
 pub use generator::{UVSphere, UVTorus};
 pub use prim::Object;
 pub use prim::Primitive;
+pub(crate) use prim::{BVHNode, MeshBVH};
+pub(crate) use prim::{load_obj_mesh, load_scaled_obj_mesh, mesh_triangles};
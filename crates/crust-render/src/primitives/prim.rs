@@ -1,24 +1,28 @@
 use crate::aabb::{AABB, triangle_aabb};
+use crate::animation::ObjectAnimation;
+use crate::error::RenderError;
 use crate::hittable::{HitRecord, Hittable};
-use crate::material::Material;
+use crate::material::SceneMaterial;
+use crate::progress;
 use crate::ray::Ray;
+use crate::validate;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::BufReader;
-use rand::Rng;
+use std::io::{self, BufReader, Read, Write};
 use std::sync::Arc;
-use std::sync::RwLock;
-use tracing::error;
-use utils::Point3;
+use std::sync::OnceLock;
+use tracing::{debug, error, trace_span};
+use utils::{Float, Mat4, Point3, Vec3};
+use web_time::Instant;
 
-use obj::{Obj, load_obj};
+use obj::{Obj, Position, load_obj};
 
 /// Geometric primitives that can be serialized.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Primitive {
     Sphere {
         center: Point3,
-        radius: f32,
+        radius: Float,
     },
     Triangle {
         v0: Point3,
@@ -31,11 +35,23 @@ pub enum Primitive {
     },
     Obj {
         path: String,
+        /// Scale of the vertices of the file, e.g. to convert them to the unit of the
+        /// scene.
+        #[serde(default = "unit_scale", skip_serializing_if = "is_unit_scale")]
+        scale: Float,
     },
 }
 
+fn unit_scale() -> Float {
+    1.0
+}
+
+fn is_unit_scale(scale: &Float) -> bool {
+    *scale == 1.0
+}
+
 impl Primitive {
-    pub fn new_sphere(center: Point3, radius: f32) -> Self {
+    pub fn new_sphere(center: Point3, radius: Float) -> Self {
         Self::Sphere { center, radius }
     }
 
@@ -46,48 +62,270 @@ impl Primitive {
         Self::Mesh { vertices, indices }
     }
     pub fn new_obj(path: String) -> Self {
-        Self::Obj { path }
+        Self::Obj { path, scale: 1.0 }
+    }
+
+    /// Scales the primitive around the origin by `factor`, e.g. to convert it to
+    /// another unit.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        match self {
+            Primitive::Sphere { center, radius } => {
+                *center = factor * *center;
+                *radius *= factor;
+            }
+            Primitive::Triangle { v0, v1, v2 } => {
+                for v in [v0, v1, v2] {
+                    *v = factor * *v;
+                }
+            }
+            Primitive::Mesh { vertices, .. } => {
+                for v in vertices {
+                    *v = factor * *v;
+                }
+            }
+            Primitive::Obj { scale, .. } => *scale *= factor,
+        }
+    }
+
+    /// The problems of the primitive, e.g. a zero radius or a mesh index out of
+    /// range, for the validation of the scene.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self {
+            Primitive::Sphere { center, radius } => {
+                validate::check_finite(&mut problems, "center", *center);
+                validate::check_positive(&mut problems, "radius", *radius);
+            }
+            Primitive::Triangle { v0, v1, v2 } => {
+                for v in [v0, v1, v2] {
+                    validate::check_finite(&mut problems, "vertex", *v);
+                }
+            }
+            Primitive::Mesh { vertices, indices } => {
+                if let Some(v) = vertices.iter().find(|&&v| !validate::is_finite(v)) {
+                    validate::check_finite(&mut problems, "vertex", *v);
+                }
+                if indices.len() % 3 != 0 {
+                    problems.push(format!(
+                        "the {} indices are not a multiple of 3",
+                        indices.len()
+                    ));
+                }
+                if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+                    problems.push(format!(
+                        "the index {index} is out of the {} vertices",
+                        vertices.len()
+                    ));
+                }
+            }
+            Primitive::Obj { path, scale } => {
+                validate::check_positive(&mut problems, "scale", *scale);
+                if !std::path::Path::new(path).is_file() {
+                    problems.push(format!("the OBJ file {path} does not exist"));
+                }
+            }
+        }
+        problems
     }
 }
 
 pub struct Object {
     pub primitive: Primitive,
-    pub material: Arc<dyn Material>,
-    pub obj_cache: RwLock<Option<Arc<dyn Hittable>>>,
+    pub material: Arc<SceneMaterial>,
+    /// The BVH of the triangles of a mesh, built on the first hit unless it was
+    /// loaded with the scene, or `None` when the mesh failed to load or is empty.
+    pub obj_cache: OnceLock<Option<Arc<MeshBVH>>>,
+    /// The object and material IDs stamped on the hit records, for the ID AOVs.
+    pub ids: (u32, u32),
+    /// The displacement of the object over the shutter interval, for motion blur.
+    pub velocity: Vec3,
+    /// The displacement of the object from where it is defined, e.g. at a frame of an
+    /// animation.
+    pub translation: Vec3,
+    /// The keyframed transform of the object, applied before the displacements.
+    pub keyframes: Option<Keyframes>,
+}
+
+/// The keyframed transform of an object at a frame of its animation, the shutter
+/// interval spanning the frame.
+pub struct Keyframes {
+    animation: Arc<ObjectAnimation>,
+    frame: Float,
+    /// The transform and its inverse at the shutter opening, the time of most rays.
+    opening: (Mat4, Mat4),
+}
+
+impl Keyframes {
+    pub fn new(animation: Arc<ObjectAnimation>, frame: Float) -> Self {
+        let opening = Self::pose(&animation, frame);
+        Self {
+            animation,
+            frame,
+            opening,
+        }
+    }
+
+    fn pose(animation: &ObjectAnimation, frame: Float) -> (Mat4, Mat4) {
+        let transform = animation.transform_at(frame);
+        // A singular transform, e.g. of a zero scale, is left out
+        let inverse = transform.inverse().unwrap_or_default();
+        (transform, inverse)
+    }
+
+    /// The transform and its inverse at a time of the shutter interval.
+    fn at(&self, time: Float) -> (Mat4, Mat4) {
+        if time == 0.0 {
+            self.opening
+        } else {
+            Self::pose(&self.animation, self.frame + time)
+        }
+    }
+
+    /// The box of the object over the shutter interval, from the box where it is
+    /// defined transformed at a few times, padded by how far they are apart.
+    fn swept(&self, defined: AABB) -> AABB {
+        const TIMES: usize = 8;
+        let corners: Vec<Point3> = (0..8)
+            .map(|i| {
+                Point3::new(
+                    if i & 1 == 0 {
+                        defined.minimum.x()
+                    } else {
+                        defined.maximum.x()
+                    },
+                    if i & 2 == 0 {
+                        defined.minimum.y()
+                    } else {
+                        defined.maximum.y()
+                    },
+                    if i & 4 == 0 {
+                        defined.minimum.z()
+                    } else {
+                        defined.maximum.z()
+                    },
+                )
+            })
+            .collect();
+        let mut minimum = Point3::new(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+        let mut maximum = -minimum;
+        let mut step: Float = 0.0;
+        let mut previous: Option<Vec<Point3>> = None;
+        for i in 0..=TIMES {
+            let (transform, _) = self.at(i as Float / TIMES as Float);
+            let moved: Vec<Point3> = corners
+                .iter()
+                .map(|&c| transform.transform_point(c))
+                .collect();
+            for p in &moved {
+                minimum = Point3::new(
+                    minimum.x().min(p.x()),
+                    minimum.y().min(p.y()),
+                    minimum.z().min(p.z()),
+                );
+                maximum = Point3::new(
+                    maximum.x().max(p.x()),
+                    maximum.y().max(p.y()),
+                    maximum.z().max(p.z()),
+                );
+            }
+            if let Some(previous) = &previous {
+                for (a, b) in previous.iter().zip(&moved) {
+                    step = step.max((*b - *a).length());
+                }
+            }
+            previous = Some(moved);
+        }
+        let pad = Vec3::new(step, step, step) / 2.0;
+        AABB::new(minimum - pad, maximum + pad)
+    }
 }
 
 impl Object {
-    pub fn new_sphere(center: Point3, radius: f32, material: Arc<dyn Material>) -> Self {
+    pub fn new_sphere(center: Point3, radius: Float, material: Arc<SceneMaterial>) -> Self {
         Self {
             primitive: Primitive::new_sphere(center, radius),
             material,
-            obj_cache: RwLock::new(None),
+            obj_cache: OnceLock::new(),
+            ids: (0, 0),
+            velocity: Vec3::zero(),
+            translation: Vec3::zero(),
+            keyframes: None,
         }
     }
 
-    pub fn new_triangle(v0: Point3, v1: Point3, v2: Point3, material: Arc<dyn Material>) -> Self {
+    pub fn new_triangle(v0: Point3, v1: Point3, v2: Point3, material: Arc<SceneMaterial>) -> Self {
         Self {
             primitive: Primitive::new_triangle(v0, v1, v2),
             material,
-            obj_cache: RwLock::new(None),
+            obj_cache: OnceLock::new(),
+            ids: (0, 0),
+            velocity: Vec3::zero(),
+            translation: Vec3::zero(),
+            keyframes: None,
         }
     }
 
-    pub fn new_mesh(vertices: Vec<Point3>, indices: Vec<u32>, material: Arc<dyn Material>) -> Self {
+    pub fn new_mesh(
+        vertices: Vec<Point3>,
+        indices: Vec<u32>,
+        material: Arc<SceneMaterial>,
+    ) -> Self {
         Self {
             primitive: Primitive::Mesh { vertices, indices },
             material,
-            obj_cache: RwLock::new(None),
+            obj_cache: OnceLock::new(),
+            ids: (0, 0),
+            velocity: Vec3::zero(),
+            translation: Vec3::zero(),
+            keyframes: None,
         }
     }
 
-    pub fn new_obj(path: String, material: Arc<dyn Material>) -> Self {
+    /// Creates a mesh loaded from an OBJ file on the first hit, its vertices scaled by
+    /// `scale`.
+    pub fn new_obj(path: String, scale: Float, material: Arc<SceneMaterial>) -> Self {
         Self {
-            primitive: Primitive::Obj { path },
+            primitive: Primitive::Obj { path, scale },
             material,
-            obj_cache: RwLock::new(None),
+            obj_cache: OnceLock::new(),
+            ids: (0, 0),
+            velocity: Vec3::zero(),
+            translation: Vec3::zero(),
+            keyframes: None,
         }
     }
+
+    /// Sets the object and material IDs reported by the hits on this object.
+    pub fn with_ids(mut self, object_id: u32, material_id: u32) -> Self {
+        self.ids = (object_id, material_id);
+        self
+    }
+
+    /// Moves the object linearly by `velocity` over the shutter interval.
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    /// Moves the object by `translation` from where it is defined.
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    /// Transforms the object by its keyframed animation at `frame`, and over the
+    /// frame for motion blur.
+    pub fn with_keyframes(mut self, animation: Arc<ObjectAnimation>, frame: Float) -> Self {
+        self.keyframes = Some(Keyframes::new(animation, frame));
+        self
+    }
+
+    /// Sets the BVH of a mesh built beforehand, e.g. read from the BVH cache of a
+    /// baked scene, instead of building it on the first hit.
+    pub(crate) fn with_bvh(mut self, bvh: Arc<MeshBVH>) -> Self {
+        self.obj_cache = OnceLock::from(Some(bvh));
+        self
+    }
 }
 
 impl Hittable for Object {
@@ -95,10 +333,11 @@ impl Hittable for Object {
         match &self.primitive {
             Primitive::Sphere { center, radius } => {
                 let r_vec = Point3::new(*radius, *radius, *radius);
-                Some(AABB::new(*center - r_vec, *center + r_vec))
+                let start = AABB::new(*center - r_vec, *center + r_vec);
+                Some(self.swept(start))
             }
 
-            Primitive::Triangle { v0, v1, v2 } => Some(triangle_aabb(*v0, *v1, *v2)),
+            Primitive::Triangle { v0, v1, v2 } => Some(self.swept(triangle_aabb(*v0, *v1, *v2))),
 
             Primitive::Mesh { .. } | Primitive::Obj { .. } => {
                 // These will be handled via BVH built at load time,
@@ -107,9 +346,66 @@ impl Hittable for Object {
             }
         }
     }
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // A moved object is intersected in its frame at the time of the ray
+        let time = r.time();
+        let offset = self.translation + self.velocity * time;
+        let moving = offset.length_squared() > 0.0;
+        let pose = self.keyframes.as_ref().map(|keyframes| keyframes.at(time));
+        let mut rec = match &pose {
+            Some((_, inverse)) => {
+                let local = r.spawn(
+                    inverse.transform_point(r.origin() - offset),
+                    inverse.transform_direction(r.direction()),
+                );
+                self.hit_primitive(&local, t_min, t_max)
+            }
+            None if moving => {
+                let local = r.spawn(r.origin() - offset, r.direction());
+                self.hit_primitive(&local, t_min, t_max)
+            }
+            None => self.hit_primitive(r, t_min, t_max),
+        }?;
+        rec.velocity = self.velocity;
+        if let (Some((transform, inverse)), Some(keyframes)) = (&pose, &self.keyframes) {
+            let local = rec.p;
+            rec.p = transform.transform_point(local);
+            rec.normal = inverse.transpose_direction(rec.normal).unit_vector();
+            let (closing, _) = keyframes.at(1.0);
+            rec.velocity +=
+                closing.transform_point(local) - keyframes.opening.0.transform_point(local);
+        }
+        if moving {
+            rec.p += offset;
+        }
+        (rec.object_id, rec.material_id) = self.ids;
+        Some(rec)
+    }
+}
+
+impl Object {
+    /// Moves the box of the object where it is defined to the shutter opening, and
+    /// extends it to cover the motion of the object.
+    fn swept(&self, defined: AABB) -> AABB {
+        let defined = match &self.keyframes {
+            Some(keyframes) => keyframes.swept(defined),
+            None => defined,
+        };
+        let start = AABB::new(
+            defined.minimum + self.translation,
+            defined.maximum + self.translation,
+        );
+        if self.velocity.length_squared() == 0.0 {
+            return start;
+        }
+        let end = AABB::new(start.minimum + self.velocity, start.maximum + self.velocity);
+        AABB::surrounding_box(start, end)
+    }
+
+    fn hit_primitive(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         match &self.primitive {
             Primitive::Sphere { center, radius } => {
+                progress::count_primitive_test();
                 let oc = r.origin() - *center;
                 let a = r.direction().length_squared();
                 let half_b = utils::dot(oc, r.direction());
@@ -117,7 +413,7 @@ impl Hittable for Object {
                 let discriminant = half_b * half_b - a * c;
 
                 if discriminant < 0.0 {
-                    return false;
+                    return None;
                 }
 
                 let sqrt_d = discriminant.sqrt();
@@ -126,245 +422,413 @@ impl Hittable for Object {
                 if root <= t_min || root >= t_max {
                     root = (-half_b + sqrt_d) / a;
                     if root <= t_min || root >= t_max {
-                        return false;
+                        return None;
                     }
                 }
 
-                rec.t = root;
-                rec.p = r.at(root);
-                let outward_normal = (rec.p - *center) / *radius;
-                rec.set_face_normal(r, outward_normal);
-                rec.mat = Some(self.material.clone());
-                true
+                let outward_normal = (r.at(root) - *center) / *radius;
+                Some(HitRecord::new(
+                    r,
+                    root,
+                    outward_normal,
+                    self.material.as_ref(),
+                ))
             }
 
             Primitive::Triangle { v0, v1, v2 } => {
-                triangle_hit(r, *v0, *v1, *v2, t_min, t_max, rec, &self.material)
-            }
-
-            Primitive::Mesh { vertices, indices } => {
-                indexed_mesh_hit(r, vertices, indices, t_min, t_max, rec, &self.material)
+                triangle_hit(r, *v0, *v1, *v2, t_min, t_max, self.material.as_ref())
             }
 
-            Primitive::Obj { path } => {
-                // Step 1: Check cache
-                {
-                    let cache = self.obj_cache.read().unwrap();
-                    if let Some(bvh) = &*cache {
-                        return bvh.hit(r, t_min, t_max, rec);
-                    }
-                }
-
-                // Step 2: Load the OBJ
-                use std::path::Path;
-                if !Path::new(path).exists() {
-                    error!("OBJ file {} does not exist", path);
-                    return false;
-                }
-
-                let file = File::open(path).unwrap_or_else(|e| {
-                    error!("Failed to open OBJ file {}: {}", path, e);
-                    panic!("Cannot open OBJ file");
-                });
-
-                let input = BufReader::new(file);
-                let obj: Obj = match load_obj(input) {
-                    Ok(o) => o,
-                    Err(e) => {
-                        error!("Failed to parse OBJ file {}: {}", path, e);
-                        return false;
-                    }
-                };
-
-                // Step 3: Convert to triangle Objects
-                let vertices: Vec<Point3> =
-                    obj.vertices.iter().map(|v| v.position.into()).collect();
-                let indices: Vec<u32> = obj.indices.iter().map(|&i| i as u32).collect();
+            Primitive::Mesh { vertices, indices } => self.mesh_hit(r, t_min, t_max, || {
+                Some((vertices.clone(), indices.clone()))
+            }),
 
-                let mut triangle_objs: Vec<Arc<dyn Hittable>> =
-                    Vec::with_capacity(indices.len() / 3);
-
-                for i in (0..indices.len()).step_by(3) {
-                    let v0 = vertices[indices[i] as usize];
-                    let v1 = vertices[indices[i + 1] as usize];
-                    let v2 = vertices[indices[i + 2] as usize];
+            Primitive::Obj { path, scale } => self.mesh_hit(r, t_min, t_max, || {
+                load_scaled_obj_mesh(path, *scale)
+                    .map_err(|e| error!("Failed to load OBJ file {}: {}", path, e))
+                    .ok()
+            }),
+        }
+    }
 
-                    let tri = Arc::new(Object::new_triangle(v0, v1, v2, self.material.clone()));
-                    triangle_objs.push(tri);
-                }
+    /// Intersects the triangles of a mesh through their BVH, built from the mesh
+    /// returned by `load` on the first hit and cached.
+    fn mesh_hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        load: impl FnOnce() -> Option<(Vec<Point3>, Vec<u32>)>,
+    ) -> Option<HitRecord<'_>> {
+        // The other threads wait for the first to build it
+        let bvh = self.obj_cache.get_or_init(|| {
+            let (vertices, indices) = load()?;
+            let triangles = mesh_triangles(&vertices, &indices);
+            if triangles.is_empty() {
+                return None;
+            }
+            // A stream of its own, so that the BVH and the random numbers of the pixel
+            // hitting the mesh first do not depend on which pixel it is
+            Some(Arc::new(utils::with_random_stream(0, 0, || {
+                MeshBVH::build(triangles)
+            })))
+        });
+        bvh.as_ref()?.hit(r, t_min, t_max, self.material.as_ref())
+    }
+}
 
-                // Step 4: Build BVH
-                let bvh = BVHNode::build(triangle_objs);
+/// The triangles of a mesh, from its vertices and triangle indices.
+pub(crate) fn mesh_triangles(vertices: &[Point3], indices: &[u32]) -> Vec<[Point3; 3]> {
+    indices
+        .chunks_exact(3)
+        .map(|i| {
+            [
+                vertices[i[0] as usize],
+                vertices[i[1] as usize],
+                vertices[i[2] as usize],
+            ]
+        })
+        .collect()
+}
 
-                // Step 5: Cache it
-                {
-                    let mut cache = self.obj_cache.write().unwrap();
-                    *cache = Some(bvh.clone());
-                }
+/// Loads the vertices and triangle indices of an OBJ file, whether its faces have
+/// normals and texture coordinates or not.
+pub(crate) fn load_obj_mesh(path: &str) -> Result<(Vec<Point3>, Vec<u32>), RenderError> {
+    let input = BufReader::new(File::open(path).map_err(|e| RenderError::file(path, e))?);
+    let obj: Obj<Position, u32> =
+        load_obj(input).map_err(|e| RenderError::Parse(format!("{path}: {e}")))?;
+    let vertices = obj
+        .vertices
+        .iter()
+        .map(|v| v.position.map(|x| x as Float).into())
+        .collect();
+    Ok((vertices, obj.indices))
+}
 
-                // Step 6: Intersect using the cached BVH
-                bvh.hit(r, t_min, t_max, rec)
-            }
+/// Loads the vertices, scaled by `scale`, and triangle indices of an OBJ file.
+pub(crate) fn load_scaled_obj_mesh(
+    path: &str,
+    scale: Float,
+) -> Result<(Vec<Point3>, Vec<u32>), RenderError> {
+    let (mut vertices, indices) = load_obj_mesh(path)?;
+    if scale != 1.0 {
+        for v in &mut vertices {
+            *v = scale * *v;
         }
     }
+    Ok((vertices, indices))
 }
 
-fn triangle_hit(
+fn triangle_hit<'a>(
     ray: &Ray,
     v0: Point3,
     v1: Point3,
     v2: Point3,
-    t_min: f32,
-    t_max: f32,
-    rec: &mut HitRecord,
-    material: &Arc<dyn Material>,
-) -> bool {
+    t_min: Float,
+    t_max: Float,
+    material: &'a SceneMaterial,
+) -> Option<HitRecord<'a>> {
+    progress::count_primitive_test();
     let edge1 = v1 - v0;
     let edge2 = v2 - v0;
     let h = utils::cross(ray.direction(), edge2);
     let a = utils::dot(edge1, h);
 
     if a.abs() < 1e-6 {
-        return false;
+        return None;
     }
 
     let f = 1.0 / a;
     let s = ray.origin() - v0;
     let u = f * utils::dot(s, h);
     if !(0.0..=1.0).contains(&u) {
-        return false;
+        return None;
     }
 
     let q = utils::cross(s, edge1);
     let v = f * utils::dot(ray.direction(), q);
     if v < 0.0 || u + v > 1.0 {
-        return false;
+        return None;
     }
 
     let t = f * utils::dot(edge2, q);
     if t < t_min || t > t_max {
-        return false;
+        return None;
     }
 
-    rec.t = t;
-    rec.p = ray.at(t);
     let normal = utils::cross(edge1, edge2).unit_vector();
-    rec.set_face_normal(ray, normal);
-    rec.mat = Some(material.clone());
-    true
+    Some(HitRecord::new(ray, t, normal, material))
 }
 
-fn indexed_mesh_hit(
-    ray: &Ray,
-    vertices: &[Point3],
-    indices: &[u32],
-    t_min: f32,
-    t_max: f32,
-    rec: &mut HitRecord,
-    material: &Arc<dyn Material>,
-) -> bool {
-    let mut hit_anything = false;
-    let mut closest_so_far = t_max;
-
-    for i in (0..indices.len()).step_by(3) {
-        if i + 2 >= indices.len() {
-            break;
-        }
-
-        let v0 = vertices[indices[i] as usize];
-        let v1 = vertices[indices[i + 1] as usize];
-        let v2 = vertices[indices[i + 2] as usize];
+/// A child of a BVH node: another node or a triangle, by index in the pools of the BVH.
+#[derive(Clone, Copy)]
+enum BVHChild {
+    Node(u32),
+    Triangle(u32),
+}
 
-        let mut temp_rec = HitRecord::default();
+impl BVHChild {
+    /// Bit of an encoded child telling a triangle from a node.
+    const TRIANGLE: u32 = 1 << 31;
 
-        if triangle_hit(
-            ray,
-            v0,
-            v1,
-            v2,
-            t_min,
-            closest_so_far,
-            &mut temp_rec,
-            material,
-        ) {
-            closest_so_far = temp_rec.t;
-            *rec = temp_rec;
-            hit_anything = true;
+    /// The child as written to a BVH cache, see `MeshBVH::write`.
+    fn encode(self) -> u32 {
+        match self {
+            BVHChild::Node(index) => index,
+            BVHChild::Triangle(index) => index | Self::TRIANGLE,
         }
     }
 
-    hit_anything
+    /// The child of an encoded one, or `None` if out of the pools of the BVH.
+    fn decode(value: u32, nodes: usize, triangles: usize) -> Option<Self> {
+        let index = value & !Self::TRIANGLE;
+        if value & Self::TRIANGLE != 0 {
+            ((index as usize) < triangles).then_some(BVHChild::Triangle(index))
+        } else {
+            ((index as usize) < nodes).then_some(BVHChild::Node(index))
+        }
+    }
 }
 
 pub struct BVHNode {
-    pub left: Arc<dyn Hittable>,
-    pub right: Arc<dyn Hittable>,
     pub bbox: AABB,
+    left: BVHChild,
+    right: BVHChild,
+}
+
+/// The BVH of the triangles of a mesh.
+///
+/// The triangles and the nodes are allocated in two pools and linked by index, the
+/// nodes in depth-first order, rather than one by one: the BVHs of meshes of millions
+/// of triangles build faster and their traversal stays in the cache.
+pub struct MeshBVH {
+    triangles: Vec<[Point3; 3]>,
+    nodes: Vec<BVHNode>,
+    root: BVHChild,
 }
 
-impl BVHNode {
-    pub fn build(mut objects: Vec<Arc<dyn Hittable>>) -> Arc<dyn Hittable> {
-        let axis: i32 = rand::random_range(0..3);
+// The triangles of large meshes are left out of the debug output of the scenes
+impl std::fmt::Debug for MeshBVH {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeshBVH")
+            .field("triangles", &self.triangles.len())
+            .field("nodes", &self.nodes.len())
+            .finish()
+    }
+}
+
+impl MeshBVH {
+    /// Builds the BVH of `triangles`, which must not be empty.
+    pub fn build(triangles: Vec<[Point3; 3]>) -> Self {
+        let _span = trace_span!("bvh_build", triangles = triangles.len()).entered();
+        let start = Instant::now();
+        let boxes: Vec<AABB> = triangles
+            .iter()
+            .map(|&[v0, v1, v2]| triangle_aabb(v0, v1, v2))
+            .collect();
+        let mut order: Vec<u32> = (0..triangles.len() as u32).collect();
+        let mut bvh = MeshBVH {
+            nodes: Vec::with_capacity(Self::shape(triangles.len()).0),
+            triangles,
+            root: BVHChild::Triangle(0),
+        };
+        bvh.root = bvh.build_node(&boxes, &mut order);
+        debug!(
+            triangles = bvh.triangles.len(),
+            nodes = bvh.nodes.len(),
+            elapsed = ?start.elapsed(),
+            "Built a mesh BVH"
+        );
+        bvh
+    }
+
+    /// Whether the BVH is of `triangles`, e.g. those of a mesh that may have changed.
+    pub(crate) fn is_of(&self, triangles: &[[Point3; 3]]) -> bool {
+        self.triangles.len() == triangles.len()
+            && self
+                .triangles
+                .iter()
+                .flatten()
+                .zip(triangles.iter().flatten())
+                .all(|(a, b)| a.x() == b.x() && a.y() == b.y() && a.z() == b.z())
+    }
+
+    /// Builds the subtree of the triangles of `order`, sorted along a random axis and
+    /// split in halves.
+    fn build_node(&mut self, boxes: &[AABB], order: &mut [u32]) -> BVHChild {
+        let axis = (utils::random() * 3.0) as i32;
         let comparator = match axis {
             0 => AABB::compare_x,
             1 => AABB::compare_y,
             _ => AABB::compare_z,
         };
 
-        objects.sort_by(|a, b| comparator(a.bounding_box().unwrap(), b.bounding_box().unwrap()));
+        order.sort_by(|&a, &b| comparator(boxes[a as usize], boxes[b as usize]));
 
-        let node: Arc<dyn Hittable> = match objects.len() {
-            1 => objects[0].clone(),
-            2 => {
-                let left = objects[0].clone();
-                let right = objects[1].clone();
-                let bbox = AABB::surrounding_box(
-                    left.bounding_box().unwrap(),
-                    right.bounding_box().unwrap(),
-                );
-                Arc::new(BVHNode { left, right, bbox })
-            }
-            _ => {
-                let mid = objects.len() / 2;
-                let left = BVHNode::build(objects[..mid].to_vec());
-                let right = BVHNode::build(objects[mid..].to_vec());
-                let bbox = AABB::surrounding_box(
-                    left.bounding_box().unwrap(),
-                    right.bounding_box().unwrap(),
-                );
-                Arc::new(BVHNode { left, right, bbox })
-            }
+        if order.len() == 1 {
+            return BVHChild::Triangle(order[0]);
+        }
+        // The node goes before its subtrees, filled in once they are built
+        let index = self.nodes.len();
+        self.nodes.push(BVHNode {
+            bbox: boxes[order[0] as usize],
+            left: BVHChild::Triangle(order[0]),
+            right: BVHChild::Triangle(order[0]),
+        });
+        let (left, right) = if order.len() == 2 {
+            (BVHChild::Triangle(order[0]), BVHChild::Triangle(order[1]))
+        } else {
+            let mid = order.len() / 2;
+            let (first, second) = order.split_at_mut(mid);
+            (
+                self.build_node(boxes, first),
+                self.build_node(boxes, second),
+            )
         };
-
-        node
+        let bbox = AABB::surrounding_box(self.bbox(left, boxes), self.bbox(right, boxes));
+        self.nodes[index] = BVHNode { bbox, left, right };
+        BVHChild::Node(index as u32)
     }
-}
 
-impl Hittable for BVHNode {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        if !self.bbox.hit(ray, t_min, t_max) {
-            return false;
+    fn bbox(&self, child: BVHChild, boxes: &[AABB]) -> AABB {
+        match child {
+            BVHChild::Node(index) => self.nodes[index as usize].bbox,
+            BVHChild::Triangle(index) => boxes[index as usize],
         }
+    }
 
-        let mut temp_rec = HitRecord::default();
-        let mut hit_anything = false;
-        let mut closest_so_far = t_max;
+    /// The number of nodes and the depth of the BVH `build` makes of `len` triangles,
+    /// the leaves being the triangles themselves.
+    pub(crate) fn shape(len: usize) -> (usize, usize) {
+        match len {
+            0 | 1 => (0, 0),
+            2 => (1, 1),
+            _ => {
+                let (left, left_depth) = MeshBVH::shape(len / 2);
+                let (right, right_depth) = MeshBVH::shape(len - len / 2);
+                (1 + left + right, 1 + left_depth.max(right_depth))
+            }
+        }
+    }
 
-        if self.left.hit(ray, t_min, closest_so_far, &mut temp_rec) {
-            closest_so_far = temp_rec.t;
-            *rec = temp_rec.clone();
-            hit_anything = true;
+    /// Writes the nodes of the BVH, after a checksum of its triangles, so that `read`
+    /// loads it for the same triangles without building it again.
+    ///
+    /// The integers are little-endian and the boxes in double precision, whatever
+    /// the precision of the build.
+    pub(crate) fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(&triangles_checksum(&self.triangles).to_le_bytes())?;
+        w.write_all(&(self.nodes.len() as u32).to_le_bytes())?;
+        w.write_all(&self.root.encode().to_le_bytes())?;
+        for node in &self.nodes {
+            let (min, max) = (node.bbox.minimum, node.bbox.maximum);
+            for value in [min.x(), min.y(), min.z(), max.x(), max.y(), max.z()] {
+                w.write_all(&(value as f64).to_le_bytes())?;
+            }
+            w.write_all(&node.left.encode().to_le_bytes())?;
+            w.write_all(&node.right.encode().to_le_bytes())?;
         }
+        Ok(())
+    }
 
-        if self.right.hit(ray, t_min, closest_so_far, &mut temp_rec) {
-            *rec = temp_rec.clone();
-            hit_anything = true;
+    /// Reads a BVH written by `write` for `triangles`.
+    ///
+    /// # Returns
+    /// - The BVH, `None` if it was written for other triangles, e.g. of a mesh edited
+    ///   since, or an error if it cannot be read.
+    pub(crate) fn read(r: &mut impl Read, triangles: Vec<[Point3; 3]>) -> io::Result<Option<Self>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid BVH node");
+        let checksum = u64::from_le_bytes(read_bytes(r)?);
+        let len = u32::from_le_bytes(read_bytes(r)?) as usize;
+        let root = u32::from_le_bytes(read_bytes(r)?);
+        // The BVH of n triangles has n - 1 nodes, checked before allocating them
+        if len >= triangles.len().max(1) {
+            return Err(invalid());
+        }
+        let mut nodes = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut bounds = [0.0; 6];
+            for value in &mut bounds {
+                *value = f64::from_le_bytes(read_bytes(r)?) as Float;
+            }
+            let left = u32::from_le_bytes(read_bytes(r)?);
+            let right = u32::from_le_bytes(read_bytes(r)?);
+            nodes.push(BVHNode {
+                bbox: AABB::new(
+                    Vec3::new(bounds[0], bounds[1], bounds[2]),
+                    Vec3::new(bounds[3], bounds[4], bounds[5]),
+                ),
+                left: BVHChild::decode(left, len, triangles.len()).ok_or_else(invalid)?,
+                right: BVHChild::decode(right, len, triangles.len()).ok_or_else(invalid)?,
+            });
         }
+        let root = BVHChild::decode(root, len, triangles.len()).ok_or_else(invalid)?;
+        if checksum != triangles_checksum(&triangles) {
+            return Ok(None);
+        }
+        Ok(Some(MeshBVH {
+            triangles,
+            nodes,
+            root,
+        }))
+    }
 
-        hit_anything
+    /// The closest hit on the triangles, of the material of the mesh.
+    fn hit<'a>(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        material: &'a SceneMaterial,
+    ) -> Option<HitRecord<'a>> {
+        self.hit_child(self.root, ray, t_min, t_max, material)
     }
 
-    fn bounding_box(&self) -> Option<AABB> {
-        Some(self.bbox)
+    fn hit_child<'a>(
+        &self,
+        child: BVHChild,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        material: &'a SceneMaterial,
+    ) -> Option<HitRecord<'a>> {
+        match child {
+            BVHChild::Triangle(index) => {
+                let [v0, v1, v2] = self.triangles[index as usize];
+                triangle_hit(ray, v0, v1, v2, t_min, t_max, material)
+            }
+            BVHChild::Node(index) => {
+                let node = &self.nodes[index as usize];
+                progress::count_bvh_node();
+                if !node.bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+
+                let left = self.hit_child(node.left, ray, t_min, t_max, material);
+                let closest_so_far = left.as_ref().map_or(t_max, |rec| rec.t);
+                self.hit_child(node.right, ray, t_min, closest_so_far, material)
+                    .or(left)
+            }
+        }
     }
 }
+
+/// FNV-1a hash of the vertices of triangles, telling whether a cached BVH was built
+/// for them.
+fn triangles_checksum(triangles: &[[Point3; 3]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for vertex in triangles.iter().flatten() {
+        for value in [vertex.x(), vertex.y(), vertex.z()] {
+            for byte in (value as f64).to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+    hash
+}
+
+fn read_bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
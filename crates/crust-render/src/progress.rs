@@ -0,0 +1,368 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use utils::Float;
+use web_time::Instant;
+
+thread_local! {
+    // Work done on this thread since the counts were last taken
+    static COUNTS: Cell<RayCounts> = const { Cell::new(RayCounts::new()) };
+}
+
+/// The work of tracing rays against the scene.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RayCounts {
+    /// Rays looking for the closest hit: the camera rays and the bounces.
+    pub(crate) closest: u64,
+    /// Rays testing the visibility of a point, up to a distance.
+    pub(crate) shadow: u64,
+    /// BVH nodes visited by the rays.
+    pub(crate) bvh_nodes: u64,
+    /// Intersection tests of the rays with spheres and triangles.
+    pub(crate) primitive_tests: u64,
+}
+
+impl RayCounts {
+    const fn new() -> Self {
+        RayCounts {
+            closest: 0,
+            shadow: 0,
+            bvh_nodes: 0,
+            primitive_tests: 0,
+        }
+    }
+}
+
+/// Counts a ray traced against the scene on this thread.
+pub(crate) fn count_ray(shadow: bool) {
+    COUNTS.with(|counts| {
+        let mut c = counts.get();
+        if shadow {
+            c.shadow += 1;
+        } else {
+            c.closest += 1;
+        }
+        counts.set(c);
+    });
+}
+
+/// Counts a BVH node visited on this thread.
+pub(crate) fn count_bvh_node() {
+    COUNTS.with(|counts| {
+        let mut c = counts.get();
+        c.bvh_nodes += 1;
+        counts.set(c);
+    });
+}
+
+/// Counts an intersection test with a primitive on this thread.
+pub(crate) fn count_primitive_test() {
+    COUNTS.with(|counts| {
+        let mut c = counts.get();
+        c.primitive_tests += 1;
+        counts.set(c);
+    });
+}
+
+/// The work counted on this thread so far.
+pub(crate) fn ray_counts() -> RayCounts {
+    COUNTS.with(Cell::get)
+}
+
+/// Takes the work counted on this thread, resetting it.
+pub(crate) fn take_ray_counts() -> RayCounts {
+    COUNTS.with(|counts| counts.replace(RayCounts::new()))
+}
+
+/// A progress bar on the terminal, hidden when the error output is not a terminal.
+///
+/// # Parameters
+/// - `len`: The steps to go through.
+/// - `prefix`: What the steps are, shown before the bar.
+pub(crate) fn progress_bar(len: u64, prefix: impl Into<String>) -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "{prefix} [{wide_bar}] {percent:>3}% {elapsed_precise} ETA {eta} {msg}",
+    )
+    .expect("valid progress bar template")
+    .progress_chars("=> ");
+    ProgressBar::new(len)
+        .with_style(style)
+        .with_prefix(prefix.into())
+}
+
+/// The progress of the renders of a renderer, shared with whoever follows them.
+///
+/// Only the integrators driven by the tiles report their progress.
+#[derive(Debug)]
+pub struct Progress {
+    /// Renders started so far, the current one being the last.
+    renders: AtomicUsize,
+    /// Pixels of the image.
+    pixels: AtomicUsize,
+    /// Tiles to render over all the passes.
+    tiles: AtomicUsize,
+    /// Tiles rendered over all the passes.
+    tiles_done: AtomicUsize,
+    /// Camera samples taken.
+    samples: AtomicU64,
+    /// Closest-hit rays traced, for the camera samples and along their paths.
+    rays: AtomicU64,
+    /// Shadow rays traced.
+    shadow_rays: AtomicU64,
+    /// BVH nodes visited.
+    bvh_nodes: AtomicU64,
+    /// Intersection tests with primitives.
+    primitive_tests: AtomicU64,
+    /// Camera samples of NaN or infinite radiance, rendered black.
+    invalid_samples: AtomicU64,
+    /// Time spent in the tiles over all the threads, and in the slowest tile, in
+    /// nanoseconds.
+    tile_time: AtomicU64,
+    max_tile_time: AtomicU64,
+    /// When the current render started, and how long it took once it is done.
+    time: Mutex<(Instant, Option<Duration>)>,
+}
+
+/// A snapshot of the progress of a render.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    /// Number of the render, starting at 1, 0 before the first.
+    pub render: usize,
+    /// Fraction of the tiles rendered over all the passes.
+    pub fraction: Float,
+    /// Camera samples taken per pixel on average.
+    pub samples_per_pixel: Float,
+    /// Time since the start of the render, or its duration once it is done.
+    pub elapsed: Duration,
+    /// Estimated time left, once a tile has been rendered. The adaptive sampling makes
+    /// it pessimistic.
+    pub eta: Option<Duration>,
+    /// Camera rays traced.
+    pub primary_rays: u64,
+    /// Rays traced from the surfaces and media along the paths.
+    pub bounce_rays: u64,
+    /// Rays testing the visibility of the lights or of the occluders.
+    pub shadow_rays: u64,
+    /// BVH nodes visited by all the rays.
+    pub bvh_nodes: u64,
+    /// Intersection tests of all the rays with spheres and triangles.
+    pub primitive_tests: u64,
+    /// Closest-hit rays per camera ray, the average number of segments of the paths.
+    pub average_path_length: Float,
+    /// Rays traced per second.
+    pub rays_per_second: f64,
+    /// Tiles rendered over all the passes.
+    pub tiles: usize,
+    /// Average time a thread spent in a tile.
+    pub mean_tile_time: Duration,
+    /// Time spent in the slowest tile.
+    pub max_tile_time: Duration,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            renders: AtomicUsize::new(0),
+            pixels: AtomicUsize::new(0),
+            tiles: AtomicUsize::new(0),
+            tiles_done: AtomicUsize::new(0),
+            samples: AtomicU64::new(0),
+            rays: AtomicU64::new(0),
+            shadow_rays: AtomicU64::new(0),
+            bvh_nodes: AtomicU64::new(0),
+            primitive_tests: AtomicU64::new(0),
+            invalid_samples: AtomicU64::new(0),
+            tile_time: AtomicU64::new(0),
+            max_tile_time: AtomicU64::new(0),
+            time: Mutex::new((Instant::now(), None)),
+        }
+    }
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resets the progress for a new render.
+    ///
+    /// # Parameters
+    /// - `pixels`: The pixels of the image.
+    /// - `tiles`: The tiles to render over all the passes.
+    pub(crate) fn start(&self, pixels: usize, tiles: usize) {
+        *self.time.lock().expect("progress lock poisoned") = (Instant::now(), None);
+        self.pixels.store(pixels, Ordering::Relaxed);
+        self.tiles.store(tiles, Ordering::Relaxed);
+        for counter in [
+            &self.samples,
+            &self.rays,
+            &self.shadow_rays,
+            &self.bvh_nodes,
+            &self.primitive_tests,
+            &self.invalid_samples,
+            &self.tile_time,
+            &self.max_tile_time,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.tiles_done.store(0, Ordering::Relaxed);
+        self.renders.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a rendered tile.
+    ///
+    /// # Parameters
+    /// - `samples`: The camera samples taken in the tile.
+    /// - `counts`: The rays traced for the tile.
+    /// - `time`: The time spent in the tile.
+    pub(crate) fn add_tile(&self, samples: u64, counts: RayCounts, time: Duration) {
+        let nanos = time.as_nanos() as u64;
+        self.samples.fetch_add(samples, Ordering::Relaxed);
+        self.rays.fetch_add(counts.closest, Ordering::Relaxed);
+        self.shadow_rays.fetch_add(counts.shadow, Ordering::Relaxed);
+        self.bvh_nodes
+            .fetch_add(counts.bvh_nodes, Ordering::Relaxed);
+        self.primitive_tests
+            .fetch_add(counts.primitive_tests, Ordering::Relaxed);
+        self.tile_time.fetch_add(nanos, Ordering::Relaxed);
+        self.max_tile_time.fetch_max(nanos, Ordering::Relaxed);
+        self.tiles_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a camera sample of NaN or infinite radiance.
+    pub(crate) fn add_invalid_sample(&self) {
+        self.invalid_samples.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The camera samples of NaN or infinite radiance of the render.
+    pub(crate) fn invalid_samples(&self) -> u64 {
+        self.invalid_samples.load(Ordering::Relaxed)
+    }
+
+    /// Stops the clock of the render.
+    pub(crate) fn finish(&self) {
+        let mut time = self.time.lock().expect("progress lock poisoned");
+        time.1 = Some(time.0.elapsed());
+    }
+
+    /// The number of the current render, which changes when a new render starts.
+    pub fn render(&self) -> usize {
+        self.renders.load(Ordering::Relaxed)
+    }
+
+    /// Takes a snapshot of the progress.
+    pub fn stats(&self) -> Stats {
+        let elapsed = {
+            let time = self.time.lock().expect("progress lock poisoned");
+            time.1.unwrap_or_else(|| time.0.elapsed())
+        };
+        let tiles = self.tiles.load(Ordering::Relaxed);
+        let tiles_done = self.tiles_done.load(Ordering::Relaxed);
+        let fraction = if tiles == 0 {
+            0.0
+        } else {
+            tiles_done as Float / tiles as Float
+        };
+        let samples = self.samples.load(Ordering::Relaxed);
+        let rays = self.rays.load(Ordering::Relaxed);
+        let shadow_rays = self.shadow_rays.load(Ordering::Relaxed);
+        let pixels = self.pixels.load(Ordering::Relaxed).max(1);
+        let eta = (fraction > 0.0).then(|| elapsed.mul_f64(((1.0 - fraction) / fraction) as f64));
+        let seconds = elapsed.as_secs_f64();
+        Stats {
+            render: self.render(),
+            fraction,
+            samples_per_pixel: samples as Float / pixels as Float,
+            elapsed,
+            eta,
+            primary_rays: samples,
+            bounce_rays: rays.saturating_sub(samples),
+            shadow_rays,
+            bvh_nodes: self.bvh_nodes.load(Ordering::Relaxed),
+            primitive_tests: self.primitive_tests.load(Ordering::Relaxed),
+            average_path_length: rays as Float / samples.max(1) as Float,
+            rays_per_second: if seconds > 0.0 {
+                (rays + shadow_rays) as f64 / seconds
+            } else {
+                0.0
+            },
+            tiles: tiles_done,
+            mean_tile_time: Duration::from_nanos(
+                self.tile_time.load(Ordering::Relaxed) / tiles_done.max(1) as u64,
+            ),
+            max_tile_time: Duration::from_nanos(self.max_tile_time.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Stats {
+    /// The statistics as a JSON object, the times in seconds.
+    pub fn to_json(&self) -> String {
+        let eta = match self.eta {
+            Some(eta) => format!("{:.3}", eta.as_secs_f64()),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"render\": {}, \"progress\": {:.4}, \"samples_per_pixel\": {:.2}, \
+             \"elapsed\": {:.3}, \"eta\": {}, \"primary_rays\": {}, \"bounce_rays\": {}, \
+             \"shadow_rays\": {}, \"bvh_nodes\": {}, \"primitive_tests\": {}, \
+             \"average_path_length\": {:.3}, \
+             \"rays_per_second\": {:.0}, \"tiles\": {}, \"mean_tile_time\": {:.6}, \
+             \"max_tile_time\": {:.6}}}",
+            self.render,
+            self.fraction,
+            self.samples_per_pixel,
+            self.elapsed.as_secs_f64(),
+            eta,
+            self.primary_rays,
+            self.bounce_rays,
+            self.shadow_rays,
+            self.bvh_nodes,
+            self.primitive_tests,
+            self.average_path_length,
+            self.rays_per_second,
+            self.tiles,
+            self.mean_tile_time.as_secs_f64(),
+            self.max_tile_time.as_secs_f64()
+        )
+    }
+}
+
+impl fmt::Display for Stats {
+    /// A report of the render, one statistic per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rays = self.primary_rays + self.bounce_rays + self.shadow_rays;
+        writeln!(f, "Render statistics:")?;
+        writeln!(f, "  Samples per pixel:   {:.2}", self.samples_per_pixel)?;
+        writeln!(f, "  Primary rays:        {}", self.primary_rays)?;
+        writeln!(f, "  Bounce rays:         {}", self.bounce_rays)?;
+        writeln!(f, "  Shadow rays:         {}", self.shadow_rays)?;
+        writeln!(
+            f,
+            "  BVH nodes visited:   {} ({:.1} per ray)",
+            self.bvh_nodes,
+            self.bvh_nodes as f64 / rays.max(1) as f64
+        )?;
+        writeln!(
+            f,
+            "  Primitive tests:     {} ({:.1} per ray)",
+            self.primitive_tests,
+            self.primitive_tests as f64 / rays.max(1) as f64
+        )?;
+        writeln!(f, "  Average path length: {:.2}", self.average_path_length)?;
+        writeln!(
+            f,
+            "  Rays per second:     {:.2} M",
+            self.rays_per_second / 1e6
+        )?;
+        writeln!(
+            f,
+            "  Time per tile:       {:?} on average, {:?} at most, over {} tiles",
+            self.mean_tile_time, self.max_tile_time, self.tiles
+        )?;
+        write!(f, "  Render time:         {:?}", self.elapsed)
+    }
+}
@@ -1,13 +1,26 @@
-use utils::{Point3, Vec3};
+use crate::spectrum::Wavelength;
+use utils::{Float, Point3, Vec3};
 
 /// The `Ray` struct represents a ray in 3D space, defined by an origin and a direction.
 /// Rays are used in ray tracing to determine intersections with objects in the scene.
+///
+/// A ray also carries its time in the shutter interval, so that the moving objects it
+/// hits are intersected where they are at that time. The rays spawned along a path,
+/// scattered or shadow rays, keep the time of the camera ray with `spawn`, so the
+/// path stays consistent while the objects blur across the samples.
+///
+/// It carries the wavelength of its path the same way, set by the camera sample in
+/// spectral renders or by the first dispersive surface of an RGB path.
 #[derive(Default)]
 pub struct Ray {
     /// The origin point of the ray.
     orig: Point3,
     /// The direction vector of the ray.
     dir: Vec3,
+    /// The time of the ray, in [0, 1) over the shutter interval.
+    time: Float,
+    /// The wavelength of the path of the ray.
+    wavelength: Wavelength,
 }
 
 impl Ray {
@@ -23,9 +36,35 @@ impl Ray {
         Ray {
             orig: origin,
             dir: direction,
+            time: 0.0,
+            wavelength: Wavelength::Rgb,
         }
     }
 
+    /// Sets the time of the ray in the shutter interval.
+    pub fn with_time(mut self, time: Float) -> Ray {
+        self.time = time;
+        self
+    }
+
+    /// Sets the wavelength of the path of the ray.
+    pub fn with_wavelength(mut self, wavelength: Wavelength) -> Ray {
+        self.wavelength = wavelength;
+        self
+    }
+
+    /// Creates a ray at the same time and wavelength as this one, e.g. scattered at
+    /// its hit.
+    ///
+    /// # Parameters
+    /// - `origin`: The starting point of the new ray.
+    /// - `direction`: The direction vector of the new ray.
+    pub fn spawn(&self, origin: Point3, direction: Vec3) -> Ray {
+        Ray::new(origin, direction)
+            .with_time(self.time)
+            .with_wavelength(self.wavelength)
+    }
+
     /// Returns the origin of the ray.
     ///
     /// # Returns
@@ -42,6 +81,16 @@ impl Ray {
         self.dir
     }
 
+    /// Returns the time of the ray, in [0, 1) over the shutter interval.
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
+    /// Returns the wavelength of the path of the ray.
+    pub fn wavelength(&self) -> Wavelength {
+        self.wavelength
+    }
+
     /// Computes the point along the ray at a given parameter `t`.
     ///
     /// # Parameters
@@ -49,7 +98,7 @@ impl Ray {
     ///
     /// # Returns
     /// - A `Point3` representing the point at parameter `t`.
-    pub fn at(&self, t: f32) -> Point3 {
+    pub fn at(&self, t: Float) -> Point3 {
         self.orig + t * self.dir
     }
 }
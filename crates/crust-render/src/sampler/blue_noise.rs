@@ -0,0 +1,185 @@
+use crate::sampler::Sampler;
+use std::sync::OnceLock;
+use utils::Float;
+
+/// Side length of the tileable blue-noise mask.
+const MASK_SIZE: usize = 64;
+/// Width of the Gaussian energy kernel used by void-and-cluster.
+const SIGMA: Float = 1.9;
+
+static MASK: OnceLock<Vec<Float>> = OnceLock::new();
+
+/// Returns the tileable blue-noise dither mask, generating it on first use.
+///
+/// The mask is built once with Ulichney's void-and-cluster method from a fixed seed,
+/// so its content is identical across runs and threads.
+pub fn blue_noise_mask() -> &'static [Float] {
+    MASK.get_or_init(void_and_cluster)
+}
+
+/// Returns the blue-noise value in [0, 1) of a pixel, for a given dimension.
+///
+/// Successive dimensions read the mask at shifted positions (R2 sequence offsets)
+/// so that their values are decorrelated while each stays blue-noise distributed.
+pub fn blue_noise_value(pixel: (usize, usize), dimension: u32) -> Float {
+    const G: Float = 1.324_718;
+    let shift_x = ((dimension as Float / G).fract() * MASK_SIZE as Float) as usize;
+    let shift_y = ((dimension as Float / (G * G)).fract() * MASK_SIZE as Float) as usize;
+    let x = (pixel.0 + shift_x) % MASK_SIZE;
+    let y = (pixel.1 + shift_y) % MASK_SIZE;
+    blue_noise_mask()[y * MASK_SIZE + x]
+}
+
+/// A sampler wrapper applying a per-pixel blue-noise Cranley-Patterson rotation.
+///
+/// The wrapped sampler is expected to produce the same sequence for every pixel;
+/// the toroidal shift by the mask value is then the only per-pixel difference, which
+/// distributes the remaining error as high-frequency blue noise across the image.
+pub struct BlueNoiseSampler {
+    inner: Box<dyn Sampler>,
+    pixel: (usize, usize),
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(inner: Box<dyn Sampler>, pixel: (usize, usize)) -> Self {
+        Self {
+            inner,
+            pixel,
+            dimension: 0,
+        }
+    }
+
+    fn next_offset(&mut self) -> Float {
+        let offset = blue_noise_value(self.pixel, self.dimension);
+        self.dimension += 1;
+        offset
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel(&mut self, pixel: (usize, usize)) {
+        // The pixels share the sequence of the wrapped sampler, only the shift moves
+        self.pixel = pixel;
+        self.inner.start_pixel((0, 0));
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.inner.start_sample(index);
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let offset = self.next_offset();
+        toroidal_shift(self.inner.get_1d(), offset)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let offset_x = self.next_offset();
+        let offset_y = self.next_offset();
+        let (x, y) = self.inner.get_2d();
+        (toroidal_shift(x, offset_x), toroidal_shift(y, offset_y))
+    }
+}
+
+fn toroidal_shift(value: Float, offset: Float) -> Float {
+    let shifted = value + offset;
+    let wrapped = if shifted >= 1.0 {
+        shifted - 1.0
+    } else {
+        shifted
+    };
+    // Guard against rounding up to exactly 1.0
+    wrapped.min(1.0 - Float::EPSILON)
+}
+
+fn void_and_cluster() -> Vec<Float> {
+    let n = MASK_SIZE * MASK_SIZE;
+
+    // Toroidal Gaussian kernel indexed by (dx, dy)
+    let mut kernel: Vec<Float> = vec![0.0; n];
+    for dy in 0..MASK_SIZE {
+        for dx in 0..MASK_SIZE {
+            let wx = dx.min(MASK_SIZE - dx) as Float;
+            let wy = dy.min(MASK_SIZE - dy) as Float;
+            kernel[dy * MASK_SIZE + dx] = (-(wx * wx + wy * wy) / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+    let splat = |energy: &mut [Float], index: usize, sign: Float| {
+        let (px, py) = (index % MASK_SIZE, index / MASK_SIZE);
+        for y in 0..MASK_SIZE {
+            let dy = (y + MASK_SIZE - py) % MASK_SIZE;
+            for x in 0..MASK_SIZE {
+                let dx = (x + MASK_SIZE - px) % MASK_SIZE;
+                energy[y * MASK_SIZE + x] += sign * kernel[dy * MASK_SIZE + dx];
+            }
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[Float]| {
+        (0..n)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[Float]| {
+        (0..n)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Initial binary pattern: ~10% random points from a fixed-seed xorshift
+    let mut state: u32 = 0x2545_f491;
+    let mut pattern = vec![false; n];
+    let mut energy: Vec<Float> = vec![0.0; n];
+    let initial = n / 10;
+    let mut placed = 0;
+    while placed < initial {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let i = state as usize % n;
+        if !pattern[i] {
+            pattern[i] = true;
+            splat(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Relax: move the tightest cluster into the largest void until stable
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+
+    // Phase 1: rank the initial points by removing tightest clusters
+    let mut working = pattern.clone();
+    let mut working_energy = energy.clone();
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&working, &working_energy);
+        working[cluster] = false;
+        splat(&mut working_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Phase 2 & 3: fill the largest voids until the mask is complete
+    for r in initial..n {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| (r as Float + 0.5) / n as Float)
+        .collect()
+}
@@ -0,0 +1,48 @@
+use utils::{Float, random};
+
+/// Generate a 2D CMJ sample grid
+pub fn generate_cmj_2d(samples_per_side: usize) -> Vec<(Float, Float)> {
+    let n = samples_per_side;
+    let n_f32 = n as Float;
+
+    let mut xs: Vec<usize> = (0..n).collect();
+    let mut ys: Vec<usize> = (0..n).collect();
+
+    // Shuffle for jittering
+    utils::shuffle(&mut xs);
+    utils::shuffle(&mut ys);
+
+    let mut samples = Vec::with_capacity(n * n);
+
+    for j in 0..n {
+        for i in 0..n {
+            let x = (i as Float + (j as Float + random()) / n_f32) / n_f32;
+            let y = (j as Float + (i as Float + random()) / n_f32) / n_f32;
+            samples.push((x, y));
+        }
+    }
+
+    samples
+}
+
+/// Generate a 2D stratified (jittered grid) sample set.
+///
+/// Each of the `samples_per_side * samples_per_side` strata receives exactly one
+/// jittered sample. The strata are returned in shuffled order so that any prefix
+/// of the set (as consumed by adaptive sampling) still covers the whole domain.
+pub fn generate_stratified_2d(samples_per_side: usize) -> Vec<(Float, Float)> {
+    let n = samples_per_side;
+    let n_f32 = n as Float;
+
+    let mut samples = Vec::with_capacity(n * n);
+    for j in 0..n {
+        for i in 0..n {
+            let x = (i as Float + random()) / n_f32;
+            let y = (j as Float + random()) / n_f32;
+            samples.push((x, y));
+        }
+    }
+
+    utils::shuffle(&mut samples);
+    samples
+}
@@ -0,0 +1,19 @@
+use crate::sampler::Sampler;
+use utils::{Float, random};
+
+/// A sampler returning independent uniform random numbers for every dimension.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_pixel(&mut self, _pixel: (usize, usize)) {}
+
+    fn start_sample(&mut self, _index: u32) {}
+
+    fn get_1d(&mut self) -> Float {
+        random()
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (random(), random())
+    }
+}
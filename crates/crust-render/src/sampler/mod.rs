@@ -0,0 +1,116 @@
+mod cmj;
+pub use cmj::{generate_cmj_2d, generate_stratified_2d};
+mod independent;
+pub use independent::IndependentSampler;
+mod stratified;
+pub use stratified::StratifiedSampler;
+mod blue_noise;
+pub use blue_noise::{BlueNoiseSampler, blue_noise_mask, blue_noise_value};
+mod sobol;
+use serde::{Deserialize, Serialize};
+pub use sobol::SobolSampler;
+use utils::Float;
+
+/// The `Sampler` trait provides the sample values consumed while tracing one pixel.
+///
+/// Every call to `get_1d` or `get_2d` consumes the next dimension(s) of the current
+/// sample vector. Consumers must request the dimensions in the same order for each
+/// sample so that well-distributed samplers stay well distributed per dimension.
+///
+/// A sampler is started on a pixel with `start_pixel`, then on each of its samples with
+/// `start_sample`, so that the camera jitter, the lens, the light samples and the BSDF
+/// samples are drawn from it whichever sampler it is.
+pub trait Sampler {
+    /// Moves the sampler to a pixel, so that one sampler serves all the pixels of a
+    /// tile; the samplers created for a pixel already start on it.
+    ///
+    /// # Parameters
+    /// - `pixel`: The pixel coordinates, used to decorrelate neighboring pixels.
+    fn start_pixel(&mut self, pixel: (usize, usize));
+
+    /// Starts a new sample vector for the current pixel.
+    ///
+    /// # Parameters
+    /// - `index`: The index of the sample within the pixel.
+    fn start_sample(&mut self, index: u32);
+
+    /// Returns the next 1D sample value in [0, 1).
+    fn get_1d(&mut self) -> Float;
+
+    /// Returns the next 2D sample value in [0, 1)².
+    fn get_2d(&mut self) -> (Float, Float);
+}
+
+/// The seed of the random numbers of the samples of a render from the sample `first`
+/// on, each pixel drawing them from its own stream of it with
+/// `utils::with_random_stream`, so that they do not depend on the scheduling.
+pub(crate) fn stream_seed(seed: u32, first: u32) -> u64 {
+    ((seed as u64) << 32) | first as u64
+}
+
+/// The first random stream of the photons of the photon passes, above the streams of
+/// the pixels, numbered by their index.
+pub(crate) const PHOTON_STREAMS: u64 = 1 << 32;
+
+/// The first random stream of the pixels of the training passes of the path guiding,
+/// apart from those of its final pass.
+pub(crate) const TRAINING_STREAMS: u64 = 2 << 32;
+
+/// The sample generators selectable in the render settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SamplerType {
+    /// Uncorrelated uniform random numbers.
+    Independent,
+    /// Jittered grid for the first dimensions, random beyond.
+    Stratified,
+    /// Owen-scrambled Sobol (0,2)-sequence, padded across dimensions.
+    #[default]
+    Sobol,
+}
+
+impl SamplerType {
+    /// Creates a sampler for a given pixel.
+    ///
+    /// # Parameters
+    /// - `pixel`: The pixel coordinates, used to decorrelate neighboring pixels.
+    /// - `samples_per_pixel`: The maximum number of samples that will be drawn.
+    pub fn create(&self, pixel: (usize, usize), samples_per_pixel: u32) -> Box<dyn Sampler> {
+        self.create_seeded(pixel, samples_per_pixel, 0)
+    }
+
+    /// Creates a sampler for a given pixel, decorrelated from the other renders by a
+    /// seed.
+    ///
+    /// # Parameters
+    /// - `pixel`: The pixel coordinates, used to decorrelate neighboring pixels.
+    /// - `samples_per_pixel`: The maximum number of samples that will be drawn.
+    /// - `seed`: The seed of the render, 0 giving the samples of `create`. The random
+    ///   samplers draw from the random stream of the pixel, seeded by the caller.
+    pub fn create_seeded(
+        &self,
+        pixel: (usize, usize),
+        samples_per_pixel: u32,
+        seed: u32,
+    ) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(pixel).with_seed(seed)),
+        }
+    }
+
+    /// Creates a sampler for a given pixel, dithered by the blue-noise mask.
+    ///
+    /// All pixels share the same underlying sequence and only differ by a blue-noise
+    /// toroidal shift, so the error of low sample counts is spread as blue noise.
+    /// This is most effective with the `Sobol` sampler, whose sequence is deterministic.
+    pub fn create_dithered(
+        &self,
+        pixel: (usize, usize),
+        samples_per_pixel: u32,
+        seed: u32,
+    ) -> Box<dyn Sampler> {
+        let inner = self.create_seeded((0, 0), samples_per_pixel, seed);
+        Box::new(BlueNoiseSampler::new(inner, pixel))
+    }
+}
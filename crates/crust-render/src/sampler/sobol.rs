@@ -0,0 +1,147 @@
+use crate::sampler::Sampler;
+use utils::Float;
+
+/// Direction numbers of the second Sobol dimension (primitive polynomial x + 1).
+/// The first dimension is the van der Corput sequence and needs no table.
+const SOBOL_DIM1: [u32; 32] = sobol_dim1_directions();
+
+const fn sobol_dim1_directions() -> [u32; 32] {
+    let mut v = [0u32; 32];
+    v[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        v[i] = v[i - 1] ^ (v[i - 1] >> 1);
+        i += 1;
+    }
+    v
+}
+
+/// A scrambled, shuffled Sobol sampler (Burley 2020, "Practical Hash-based Owen Scrambling").
+///
+/// Every 2D request draws from the first two Sobol dimensions, which form a (0,2)-sequence.
+/// Each request gets its own Owen scrambling and its own shuffle of the sample index, which
+/// keeps the per-dimension stratification while decorrelating the dimensions ("padding").
+pub struct SobolSampler {
+    pixel: (usize, usize),
+    /// The seed of the render.
+    render_seed: u32,
+    /// The scrambling seed of the pixel, from its coordinates and the seed of the render.
+    seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(pixel: (usize, usize)) -> Self {
+        let mut sampler = Self {
+            pixel,
+            render_seed: 0,
+            seed: 0,
+            index: 0,
+            dimension: 0,
+        };
+        sampler.reseed();
+        sampler
+    }
+
+    /// Decorrelates the sequence from the one of the same pixel in other renders, 0
+    /// keeping the sequence of the pixel.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.render_seed = seed;
+        self.reseed();
+        self
+    }
+
+    fn reseed(&mut self) {
+        self.seed = hash_combine(hash(self.pixel.0 as u32), hash(self.pixel.1 as u32));
+        if self.render_seed != 0 {
+            self.seed = hash_combine(self.seed, hash(self.render_seed));
+        }
+    }
+
+    fn next_dimension_seed(&mut self) -> u32 {
+        let seed = hash_combine(self.seed, hash(self.dimension));
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, pixel: (usize, usize)) {
+        self.pixel = pixel;
+        self.reseed();
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let seed = self.next_dimension_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        let x = nested_uniform_scramble(sobol_dim0(index), hash_combine(seed, 0));
+        to_unit_float(x)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let seed = self.next_dimension_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        let x = nested_uniform_scramble(sobol_dim0(index), hash_combine(seed, 0));
+        let y = nested_uniform_scramble(sobol_dim1(index), hash_combine(seed, 1));
+        (to_unit_float(x), to_unit_float(y))
+    }
+}
+
+fn sobol_dim0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+fn sobol_dim1(mut index: u32) -> u32 {
+    let mut x = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= SOBOL_DIM1[bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Owen scrambling of a 32-bit fixed point value.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn hash(mut x: u32) -> u32 {
+    // https://github.com/skeeto/hash-prospector
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x21f0aaad);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0xd35a2d97);
+    x ^= x >> 15;
+    x
+}
+
+fn hash_combine(seed: u32, v: u32) -> u32 {
+    seed ^ (v
+        .wrapping_add(seed << 6)
+        .wrapping_add(seed >> 2)
+        .wrapping_add(0x9e3779b9))
+}
+
+fn to_unit_float(x: u32) -> Float {
+    // Keep 24 bits so the result is exactly representable and strictly below 1.0
+    (x >> 8) as Float * (1.0 / (1u32 << 24) as Float)
+}
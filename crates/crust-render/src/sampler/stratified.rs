@@ -0,0 +1,80 @@
+use crate::sampler::Sampler;
+use crate::sampler::generate_stratified_2d;
+use utils::{Float, random};
+
+/// Number of leading 1D and 2D dimensions that receive their own stratification.
+/// Deeper dimensions (late bounces) fall back to independent random numbers.
+const STRATIFIED_DIMENSIONS: usize = 4;
+
+/// A sampler drawing jittered-grid samples for the first dimensions of each pixel.
+///
+/// Strata tables are built lazily, one per dimension, and shuffled independently
+/// so that the dimensions stay decorrelated from each other.
+pub struct StratifiedSampler {
+    samples_per_side: usize,
+    index: usize,
+    dimension_1d: usize,
+    dimension_2d: usize,
+    strata_1d: Vec<Vec<Float>>,
+    strata_2d: Vec<Vec<(Float, Float)>>,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32) -> Self {
+        let samples_per_side = (samples_per_pixel as Float).sqrt().ceil().max(1.0) as usize;
+        Self {
+            samples_per_side,
+            index: 0,
+            dimension_1d: 0,
+            dimension_2d: 0,
+            strata_1d: Vec::new(),
+            strata_2d: Vec::new(),
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, _pixel: (usize, usize)) {
+        // The strata of the next pixel are jittered and shuffled anew
+        self.strata_1d.clear();
+        self.strata_2d.clear();
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index as usize;
+        self.dimension_1d = 0;
+        self.dimension_2d = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.dimension_1d;
+        self.dimension_1d += 1;
+        if dimension >= STRATIFIED_DIMENSIONS {
+            return random();
+        }
+        if dimension == self.strata_1d.len() {
+            let n = self.samples_per_side * self.samples_per_side;
+            let mut strata: Vec<Float> = (0..n)
+                .map(|i| (i as Float + random()) / n as Float)
+                .collect();
+            utils::shuffle(&mut strata);
+            self.strata_1d.push(strata);
+        }
+        let strata = &self.strata_1d[dimension];
+        strata[self.index % strata.len()]
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let dimension = self.dimension_2d;
+        self.dimension_2d += 1;
+        if dimension >= STRATIFIED_DIMENSIONS {
+            return (random(), random());
+        }
+        if dimension == self.strata_2d.len() {
+            self.strata_2d
+                .push(generate_stratified_2d(self.samples_per_side));
+        }
+        let strata = &self.strata_2d[dimension];
+        strata[self.index % strata.len()]
+    }
+}
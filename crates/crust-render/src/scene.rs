@@ -0,0 +1,69 @@
+use crate::camera::Camera;
+use crate::hittable_list::HittableList;
+use crate::light::LightList;
+use crate::medium::MediumList;
+use crate::ray::Ray;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utils::Color;
+
+/// The radiance of the rays escaping the geometry of a scene.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Background {
+    /// A gradient from white at the horizon to light blue at the zenith.
+    #[default]
+    Sky,
+    /// The same radiance in every direction, e.g. black for the scenes lit by their
+    /// lights alone.
+    Uniform(Color),
+}
+
+impl Background {
+    /// The radiance of the background seen along a ray.
+    pub fn radiance(&self, r: &Ray) -> Color {
+        match *self {
+            Background::Sky => {
+                let unit_direction = utils::unit_vector(r.direction());
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Uniform(color) => color,
+        }
+    }
+}
+
+/// A scene ready to render: the camera, the geometry and lights built from the objects
+/// of a document at a frame, the participating media and the background.
+///
+/// The renderer owns it and hands it to the integrators, see `Document::scene`.
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    pub lights: LightList,
+    pub media: Arc<MediumList>,
+    pub background: Background,
+}
+
+impl Scene {
+    /// Creates a scene without media, under the sky.
+    pub fn new(camera: Camera, world: HittableList, lights: LightList) -> Self {
+        Self {
+            camera,
+            world,
+            lights,
+            media: Arc::new(MediumList::new()),
+            background: Background::default(),
+        }
+    }
+
+    /// Fills the scene with participating media, rendered by the path tracer.
+    pub fn with_media(mut self, media: MediumList) -> Self {
+        self.media = Arc::new(media);
+        self
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+}
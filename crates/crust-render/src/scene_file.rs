@@ -0,0 +1,492 @@
+use crate::animation::Animation;
+use crate::camera::Camera;
+use crate::document::{Document, ObjectList, is_json};
+use crate::error::RenderError;
+use crate::medium::{Fog, Medium};
+use crate::primitives::Primitive;
+use crate::scene::Background;
+use crate::tracer::RenderSettings;
+use crate::unit::Unit;
+use crate::validate::{Issue, SceneError};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error};
+
+/// Depth of the nested includes past which a scene is assumed to include itself.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A scene file as written: its sections, any of them being left to the scenes it
+/// includes, and the overrides applied on top of them.
+#[derive(Debug, Default, Deserialize)]
+struct SceneFile {
+    /// Scenes, relative to this one, the missing sections are taken from, the
+    /// later ones replacing the sections of the earlier ones.
+    #[serde(default)]
+    include: Vec<String>,
+    camera: Option<Camera>,
+    object_list: Option<ObjectList>,
+    settings: Option<RenderSettings>,
+    media: Option<Vec<Medium>>,
+    fog: Option<Fog>,
+    animation: Option<Animation>,
+    background: Option<Background>,
+    material_library: Option<String>,
+    /// BVH cache of the meshes, relative to this scene.
+    bvh_cache: Option<String>,
+    /// Length unit of the scene, meters by default, the lengths of the scenes it
+    /// includes being converted to it.
+    unit: Option<Unit>,
+    /// Values set in the complete scene, e.g. `settings.samples_per_pixel=256`.
+    #[serde(default)]
+    overrides: Vec<String>,
+    #[serde(skip)]
+    sources: Sources,
+}
+
+impl SceneFile {
+    /// The sections of this scene, those it does not have coming from `base`.
+    fn over(self, base: SceneFile) -> SceneFile {
+        SceneFile {
+            include: Vec::new(),
+            camera: self.camera.or(base.camera),
+            object_list: self.object_list.or(base.object_list),
+            settings: self.settings.or(base.settings),
+            media: self.media.or(base.media),
+            fog: self.fog.or(base.fog),
+            animation: self.animation.or(base.animation),
+            background: self.background.or(base.background),
+            material_library: self.material_library.or(base.material_library),
+            bvh_cache: self.bvh_cache.or(base.bvh_cache),
+            unit: self.unit.or(base.unit),
+            overrides: Vec::new(),
+            sources: Sources {
+                files: base
+                    .sources
+                    .files
+                    .into_iter()
+                    .chain(self.sources.files)
+                    .collect(),
+                ..self.sources
+            },
+        }
+    }
+
+    /// Converts the lengths of the sections of the scene to `unit`.
+    fn convert(&mut self, unit: Unit) {
+        let factor = self.unit.unwrap_or_default().to(unit);
+        self.unit = Some(unit);
+        if factor == 1.0 {
+            return;
+        }
+        if let Some(camera) = &mut self.camera {
+            camera.rescale(factor);
+        }
+        if let Some(object_list) = &mut self.object_list {
+            object_list.rescale(factor);
+        }
+        if let Some(settings) = &mut self.settings {
+            settings.rescale(factor);
+        }
+        for medium in self.media.iter_mut().flatten() {
+            medium.rescale(factor);
+        }
+        if let Some(fog) = &mut self.fog {
+            fog.rescale(factor);
+        }
+        if let Some(animation) = &mut self.animation {
+            animation.rescale(factor);
+        }
+    }
+
+    fn into_document(self, path: &Path) -> Result<Document, RenderError> {
+        let missing = |section: &str| invalid(format!("{} has no {section}", path.display()));
+        let mut doc = Document::new(
+            self.camera.ok_or_else(|| missing("camera"))?,
+            self.object_list.ok_or_else(|| missing("object_list"))?,
+            self.settings.ok_or_else(|| missing("settings"))?,
+        )
+        .with_media(self.media.unwrap_or_default())
+        .with_animation(self.animation.unwrap_or_default())
+        .with_background(self.background.unwrap_or_default());
+        doc.fog = self.fog;
+        doc.material_library = self.material_library;
+        doc.bvh_cache = self.bvh_cache;
+        doc.unit = self.unit.unwrap_or_default();
+        Ok(doc)
+    }
+}
+
+impl From<Document> for SceneFile {
+    fn from(doc: Document) -> Self {
+        SceneFile {
+            include: Vec::new(),
+            camera: Some(doc.camera),
+            object_list: Some(doc.object_list),
+            settings: Some(doc.settings),
+            media: Some(doc.media),
+            fog: doc.fog,
+            animation: Some(doc.animation),
+            background: Some(doc.background),
+            material_library: doc.material_library,
+            bvh_cache: doc.bvh_cache,
+            unit: Some(doc.unit),
+            overrides: Vec::new(),
+            sources: Sources::default(),
+        }
+    }
+}
+
+/// The sections of a scene, `camera`, `object_list`, `settings`, `media`, `fog`,
+/// `animation` and `background`.
+const SECTIONS: [&str; 7] = [
+    "camera",
+    "object_list",
+    "settings",
+    "media",
+    "fog",
+    "animation",
+    "background",
+];
+
+/// The files the sections of a scene come from, to locate its problems.
+#[derive(Debug, Default)]
+pub(crate) struct Sources {
+    /// The scene read, with the sections of no other file.
+    scene: PathBuf,
+    files: BTreeMap<&'static str, PathBuf>,
+}
+
+impl Sources {
+    pub(crate) fn new(scene: &Path) -> Self {
+        Sources {
+            scene: scene.to_path_buf(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// The problems at the files of their sections, and at the lines of their
+    /// objects or sections there.
+    pub(crate) fn locate(&self, issues: Vec<Issue>) -> SceneError {
+        let mut texts: BTreeMap<&Path, Option<String>> = BTreeMap::new();
+        let issues = issues
+            .into_iter()
+            .map(|issue| {
+                let path = self.files.get(issue.section).unwrap_or(&self.scene);
+                let text = texts
+                    .entry(path)
+                    .or_insert_with(|| std::fs::read_to_string(path).ok());
+                let line = text.as_deref().and_then(|text| {
+                    let mut lines = text.lines().zip(1..);
+                    match &issue.object {
+                        Some(name) => {
+                            let name = format!("\"{name}\"");
+                            lines.find(|(l, _)| l.contains("name") && l.contains(&name))
+                        }
+                        None => {
+                            // The section, or an override of its values
+                            let keys = [
+                                format!("{}:", issue.section),
+                                format!("\"{}\"", issue.section),
+                            ];
+                            let overridden = format!("\"{}.", issue.section);
+                            lines.find(|(l, _)| {
+                                keys.iter()
+                                    .any(|key| l.trim_start().starts_with(key.as_str()))
+                                    || l.contains(&overridden)
+                            })
+                        }
+                    }
+                    .map(|(_, line)| line)
+                });
+                (path.clone(), line, issue)
+            })
+            .collect();
+        SceneError::new(issues)
+    }
+}
+
+/// Reads a RON or JSON scene with its includes and overrides, and the files its
+/// sections come from. The material library is made relative to the working
+/// directory rather than to the scene declaring it, as is the BVH cache.
+pub(crate) fn read(path: &Path) -> Result<(Document, Sources), RenderError> {
+    let mut file = read_layer(path, 0)?;
+    let sources = std::mem::take(&mut file.sources);
+    Ok((
+        file.into_document(path)?,
+        Sources {
+            scene: path.to_path_buf(),
+            ..sources
+        },
+    ))
+}
+
+fn read_layer(path: &Path, depth: usize) -> Result<SceneFile, RenderError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(invalid(format!(
+            "{}: includes nested too deep",
+            path.display()
+        )));
+    }
+    let mut file = parse(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let unit = file.unit.unwrap_or_default();
+    if let Some(problem) = unit.problems().into_iter().next() {
+        return Err(invalid(format!("{}: {problem}", path.display())));
+    }
+    file.unit = Some(unit);
+    let present = [
+        file.camera.is_some(),
+        file.object_list.is_some(),
+        file.settings.is_some(),
+        file.media.is_some(),
+        file.fog.is_some(),
+        file.animation.is_some(),
+        file.background.is_some(),
+    ];
+    for (section, present) in SECTIONS.into_iter().zip(present) {
+        if present {
+            file.sources.files.insert(section, path.to_path_buf());
+        }
+    }
+    if let Some(library) = &file.material_library {
+        file.material_library = Some(directory.join(library).to_string_lossy().into_owned());
+    }
+    if let Some(cache) = &file.bvh_cache {
+        file.bvh_cache = Some(directory.join(cache).to_string_lossy().into_owned());
+    }
+    let mut base = SceneFile::default();
+    for include in std::mem::take(&mut file.include) {
+        let include = directory.join(include);
+        debug!("Including the scene: {:?}", include);
+        let mut layer = read_layer(&include, depth + 1)?;
+        layer.convert(unit);
+        base = layer.over(base);
+    }
+    let overrides = std::mem::take(&mut file.overrides);
+    let file = file.over(base);
+    if overrides.is_empty() {
+        return Ok(file);
+    }
+    let mut sources = file.sources;
+    let mut doc = SceneFile {
+        sources: Sources::default(),
+        ..file
+    }
+    .into_document(path)?;
+    for assignment in &overrides {
+        set(&mut doc, assignment, directory)?;
+        let key = assignment
+            .split(['.', '='])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        if let Some(section) = SECTIONS.into_iter().find(|&section| section == key) {
+            sources.files.insert(section, path.to_path_buf());
+        }
+    }
+    Ok(SceneFile {
+        sources,
+        ..doc.into()
+    })
+}
+
+/// Parses a scene file alone, without its includes.
+fn parse(path: &Path) -> Result<SceneFile, RenderError> {
+    let reader = std::fs::File::open(path).map_err(|e| RenderError::file(path, e))?;
+    let reader = std::io::BufReader::new(reader);
+    let file = if is_json(path) {
+        serde_json::from_reader(reader).map_err(|e| e.to_string())
+    } else {
+        // The sections are optional without having to be written `Some(..)`
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_reader(reader)
+            .map_err(|e| e.to_string())
+    };
+    file.map_err(|e| {
+        error!("Failed to deserialize Document: {}", e);
+        invalid(format!(
+            "Failed to deserialize Document {}: {e}",
+            path.display()
+        ))
+    })
+}
+
+/// The files a RON or JSON scene refers to, as they are resolved when it is read,
+/// without reading them: the scenes it includes, its material library and BVH
+/// cache, the scenes of its `@` overrides and its OBJ files.
+pub(crate) fn references(path: &Path) -> Result<Vec<PathBuf>, RenderError> {
+    let file = parse(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let overrides = file
+        .overrides
+        .iter()
+        .filter_map(|assignment| assignment.split_once('=')?.1.trim().strip_prefix('@'));
+    let objects = file.object_list.iter().flat_map(|list| list.objects());
+    Ok(file
+        .include
+        .iter()
+        .map(String::as_str)
+        .chain(file.material_library.as_deref())
+        .chain(file.bvh_cache.as_deref())
+        .chain(overrides)
+        .map(|reference| directory.join(reference))
+        .chain(objects.filter_map(|object| match object.object() {
+            Primitive::Obj { path, .. } => Some(PathBuf::from(path)),
+            _ => None,
+        }))
+        .collect())
+}
+
+fn invalid(message: String) -> RenderError {
+    RenderError::Parse(message)
+}
+
+/// Sets the value at a dotted path of the scene, e.g. `settings.samples_per_pixel=256`
+/// or `settings.integrator=Normal`, the indices selecting the elements of
+/// the lists. The value is JSON, a bare word being a string, or `@scene.ron` for the
+/// value at the same path of another scene, relative to `directory`, converted to the
+/// unit of the scene.
+pub(crate) fn set(
+    doc: &mut Document,
+    assignment: &str,
+    directory: &Path,
+) -> Result<(), RenderError> {
+    let Some((path, value)) = assignment.split_once('=') else {
+        return Err(invalid(format!("{assignment:?} is not path=value")));
+    };
+    let keys: Vec<&str> = path.trim().split('.').collect();
+    let value = value.trim();
+    let value = match value.strip_prefix('@') {
+        Some(scene) => {
+            let mut other = Document::read(&directory.join(scene))?;
+            other.convert(doc.unit);
+            let mut other = to_value(&other)?;
+            lookup(&mut other, &keys)
+                .ok_or_else(|| RenderError::NotFound(format!("{scene} has no {path}")))?
+                .clone()
+        }
+        None => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+    };
+    let mut scene = to_value(doc)?;
+    let target = lookup(&mut scene, &keys)
+        .ok_or_else(|| RenderError::NotFound(format!("the scene has no {path}")))?;
+    *target = value;
+    *doc = serde_json::from_value(scene)
+        .map_err(|e| invalid(format!("invalid value for {path}: {e}")))?;
+    Ok(())
+}
+
+fn to_value(doc: &Document) -> Result<Value, RenderError> {
+    serde_json::to_value(doc).map_err(|e| RenderError::Serialize(e.to_string()))
+}
+
+/// The value at the keys, the object fields or list indices.
+fn lookup<'a>(value: &'a mut Value, keys: &[&str]) -> Option<&'a mut Value> {
+    keys.iter().try_fold(value, |value, key| match value {
+        Value::Object(fields) => fields.get_mut(*key),
+        Value::Array(values) => values.get_mut(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of scenes, with `sample.ron` the sample scene of the repository.
+    fn scenes(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("crust-render-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../samples");
+        // The assets of the sample are relative to the root of the repository
+        let sample = std::fs::read_to_string(samples.join("scene.ron"))
+            .unwrap()
+            .replace("./samples/", &format!("{}/", samples.display()));
+        std::fs::write(directory.join("sample.ron"), sample).unwrap();
+        for (file, content) in files {
+            std::fs::write(directory.join(file), content).unwrap();
+        }
+        directory
+    }
+
+    fn samples_per_pixel(doc: &Document) -> Value {
+        to_value(doc).unwrap()["settings"]["samples_per_pixel"].clone()
+    }
+
+    #[test]
+    fn applies_the_overrides_over_the_includes() {
+        let directory = scenes(
+            "overrides",
+            &[(
+                "shot.ron",
+                r#"(include: ["sample.ron"], overrides: ["settings.samples_per_pixel=7"])"#,
+            )],
+        );
+        let doc = Document::read(&directory.join("shot.ron")).unwrap();
+        assert_eq!(samples_per_pixel(&doc), 7);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn takes_the_sections_of_the_last_include() {
+        let directory = scenes(
+            "includes",
+            &[
+                (
+                    "look.ron",
+                    r#"(include: ["sample.ron"], overrides: ["settings.samples_per_pixel=3"])"#,
+                ),
+                ("look_last.ron", r#"(include: ["sample.ron", "look.ron"])"#),
+                (
+                    "sample_last.ron",
+                    r#"(include: ["look.ron", "sample.ron"])"#,
+                ),
+            ],
+        );
+        let doc = Document::read(&directory.join("look_last.ron")).unwrap();
+        assert_eq!(samples_per_pixel(&doc), 3);
+        let doc = Document::read(&directory.join("sample_last.ron")).unwrap();
+        assert_eq!(samples_per_pixel(&doc), 64);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn sets_the_values_of_other_scenes() {
+        let directory = scenes(
+            "set",
+            &[(
+                "look.ron",
+                r#"(include: ["sample.ron"], overrides: ["settings.samples_per_pixel=5"])"#,
+            )],
+        );
+        let mut doc = Document::read(&directory.join("sample.ron")).unwrap();
+        set(&mut doc, "settings.samples_per_pixel=@look.ron", &directory).unwrap();
+        assert_eq!(samples_per_pixel(&doc), 5);
+        set(&mut doc, "settings.samples_per_pixel = 9", &directory).unwrap();
+        assert_eq!(samples_per_pixel(&doc), 9);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn refuses_the_invalid_assignments() {
+        let directory = scenes("assignments", &[]);
+        let mut doc = Document::read(&directory.join("sample.ron")).unwrap();
+        assert!(set(&mut doc, "settings.samples_per_pixel", &directory).is_err());
+        assert!(set(&mut doc, "settings.no_such_setting=1", &directory).is_err());
+        assert!(set(&mut doc, "settings.samples_per_pixel=many", &directory).is_err());
+        assert!(
+            set(
+                &mut doc,
+                "settings.samples_per_pixel=@missing.ron",
+                &directory
+            )
+            .is_err()
+        );
+        assert_eq!(samples_per_pixel(&doc), 64);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,799 @@
+use crate::buffer::Buffer;
+use crate::convert::{to_rgb8, write_image};
+use crate::document::Document;
+use crate::primitives::Primitive;
+use crate::progress::Progress;
+use crate::scene_file;
+use crate::tracer::{RenderSettings, Renderer};
+use image::codecs::jpeg::JpegEncoder;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Quality of the JPEG previews, from 1 to 100.
+const JPEG_QUALITY: u8 = 80;
+
+/// Largest request body accepted, i.e. scene submitted to the job server.
+const MAX_BODY_SIZE: usize = 64 << 20;
+
+/// Largest request line and headers accepted.
+const MAX_HEAD_SIZE: u64 = 64 << 10;
+
+/// Time after which a connection that stopped sending its request or receiving its
+/// response is dropped.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Formats of the images the job server renders to.
+const JOB_FORMATS: [&str; 3] = ["png", "jpg", "exr"];
+
+/// The page following the render, refreshing the image and the statistics.
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>crust-render</title></head>
+<body style="background:#202020;color:#ddd;font-family:monospace">
+<img id="image" src="/image.jpg" style="max-width:100%">
+<pre id="stats"></pre>
+<script>
+setInterval(() => {
+  document.getElementById("image").src = "/image.jpg?" + Date.now();
+  fetch("/stats").then((r) => r.text()).then((t) => {
+    document.getElementById("stats").textContent = t;
+  });
+}, 2000);
+</script>
+</body>
+</html>
+"#;
+
+/// What the server shows of the render.
+struct State {
+    settings: RenderSettings,
+    progress: Arc<Progress>,
+    /// The film accumulated from the tiles, with the number of its render.
+    film: Mutex<(usize, Buffer)>,
+}
+
+/// Serves the progress of the renders over HTTP, from a thread of its own:
+/// - `/`: A page following the render.
+/// - `/image.jpg`: The image accumulated so far, as the JPEG output would show it.
+/// - `/stats`: The statistics of the render as JSON, with the samples per pixel taken,
+///   the fraction of the render done, the elapsed and remaining time in seconds, and
+///   the rays traced.
+///
+/// # Parameters
+/// - `address`: The address to listen to, e.g. `127.0.0.1:8080`.
+/// - `renderer`: The renderer, which the server adds a tile preview to.
+///
+/// # Returns
+/// - The address the server listens to, or an error if it could not listen.
+pub fn serve(address: &str, renderer: &mut Renderer) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    let (width, height) = renderer.settings.get_dimensions();
+    let state = Arc::new(State {
+        settings: renderer.settings,
+        progress: renderer.progress.clone(),
+        film: Mutex::new((0, Buffer::new(width, height))),
+    });
+    let tiles = state.clone();
+    renderer.on_tile_complete(move |tile| {
+        let render = tiles.progress.render();
+        let mut film = tiles.film.lock().expect("film lock poisoned");
+        // A new render, e.g. after the camera moved in the preview window
+        if film.0 != render {
+            *film = (render, Buffer::new(width, height));
+        }
+        film.1.merge(tile);
+    });
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = respond(stream, &state) {
+                    debug!("Error answering a progress request: {}", e);
+                }
+            });
+        }
+    });
+    Ok(local_address)
+}
+
+/// Bounds the time a connection can take to send its request and receive its
+/// response, so that a slow or stalled client does not hold its thread forever.
+fn set_timeouts(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(STREAM_TIMEOUT))?;
+    stream.set_write_timeout(Some(STREAM_TIMEOUT))
+}
+
+/// Answers an HTTP request.
+fn respond(mut stream: TcpStream, state: &State) -> io::Result<()> {
+    set_timeouts(&stream)?;
+    let request = Request::read(&stream)?;
+    let response = match request.path.as_str() {
+        "/" => ("200 OK", "text/html", PAGE.as_bytes().to_vec()),
+        "/image.jpg" => ("200 OK", "image/jpeg", state.jpeg()?),
+        "/stats" => ("200 OK", "application/json", state.stats().into_bytes()),
+        _ => not_found(),
+    };
+    write_response(&mut stream, response)
+}
+
+/// A status, a content type and a body.
+type Response = (&'static str, &'static str, Vec<u8>);
+
+fn not_found() -> Response {
+    ("404 Not Found", "text/plain", b"Not found".to_vec())
+}
+
+/// Writes a response and closes the connection.
+fn write_response(
+    stream: &mut TcpStream,
+    (status, content_type, body): Response,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+/// An HTTP request, its query parameters decoded.
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads a request with its body, of the size given by its `Content-Length`.
+    ///
+    /// The request line and headers are bounded by `MAX_HEAD_SIZE` and the body by
+    /// `MAX_BODY_SIZE`, the body growing as it is received rather than allocated
+    /// upfront from the length announced.
+    fn read(stream: &TcpStream) -> io::Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut head = reader.by_ref().take(MAX_HEAD_SIZE);
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        let mut words = line.split_whitespace();
+        let method = words.next().unwrap_or("GET").to_string();
+        let target = words.next().unwrap_or("/");
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+                (decode(name), decode(value))
+            })
+            .collect();
+        let path = path.to_string();
+        // The headers are read out, so that closing the connection does not reset it
+        let (mut length, mut content_type) = (0, String::new());
+        let mut header = String::new();
+        while head.read_line(&mut header)? > 2 {
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.parse().map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length")
+                    })?;
+                } else if name.eq_ignore_ascii_case("content-type") {
+                    content_type = value.to_ascii_lowercase();
+                }
+            }
+            header.clear();
+        }
+        if head.limit() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("request headers over {MAX_HEAD_SIZE} bytes"),
+            ));
+        }
+        if length > MAX_BODY_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("request body of {length} bytes over {MAX_BODY_SIZE}"),
+            ));
+        }
+        let mut body = Vec::new();
+        reader.take(length as u64).read_to_end(&mut body)?;
+        if body.len() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "request body shorter than its Content-Length",
+            ));
+        }
+        Ok(Request {
+            method,
+            path,
+            query,
+            content_type,
+            body,
+        })
+    }
+
+    /// The values of a query parameter, in order.
+    fn parameters<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.query
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Decodes a component of a URL, its `%XX` escapes and its `+` for spaces.
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = component
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+impl State {
+    /// The film accumulated so far, as a JPEG image.
+    fn jpeg(&self) -> io::Result<Vec<u8>> {
+        let image = {
+            let film = self.film.lock().expect("film lock poisoned");
+            to_rgb8(&film.1, &self.settings)
+        };
+        let mut jpeg = Vec::new();
+        image
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+            .map_err(io::Error::other)?;
+        Ok(jpeg)
+    }
+
+    /// The statistics of the render, as JSON.
+    fn stats(&self) -> String {
+        self.progress.stats().to_json()
+    }
+}
+
+/// The state of a render job.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Queued,
+    Rendering,
+    Done,
+    /// Cancelled, the image holding what was rendered if it was rendering.
+    Cancelled,
+    Failed,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Rendering => "rendering",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// A scene submitted to the job server, and its render.
+struct Job {
+    id: usize,
+    settings: RenderSettings,
+    /// The image the render is written to.
+    output: PathBuf,
+    /// The state of the job, with the error that failed it.
+    status: Mutex<(Status, Option<String>)>,
+    progress: Arc<Progress>,
+    /// The film accumulated from the tiles.
+    film: Mutex<Buffer>,
+    cancel: Arc<AtomicBool>,
+    /// Whether the image has been written.
+    written: AtomicBool,
+}
+
+/// The largest renders the job server accepts, so that a scene submitted cannot take
+/// the memory of the server or render for days.
+#[derive(Debug, Clone, Copy)]
+pub struct JobLimits {
+    /// Pixels of the image, its width times its height, 16M by default.
+    pub max_pixels: usize,
+    /// Samples of a pixel, 65536 by default.
+    pub max_samples_per_pixel: u32,
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: 4096 * 4096,
+            max_samples_per_pixel: 1 << 16,
+        }
+    }
+}
+
+impl JobLimits {
+    /// Refuses the settings of a render over the limits.
+    fn check(&self, settings: &RenderSettings) -> Result<(), String> {
+        let (width, height) = settings.get_dimensions();
+        if width
+            .checked_mul(height)
+            .is_none_or(|pixels| pixels > self.max_pixels)
+        {
+            return Err(format!(
+                "the image of {width}x{height} pixels is over the limit of {} pixels",
+                self.max_pixels
+            ));
+        }
+        let samples_per_pixel = settings.samples_per_pixel();
+        if samples_per_pixel > self.max_samples_per_pixel {
+            return Err(format!(
+                "{samples_per_pixel} samples per pixel are over the limit of {}",
+                self.max_samples_per_pixel
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The jobs of the job server, rendered one after the other.
+struct Jobs {
+    /// The directory the scenes and images of the jobs are written to.
+    directory: PathBuf,
+    limits: JobLimits,
+    jobs: Mutex<Vec<Arc<Job>>>,
+    /// The number of scenes submitted, which name the scenes as they are read.
+    uploads: AtomicUsize,
+    queue: Sender<(Arc<Job>, Document)>,
+}
+
+/// Serves a queue of render jobs over HTTP, from threads of their own, so that the
+/// renderer can back a web service or a CI pipeline:
+/// - `POST /jobs`: Queues the render of the scene in the body, RON, or JSON for a
+///   `Content-Type` of `application/json`, or of the built-in scene of a `builtin`
+///   parameter, e.g. `/jobs?builtin=cornell-box`. The `set` parameters set values of
+///   the scene as `--set` does, e.g. `set=settings.samples_per_pixel%3D64`, and
+///   `format` the format of the image, `png` by default, `jpg` or `exr`. Answers the
+///   job as `GET /jobs/<id>` does.
+/// - `GET /jobs`: The jobs, as a JSON array.
+/// - `GET /jobs/<id>`: The job as JSON: its `status`, `queued`, `rendering`, `done`,
+///   `cancelled` or `failed` with its `error`, the path of its image, and the
+///   statistics of its render as `/stats` gives them.
+/// - `GET /jobs/<id>/image.jpg`: The image of the job accumulated so far.
+/// - `GET /jobs/<id>/output`: The image of the job, once written.
+/// - `DELETE /jobs/<id>`: Cancels the job, the image holding what was rendered.
+///
+/// The scenes submitted are written to `directory` as `job-<id>.ron` or
+/// `job-<id>.json`, the paths they refer to being relative to it, and the images as
+/// `job-<id>.<format>`. The scenes referring to files outside of it are refused, and
+/// those over the `limits` too.
+///
+/// Each connection is answered on a thread of its own, and dropped after
+/// `STREAM_TIMEOUT` without progress.
+///
+/// # Parameters
+/// - `address`: The address to listen to, e.g. `127.0.0.1:8080`.
+/// - `directory`: The directory of the jobs, created if missing.
+/// - `limits`: The largest renders accepted.
+///
+/// # Returns
+/// - The address the server listens to, or an error if it could not listen.
+pub fn serve_jobs(address: &str, directory: &Path, limits: JobLimits) -> io::Result<SocketAddr> {
+    std::fs::create_dir_all(directory)?;
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    let (queue, queued) = mpsc::channel();
+    let jobs = Arc::new(Jobs {
+        directory: directory.to_path_buf(),
+        limits,
+        jobs: Mutex::new(Vec::new()),
+        uploads: AtomicUsize::new(0),
+        queue,
+    });
+    std::thread::spawn(move || render_jobs(queued));
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let jobs = jobs.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = jobs.respond(stream) {
+                    debug!("Error answering a job request: {}", e);
+                }
+            });
+        }
+    });
+    Ok(local_address)
+}
+
+/// Renders the jobs in the order they were queued, until the server stops.
+fn render_jobs(queued: Receiver<(Arc<Job>, Document)>) {
+    for (job, doc) in queued {
+        if job.cancel.load(Ordering::Relaxed) {
+            continue;
+        }
+        job.set_status(Status::Rendering, None);
+        info!("Rendering job {} to {:?}", job.id, job.output);
+        let mut renderer = doc.renderer(job.settings).with_cancel(job.cancel.clone());
+        renderer.progress = job.progress.clone();
+        let tiles = job.clone();
+        renderer.on_tile_complete(move |tile| {
+            tiles.film.lock().expect("film lock poisoned").merge(tile);
+        });
+        let buffer = renderer.render();
+        match write_image(&buffer, &job.output.to_string_lossy(), &job.settings) {
+            Ok(_) => {
+                job.written.store(true, Ordering::Relaxed);
+                if renderer.is_cancelled() {
+                    warn!("Job {} cancelled, partial image written", job.id);
+                    job.set_status(Status::Cancelled, None);
+                } else {
+                    info!("Job {} done", job.id);
+                    job.set_status(Status::Done, None);
+                }
+            }
+            Err(e) => {
+                error!("Error writing the image of job {}: {}", job.id, e);
+                job.set_status(Status::Failed, Some(e.to_string()));
+            }
+        }
+    }
+}
+
+impl Jobs {
+    /// Answers an HTTP request.
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        set_timeouts(&stream)?;
+        let response = match Request::read(&stream) {
+            Ok(request) => self.route(&request)?,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                ("400 Bad Request", "text/plain", e.to_string().into_bytes())
+            }
+            Err(e) => return Err(e),
+        };
+        write_response(&mut stream, response)
+    }
+
+    fn route(&self, request: &Request) -> io::Result<Response> {
+        let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+        let method = request.method.as_str();
+        let job = match segments.get(1) {
+            Some(id) => match self.job(id) {
+                Some(job) => Some(job),
+                None => return Ok(not_found()),
+            },
+            None => None,
+        };
+        Ok(match (method, segments.as_slice(), job) {
+            ("GET", ["jobs"], _) => {
+                let jobs = self.jobs.lock().expect("jobs lock poisoned");
+                let jobs: Vec<String> = jobs.iter().map(|job| job.to_json()).collect();
+                json(format!("[{}]", jobs.join(", ")))
+            }
+            ("POST", ["jobs"], _) => match self.submit(request) {
+                Ok(job) => (
+                    "201 Created",
+                    "application/json",
+                    job.to_json().into_bytes(),
+                ),
+                Err(e) => ("400 Bad Request", "text/plain", e.into_bytes()),
+            },
+            ("GET", ["jobs", _], Some(job)) => json(job.to_json()),
+            ("DELETE", ["jobs", _], Some(job)) => {
+                job.cancel();
+                json(job.to_json())
+            }
+            ("GET", ["jobs", _, "image.jpg"], Some(job)) => ("200 OK", "image/jpeg", job.jpeg()?),
+            ("GET", ["jobs", _, "output"], Some(job)) => {
+                if job.written.load(Ordering::Relaxed) {
+                    ("200 OK", job.content_type(), std::fs::read(&job.output)?)
+                } else {
+                    (
+                        "409 Conflict",
+                        "text/plain",
+                        b"The image is not written yet".to_vec(),
+                    )
+                }
+            }
+            (_, ["jobs"] | ["jobs", _] | ["jobs", _, "image.jpg" | "output"], _) => (
+                "405 Method Not Allowed",
+                "text/plain",
+                b"Method not allowed".to_vec(),
+            ),
+            _ => not_found(),
+        })
+    }
+
+    /// The job of an ID.
+    fn job(&self, id: &str) -> Option<Arc<Job>> {
+        let id: usize = id.parse().ok()?;
+        let jobs = self.jobs.lock().expect("jobs lock poisoned");
+        jobs.get(id.checked_sub(1)?).cloned()
+    }
+
+    /// Reads the scene of a request and queues its render.
+    ///
+    /// # Returns
+    /// - The job, or the reason the scene was refused.
+    fn submit(&self, request: &Request) -> Result<Arc<Job>, String> {
+        let format = request.parameters("format").last().unwrap_or("png");
+        if !JOB_FORMATS.contains(&format) {
+            return Err(format!(
+                "unsupported format {format:?}, the formats are {}",
+                JOB_FORMATS.join(", ")
+            ));
+        }
+        let extension = if request.content_type.contains("json") {
+            "json"
+        } else {
+            "ron"
+        };
+        // The scene is read under a name of its own, the jobs being locked only once
+        // it is accepted, to take its ID
+        let upload = self.uploads.fetch_add(1, Ordering::Relaxed) + 1;
+        let scene = self.directory.join(format!("upload-{upload}.{extension}"));
+        let doc = read_scene(request, &scene, &self.directory).and_then(|doc| {
+            self.limits.check(&doc.settings())?;
+            Ok(doc)
+        });
+        let doc = match doc {
+            Ok(doc) => doc,
+            Err(e) => {
+                if scene.exists() {
+                    let _ = std::fs::remove_file(&scene);
+                }
+                return Err(e);
+            }
+        };
+        let settings = doc.settings();
+        let (width, height) = settings.get_dimensions();
+        let film = Buffer::new(width, height);
+
+        let mut jobs = self.jobs.lock().expect("jobs lock poisoned");
+        let id = jobs.len() + 1;
+        if scene.exists()
+            && let Err(e) =
+                std::fs::rename(&scene, self.directory.join(format!("job-{id}.{extension}")))
+        {
+            warn!("Error renaming the scene of job {}: {}", id, e);
+        }
+        let job = Arc::new(Job {
+            id,
+            settings,
+            output: self.directory.join(format!("job-{id}.{format}")),
+            status: Mutex::new((Status::Queued, None)),
+            progress: Arc::new(Progress::new()),
+            film: Mutex::new(film),
+            cancel: Arc::new(AtomicBool::new(false)),
+            written: AtomicBool::new(false),
+        });
+        info!("Job {} queued", id);
+        self.queue
+            .send((job.clone(), doc))
+            .map_err(|_| "The renderer has stopped".to_string())?;
+        jobs.push(job.clone());
+        Ok(job)
+    }
+}
+
+/// Reads the scene of a request, its body written to `scene` unless it names a
+/// built-in scene, with the values its `set` parameters set.
+///
+/// The files the scene refers to, its includes, material library, `@` overrides and
+/// OBJ files, the scenes of the `@` values of the `set` parameters and the OBJ files
+/// of the scene once they are set must be in the job `directory`, so that the scenes
+/// submitted cannot read the other files of the server, nor tell whether they exist.
+fn read_scene(request: &Request, scene: &Path, directory: &Path) -> Result<Document, String> {
+    let mut doc = match request.parameters("builtin").last() {
+        Some(name) => Document::read(Path::new(&format!("builtin:{name}"))),
+        None => {
+            std::fs::write(scene, &request.body)
+                .map_err(|e| format!("Error writing the scene {scene:?}: {e}"))?;
+            let references = scene_file::references(scene)
+                .map_err(|e| format!("Error reading the scene: {e}"))?;
+            for reference in references {
+                check_within(&reference, directory)?;
+            }
+            Document::read(scene)
+        }
+    }
+    .map_err(|e| format!("Error reading the scene: {e}"))?;
+    for assignment in request.parameters("set") {
+        if let Some((_, value)) = assignment.split_once('=')
+            && let Some(other) = value.trim().strip_prefix('@')
+        {
+            check_within(Path::new(other), directory)?;
+        }
+        doc.set(assignment)
+            .map_err(|e| format!("Failed to set {assignment:?}: {e}"))?;
+    }
+    // The values set may point the OBJ files anywhere, which the validation would
+    // look for
+    for object in doc.object_list.objects() {
+        if let Primitive::Obj { path, .. } = object.object() {
+            check_within(Path::new(path), directory)?;
+        }
+    }
+    let issues = doc.validate();
+    if !issues.is_empty() {
+        let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+        return Err(format!("Invalid scene: {}", issues.join(", ")));
+    }
+    Ok(doc)
+}
+
+/// Refuses a file outside of the job directory, or missing.
+fn check_within(path: &Path, directory: &Path) -> Result<(), String> {
+    match (path.canonicalize(), directory.canonicalize()) {
+        (Ok(path), Ok(directory)) if path.starts_with(&directory) => Ok(()),
+        _ => Err(format!("{path:?} is not a file of the job directory")),
+    }
+}
+
+fn json(body: String) -> Response {
+    ("200 OK", "application/json", body.into_bytes())
+}
+
+impl Job {
+    fn set_status(&self, status: Status, error: Option<String>) {
+        *self.status.lock().expect("status lock poisoned") = (status, error);
+    }
+
+    /// Cancels the job, stopping its render if it is rendering.
+    fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        let mut status = self.status.lock().expect("status lock poisoned");
+        if status.0 == Status::Queued {
+            *status = (Status::Cancelled, None);
+        }
+    }
+
+    /// The content type of the image of the job.
+    fn content_type(&self) -> &'static str {
+        match self.output.extension().and_then(|e| e.to_str()) {
+            Some("png") => "image/png",
+            Some("jpg") => "image/jpeg",
+            _ => "image/x-exr",
+        }
+    }
+
+    /// The film accumulated so far, as a JPEG image.
+    fn jpeg(&self) -> io::Result<Vec<u8>> {
+        let image = {
+            let film = self.film.lock().expect("film lock poisoned");
+            to_rgb8(&film, &self.settings)
+        };
+        let mut jpeg = Vec::new();
+        image
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+            .map_err(io::Error::other)?;
+        Ok(jpeg)
+    }
+
+    /// The job as a JSON object.
+    fn to_json(&self) -> String {
+        let (status, error) = self.status.lock().expect("status lock poisoned").clone();
+        let error = match error {
+            Some(error) => serde_json::Value::String(error).to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"id\": {}, \"status\": \"{}\", \"error\": {}, \"output\": {}, \"stats\": {}}}",
+            self.id,
+            status.name(),
+            error,
+            serde_json::Value::String(self.output.to_string_lossy().into_owned()),
+            self.progress.stats().to_json()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A job directory of its own, emptied.
+    fn job_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("crust-render-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn request(query: &[(&str, &str)], body: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/jobs".to_string(),
+            query: query
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            content_type: String::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn refuses_files_outside_the_job_directory() {
+        let directory = job_directory("within");
+        let inside = directory.join("mesh.obj");
+        std::fs::write(&inside, "").unwrap();
+        assert!(check_within(&inside, &directory).is_ok());
+        assert!(check_within(&directory.join("../mesh.obj"), &directory).is_err());
+        assert!(check_within(&directory.join("missing.obj"), &directory).is_err());
+        assert!(check_within(Path::new("/"), &directory).is_err());
+    }
+
+    #[test]
+    fn refuses_obj_files_set_outside_the_job_directory() {
+        let directory = job_directory("set");
+        let scene = directory.join("job.ron");
+        let outside = std::env::temp_dir().join("crust-render-outside.obj");
+        std::fs::write(&outside, "").unwrap();
+        for path in [outside.clone(), PathBuf::from("/no/such/file.obj")] {
+            let set = format!(
+                "object_list.objects.0.object={{\"Obj\":{{\"path\":{:?}}}}}",
+                path.to_string_lossy()
+            );
+            let request = request(&[("builtin", "furnace"), ("set", &set)], "");
+            let error = read_scene(&request, &scene, &directory).unwrap_err();
+            assert!(
+                error.contains("is not a file of the job directory"),
+                "{error}"
+            );
+        }
+        let request = request(&[("builtin", "furnace")], "");
+        assert!(read_scene(&request, &scene, &directory).is_ok());
+    }
+
+    #[test]
+    fn refuses_set_scenes_outside_the_job_directory() {
+        let directory = job_directory("override");
+        let scene = directory.join("job.ron");
+        let request = request(
+            &[("builtin", "furnace"), ("set", "camera=@../other.ron")],
+            "",
+        );
+        let error = read_scene(&request, &scene, &directory).unwrap_err();
+        assert!(
+            error.contains("is not a file of the job directory"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn limits_the_renders() {
+        let limits = JobLimits {
+            max_pixels: 100 * 100,
+            max_samples_per_pixel: 16,
+        };
+        let settings = RenderSettings::default().with_samples_per_pixel(16);
+        assert!(limits.check(&settings.with_dimensions(100, 100)).is_ok());
+        assert!(limits.check(&settings.with_dimensions(101, 100)).is_err());
+        assert!(
+            limits
+                .check(&settings.with_dimensions(usize::MAX, 2))
+                .is_err()
+        );
+        let settings = settings.with_dimensions(10, 10).with_samples_per_pixel(17);
+        assert!(limits.check(&settings).is_err());
+    }
+}
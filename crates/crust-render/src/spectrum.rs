@@ -0,0 +1,163 @@
+use crate::color::XYZ_TO_LINEAR_SRGB;
+use std::sync::OnceLock;
+use utils::{Color, Float};
+
+/// Shortest wavelength sampled, in nanometers.
+pub const LAMBDA_MIN: Float = 360.0;
+/// Longest wavelength sampled, in nanometers.
+pub const LAMBDA_MAX: Float = 830.0;
+
+/// The wavelength a path is traced at, carried by its rays for the dispersive
+/// materials and the conductors.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Wavelength {
+    /// An RGB path not dispersed yet: the first dispersive surface it meets picks one
+    /// of its channels.
+    #[default]
+    Rgb,
+    /// An RGB path dispersed into a channel, which it carries alone from then on so
+    /// that the next dispersions refract it at the same wavelength.
+    Channel(usize),
+    /// A spectral path, at the wavelength sampled for it in nanometers.
+    Sampled(Float),
+}
+
+/// The wavelength carried by a camera sample in spectral renders.
+///
+/// Each sample traces a single wavelength, at which the dispersive materials and
+/// the conductors are evaluated along the whole path, the throughput itself staying
+/// RGB: this is not hero wavelength sampling, whose paths carry several wavelengths
+/// and a spectral throughput. The wavelengths are spread over the samples of a pixel by the
+/// sampler, which converges the color of the pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelength {
+    lambda: Float,
+}
+
+impl SampledWavelength {
+    /// Samples the wavelength uniformly over the visible range from a value in [0, 1).
+    pub fn sample(u: Float) -> Self {
+        Self {
+            lambda: LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN),
+        }
+    }
+
+    /// The wavelength, in nanometers.
+    pub fn lambda(&self) -> Float {
+        self.lambda
+    }
+
+    /// Converts the radiance of a path into the linear RGB response of the film.
+    ///
+    /// The RGB radiance is upsampled to a spectrum, evaluated at the wavelength and
+    /// projected onto the CIE matching functions, weighted by the inverse of its
+    /// uniform density.
+    ///
+    /// # Parameters
+    /// - `radiance`: The radiance returned by the integrator.
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        let coefficients = mul(&basis().inverse, radiance);
+        let weighted = rgb_basis(self.lambda) * coefficients;
+        let value = weighted.r() + weighted.g() + weighted.b();
+        let xyz = value * (LAMBDA_MAX - LAMBDA_MIN) * cie_xyz(self.lambda);
+        mul(&XYZ_TO_LINEAR_SRGB, xyz / basis().y_integral)
+    }
+}
+
+/// Round trip of the RGB basis through the matching functions, computed once.
+struct Basis {
+    /// Maps RGB to the basis coefficients reproducing it at the film.
+    inverse: [[Float; 3]; 3],
+    /// Integral of the luminance matching function, normalizing a flat spectrum to Y = 1.
+    y_integral: Float,
+}
+
+fn basis() -> &'static Basis {
+    static BASIS: OnceLock<Basis> = OnceLock::new();
+    BASIS.get_or_init(|| {
+        let mut response = [Color::zero(); 3];
+        let mut y_integral = 0.0;
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let xyz = cie_xyz(lambda);
+            let b = rgb_basis(lambda);
+            for (j, r) in response.iter_mut().enumerate() {
+                *r += b[j] * xyz;
+            }
+            y_integral += xyz.g();
+            lambda += 1.0;
+        }
+        // Columns of the RGB produced by each basis function
+        let columns = response.map(|xyz| mul(&XYZ_TO_LINEAR_SRGB, xyz / y_integral));
+        let forward = [
+            [columns[0].r(), columns[1].r(), columns[2].r()],
+            [columns[0].g(), columns[1].g(), columns[2].g()],
+            [columns[0].b(), columns[1].b(), columns[2].b()],
+        ];
+        Basis {
+            inverse: invert(&forward),
+            y_integral,
+        }
+    })
+}
+
+/// Smooth red, green and blue bands forming a partition of unity over the spectrum.
+fn rgb_basis(lambda: Float) -> Color {
+    let smoothstep = |lo: Float, hi: Float| {
+        let t = ((lambda - lo) / (hi - lo)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let blue_to_green = smoothstep(470.0, 520.0);
+    let green_to_red = smoothstep(570.0, 620.0);
+    Color::new(
+        green_to_red,
+        blue_to_green - green_to_red,
+        1.0 - blue_to_green,
+    )
+}
+
+/// CIE 1931 color matching functions, multi-lobe fit of Wyman et al. 2013.
+pub fn cie_xyz(lambda: Float) -> Color {
+    let g = |mu: Float, sigma_lo: Float, sigma_hi: Float| {
+        let t = (lambda - mu) / if lambda < mu { sigma_lo } else { sigma_hi };
+        (-0.5 * t * t).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn mul(m: &[[Float; 3]; 3], v: Color) -> Color {
+    Color::new(
+        m[0][0] * v.r() + m[0][1] * v.g() + m[0][2] * v.b(),
+        m[1][0] * v.r() + m[1][1] * v.g() + m[1][2] * v.b(),
+        m[2][0] * v.r() + m[2][1] * v.g() + m[2][2] * v.b(),
+    )
+}
+
+fn invert(m: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    let inv_det = 1.0 / det;
+    [
+        [
+            cofactor(1, 2, 1, 2) * inv_det,
+            -cofactor(0, 2, 1, 2) * inv_det,
+            cofactor(0, 1, 1, 2) * inv_det,
+        ],
+        [
+            -cofactor(1, 2, 0, 2) * inv_det,
+            cofactor(0, 2, 0, 2) * inv_det,
+            -cofactor(0, 1, 0, 2) * inv_det,
+        ],
+        [
+            cofactor(1, 2, 0, 1) * inv_det,
+            -cofactor(0, 2, 0, 1) * inv_det,
+            cofactor(0, 1, 0, 1) * inv_det,
+        ],
+    ]
+}
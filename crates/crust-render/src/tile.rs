@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use utils::Float;
+
+/// The order in which the tiles of the image are handed to the threads.
+///
+/// The order does not change the image, only how it fills up, which matters when
+/// following the progress or when the render is stopped early.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileOrder {
+    /// Row by row, from the top of the image.
+    #[default]
+    Scanline,
+    /// Ring by ring, from the center of the image outwards, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles close for cache coherence.
+    Hilbert,
+}
+
+/// Splits an image into square tiles, listed in the given order.
+///
+/// # Parameters
+/// - `width`, `height`: The size of the image in pixels.
+/// - `size`: The side of the tiles in pixels; the tiles of the last row and column are
+///   cropped to the image.
+/// - `order`: The order of the tiles.
+///
+/// # Returns
+/// - The position of the bottom-left pixel of each tile, in the buffer coordinates
+///   where `y` grows upwards.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<(usize, usize)> {
+    let size = size.max(1);
+    let (columns, rows) = (width.div_ceil(size), height.div_ceil(size));
+    let mut tiles: Vec<(usize, usize)> = (0..rows)
+        .rev()
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center = (columns as Float / 2.0 - 0.5, rows as Float / 2.0 - 0.5);
+            let key = |&(column, row): &(usize, usize)| {
+                let (dx, dy) = (column as Float - center.0, row as Float - center.1);
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            tiles.sort_by(|a, b| {
+                let (ring_a, angle_a) = key(a);
+                let (ring_b, angle_b) = key(b);
+                ring_a.total_cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+            });
+        }
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            tiles.sort_by_key(|&(column, row)| hilbert_index(side, column, rows - 1 - row));
+        }
+    }
+    tiles
+        .into_iter()
+        .map(|(column, row)| (column * size, row * size))
+        .collect()
+}
+
+/// The distance along the Hilbert curve filling a `side` x `side` grid of the cell
+/// `(x, y)`, `side` being a power of two.
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        index += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant so that the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
@@ -1,93 +1,1148 @@
-use crate::buffer::Buffer;
-use crate::hittable::{HitRecord, Hittable};
+use crate::bake::{self, BakeMode, UvMesh};
+use crate::buffer::{Aovs, Buffer, ExrOptions, NormalSpace};
+use crate::color::{ColorSpace, Display};
+use crate::convert::ToneMapper;
+use crate::cryptomatte::Cryptomatte;
+use crate::denoise::{Denoiser, Features};
+use crate::document::Document;
+use crate::error::RenderError;
+use crate::filter::Filter;
+use crate::hittable::Hittable;
+use crate::integrator::{
+    GuidedPathIntegrator, Integrator, IntegratorType, MltIntegrator, RestirIntegrator,
+    SppmIntegrator,
+};
+use crate::material::SceneMaterial;
+use crate::progress::{self, Progress, Stats};
 use crate::ray::Ray;
-use crate::sampler::generate_cmj_2d;
-use crate::{LightList, camera::Camera, hittable_list::HittableList};
+use crate::sampler::{Sampler, SamplerType, stream_seed};
+use crate::scene::Scene;
+use crate::spectrum::{SampledWavelength, Wavelength};
+use crate::tile::{self, TileOrder};
+use indicatif::ProgressBar;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use utils::Color;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tracing::{debug, trace_span, warn};
+use utils::{Color, Float, Onb, Point3, Vec3};
+use web_time::Instant;
+
+/// Renders a scene with `settings`, e.g. those of the scene, at the first frame of its
+/// animation.
+///
+/// ```no_run
+/// let scene = crust_render::builtin_scene("cornell-box").unwrap();
+/// let film = crust_render::render(&scene, scene.settings().with_samples_per_pixel(16));
+/// crust_render::write_image(&film, "cornell-box.png", &scene.settings()).unwrap();
+/// ```
+///
+/// # Returns
+/// - The film of the render, with its AOVs and cryptomattes when the settings ask
+///   for them.
+pub fn render(scene: &Document, settings: RenderSettings) -> Buffer {
+    scene.renderer(settings).render()
+}
+
+/// A callback receiving the film of a render in progress.
+pub type Preview = dyn Fn(&Buffer) + Send + Sync;
+
+/// A callback receiving the film after a pass of a render, with the number of the
+/// pass, from 1, and the number of passes.
+pub type PassCallback = dyn Fn(u32, u32, &Buffer) + Send + Sync;
+
+/// A callback receiving the statistics of a render in progress.
+pub type ProgressCallback = dyn Fn(&Stats) + Send + Sync;
 
 pub struct Renderer {
-    pub camera: Camera,
-    pub world: HittableList,
-    pub lights: LightList,
+    pub scene: Scene,
     pub settings: RenderSettings,
+    pub integrator: Box<dyn Integrator>,
+    /// The names of the objects and materials by ID, for the cryptomattes.
+    pub names: (Vec<String>, Vec<String>),
+    /// Called with each tile as soon as it is rendered, from the rendering threads.
+    pub tile_callbacks: Vec<Box<Preview>>,
+    /// Called with the film after each pass of a render.
+    pub pass_callbacks: Vec<Box<PassCallback>>,
+    /// Called with the statistics of the render after each tile, from the rendering
+    /// threads, and once it is done.
+    pub progress_callbacks: Vec<Box<ProgressCallback>>,
+    /// The progress of the render, for those following it.
+    pub progress: Arc<Progress>,
+    /// Raised to stop the render early, keeping the samples taken so far.
+    pub cancel: Arc<AtomicBool>,
 }
 
 impl Renderer {
-    pub fn new(
-        camera: Camera,
-        world: HittableList,
-        lights: LightList,
-        settings: RenderSettings,
-    ) -> Self {
+    pub fn new(scene: Scene, settings: RenderSettings) -> Self {
+        let integrator = settings.integrator.create(
+            settings.max_depth,
+            &scene,
+            settings.regularization,
+            settings.seed,
+        );
         Renderer {
-            camera,
-            world,
-            lights,
+            scene,
             settings,
+            integrator,
+            names: (Vec::new(), Vec::new()),
+            tile_callbacks: Vec::new(),
+            pass_callbacks: Vec::new(),
+            progress_callbacks: Vec::new(),
+            progress: Arc::new(Progress::new()),
+            cancel: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Names the objects and materials in the cryptomattes, the ID `n` being at index
+    /// `n - 1`. Unnamed IDs are numbered.
+    pub fn with_names(mut self, objects: Vec<String>, materials: Vec<String>) -> Self {
+        self.names = (objects, materials);
+        self
+    }
+
+    /// Hands the film accumulated so far to `preview` after each pass of a progressive
+    /// render but the last, typically to write it out so that the render can be
+    /// followed.
+    pub fn with_preview(mut self, preview: impl Fn(&Buffer) + Send + Sync + 'static) -> Self {
+        self.on_pass_complete(move |pass, passes, film| {
+            if pass < passes {
+                preview(film);
+            }
+        });
+        self
+    }
+
+    /// Hands each tile to `on_tile` as soon as it is rendered, see `on_tile_complete`.
+    pub fn with_tile_preview(mut self, on_tile: impl Fn(&Buffer) + Send + Sync + 'static) -> Self {
+        self.on_tile_complete(on_tile);
+        self
+    }
+
+    /// Hands each tile to `on_tile` as soon as it is rendered, from the rendering
+    /// threads, to follow the render closely. Merging the tiles accumulates the film,
+    /// pass after pass.
+    ///
+    /// The tiles go to every callback added. Only the integrators driven by the tiles
+    /// report them, as they do their passes and progress.
+    pub fn on_tile_complete(
+        &mut self,
+        on_tile: impl Fn(&Buffer) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.tile_callbacks.push(Box::new(on_tile));
+        self
+    }
+
+    /// Hands the film accumulated so far to `on_pass` after each pass of the render,
+    /// the last one included, with the number of the pass and the number of passes. A
+    /// cancelled pass is not reported.
+    pub fn on_pass_complete(
+        &mut self,
+        on_pass: impl Fn(u32, u32, &Buffer) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.pass_callbacks.push(Box::new(on_pass));
+        self
+    }
+
+    /// Hands the statistics of the render to `on_progress` after each tile, from the
+    /// rendering threads, and once the render is done, e.g. to show its progress and
+    /// its time left.
+    pub fn on_progress(
+        &mut self,
+        on_progress: impl Fn(&Stats) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.progress_callbacks.push(Box::new(on_progress));
+        self
+    }
+
+    /// Stops the render when `cancel` is raised: the tiles being rendered are finished
+    /// but no new tile is started, and the film holds the samples taken so far.
+    ///
+    /// Only the integrators driven by the tiles honor it.
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Whether the render has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Renders the image, along with the AOVs and cryptomattes when they are enabled
+    /// in the settings. A cancelled render skips them.
+    ///
+    /// The film carries the metadata of the render, written to the EXR header.
     pub fn render(&self) -> Buffer {
-        let mut buffer = Buffer::new(self.settings.width, self.settings.height);
-        let samples_sqrt = (self.settings.samples_per_pixel as f32).sqrt().ceil() as usize;
-        let cmj_samples = generate_cmj_2d(samples_sqrt);
-        for j in (0..self.settings.height).rev() {
-            eprint!("\rScanlines remaining: {} ", j);
-            let pixel_colors: Vec<_> = (0..self.settings.width)
-                .into_par_iter()
-                .map(|i| {
-                    let mut sum = Color::new(0.0, 0.0, 0.0);
-                    let mut sum_sq = Color::new(0.0, 0.0, 0.0);
-                    let mut samples = 0;
+        let _span = trace_span!("render").entered();
+        let start = Instant::now();
+        let mut film = self.render_beauty();
+        if !self.is_cancelled() {
+            if self.settings.aovs {
+                self.render_aovs(&mut film);
+            }
+            if self.settings.cryptomatte {
+                self.render_cryptomattes(&mut film);
+            }
+        }
+        self.set_metadata(&mut film, start.elapsed());
+        film
+    }
 
-                    loop {
-                        let (u_offset, v_offset) = if samples < cmj_samples.len() {
-                            cmj_samples[samples]
-                        } else {
-                            (utils::random(), utils::random())
-                        };
-                        let u = ((i as f32) + u_offset) / (self.settings.width - 1) as f32;
-                        let v = ((j as f32) + v_offset) / (self.settings.height - 1) as f32;
-                        let r = self.camera.get_ray(u, v);
-                        let col = ray_color(
-                            &r,
-                            &self.world,
-                            &self.lights,
-                            self.settings.max_depth as i32,
-                        );
+    /// Records in the film what it takes to render it again, written to the EXR header:
+    /// - `crust/version`: The version of the renderer.
+    /// - `crust/camera`: The camera, as in the scene files.
+    /// - `crust/settings`: The render settings, as in the scene files.
+    /// - `crust/samplesPerPixel` and `crust/integrator`: The main settings, for a glance.
+    /// - `crust/seed`: The seed of the samples.
+    /// - `crust/renderTime`: The time the render took, in seconds.
+    /// - `crust/cancelled`: Present when the render was cancelled before its end.
+    fn set_metadata(&self, film: &mut Buffer, time: std::time::Duration) {
+        for (name, value) in self.metadata() {
+            film.set_metadata(&name, value);
+        }
+        film.set_metadata("crust/renderTime", format!("{:.3}", time.as_secs_f64()));
+        if self.is_cancelled() {
+            film.set_metadata("crust/cancelled", "true");
+        }
+    }
 
-                        sum += col;
-                        sum_sq += col * col;
-                        samples += 1;
+    /// The metadata of the render known before it starts, as names and values.
+    fn metadata(&self) -> Vec<(String, String)> {
+        [
+            ("crust/version", env!("CARGO_PKG_VERSION").to_string()),
+            ("crust/camera", to_ron(&self.scene.camera)),
+            ("crust/settings", to_ron(&self.settings)),
+            (
+                "crust/samplesPerPixel",
+                self.settings.samples_per_pixel.to_string(),
+            ),
+            ("crust/integrator", to_ron(&self.settings.integrator)),
+            ("crust/seed", self.settings.seed.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+    }
 
-                        if samples >= self.settings.min_samples_per_pixel as usize {
-                            let mean = sum / samples as f32;
-                            let mean_sq = sum_sq / samples as f32;
-                            let variance = mean_sq - mean * mean;
+    /// Renders the image straight to an EXR file, a band of rows at a time, so that
+    /// images of any resolution render without their film in memory: only the band
+    /// rendered and the rows the filter reaches from it are held.
+    ///
+    /// Each band takes all its samples at once rather than in passes, and only the
+    /// beauty is written: the AOVs and cryptomattes need the whole film. The header
+    /// is written first, without the render time. A cancelled render leaves the rows
+    /// it did not reach black.
+    ///
+    /// Only the integrators driven by the tiles stream, the others rendering the whole
+    /// image at once.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the EXR file, whose tiles are the size of the bands.
+    pub fn render_to_exr(&self, path: &str) -> Result<(), RenderError> {
+        if matches!(
+            self.settings.integrator,
+            IntegratorType::Mlt { .. }
+                | IntegratorType::Sppm { .. }
+                | IntegratorType::Restir { .. }
+                | IntegratorType::Guided { .. }
+        ) {
+            return Err(RenderError::CannotStream(to_ron(&self.settings.integrator)));
+        }
+        if self.settings.aovs || self.settings.cryptomatte {
+            warn!("A streamed render writes the beauty only, without the AOVs and cryptomattes");
+        }
+        let _span = trace_span!("render").entered();
+        let (width, height) = (self.settings.width, self.settings.height);
+        let spp = self.settings.samples_per_pixel.max(1);
+        let tile_size = self.settings.tile_size.max(1);
+        let margin = (self.settings.filter.radius() + 0.5).ceil() as usize;
+        // A band is finished once the next one is rendered, which the filter reaches
+        // no further than its margin
+        let band = margin.div_ceil(tile_size).max(1) * tile_size;
+        let columns = width.div_ceil(tile_size);
+        let tile_count: usize = (0..height)
+            .step_by(band)
+            .map(|y| (band.min(height - y)).div_ceil(tile_size) * columns)
+            .sum();
+        self.progress.start(width * height, tile_count);
+        let bar = progress::progress_bar(tile_count as u64, "Tiles");
+        // The rows rendered and not written yet, with those the filter reaches below
+        let mut film = Buffer::tile((0, height), width, 0);
+        // The first row rendered, the bands going down from the top of the image
+        let mut rendered = height;
+        let result = crate::buffer::write_exr_rows(
+            path,
+            (width, height),
+            band,
+            self.settings.exr(),
+            self.settings.working_space(),
+            &self.metadata(),
+            |rows| {
+                while rendered > 0 && rendered + margin > rows.start {
+                    let bottom = rendered.saturating_sub(band);
+                    let tiles: Vec<_> = (bottom..rendered)
+                        .step_by(tile_size)
+                        .flat_map(|y| (0..width).step_by(tile_size).map(move |x| (x, y)))
+                        .collect();
+                    let pixels = vec![PixelState::default(); width * (rendered - bottom)];
+                    let tiles = self.render_pass(&pixels, &tiles, bottom..rendered, spp, &bar);
+                    let reach = bottom.saturating_sub(margin);
+                    let mut next = Buffer::tile((0, reach), width, rows.end - reach);
+                    next.merge(&film);
+                    for (tile, _) in &tiles {
+                        next.merge(tile);
+                    }
+                    (film, rendered) = (next, bottom);
+                }
+                let mut finished = Buffer::tile((0, rows.start), width, rows.len());
+                finished.merge(&film);
+                finished
+            },
+        );
+        self.finish_progress(&bar);
+        Ok(result?)
+    }
 
-                            if variance.max_component() < self.settings.variance_threshold
-                                || samples >= self.settings.samples_per_pixel as usize
-                            {
-                                break mean; // Use `mean` as final_color and break early
-                            }
-                        }
+    /// Renders the image pass after pass, each item being the film accumulated so far,
+    /// so that the passes can be shown as they come or the render stopped early by
+    /// dropping the iterator, e.g. once the image is clean enough.
+    ///
+    /// The films hold the beauty, and the light groups when the AOVs are enabled; the
+    /// other AOVs and the cryptomattes are left to `render`. The pass callbacks are
+    /// called as in `render`, and a cancelled render ends with the film of its
+    /// unfinished pass.
+    ///
+    /// The integrators rendering the whole image at once have a single pass.
+    pub fn passes(&self) -> Passes<'_> {
+        Passes::new(self)
+    }
+
+    fn render_beauty(&self) -> Buffer {
+        let mut passes = self.passes();
+        while passes.advance() {}
+        passes.into_film()
+    }
+
+    /// Renders the image with the integrators rendering the whole image at once.
+    ///
+    /// # Returns
+    /// - The film, or `None` for the integrators driven by the tiles.
+    fn render_whole_image(&self) -> Option<Buffer> {
+        if let IntegratorType::Mlt {
+            mutations_per_pixel,
+            large_step_probability,
+            sigma,
+            chains,
+            bootstrap_samples,
+        } = self.settings.integrator
+        {
+            let mlt = MltIntegrator::new(
+                mutations_per_pixel,
+                large_step_probability,
+                sigma,
+                chains,
+                bootstrap_samples,
+            )
+            .with_seed(self.settings.seed);
+            return Some(mlt.render(
+                &self.scene,
+                self.integrator.as_ref(),
+                self.settings.width,
+                self.settings.height,
+            ));
+        }
+        if let IntegratorType::Sppm {
+            iterations,
+            photons_per_iteration,
+            initial_radius,
+        } = self.settings.integrator
+        {
+            let sppm = SppmIntegrator::new(
+                iterations,
+                photons_per_iteration,
+                initial_radius,
+                self.settings.max_depth,
+            )
+            .with_seed(self.settings.seed);
+            return Some(sppm.render(
+                &self.scene,
+                self.settings.sampler,
+                self.settings.width,
+                self.settings.height,
+            ));
+        }
+        if let IntegratorType::Restir {
+            initial_candidates,
+            spatial_neighbors,
+            spatial_radius,
+            tile_size,
+        } = self.settings.integrator
+        {
+            let restir = RestirIntegrator::new(
+                initial_candidates,
+                spatial_neighbors,
+                spatial_radius,
+                tile_size,
+                self.settings.max_depth,
+            )
+            .with_seed(self.settings.seed);
+            return Some(restir.render(
+                &self.scene,
+                self.settings.sampler,
+                self.settings.samples_per_pixel,
+                self.settings.width,
+                self.settings.height,
+            ));
+        }
+        if let IntegratorType::Guided {
+            training_iterations,
+            guiding_fraction,
+        } = self.settings.integrator
+        {
+            let guided = GuidedPathIntegrator::new(
+                training_iterations,
+                guiding_fraction,
+                self.settings.max_depth,
+            )
+            .with_seed(self.settings.seed);
+            return Some(guided.render(
+                &self.scene,
+                self.settings.sampler,
+                self.settings.samples_per_pixel,
+                self.settings.width,
+                self.settings.height,
+            ));
+        }
+        None
+    }
 
-                        if samples >= self.settings.samples_per_pixel as usize {
-                            break sum / samples as f32;
+    /// Renders the tiles of one pass, taking every pixel up to `end` samples unless it
+    /// has converged.
+    ///
+    /// The tiles are cut at the end of `rows`, the rows `pixels` holds the state of.
+    ///
+    /// # Returns
+    /// - The tiles in their order, with the new state of their pixels by index in
+    ///   `pixels`.
+    fn render_pass(
+        &self,
+        pixels: &[PixelState],
+        tiles: &[(usize, usize)],
+        rows: Range<usize>,
+        end: u32,
+        bar: &ProgressBar,
+    ) -> Vec<(Buffer, Vec<(usize, PixelState)>)> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let tile_size = self.settings.tile_size.max(1);
+        // Tiles extend beyond their pixels by the reach of the filter
+        let margin = (self.settings.filter.radius() + 0.5).ceil() as usize;
+        // The threads take the tiles in order from a shared cursor, rather than from
+        // the halves of the list rayon would split
+        let next = AtomicUsize::new(0);
+        let mut rendered: Vec<_> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .flat_map_iter(|_| {
+                std::iter::from_fn(|| {
+                    if self.is_cancelled() {
+                        return None;
+                    }
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let &(x0, y0) = tiles.get(index)?;
+                    let _span = trace_span!("tile", x = x0, y = y0).entered();
+                    let (x1, y1) = ((x0 + tile_size).min(width), (y0 + tile_size).min(rows.end));
+                    let origin = (x0.saturating_sub(margin), y0.saturating_sub(margin));
+                    let mut tile = Buffer::tile(
+                        origin,
+                        (x1 + margin).min(width) - origin.0,
+                        (y1 + margin).min(height) - origin.1,
+                    );
+                    let mut states = Vec::with_capacity((x1 - x0) * (y1 - y0));
+                    let mut samples = 0;
+                    let start = Instant::now();
+                    // Drops the rays traced on this thread outside of the tiles
+                    progress::take_ray_counts();
+                    // A sampler for the tile, moved from pixel to pixel
+                    let mut sampler = self.pixel_sampler((x0, y0));
+                    for j in y0..y1 {
+                        for i in x0..x1 {
+                            let index = (j - rows.start) * width + i;
+                            let mut state = pixels[index];
+                            sampler.start_pixel((i, j));
+                            self.render_pixel((i, j), sampler.as_mut(), &mut tile, &mut state, end);
+                            samples += u64::from(state.samples - pixels[index].samples);
+                            states.push((index, state));
                         }
                     }
+                    self.progress
+                        .add_tile(samples, progress::take_ray_counts(), start.elapsed());
+                    for on_tile in &self.tile_callbacks {
+                        on_tile(&tile);
+                    }
+                    let stats = self.progress.stats();
+                    for on_progress in &self.progress_callbacks {
+                        on_progress(&stats);
+                    }
+                    bar.set_message(format!(
+                        "{:.0} samples/s",
+                        stats.primary_rays as f64 / stats.elapsed.as_secs_f64().max(1e-3)
+                    ));
+                    bar.inc(1);
+                    Some((index, tile, states))
                 })
+            })
+            .collect();
+        // Merged in a fixed order, so that the sums do not depend on the scheduling
+        rendered.sort_by_key(|(index, _, _)| *index);
+        rendered
+            .into_iter()
+            .map(|(_, tile, states)| (tile, states))
+            .collect()
+    }
+
+    /// The sampler of the settings for a pixel, dithered by the blue-noise mask if
+    /// enabled and decorrelated from other renders by the seed.
+    fn pixel_sampler(&self, pixel: (usize, usize)) -> Box<dyn Sampler> {
+        let (sampler, samples_per_pixel) = (self.settings.sampler, self.settings.samples_per_pixel);
+        if self.settings.blue_noise {
+            sampler.create_dithered(pixel, samples_per_pixel, self.settings.seed)
+        } else {
+            sampler.create_seeded(pixel, samples_per_pixel, self.settings.seed)
+        }
+    }
+
+    /// Samples a pixel until it converges or reaches `end` samples, splatting the
+    /// samples into a tile along with the contribution of the light groups when the
+    /// AOVs are enabled.
+    ///
+    /// The pixel resumes from `state`, left by the previous passes, and updates it.
+    fn render_pixel(
+        &self,
+        (i, j): (usize, usize),
+        sampler: &mut dyn Sampler,
+        tile: &mut Buffer,
+        state: &mut PixelState,
+        end: u32,
+    ) {
+        if state.converged || state.samples >= end {
+            return;
+        }
+        let filter = self.settings.filter;
+
+        let seed = stream_seed(self.settings.seed, state.samples);
+        let stream = (j * self.settings.width + i) as u64;
+        utils::with_random_stream(seed, stream, || {
+            loop {
+                sampler.start_sample(state.samples);
+                let (u_offset, v_offset) = sampler.get_2d();
+                let (lens_u, lens_v) = sampler.get_2d();
+                let u = ((i as Float) + u_offset) / (self.settings.width - 1) as Float;
+                let v = ((j as Float) + v_offset) / (self.settings.height - 1) as Float;
+                let time = if self.settings.motion_blur {
+                    sampler.get_1d()
+                } else {
+                    0.0
+                };
+                let r = self.scene.camera.get_ray_lens(u, v, lens_u, lens_v, time);
+                let (col, groups) = self.trace(&r, sampler);
+                // A NaN or infinite sample would spoil its pixel, it is rendered black
+                let (col, groups) = if col.is_finite() {
+                    (col, groups)
+                } else {
+                    self.progress.add_invalid_sample();
+                    (Color::zero(), vec![Color::zero(); groups.len()])
+                };
+                let scale = match self.settings.max_radiance {
+                    Some(max_radiance) => clamp_factor(col, max_radiance),
+                    None => 1.0,
+                };
+                let col = col * scale;
+
+                let position = (i as Float + u_offset, j as Float + v_offset);
+                tile.splat(position, col, &filter);
+                if self.settings.aovs {
+                    for (name, group) in self.scene.lights.groups.iter().zip(groups) {
+                        tile.aov_mut(&format!("light_{name}")).splat(
+                            position,
+                            group * scale,
+                            &filter,
+                        );
+                    }
+                }
+
+                state.sum += col;
+                state.sum_sq += col * col;
+                state.samples += 1;
+
+                if state.samples >= self.settings.min_samples_per_pixel {
+                    let mean = state.sum / state.samples as Float;
+                    let mean_sq = state.sum_sq / state.samples as Float;
+                    let variance = mean_sq - mean * mean;
+
+                    if variance.max_component() < self.settings.variance_threshold {
+                        state.converged = true;
+                        break; // Converged, stop sampling early
+                    }
+                }
+
+                if state.samples >= end {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Stops the clock and the progress bar of the render, reporting its final
+    /// statistics and warning of its samples of NaN or infinite radiance, which point
+    /// to a bug of an integrator or a material.
+    fn finish_progress(&self, bar: &ProgressBar) {
+        self.progress.finish();
+        bar.finish();
+        let stats = self.progress.stats();
+        for on_progress in &self.progress_callbacks {
+            on_progress(&stats);
+        }
+        let invalid = self.progress.invalid_samples();
+        if invalid > 0 {
+            warn!(
+                samples = invalid,
+                "Samples of NaN or infinite radiance were rendered black"
+            );
+        }
+    }
+
+    /// Estimates the radiance of a camera ray, spectrally when enabled in the settings.
+    ///
+    /// # Returns
+    /// - The radiance and the part of it recorded for each light group.
+    fn trace(&self, r: &Ray, sampler: &mut dyn Sampler) -> (Color, Vec<Color>) {
+        let groups = self.scene.lights.groups.len();
+        if self.settings.spectral {
+            let wavelength = SampledWavelength::sample(sampler.get_1d());
+            let r = r
+                .spawn(r.origin(), r.direction())
+                .with_wavelength(Wavelength::Sampled(wavelength.lambda()));
+            let mut recorded = vec![Color::zero(); groups];
+            let radiance = self
+                .integrator
+                .li_groups(&r, &self.scene, sampler, &mut recorded);
+            let recorded = recorded
+                .into_iter()
+                .map(|group| wavelength.to_rgb(group))
                 .collect();
-            for (i, pixel_color) in pixel_colors.into_iter().enumerate() {
-                buffer.set_pixel(i, j, pixel_color);
+            (wavelength.to_rgb(radiance), recorded)
+        } else {
+            let mut recorded = vec![Color::zero(); groups];
+            let radiance = self
+                .integrator
+                .li_groups(r, &self.scene, sampler, &mut recorded);
+            (radiance, recorded)
+        }
+    }
+
+    /// Renders the AOVs recorded by the integrator into the film.
+    ///
+    /// The AOVs are averaged over a few jittered samples per pixel, in a pass of
+    /// their own so that every integrator gets them, whatever its rendering passes.
+    pub fn render_aovs(&self, film: &mut Buffer) {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let pixels: Vec<Aovs> = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let seed = stream_seed(self.settings.seed, 0);
+                utils::with_random_stream(seed, index as u64, || self.pixel_aovs(index))
+            })
+            .collect();
+        let camera = self.scene.camera;
+        for (index, aovs) in pixels.into_iter().enumerate() {
+            for (name, value) in aovs.iter() {
+                // The averaged normals are turned as they are, the rotation being linear
+                let value = match (name, self.settings.normal_space) {
+                    ("N", NormalSpace::Camera) => {
+                        let (x, y, z) = value.rgb();
+                        Color::from(camera.to_camera_space(Vec3::new(x, y, z)))
+                    }
+                    _ => value,
+                };
+                film.aov_mut(name)
+                    .set_pixel(index % width, index / width, value);
+            }
+        }
+    }
+
+    /// Averages the AOVs of a pixel over a few jittered samples.
+    fn pixel_aovs(&self, index: usize) -> Aovs {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let (i, j) = (index % width, index / width);
+        let mut sampler = self.settings.sampler.create((i, j), AOV_SAMPLES);
+        let mut aovs = Aovs::new();
+        for s in 0..AOV_SAMPLES {
+            sampler.start_sample(s);
+            let (u_offset, v_offset) = sampler.get_2d();
+            let (lens_u, lens_v) = sampler.get_2d();
+            let u = (i as Float + u_offset) / (width - 1) as Float;
+            let v = (j as Float + v_offset) / (height - 1) as Float;
+            // The AOVs are taken at the middle of the shutter interval
+            let ray = self.scene.camera.get_ray_lens(u, v, lens_u, lens_v, 0.5);
+            self.integrator.aovs(&ray, &self.scene, &mut aovs);
+            aovs.add_unfiltered("motion", self.motion_vector(&ray));
+        }
+        aovs
+    }
+
+    /// Renders the `CryptoObject` and `CryptoMaterial` cryptomattes into the film.
+    ///
+    /// The coverage of each ID is the fraction of the camera rays of the pixel that
+    /// see it first, over a few jittered samples as for the AOVs.
+    pub fn render_cryptomattes(&self, film: &mut Buffer) {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let pixels: Vec<_> = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let seed = stream_seed(self.settings.seed, 0);
+                utils::with_random_stream(seed, index as u64, || self.pixel_coverage(index))
+            })
+            .collect();
+        let mut objects = Cryptomatte::new("CryptoObject", self.names.0.clone(), width, height);
+        let mut materials = Cryptomatte::new("CryptoMaterial", self.names.1.clone(), width, height);
+        for (index, (object_coverage, material_coverage)) in pixels.into_iter().enumerate() {
+            objects.set_pixel(index % width, index / width, &object_coverage);
+            materials.set_pixel(index % width, index / width, &material_coverage);
+        }
+        film.add_cryptomatte(objects);
+        film.add_cryptomatte(materials);
+    }
+
+    /// The coverage of the objects and materials seen first by the camera rays of a
+    /// pixel, over a few jittered samples.
+    fn pixel_coverage(&self, index: usize) -> (Coverage, Coverage) {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let (i, j) = (index % width, index / width);
+        let mut sampler = self.settings.sampler.create((i, j), AOV_SAMPLES);
+        let mut objects: Coverage = Vec::new();
+        let mut materials: Coverage = Vec::new();
+        let weight = 1.0 / AOV_SAMPLES as Float;
+        for s in 0..AOV_SAMPLES {
+            sampler.start_sample(s);
+            let (u_offset, v_offset) = sampler.get_2d();
+            let (lens_u, lens_v) = sampler.get_2d();
+            let u = (i as Float + u_offset) / (width - 1) as Float;
+            let v = (j as Float + v_offset) / (height - 1) as Float;
+            let ray = self.scene.camera.get_ray_lens(u, v, lens_u, lens_v, 0.5);
+            let hit = self.scene.world.hit(&ray, 0.001, Float::INFINITY);
+            if let Some(rec) = hit {
+                add_coverage(&mut objects, rec.object_id, weight);
+                add_coverage(&mut materials, rec.material_id, weight);
+            }
+        }
+        (objects, materials)
+    }
+
+    /// Motion of the first hit over the shutter interval, in pixels on the film.
+    ///
+    /// # Returns
+    /// - The horizontal and vertical motion in the red and green channels.
+    fn motion_vector(&self, ray: &Ray) -> Color {
+        let Some(rec) = self.scene.world.hit(ray, 0.001, Float::INFINITY) else {
+            return Color::zero();
+        };
+        let start = self.scene.camera.project(rec.p - 0.5 * rec.velocity);
+        let end = self.scene.camera.project(rec.p + 0.5 * rec.velocity);
+        let (Some((s0, t0)), Some((s1, t1))) = (start, end) else {
+            return Color::zero();
+        };
+        Color::new(
+            (s1 - s0) * (self.settings.width - 1) as Float,
+            (t1 - t0) * (self.settings.height - 1) as Float,
+            0.0,
+        )
+    }
+
+    /// Renders the feature buffers guiding the denoisers.
+    pub fn render_features(&self) -> Features {
+        let mut film = Buffer::new(self.settings.width, self.settings.height);
+        self.render_aovs(&mut film);
+        Features::from_film(&film)
+    }
+
+    /// Bakes the lighting of a mesh of the scene into a square lightmap over its UV
+    /// layout, for the real-time engines.
+    ///
+    /// Each texel covered by the mesh is baked at the surface at its center, see
+    /// `bake_point`. The texels around the UV islands are padded with their neighbors.
+    ///
+    /// # Parameters
+    /// - `mesh`: The mesh, which should be in the scene for its shadows.
+    /// - `material`: The material of the mesh, shading the `Lighting` mode.
+    /// - `resolution`: The width and height of the lightmap in texels.
+    /// - `mode`: What the lightmap holds.
+    pub fn bake_lightmap(
+        &self,
+        mesh: &UvMesh,
+        material: &SceneMaterial,
+        resolution: usize,
+        mode: BakeMode,
+    ) -> Buffer {
+        let _span = trace_span!("bake_lightmap", triangles = mesh.len(), resolution).entered();
+        let texels = mesh.rasterize(resolution);
+        // The rays leave the surface by a fraction of the mesh, not to hit it again
+        let offset = 1e-4 * mesh.extent();
+        let mut values: Vec<Option<Color>> = texels
+            .par_iter()
+            .enumerate()
+            .map(|(index, texel)| {
+                let (x, y) = (index % resolution, index / resolution);
+                Some(self.bake_point((*texel)?, offset, (x, y), index, material, mode))
+            })
+            .collect();
+        bake::dilate(&mut values, resolution);
+        let mut lightmap = Buffer::new(resolution, resolution);
+        for (index, value) in values.into_iter().enumerate() {
+            if let Some(value) = value {
+                // The buffer goes up from its bottom row, the texels down from the top
+                lightmap.set_pixel(
+                    index % resolution,
+                    resolution - 1 - index / resolution,
+                    value,
+                );
+            }
+        }
+        lightmap
+    }
+
+    /// Bakes the lighting of a mesh of the scene at its vertices, e.g. for the vertex
+    /// colors of the engines, see `bake_point`.
+    ///
+    /// # Parameters
+    /// - `vertices`: The positions and normals of the vertices of the mesh, which
+    ///   should be in the scene for its shadows.
+    /// - `material`: The material of the mesh, shading the `Lighting` mode.
+    /// - `mode`: What is baked.
+    ///
+    /// # Returns
+    /// - The value baked at each vertex.
+    pub fn bake_vertices(
+        &self,
+        vertices: &[(Point3, Vec3)],
+        material: &SceneMaterial,
+        mode: BakeMode,
+    ) -> Vec<Color> {
+        let _span = trace_span!("bake_vertices", vertices = vertices.len()).entered();
+        let offset = 1e-4 * bake::extent(vertices.iter().map(|&(p, _)| p));
+        vertices
+            .par_iter()
+            .enumerate()
+            .map(|(index, &vertex)| {
+                self.bake_point(vertex, offset, (index, 0), index, material, mode)
+            })
+            .collect()
+    }
+
+    /// Bakes a point of a surface, tracing the samples per pixel of the settings over
+    /// the cosine-weighted hemisphere of its normal.
+    ///
+    /// The radiance coming from each direction is estimated with the integrator, and
+    /// the occlusion by the hits within the maximum distance.
+    ///
+    /// # Parameters
+    /// - `(p, normal)`: The point and the normal of the surface there.
+    /// - `offset`: The distance the rays leave the surface by, not to hit it again.
+    /// - `pixel`: The pixel of the sampler of the point.
+    /// - `stream`: The random stream of the point.
+    fn bake_point(
+        &self,
+        (p, normal): (Point3, Vec3),
+        offset: Float,
+        pixel: (usize, usize),
+        stream: usize,
+        material: &SceneMaterial,
+        mode: BakeMode,
+    ) -> Color {
+        let samples = self.settings.samples_per_pixel.max(1);
+        let mut sampler = self
+            .settings
+            .sampler
+            .create_seeded(pixel, samples, self.settings.seed);
+        let onb = Onb::from_normal(normal);
+        let seed = stream_seed(self.settings.seed, 0);
+        let sum = utils::with_random_stream(seed, stream as u64, || {
+            let mut sum = Color::zero();
+            for s in 0..samples {
+                sampler.start_sample(s);
+                let (u1, u2) = sampler.get_2d();
+                let r = u2.sqrt();
+                let phi = 2.0 * utils::consts::PI * u1;
+                let local = Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u2).sqrt());
+                let ray = Ray::new(p + offset * normal, onb.to_world(local));
+                let value = match mode {
+                    BakeMode::Occlusion { max_distance } => {
+                        match self.scene.world.hit(&ray, 0.001, max_distance) {
+                            Some(_) => Color::zero(),
+                            None => Color::new(1.0, 1.0, 1.0),
+                        }
+                    }
+                    BakeMode::Irradiance | BakeMode::Lighting => {
+                        self.trace(&ray, sampler.as_mut()).0
+                    }
+                };
+                if value.is_finite() {
+                    sum += value;
+                } else {
+                    self.progress.add_invalid_sample();
+                }
+            }
+            sum
+        });
+        let average = sum / samples as Float;
+        // The cosine-weighted directions make the irradiance pi times the average
+        // radiance, and the reflected radiance of a diffuse surface its albedo times it
+        match mode {
+            BakeMode::Irradiance => utils::consts::PI * average,
+            BakeMode::Lighting => material.albedo() * average + material.emitted(),
+            BakeMode::Occlusion { .. } => average,
+        }
+    }
+
+    /// Denoises a render with the denoiser selected in the settings.
+    ///
+    /// The feature buffers are taken from the AOVs of the render when it has them.
+    ///
+    /// # Returns
+    /// - The denoised image, or `None` when no denoiser is selected.
+    pub fn denoise(&self, beauty: &Buffer) -> Option<Buffer> {
+        if matches!(self.settings.denoiser, Denoiser::None) {
+            return None;
+        }
+        let features = if beauty.aov("albedo").is_some() {
+            Features::from_film(beauty)
+        } else {
+            self.render_features()
+        };
+        self.settings.denoiser.apply(beauty, &features)
+    }
+}
+
+/// The passes of a render, see `Renderer::passes`.
+pub struct Passes<'a> {
+    renderer: &'a Renderer,
+    tiles: Vec<(usize, usize)>,
+    pixels: Vec<PixelState>,
+    film: Buffer,
+    /// The passes rendered so far and the number of passes.
+    pass: u32,
+    passes: u32,
+    /// The samples per pixel of a pass and of the whole render.
+    pass_samples: u32,
+    samples_per_pixel: u32,
+    /// Whether the integrator renders the whole image at once, in a single pass.
+    whole_image: bool,
+    finished: bool,
+    bar: ProgressBar,
+}
+
+impl<'a> Passes<'a> {
+    fn new(renderer: &'a Renderer) -> Self {
+        let settings = &renderer.settings;
+        let (width, height) = (settings.width, settings.height);
+        let whole_image = matches!(
+            settings.integrator,
+            IntegratorType::Mlt { .. }
+                | IntegratorType::Sppm { .. }
+                | IntegratorType::Restir { .. }
+                | IntegratorType::Guided { .. }
+        );
+        let samples_per_pixel = settings.samples_per_pixel.max(1);
+        let pass_samples = match settings.pass_samples {
+            0 => samples_per_pixel,
+            n => n,
+        };
+        let (tiles, passes, bar) = if whole_image {
+            (Vec::new(), 1, ProgressBar::hidden())
+        } else {
+            let tiles = tile::tiles(width, height, settings.tile_size, settings.tile_order);
+            let passes = samples_per_pixel.div_ceil(pass_samples);
+            let count = tiles.len() * passes as usize;
+            renderer.progress.start(width * height, count);
+            (tiles, passes, progress::progress_bar(count as u64, "Tiles"))
+        };
+        Passes {
+            renderer,
+            tiles,
+            pixels: if whole_image {
+                Vec::new()
+            } else {
+                vec![PixelState::default(); width * height]
+            },
+            film: Buffer::new(width, height),
+            pass: 0,
+            passes,
+            pass_samples,
+            samples_per_pixel,
+            whole_image,
+            finished: false,
+            bar,
+        }
+    }
+
+    /// Renders the next pass into the film.
+    ///
+    /// # Returns
+    /// - Whether a pass was rendered, `false` once they all are or the render was
+    ///   cancelled.
+    fn advance(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+        let renderer = self.renderer;
+        self.pass += 1;
+        let (pass, passes) = (self.pass, self.passes);
+        if let Some(film) = renderer.render_whole_image() {
+            self.film = film;
+        } else {
+            if passes > 1 {
+                self.bar.set_prefix(format!("Pass {pass}/{passes}"));
+            }
+            let start = Instant::now();
+            let end = (pass * self.pass_samples).min(self.samples_per_pixel);
+            let height = renderer.settings.height;
+            let rendered =
+                renderer.render_pass(&self.pixels, &self.tiles, 0..height, end, &self.bar);
+            for (tile, states) in rendered {
+                self.film.merge(&tile);
+                for (index, state) in states {
+                    self.pixels[index] = state;
+                }
+            }
+            debug!(
+                pass,
+                passes,
+                samples_per_pixel = end,
+                elapsed = ?start.elapsed(),
+                "Rendered a pass"
+            );
+        }
+        if !renderer.is_cancelled() {
+            for on_pass in &renderer.pass_callbacks {
+                on_pass(pass, passes, &self.film);
             }
         }
-        buffer
+        if pass == passes || renderer.is_cancelled() {
+            self.finish();
+        }
+        true
+    }
+
+    /// Stops the progress of the render, once its passes are rendered or it is
+    /// stopped early.
+    fn finish(&mut self) {
+        if !self.finished && !self.whole_image {
+            self.renderer.finish_progress(&self.bar);
+        }
+        self.finished = true;
+    }
+
+    /// The film of the passes rendered, with the share of the samples and the standard
+    /// error of each pixel when the AOVs are enabled.
+    fn into_film(mut self) -> Buffer {
+        self.finish();
+        let mut film = std::mem::replace(&mut self.film, Buffer::new(0, 0));
+        if self.renderer.settings.aovs && !self.whole_image {
+            let width = film.width();
+            // The share of the sample budget each pixel took, to tune the adaptive
+            // sampling by eye
+            let samples = film.aov_mut("samples");
+            for (index, pixel) in self.pixels.iter().enumerate() {
+                let spent = pixel.samples as Float / self.samples_per_pixel as Float;
+                samples.set_pixel(
+                    index % width,
+                    index / width,
+                    Color::new(spent, spent, spent),
+                );
+            }
+            let standard_error = film.aov_mut("standard_error");
+            for (index, pixel) in self.pixels.iter().enumerate() {
+                standard_error.set_pixel(index % width, index / width, pixel.standard_error());
+            }
+        }
+        film
     }
 }
 
+impl Iterator for Passes<'_> {
+    type Item = Buffer;
+
+    fn next(&mut self) -> Option<Buffer> {
+        self.advance().then(|| self.film.clone())
+    }
+}
+
+impl Drop for Passes<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// The sampling statistics of a pixel, carried from one pass to the next.
+#[derive(Debug, Clone, Copy, Default)]
+struct PixelState {
+    samples: u32,
+    sum: Color,
+    sum_sq: Color,
+    converged: bool,
+}
+
+impl PixelState {
+    /// The standard error of the mean of the samples, per channel, from their unbiased
+    /// variance. Zero below two samples, where it cannot be estimated.
+    fn standard_error(&self) -> Color {
+        if self.samples < 2 {
+            return Color::zero();
+        }
+        let n = self.samples as Float;
+        let variance = (self.sum_sq - self.sum * self.sum / n) / (n - 1.0);
+        Color::new(
+            (variance.r().max(0.0) / n).sqrt(),
+            (variance.g().max(0.0) / n).sqrt(),
+            (variance.b().max(0.0) / n).sqrt(),
+        )
+    }
+}
+
+/// A value as in the scene files, on a single line.
+fn to_ron(value: &impl Serialize) -> String {
+    ron::to_string(value).unwrap_or_default()
+}
+
+/// Jittered samples per pixel of the AOVs.
+const AOV_SAMPLES: u32 = 16;
+
+/// The IDs seen by a pixel and their coverage.
+type Coverage = Vec<(u32, Float)>;
+
+/// Adds the coverage of a sample to an ID.
+fn add_coverage(coverage: &mut Coverage, id: u32, weight: Float) {
+    match coverage.iter_mut().find(|(i, _)| *i == id) {
+        Some((_, sum)) => *sum += weight,
+        None => coverage.push((id, weight)),
+    }
+}
+
+/// Factor scaling a sample down so that its brightest channel does not exceed
+/// `max_radiance`, keeping its hue.
+fn clamp_factor(radiance: Color, max_radiance: Float) -> Float {
+    let max = radiance.max_component();
+    if max > max_radiance {
+        max_radiance / max
+    } else {
+        1.0
+    }
+}
+
+/// The AOVs rendered along with the image, but for the light groups: the albedo,
+/// normal, depth, IDs and their previews, motion, samples and standard error.
+const AOVS: usize = 10;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RenderSettings {
     samples_per_pixel: u32,
@@ -95,8 +1150,104 @@ pub struct RenderSettings {
     width: usize,
     height: usize,
     min_samples_per_pixel: u32,
-    variance_threshold: f32,
+    variance_threshold: Float,
+    #[serde(default)]
+    sampler: SamplerType,
+    #[serde(default)]
+    blue_noise: bool,
+    #[serde(default)]
+    integrator: IntegratorType,
+    #[serde(default)]
+    spectral: bool,
+    /// Maximum radiance of a camera sample, clamped to tame fireflies at the cost of
+    /// some energy.
+    #[serde(default)]
+    max_radiance: Option<Float>,
+    /// Minimum roughness of the glossy lobes after a non-specular bounce, 0 to disable.
+    #[serde(default)]
+    regularization: Float,
+    #[serde(default)]
+    denoiser: Denoiser,
+    /// Whether the AOVs of the integrator are rendered along with the image, with the
+    /// light groups and, for the pixel-sampling integrators, the share of the samples
+    /// each pixel took and the standard error of its estimate.
+    #[serde(default)]
+    aovs: bool,
+    /// Space of the shading normals of the `N` AOV.
+    #[serde(default)]
+    normal_space: NormalSpace,
+    /// Whether the samples are spread over the shutter interval, blurring the moving
+    /// objects. Only the pixel-sampling integrators honor it.
+    #[serde(default)]
+    motion_blur: bool,
+    /// Whether object and material cryptomattes are rendered along with the image.
+    #[serde(default)]
+    cryptomatte: bool,
+    /// Pixel type and compression of the EXR output.
+    #[serde(default)]
+    exr: ExrOptions,
+    /// Exposure adjustment of the PNG and JPEG outputs, in stops.
+    #[serde(default)]
+    exposure: Float,
+    /// Color temperature, in kelvins, of the light shown white in the PNG and JPEG
+    /// outputs, e.g. 3200 for tungsten, none to leave the colors as rendered.
+    #[serde(default)]
+    white_balance: Option<Float>,
+    /// Darkening of the corners of the PNG and JPEG outputs, from 0 for none to 1
+    /// for black corners.
+    #[serde(default)]
+    vignette: Float,
+    /// Lateral chromatic aberration of the PNG and JPEG outputs: the fraction by which
+    /// the red channel is magnified and the blue one shrunk about the center.
+    #[serde(default)]
+    chromatic_aberration: Float,
+    /// Near and far distances of the depth image written along the PNG and JPEG
+    /// outputs, from the depth AOV `Z`, none not to write it.
+    #[serde(default)]
+    depth_range: Option<(Float, Float)>,
+    /// Curve compressing the image into the display range of the PNG and JPEG outputs,
+    /// the view of the color management.
+    #[serde(default)]
+    tone_mapper: ToneMapper,
+    /// Linear RGB space of the scene colors and of the rendered image.
+    #[serde(default)]
+    working_space: ColorSpace,
+    /// Display the PNG and JPEG outputs are encoded for.
+    #[serde(default)]
+    display: Display,
+    /// Reconstruction filter splatting the samples into the pixels.
+    #[serde(default)]
+    filter: Filter,
+    /// Side of the square tiles rendered in parallel, in pixels.
+    #[serde(default = "default_tile_size")]
+    tile_size: usize,
+    /// Order in which the tiles are rendered.
+    #[serde(default)]
+    tile_order: TileOrder,
+    /// Samples per pixel of each pass of a progressive render, 0 to render in a single
+    /// pass. A preview of the image is produced after each pass.
+    #[serde(default)]
+    pass_samples: u32,
+    /// Seed decorrelating the samples from those of other renders of the scene, so
+    /// that renders with different seeds can be merged, whatever the integrator. The
+    /// random numbers are drawn from a stream per pixel, or per photon, so that renders
+    /// of the same seed are the same whatever the threads.
+    #[serde(default)]
+    seed: u32,
+}
+
+fn default_tile_size() -> usize {
+    16
+}
+
+impl Default for RenderSettings {
+    /// A 400x225 image of 64 samples per pixel, adaptive from 32, and paths of up to
+    /// 32 bounces.
+    fn default() -> Self {
+        Self::new(64, 32, 400, 225, 32, 0.05)
+    }
 }
+
 impl RenderSettings {
     pub fn new(
         samples_per_pixel: u32,
@@ -104,7 +1255,7 @@ impl RenderSettings {
         width: usize,
         height: usize,
         min_samples_per_pixel: u32,
-        variance_threshold: f32,
+        variance_threshold: Float,
     ) -> Self {
         RenderSettings {
             samples_per_pixel,
@@ -113,88 +1264,283 @@ impl RenderSettings {
             height,
             min_samples_per_pixel,
             variance_threshold,
+            sampler: SamplerType::default(),
+            blue_noise: false,
+            integrator: IntegratorType::default(),
+            spectral: false,
+            max_radiance: None,
+            regularization: 0.0,
+            denoiser: Denoiser::None,
+            aovs: false,
+            normal_space: NormalSpace::default(),
+            motion_blur: false,
+            cryptomatte: false,
+            exr: ExrOptions::default(),
+            exposure: 0.0,
+            white_balance: None,
+            vignette: 0.0,
+            chromatic_aberration: 0.0,
+            depth_range: None,
+            tone_mapper: ToneMapper::default(),
+            working_space: ColorSpace::default(),
+            display: Display::default(),
+            filter: Filter::default(),
+            tile_size: default_tile_size(),
+            tile_order: TileOrder::default(),
+            pass_samples: 0,
+            seed: 0,
+        }
+    }
+    pub fn with_sampler(mut self, sampler: SamplerType) -> Self {
+        self.sampler = sampler;
+        self
+    }
+    pub fn with_blue_noise(mut self, blue_noise: bool) -> Self {
+        self.blue_noise = blue_noise;
+        self
+    }
+    pub fn with_integrator(mut self, integrator: IntegratorType) -> Self {
+        self.integrator = integrator;
+        self
+    }
+    /// Traces a single wavelength per sample instead of RGB, for dispersion and measured
+    /// metals, the throughput of the paths staying RGB.
+    ///
+    /// This applies to the integrators driven by the pixel loop; Metropolis, SPPM and
+    /// ReSTIR keep rendering in RGB.
+    pub fn with_spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+    pub fn with_max_radiance(mut self, max_radiance: Float) -> Self {
+        self.max_radiance = Some(max_radiance);
+        self
+    }
+    pub fn with_regularization(mut self, regularization: Float) -> Self {
+        self.regularization = regularization;
+        self
+    }
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = denoiser;
+        self
+    }
+    pub fn with_aovs(mut self, aovs: bool) -> Self {
+        self.aovs = aovs;
+        self
+    }
+    pub fn with_normal_space(mut self, normal_space: NormalSpace) -> Self {
+        self.normal_space = normal_space;
+        self
+    }
+    pub fn with_motion_blur(mut self, motion_blur: bool) -> Self {
+        self.motion_blur = motion_blur;
+        self
+    }
+    pub fn with_cryptomatte(mut self, cryptomatte: bool) -> Self {
+        self.cryptomatte = cryptomatte;
+        self
+    }
+    pub fn with_exr(mut self, exr: ExrOptions) -> Self {
+        self.exr = exr;
+        self
+    }
+    pub fn exr(&self) -> ExrOptions {
+        self.exr
+    }
+    pub fn with_exposure(mut self, exposure: Float) -> Self {
+        self.exposure = exposure;
+        self
+    }
+    pub fn exposure(&self) -> Float {
+        self.exposure
+    }
+    pub fn with_white_balance(mut self, kelvin: Float) -> Self {
+        self.white_balance = Some(kelvin);
+        self
+    }
+    pub fn white_balance(&self) -> Option<Float> {
+        self.white_balance
+    }
+    pub fn with_vignette(mut self, vignette: Float) -> Self {
+        self.vignette = vignette;
+        self
+    }
+    pub fn vignette(&self) -> Float {
+        self.vignette
+    }
+    pub fn with_chromatic_aberration(mut self, chromatic_aberration: Float) -> Self {
+        self.chromatic_aberration = chromatic_aberration;
+        self
+    }
+    pub fn chromatic_aberration(&self) -> Float {
+        self.chromatic_aberration
+    }
+    /// The settings writing a baked lightmap: the exposure, white balance, vignette,
+    /// chromatic aberration and depth image of the camera are left out, only the
+    /// tone mapper and the display encoding the 8-bit outputs.
+    pub fn for_bake(&self) -> Self {
+        Self {
+            exposure: 0.0,
+            white_balance: None,
+            vignette: 0.0,
+            chromatic_aberration: 0.0,
+            depth_range: None,
+            ..*self
         }
     }
+    /// Writes the depth AOV `Z` along the PNG and JPEG outputs, normalized from white
+    /// at `near` to black at `far`. The AOVs must be rendered.
+    pub fn with_depth_range(mut self, near: Float, far: Float) -> Self {
+        self.depth_range = Some((near, far));
+        self
+    }
+    pub fn depth_range(&self) -> Option<(Float, Float)> {
+        self.depth_range
+    }
+    pub fn with_tone_mapper(mut self, tone_mapper: ToneMapper) -> Self {
+        self.tone_mapper = tone_mapper;
+        self
+    }
+    pub fn tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+    pub fn with_working_space(mut self, working_space: ColorSpace) -> Self {
+        self.working_space = working_space;
+        self
+    }
+    pub fn working_space(&self) -> ColorSpace {
+        self.working_space
+    }
+    pub fn with_display(mut self, display: Display) -> Self {
+        self.display = display;
+        self
+    }
+    pub fn display(&self) -> Display {
+        self.display
+    }
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+    pub fn with_tiles(mut self, tile_size: usize, tile_order: TileOrder) -> Self {
+        self.tile_size = tile_size;
+        self.tile_order = tile_order;
+        self
+    }
+    pub fn with_pass_samples(mut self, pass_samples: u32) -> Self {
+        self.pass_samples = pass_samples;
+        self
+    }
+    pub fn pass_samples(&self) -> u32 {
+        self.pass_samples
+    }
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+    pub fn integrator(&self) -> IntegratorType {
+        self.integrator
+    }
+    /// Sets the samples per pixel, lowering the minimum of the adaptive sampling to
+    /// them if needed.
+    pub fn with_samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.samples_per_pixel = samples_per_pixel;
+        self.min_samples_per_pixel = self.min_samples_per_pixel.min(samples_per_pixel);
+        self
+    }
+    /// Sets the size of the image in pixels. The camera keeps its aspect ratio, which
+    /// the size should match.
+    pub fn with_dimensions(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+    /// Sets the maximum number of bounces of the paths.
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
     pub fn get_dimensions(&self) -> (usize, usize) {
         (self.width, self.height)
     }
-}
 
-pub fn ray_color(r: &Ray, world: &dyn Hittable, lights: &LightList, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::zero(); // recursion limit
+    /// Estimated memory of the film, in bytes: the image and, when they are enabled,
+    /// its AOVs, one per light group, its cryptomattes, and the features and result of
+    /// the denoiser.
+    pub(crate) fn film_memory(&self, light_groups: usize) -> usize {
+        let pixels = self.width * self.height;
+        let mut buffers = 1;
+        if self.aovs {
+            buffers += AOVS + light_groups;
+        }
+        if !matches!(self.denoiser, Denoiser::None) {
+            buffers += 4;
+        }
+        let mut bytes = buffers * pixels * Buffer::PIXEL_BYTES;
+        if self.cryptomatte {
+            bytes += 2 * pixels * Cryptomatte::PIXEL_BYTES;
+        }
+        bytes
     }
 
-    let mut rec = HitRecord::new();
-    let cmj_samples = generate_cmj_2d(4);
-
-    if world.hit(r, 0.001, f32::INFINITY, &mut rec) {
-        let emitted = rec.mat.as_ref().unwrap().emitted();
-        let mut total_light = emitted;
-
-        // === 1. Direct Lighting via Light Sampling ===
-        for (light_idx, light) in lights.lights.iter().enumerate() {
-            let (u, v) = cmj_samples[light_idx % cmj_samples.len()];
-            let light_point = light.sample_cmj(u, v);
-            let light_dir = light_point - rec.p;
-            let light_distance = light_dir.length();
-            let light_dir_unit = utils::unit_vector(light_dir);
-
-            let shadow_ray = Ray::new(rec.p, light_dir_unit);
-            let mut shadow_hit = HitRecord::new();
-
-            if !world.hit(&shadow_ray, 0.001, light_distance - 0.001, &mut shadow_hit) {
-                let cosine = f32::max(utils::dot(rec.normal, light_dir_unit), 0.0);
-                let light_pdf = light.pdf(rec.p, light_point);
+    /// Scales the lengths of the integrator and the depth range by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Float) {
+        self.integrator.rescale(factor);
+        self.depth_range = self
+            .depth_range
+            .map(|(near, far)| (factor * near, factor * far));
+    }
 
-                if let Some((_, brdf_value, brdf_pdf)) =
-                    rec.mat.as_ref().unwrap().scatter_importance(r, &rec)
-                {
-                    let weight = utils::balance_heuristic(light_pdf, brdf_pdf);
-                    total_light += light.color() * brdf_value * cosine * weight / light_pdf;
-                }
-            }
+    /// The problems of the settings, e.g. an empty image, for the validation of the
+    /// scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.width == 0 || self.height == 0 {
+            problems.push(format!("the image {}x{} is empty", self.width, self.height));
         }
-
-        // === 2. Indirect Lighting via BRDF Sampling ===
-        if let Some((scattered, brdf_value, brdf_pdf)) =
-            rec.mat.as_ref().unwrap().scatter_importance(r, &rec)
+        if self.samples_per_pixel == 0 {
+            problems.push("the samples per pixel are 0".to_string());
+        }
+        if self.max_depth == 0 {
+            problems.push("the maximum depth is 0".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.regularization) {
+            problems.push(format!(
+                "the regularization {} is out of [0, 1]",
+                self.regularization
+            ));
+        }
+        if let Some(kelvin) = self.white_balance
+            && !(1667.0..=25000.0).contains(&kelvin)
         {
-            let cosine = f32::max(
-                utils::dot(rec.normal, utils::unit_vector(scattered.direction())),
-                0.0,
-            );
-
-            let mut light_hit = HitRecord::new();
-            let mut add_emission = Color::zero();
-
-            if world.hit(&scattered, 0.001, f32::INFINITY, &mut light_hit) {
-                let emitted = light_hit.mat.as_ref().unwrap().emitted();
-                if emitted.length_squared() > 0.0 {
-                    let light_pdf_sum: f32 = lights
-                        .lights
-                        .iter()
-                        .map(|light| light.pdf(rec.p, light_hit.p))
-                        .sum();
-                    let light_pdf = (light_pdf_sum / lights.lights.len() as f32).max(1e-4);
-                    let weight = utils::balance_heuristic(brdf_pdf, light_pdf);
-
-                    // Add the contribution of hitting the light via BRDF
-                    add_emission = emitted * brdf_value * cosine * weight / brdf_pdf;
-                }
-            }
-
-            // Add both direct hit on light and recursive bounce
-            total_light += add_emission;
-            total_light +=
-                brdf_value * ray_color(&scattered, world, lights, depth - 1) * cosine / brdf_pdf;
+            problems.push(format!(
+                "the white balance {kelvin} K is out of [1667, 25000]"
+            ));
         }
-
-        return total_light;
+        if !(0.0..=1.0).contains(&self.vignette) {
+            problems.push(format!("the vignette {} is out of [0, 1]", self.vignette));
+        }
+        if !(0.0..=0.1).contains(&self.chromatic_aberration) {
+            problems.push(format!(
+                "the chromatic aberration {} is out of [0, 0.1]",
+                self.chromatic_aberration
+            ));
+        }
+        if let Some((near, far)) = self.depth_range
+            && !(near >= 0.0 && far > near)
+        {
+            problems.push(format!("the depth range [{near}, {far}] is empty"));
+        }
+        problems.extend(self.denoiser.problems());
+        problems
     }
-
-    // === Background ===
-    let unit_direction = utils::unit_vector(r.direction());
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
 }
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use utils::Float;
+
+/// The length unit a scene is authored in. The lengths of the scenes it includes are
+/// converted to it when they are read, so that assets authored in centimeters and in
+/// meters match up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Unit {
+    Millimeter,
+    Centimeter,
+    #[default]
+    Meter,
+    Kilometer,
+    Inch,
+    Foot,
+    /// A unit of this many meters, e.g. `Meters(0.5)`.
+    Meters(Float),
+}
+
+impl Unit {
+    /// The length of the unit in meters.
+    pub fn meters(self) -> Float {
+        match self {
+            Unit::Millimeter => 0.001,
+            Unit::Centimeter => 0.01,
+            Unit::Meter => 1.0,
+            Unit::Kilometer => 1000.0,
+            Unit::Inch => 0.0254,
+            Unit::Foot => 0.3048,
+            Unit::Meters(meters) => meters,
+        }
+    }
+
+    /// The factor converting the lengths in this unit to `unit`.
+    pub fn to(self, unit: Unit) -> Float {
+        self.meters() / unit.meters()
+    }
+
+    pub(crate) fn is_meter(&self) -> bool {
+        *self == Unit::Meter
+    }
+
+    /// The problems of the unit, a custom one not being positive, for the validation
+    /// of the scene.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Unit::Meters(meters) = self
+            && !(meters.is_finite() && *meters > 0.0)
+        {
+            problems.push(format!("the unit of {meters} meters is not positive"));
+        }
+        problems
+    }
+}
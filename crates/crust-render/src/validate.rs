@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::PathBuf;
+use utils::{Color, Float, Vec3};
+
+/// A problem of a scene, found when it is read rather than failing or spoiling its
+/// render.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// The section of the scene the problem is in, e.g. `object_list` or `settings`.
+    pub section: &'static str,
+    /// The object the problem is in, by name.
+    pub object: Option<String>,
+    pub message: String,
+}
+
+impl Issue {
+    pub(crate) fn new(section: &'static str, message: String) -> Self {
+        Issue {
+            section,
+            object: None,
+            message,
+        }
+    }
+
+    pub(crate) fn object(name: &str, message: String) -> Self {
+        Issue {
+            section: "object_list",
+            object: Some(name.to_string()),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.object {
+            Some(name) => write!(f, "object {name:?}: {}", self.message),
+            None => write!(f, "{}: {}", self.section, self.message),
+        }
+    }
+}
+
+/// The problems of a scene, each at the file, and the line when it is found, it
+/// comes from.
+#[derive(Debug)]
+pub struct SceneError {
+    issues: Vec<(PathBuf, Option<usize>, Issue)>,
+}
+
+impl SceneError {
+    pub(crate) fn new(issues: Vec<(PathBuf, Option<usize>, Issue)>) -> Self {
+        SceneError { issues }
+    }
+
+    pub fn issues(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().map(|(_, _, issue)| issue)
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the scene has {} problem(s)", self.issues.len())?;
+        for (path, line, issue) in &self.issues {
+            match line {
+                Some(line) => write!(f, "\n  {}:{line}: {issue}", path.display())?,
+                None => write!(f, "\n  {}: {issue}", path.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub(crate) fn is_finite(v: Vec3) -> bool {
+    v.x().is_finite() && v.y().is_finite() && v.z().is_finite()
+}
+
+fn show(v: Vec3) -> String {
+    format!("({}, {}, {})", v.x(), v.y(), v.z())
+}
+
+/// Checks that a point or a vector has no NaN or infinite component.
+pub(crate) fn check_finite(problems: &mut Vec<String>, name: &str, v: Vec3) {
+    if !is_finite(v) {
+        problems.push(format!("the {name} {} is not finite", show(v)));
+    }
+}
+
+/// Checks that a color is finite and not negative.
+pub(crate) fn check_color(problems: &mut Vec<String>, name: &str, c: Color) {
+    if !c.is_finite() || c.r() < 0.0 || c.g() < 0.0 || c.b() < 0.0 {
+        problems.push(format!(
+            "the {name} ({}, {}, {}) is negative or not finite",
+            c.r(),
+            c.g(),
+            c.b()
+        ));
+    }
+}
+
+/// Checks that a parameter is within [0, 1], e.g. a roughness.
+pub(crate) fn check_unit(problems: &mut Vec<String>, name: &str, value: Float) {
+    if !(0.0..=1.0).contains(&value) {
+        problems.push(format!("the {name} {value} is out of [0, 1]"));
+    }
+}
+
+/// Checks that a parameter is finite and strictly positive, e.g. a radius.
+pub(crate) fn check_positive(problems: &mut Vec<String>, name: &str, value: Float) {
+    if !(value.is_finite() && value > 0.0) {
+        problems.push(format!("the {name} {value} is not positive"));
+    }
+}
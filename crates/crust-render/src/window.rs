@@ -0,0 +1,305 @@
+use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::convert::to_rgb8;
+use crate::tracer::Renderer;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::error::Error;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::ScopedJoinHandle;
+use utils::Float;
+
+/// Exposure change of a key press, in stops.
+const EXPOSURE_STEP: f32 = 0.5;
+/// Zoom factor of a notch of the mouse wheel.
+const ZOOM_STEP: f32 = 1.25;
+/// Orbit of a drag of the mouse, in radians per pixel.
+const ORBIT_SPEED: f32 = 0.01;
+/// Color of the window around the image, as 0RGB.
+const BACKGROUND: u32 = 0x0020_2020;
+
+/// Renders in a window showing the image as the tiles complete.
+///
+/// The view is controlled with:
+/// - `+` / `-` or the up and down arrows: the exposure, by half stops, `0` resetting it.
+/// - The mouse wheel: the zoom, around the cursor.
+/// - A drag with the right button: the pan, `F` fitting the image back in the window.
+///
+/// The camera is moved with:
+/// - A drag with the left button: an orbit around the center of the focus plane.
+/// - A drag with the middle button, or with the left one and Shift: a pan.
+/// - The mouse wheel with Ctrl: a dolly towards the center of the focus plane.
+/// - `R`: back to the camera of the scene.
+///
+/// Moving the camera restarts the render. Without passes in the settings, the window
+/// renders one sample per pass so that the whole image shows up quickly.
+///
+/// The window stays open once the render is done. Closing it, or pressing Escape,
+/// cancels the render if it is still running.
+///
+/// # Parameters
+/// - `renderer`: The renderer, which the window adds a tile preview to and whose
+///   camera is left where it was moved.
+///
+/// # Returns
+/// - The film, complete unless the window was closed during the render, or an error if
+///   the window could not be opened.
+pub fn render_in_window(renderer: &mut Renderer) -> Result<Buffer, Box<dyn Error>> {
+    let (width, height) = renderer.settings.get_dimensions();
+    let mut window = Window::new(
+        "crust-render",
+        width,
+        height,
+        WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        },
+    )?;
+    window.set_target_fps(30);
+    let (sender, receiver) = mpsc::channel();
+    renderer.on_tile_complete(move |tile| {
+        // The window may be gone, the render then finishes its tiles for nothing
+        let _ = sender.send(tile.clone());
+    });
+    if renderer.settings.pass_samples() == 0 {
+        renderer.settings = renderer.settings.with_pass_samples(1);
+    }
+    let mut view = View::new(renderer.scene.camera);
+    loop {
+        let (film, moved) = {
+            let renderer = &*renderer;
+            std::thread::scope(|scope| {
+                let mut render = Some(scope.spawn(|| renderer.render()));
+                let mut film = Buffer::new(width, height);
+                let shown = show(
+                    &mut window,
+                    &mut view,
+                    renderer,
+                    &receiver,
+                    &mut render,
+                    &mut film,
+                );
+                if let Some(render) = render.take() {
+                    renderer.cancel.store(true, Ordering::Relaxed);
+                    film = join(render);
+                }
+                shown.map(|moved| (film, moved))
+            })?
+        };
+        let Some(camera) = moved else {
+            return Ok(film);
+        };
+        renderer.scene.camera = camera;
+        renderer.cancel.store(false, Ordering::Relaxed);
+        // Drops the tiles of the previous camera
+        receiver.try_iter().for_each(drop);
+    }
+}
+
+/// Runs the window until it is closed or the camera is moved, merging the tiles into
+/// `film` and replacing it with the render once it is done.
+///
+/// # Returns
+/// - The moved camera, or `None` when the window was closed.
+fn show<'scope>(
+    window: &mut Window,
+    view: &mut View,
+    renderer: &Renderer,
+    tiles: &Receiver<Buffer>,
+    render: &mut Option<ScopedJoinHandle<'scope, Buffer>>,
+    film: &mut Buffer,
+) -> Result<Option<Camera>, Box<dyn Error>> {
+    let mut frame = Vec::new();
+    let mut dirty = true;
+    // Cancelled from outside the window, e.g. by Ctrl-C
+    while window.is_open() && !window.is_key_down(Key::Escape) && !renderer.is_cancelled() {
+        for tile in tiles.try_iter() {
+            film.merge(&tile);
+            dirty = true;
+        }
+        if render.as_ref().is_some_and(|render| render.is_finished()) {
+            // The film of the render also has the passes that are not tiled
+            *film = join(render.take().expect("the render is running"));
+            dirty = true;
+        }
+        if let Some(camera) = view.navigate(window, &renderer.scene.camera, film) {
+            return Ok(Some(camera));
+        }
+        dirty |= view.handle_input(window, film);
+        if dirty {
+            let (frame_width, frame_height) = window.get_size();
+            view.draw(film, renderer, &mut frame, frame_width, frame_height);
+            window.set_title(&view.title(render.is_none()));
+            window.update_with_buffer(&frame, frame_width, frame_height)?;
+            dirty = false;
+        } else {
+            window.update();
+        }
+    }
+    Ok(None)
+}
+
+/// Waits for the render thread, passing its panic on.
+fn join(render: ScopedJoinHandle<'_, Buffer>) -> Buffer {
+    render
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// How the image is shown in the window, and how the camera is moved.
+struct View {
+    /// Exposure added to the one of the settings, in stops.
+    exposure: f32,
+    /// Window pixels per image pixel.
+    zoom: f32,
+    /// Position in the image of the top-left corner of the window, in pixels.
+    offset: (f32, f32),
+    /// Position of the mouse at the previous frame of a pan.
+    drag: Option<(f32, f32)>,
+    /// Position of the mouse at the previous frame of a camera move.
+    camera_drag: Option<(f32, f32)>,
+    /// The camera of the scene.
+    home: Camera,
+}
+
+impl View {
+    fn new(home: Camera) -> Self {
+        View {
+            exposure: 0.0,
+            zoom: 1.0,
+            offset: (0.0, 0.0),
+            drag: None,
+            camera_drag: None,
+            home,
+        }
+    }
+
+    /// Moves the camera from the keyboard and the mouse.
+    ///
+    /// # Returns
+    /// - The moved camera, or `None` when it did not move.
+    fn navigate(&mut self, window: &Window, camera: &Camera, film: &Buffer) -> Option<Camera> {
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            return Some(self.home);
+        }
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        let ctrl = window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl);
+        if ctrl
+            && let Some((_, scroll)) = window.get_scroll_wheel()
+            && scroll != 0.0
+        {
+            return Some(camera.dolly(ZOOM_STEP.powf(-scroll.signum()) as Float));
+        }
+        let left = window.get_mouse_down(MouseButton::Left);
+        let middle = window.get_mouse_down(MouseButton::Middle);
+        let position = window.get_mouse_pos(MouseMode::Pass);
+        let (Some(position), true) = (position, left || middle) else {
+            self.camera_drag = None;
+            return None;
+        };
+        let previous = self.camera_drag.replace(position)?;
+        let (dx, dy) = (position.0 - previous.0, position.1 - previous.1);
+        if (dx, dy) == (0.0, 0.0) {
+            return None;
+        }
+        if middle || shift {
+            // The scene follows the mouse, the window being flipped from the viewport
+            let scale = (
+                film.width() as f32 * self.zoom,
+                film.height() as f32 * self.zoom,
+            );
+            Some(camera.pan((-dx / scale.0) as Float, (dy / scale.1) as Float))
+        } else {
+            Some(camera.orbit((-dx * ORBIT_SPEED) as Float, (dy * ORBIT_SPEED) as Float))
+        }
+    }
+
+    /// Updates the view from the keyboard and the mouse.
+    ///
+    /// # Returns
+    /// - Whether the view changed.
+    fn handle_input(&mut self, window: &Window, film: &Buffer) -> bool {
+        let mut changed = false;
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Up | Key::Equal | Key::NumPadPlus => self.exposure += EXPOSURE_STEP,
+                Key::Down | Key::Minus | Key::NumPadMinus => self.exposure -= EXPOSURE_STEP,
+                Key::Key0 | Key::NumPad0 => self.exposure = 0.0,
+                Key::F => self.fit(window.get_size(), (film.width(), film.height())),
+                _ => continue,
+            }
+            changed = true;
+        }
+        let mouse = window.get_mouse_pos(MouseMode::Pass);
+        if let (Some((_, scroll)), Some((x, y))) = (window.get_scroll_wheel(), mouse)
+            && scroll != 0.0
+        {
+            // The image point under the cursor stays under it
+            let point = (self.offset.0 + x / self.zoom, self.offset.1 + y / self.zoom);
+            self.zoom *= ZOOM_STEP.powf(scroll.signum());
+            self.offset = (point.0 - x / self.zoom, point.1 - y / self.zoom);
+            changed = true;
+        }
+        match (window.get_mouse_down(MouseButton::Right), mouse) {
+            (true, Some(position)) => {
+                if let Some(previous) = self.drag {
+                    self.offset.0 -= (position.0 - previous.0) / self.zoom;
+                    self.offset.1 -= (position.1 - previous.1) / self.zoom;
+                    changed |= position != previous;
+                }
+                self.drag = Some(position);
+            }
+            _ => self.drag = None,
+        }
+        changed
+    }
+
+    /// Zooms to fit the image in the window, centered.
+    fn fit(
+        &mut self,
+        (window_width, window_height): (usize, usize),
+        (width, height): (usize, usize),
+    ) {
+        self.zoom = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+        self.offset = (
+            (width as f32 - window_width as f32 / self.zoom) / 2.0,
+            (height as f32 - window_height as f32 / self.zoom) / 2.0,
+        );
+    }
+
+    /// Draws the film into the frame of the window, as the 8-bit outputs would show it
+    /// with the exposure of the view.
+    fn draw(
+        &self,
+        film: &Buffer,
+        renderer: &Renderer,
+        frame: &mut Vec<u32>,
+        frame_width: usize,
+        frame_height: usize,
+    ) {
+        let settings = renderer
+            .settings
+            .with_exposure(renderer.settings.exposure() + self.exposure as Float);
+        let image = to_rgb8(film, &settings);
+        frame.clear();
+        frame.extend((0..frame_width * frame_height).map(|index| {
+            let x = self.offset.0 + (index % frame_width) as f32 / self.zoom;
+            let y = self.offset.1 + (index / frame_width) as f32 / self.zoom;
+            if x < 0.0 || y < 0.0 || x >= film.width() as f32 || y >= film.height() as f32 {
+                return BACKGROUND;
+            }
+            let [r, g, b] = image.get_pixel(x as u32, y as u32).0;
+            u32::from_be_bytes([0, r, g, b])
+        }));
+    }
+
+    /// The title of the window, with the state of the render and of the view.
+    fn title(&self, done: bool) -> String {
+        format!(
+            "crust-render - {} - exposure {:+.1} - {:.0}%",
+            if done { "done" } else { "rendering" },
+            self.exposure,
+            self.zoom * 100.0
+        )
+    }
+}
@@ -1,205 +1,587 @@
+use crate::camera::Camera;
+use crate::document::{DocObject, Document, ObjectList};
 use crate::hittable_list::HittableList;
 use crate::light::LightList;
-use crate::material::Material;
+use crate::material::BlinnPhong;
+use crate::material::MaterialType;
 use crate::material::{CookTorrance, Dielectric, Disney, Emissive, Lambertian, Metal};
-use crate::primitives::Object;
-use std::sync::Arc;
-use utils::Color;
+use crate::primitives::Primitive;
+use crate::tracer::RenderSettings;
 use utils::Point3;
+use utils::Vec3;
+use utils::{Color, Float};
 
-#[allow(dead_code)]
-pub fn random_scene() -> (HittableList, LightList) {
-    let mut world = HittableList::new();
-    let mut lights = LightList::new();
+/// The names of the built-in scenes, read with `Document::read` as `builtin:<name>`.
+pub const BUILTIN_SCENES: [&str; 4] = [
+    "cornell-box",
+    "random-spheres",
+    "material-test-spheres",
+    "furnace",
+];
 
-    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Box::new(Object::new_sphere(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = utils::random();
-            let center = Point3::new(
-                a as f32 + 0.9 * utils::random(),
-                0.2,
-                b as f32 + 0.9 * utils::random(),
-            );
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.3 {
-                    // Diffuse
-                    let albedo = Color::random() * Color::random();
-                    let sphere_material = Arc::new(Lambertian::new(albedo));
-                    world.add(Box::new(Object::new_sphere(center, 0.2, sphere_material)));
-                } else if choose_mat < 0.8 {
-                    // Cook-Torrance
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let roughness = utils::random_range(0.0, 0.5);
-                    let metallic = utils::random_range(0.0, 1.0);
-                    let sphere_material = Arc::new(CookTorrance::new(albedo, roughness, metallic));
-                    world.add(Box::new(Object::new_sphere(center, 0.2, sphere_material)));
-                } else if choose_mat < 0.95 {
-                    // Metal
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = utils::random_range(0.0, 0.5);
-                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
-                    world.add(Box::new(Object::new_sphere(center, 0.2, sphere_material)));
-                } else {
-                    // Glass
-                    let sphere_material = Arc::new(Dielectric::new(1.5));
-                    world.add(Box::new(Object::new_sphere(center, 0.2, sphere_material)));
-                }
-            }
+/// A sphere of the generated scenes.
+fn sphere(name: String, center: Point3, radius: Float, material: MaterialType) -> DocObject {
+    DocObject::new(name, Primitive::Sphere { center, radius }, material)
+}
+
+/// A spherical light of the generated scenes.
+fn light(name: &str, color: Color, center: Point3) -> DocObject {
+    let emissive = Emissive::new(color, center, 1.0);
+    sphere(
+        name.to_string(),
+        emissive.position(),
+        emissive.radius(),
+        MaterialType::Emissive(emissive),
+    )
+}
+
+/// A generator of random scenes after the cover of "Ray Tracing in One Weekend":
+/// small spheres of random materials on a grid, scattered around three large ones,
+/// under two lights. Its parameters make larger or sparser scenes, e.g. to benchmark
+/// and stress test the accelerators, and its seed makes them reproducible.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomScene {
+    /// Half the side of the grid of small spheres, one per unit cell.
+    grid: i32,
+    /// Probability of a cell to have a sphere.
+    density: Float,
+    /// Relative probabilities of the diffuse, Cook-Torrance, metal and glass spheres.
+    materials: [Float; 4],
+    seed: u32,
+}
+
+impl Default for RandomScene {
+    /// The scene of the cover, drawn the same as `builtin:random-spheres`.
+    fn default() -> Self {
+        Self {
+            grid: 11,
+            density: 1.0,
+            materials: [0.3, 0.5, 0.15, 0.05],
+            seed: 0x2545_f491,
         }
     }
+}
 
-    let material1 = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Object::new_sphere(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
+impl RandomScene {
+    /// Sets half the side of the grid, the small spheres spanning `[-grid, grid)`.
+    pub fn with_grid(mut self, grid: u32) -> Self {
+        self.grid = grid as i32;
+        self
+    }
 
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Object::new_sphere(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
+    /// Sets the probability of a cell of the grid to have a sphere.
+    pub fn with_density(mut self, density: Float) -> Self {
+        self.density = density.clamp(0.0, 1.0);
+        self
+    }
 
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Object::new_sphere(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
+    /// Sets the relative probabilities of the materials of the small spheres.
+    pub fn with_materials(
+        mut self,
+        diffuse: Float,
+        cook_torrance: Float,
+        metal: Float,
+        glass: Float,
+    ) -> Self {
+        self.materials = [diffuse, cook_torrance, metal, glass].map(|p| p.max(0.0));
+        self
+    }
 
-    let light: Arc<Emissive> = Arc::new(Emissive::new(
-        Color::new(10.0, 10.0, 10.0),
-        Point3::new(0.0, 7.0, 0.0),
-        1.0,
-    ));
-    world.add(Box::new(Object::new_sphere(
-        light.position(),
-        light.radius(),
-        light.clone(),
-    )));
-
-    lights.add(light);
-    let light2 = Arc::new(Emissive::new(
-        Color::new(20.0, 10.0, 7.0),
-        Point3::new(-4.0, 7.0, 0.0),
-        1.0,
-    ));
-    world.add(Box::new(Object::new_sphere(
-        light2.position(),
-        light2.radius(),
-        light2.clone(),
-    )));
-    lights.add(light2);
-
-    (world, lights)
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The objects of the scene, always the same for the same parameters.
+    pub fn objects(&self) -> ObjectList {
+        // Xorshift, the seed 0 being its only fixed point
+        let mut state = self.seed.max(1);
+        utils::with_random_source(
+            move || {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 8) as Float / (1u32 << 24) as Float
+            },
+            || self.draw(),
+        )
+    }
+
+    /// The scene of the objects, seen by the camera of the cover.
+    pub fn document(&self) -> Document {
+        Document::new(
+            Camera::new(
+                Point3::new(13.0, 2.0, 3.0),
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                20.0,
+                16.0 / 9.0,
+                0.1,
+                10.0,
+            ),
+            self.objects(),
+            RenderSettings::new(64, 16, 400, 225, 16, 0.05),
+        )
+    }
+
+    /// The objects, drawn with `utils::random`.
+    fn draw(&self) -> ObjectList {
+        let mut objects = ObjectList::new(vec![sphere(
+            "ground".to_string(),
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            MaterialType::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )]);
+        let total: Float = self.materials.iter().sum();
+        let [diffuse, cook_torrance, metal, _] = self.materials.map(|p| p / total);
+
+        for a in -self.grid..self.grid {
+            for b in -self.grid..self.grid {
+                // The density is drawn only when it thins the grid, which otherwise
+                // draws the same spheres as the cover
+                if self.density < 1.0 && utils::random() >= self.density {
+                    continue;
+                }
+                let choose_mat = utils::random();
+                let center = Point3::new(
+                    a as Float + 0.9 * utils::random(),
+                    0.2,
+                    b as Float + 0.9 * utils::random(),
+                );
+
+                if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                    let material = if choose_mat < diffuse {
+                        // Diffuse
+                        let albedo = Color::random() * Color::random();
+                        MaterialType::Lambertian(Lambertian::new(albedo))
+                    } else if choose_mat < diffuse + cook_torrance {
+                        // Cook-Torrance
+                        let albedo = Color::random_range(0.5, 1.0);
+                        let roughness = utils::random_range(0.0, 0.5);
+                        let metallic = utils::random_range(0.0, 1.0);
+                        MaterialType::CookTorrance(CookTorrance::new(albedo, roughness, metallic))
+                    } else if choose_mat < diffuse + cook_torrance + metal {
+                        // Metal
+                        let albedo = Color::random_range(0.5, 1.0);
+                        let fuzz = utils::random_range(0.0, 0.5);
+                        MaterialType::Metal(Metal::new(albedo, fuzz))
+                    } else {
+                        // Glass
+                        MaterialType::Dielectric(Dielectric::new(1.5))
+                    };
+                    objects.add(sphere(format!("sphere_{a}_{b}"), center, 0.2, material));
+                }
+            }
+        }
+
+        objects.add(sphere(
+            "center_sphere_1".to_string(),
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            MaterialType::Dielectric(Dielectric::new(1.5)),
+        ));
+        objects.add(sphere(
+            "center_sphere_2".to_string(),
+            Point3::new(-4.0, 1.0, 0.0),
+            1.0,
+            MaterialType::Lambertian(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+        ));
+        objects.add(sphere(
+            "center_sphere_3".to_string(),
+            Point3::new(4.0, 1.0, 0.0),
+            1.0,
+            MaterialType::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+        ));
+
+        objects.add(light(
+            "light_1",
+            Color::new(10.0, 10.0, 10.0),
+            Point3::new(0.0, 7.0, 0.0),
+        ));
+        objects.add(light(
+            "light_2",
+            Color::new(20.0, 10.0, 7.0),
+            Point3::new(-4.0, 7.0, 0.0),
+        ));
+        objects
+    }
 }
 
-pub fn simple_scene() -> (HittableList, LightList) {
-    let mut world = HittableList::new();
-    let mut lights = LightList::new();
+/// The objects of the cover scene of "Ray Tracing in One Weekend", drawn differently
+/// each time.
+///
+/// The objects can be written to a scene file with `Document::write`, so that the
+/// random scene can be rendered again and edited.
+pub fn random_objects() -> ObjectList {
+    RandomScene::default().draw()
+}
 
-    let ground_material = Arc::new(Lambertian::new(Color::new(0.8, 0.5, 0.5)));
-    world.add(Box::new(Object::new_sphere(
+pub fn random_scene() -> (HittableList, LightList) {
+    random_objects().get_world()
+}
+
+/// The objects of a deterministic scene: a grid of small spheres of preset materials
+/// around five large ones, under two lights.
+pub fn simple_objects() -> ObjectList {
+    let mut objects = ObjectList::new(vec![sphere(
+        "ground".to_string(),
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        ground_material,
-    )));
+        MaterialType::Lambertian(Lambertian::new(Color::new(0.8, 0.5, 0.5))),
+    )]);
 
     // Deterministic grid of spheres with preset materials
     for a in -2..3 {
         for b in -2..3 {
-            let center = Point3::new(a as f32, 0.2, b as f32);
-            let material: Arc<dyn Material> = match (a + b) % 4 {
-                0 => Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))),
-                1 => Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.1)),
-                2 => Arc::new(Dielectric::new(1.5)),
-                _ => Arc::new(CookTorrance::new(Color::new(0.9, 0.9, 0.9), 0.2, 0.5)),
+            let center = Point3::new(a as Float, 0.2, b as Float);
+            let material = match (a + b) % 4 {
+                0 => MaterialType::Lambertian(Lambertian::new(Color::new(0.8, 0.3, 0.3))),
+                1 => MaterialType::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.1)),
+                2 => MaterialType::Dielectric(Dielectric::new(1.5)),
+                _ => MaterialType::CookTorrance(CookTorrance::new(
+                    Color::new(0.9, 0.9, 0.9),
+                    0.2,
+                    0.5,
+                )),
             };
-
-            world.add(Box::new(Object::new_sphere(center, 0.2, material)));
+            objects.add(sphere(format!("sphere_{a}_{b}"), center, 0.2, material));
         }
     }
 
     // Center spheres
-    let material1 = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Object::new_sphere(
+    objects.add(sphere(
+        "center_sphere_1".to_string(),
         Point3::new(0.0, 1.0, 0.0),
         1.0,
-        material1,
-    )));
-
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Object::new_sphere(
+        MaterialType::Dielectric(Dielectric::new(1.5)),
+    ));
+    objects.add(sphere(
+        "center_sphere_2".to_string(),
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
-        material2,
-    )));
-
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Object::new_sphere(
+        MaterialType::Lambertian(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+    ));
+    objects.add(sphere(
+        "center_sphere_3".to_string(),
         Point3::new(4.0, 1.0, 0.0),
         1.0,
-        material3,
-    )));
-
-    let material4 = Arc::new(CookTorrance::new(Color::new(0.5, 0.5, 0.5), 0.2, 0.0));
-    world.add(Box::new(Object::new_sphere(
+        MaterialType::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+    ));
+    objects.add(sphere(
+        "center_sphere_4".to_string(),
         Point3::new(0.0, 1.0, 4.0),
         1.0,
-        material4,
-    )));
-
-    let material5 = Arc::new(Disney::new(
-        Color::new(0.5, 0.5, 0.5),
-        0.0,
-        0.2,
-        0.5,
-        0.5,
-        0.0,
-        0.5,
-        0.0,
-        1.0,
+        MaterialType::CookTorrance(CookTorrance::new(Color::new(0.5, 0.5, 0.5), 0.2, 0.0)),
     ));
-    world.add(Box::new(Object::new_sphere(
+    objects.add(sphere(
+        "center_sphere_5".to_string(),
         Point3::new(0.0, 1.0, -4.0),
         1.0,
-        material5,
-    )));
+        MaterialType::Disney(Disney::new(
+            Color::new(0.5, 0.5, 0.5),
+            0.0,
+            0.2,
+            0.5,
+            0.5,
+            0.0,
+            0.5,
+            0.0,
+            1.0,
+        )),
+    ));
 
     // Lights
-    let light1 = Arc::new(Emissive::new(
+    objects.add(light(
+        "light_1",
         Color::new(10.0, 10.0, 10.0),
         Point3::new(0.0, 7.0, 0.0),
-        1.0,
     ));
-    world.add(Box::new(Object::new_sphere(
-        light1.position(),
-        light1.radius(),
-        light1.clone(),
-    )));
-    lights.add(light1);
-
-    let light2 = Arc::new(Emissive::new(
+    objects.add(light(
+        "light_2",
         Color::new(20.0, 10.0, 7.0),
         Point3::new(-4.0, 7.0, 0.0),
-        1.0,
     ));
-    world.add(Box::new(Object::new_sphere(
-        light2.position(),
-        light2.radius(),
-        light2.clone(),
-    )));
-    lights.add(light2);
-
-    (world, lights)
+    objects
+}
+
+pub fn simple_scene() -> (HittableList, LightList) {
+    simple_objects().get_world()
+}
+
+/// A built-in scene by name, one of `BUILTIN_SCENES`, to test and benchmark the
+/// renderer without scene files:
+/// - `cornell-box`: The Cornell box, lit by the area light of its ceiling.
+/// - `random-spheres`: The scene of `RandomScene::default()`.
+/// - `material-test-spheres`: A row of spheres of every kind of material, from rough
+///   to smooth, on a ground under two lights.
+/// - `furnace`: Spheres of white materials in a uniformly emitting sphere, which they
+///   disappear into when the materials conserve energy.
+pub fn builtin_scene(name: &str) -> Option<Document> {
+    let settings = |width, height| RenderSettings::new(64, 16, width, height, 16, 0.05);
+    let doc = match name {
+        "cornell-box" => Document::new(
+            Camera::new(
+                Point3::new(278.0, 278.0, -800.0),
+                Point3::new(278.0, 278.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                1.0,
+                0.0,
+                10.0,
+            ),
+            cornell_box(),
+            settings(400, 400),
+        ),
+        "random-spheres" => RandomScene::default().document(),
+        "material-test-spheres" => Document::new(
+            Camera::new(
+                Point3::new(0.0, 2.5, 9.0),
+                Point3::new(0.0, 0.6, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                35.0,
+                2.0,
+                0.0,
+                9.0,
+            ),
+            material_test_spheres(),
+            settings(600, 300),
+        ),
+        "furnace" => Document::new(
+            Camera::new(
+                Point3::new(0.0, 0.0, 6.0),
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                1.0,
+                0.0,
+                6.0,
+            ),
+            furnace(),
+            settings(256, 256),
+        ),
+        _ => return None,
+    };
+    Some(doc)
+}
+
+/// A quad of two triangles, its corners in order around it.
+fn quad(name: &str, corners: [Point3; 4], material: MaterialType) -> DocObject {
+    DocObject::new(
+        name.to_string(),
+        Primitive::new_mesh(corners.to_vec(), vec![0, 1, 2, 0, 2, 3]),
+        material,
+    )
+}
+
+/// A box standing on the floor, turned by `angle` degrees around its vertical axis.
+fn block(
+    name: &str,
+    corner: Point3,
+    size: Vec3,
+    angle: Float,
+    material: MaterialType,
+) -> DocObject {
+    let (sin, cos) = utils::degrees_to_radians(angle).sin_cos();
+    let vertices = (0..8)
+        .map(|i| {
+            let x = if i & 1 == 0 { 0.0 } else { size.x() };
+            let y = if i & 2 == 0 { 0.0 } else { size.y() };
+            let z = if i & 4 == 0 { 0.0 } else { size.z() };
+            corner + Vec3::new(cos * x + sin * z, y, -sin * x + cos * z)
+        })
+        .collect();
+    let faces = [
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+    ];
+    let indices = faces
+        .iter()
+        .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+        .collect();
+    DocObject::new(
+        name.to_string(),
+        Primitive::new_mesh(vertices, indices),
+        material,
+    )
+}
+
+/// The objects of the Cornell box, 555 units wide, open towards -z.
+fn cornell_box() -> ObjectList {
+    let lambertian = |r, g, b| MaterialType::Lambertian(Lambertian::new(Color::new(r, g, b)));
+    let (red, white, green) = (
+        lambertian(0.65, 0.05, 0.05),
+        lambertian(0.73, 0.73, 0.73),
+        lambertian(0.12, 0.45, 0.15),
+    );
+    let p = Point3::new;
+    let size = 555.0;
+    let light_corners = [
+        p(213.0, 554.0, 227.0),
+        p(343.0, 554.0, 227.0),
+        p(343.0, 554.0, 332.0),
+        p(213.0, 554.0, 332.0),
+    ];
+    let light = Emissive::for_mesh(
+        Color::new(15.0, 15.0, 15.0),
+        &light_corners,
+        &[0, 1, 2, 0, 2, 3],
+    );
+    ObjectList::new(vec![
+        quad(
+            "floor",
+            [
+                p(0.0, 0.0, 0.0),
+                p(size, 0.0, 0.0),
+                p(size, 0.0, size),
+                p(0.0, 0.0, size),
+            ],
+            white.clone(),
+        ),
+        quad(
+            "ceiling",
+            [
+                p(0.0, size, 0.0),
+                p(0.0, size, size),
+                p(size, size, size),
+                p(size, size, 0.0),
+            ],
+            white.clone(),
+        ),
+        quad(
+            "back",
+            [
+                p(0.0, 0.0, size),
+                p(size, 0.0, size),
+                p(size, size, size),
+                p(0.0, size, size),
+            ],
+            white.clone(),
+        ),
+        quad(
+            "left",
+            [
+                p(size, 0.0, 0.0),
+                p(size, size, 0.0),
+                p(size, size, size),
+                p(size, 0.0, size),
+            ],
+            red,
+        ),
+        quad(
+            "right",
+            [
+                p(0.0, 0.0, 0.0),
+                p(0.0, 0.0, size),
+                p(0.0, size, size),
+                p(0.0, size, 0.0),
+            ],
+            green,
+        ),
+        quad("light", light_corners, MaterialType::Emissive(light)),
+        block(
+            "tall_box",
+            p(265.0, 0.0, 295.0),
+            Vec3::new(165.0, 330.0, 165.0),
+            15.0,
+            white.clone(),
+        ),
+        block(
+            "short_box",
+            p(130.0, 0.0, 65.0),
+            Vec3::new(165.0, 165.0, 165.0),
+            -18.0,
+            white,
+        ),
+    ])
+}
+
+/// The objects of the material test: a row of spheres of each kind of material.
+fn material_test_spheres() -> ObjectList {
+    let mut objects = ObjectList::new(vec![sphere(
+        "ground".to_string(),
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        MaterialType::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )]);
+    let albedo = Color::new(0.8, 0.4, 0.2);
+    let materials = [
+        (
+            "lambertian",
+            MaterialType::Lambertian(Lambertian::new(albedo)),
+        ),
+        (
+            "blinn_phong",
+            MaterialType::BlinnPhong(BlinnPhong::new(
+                albedo,
+                Color::new(0.5, 0.5, 0.5),
+                64.0,
+                Vec3::new(0.0, 1.0, 0.0),
+            )),
+        ),
+        ("metal_rough", MaterialType::Metal(Metal::new(albedo, 0.4))),
+        ("metal_smooth", MaterialType::Metal(Metal::new(albedo, 0.0))),
+        (
+            "cook_torrance_rough",
+            MaterialType::CookTorrance(CookTorrance::new(albedo, 0.6, 1.0)),
+        ),
+        (
+            "cook_torrance_smooth",
+            MaterialType::CookTorrance(CookTorrance::new(albedo, 0.1, 0.0)),
+        ),
+        (
+            "disney",
+            MaterialType::Disney(Disney::new(albedo, 0.0, 0.4, 0.5, 0.0, 0.0, 0.0, 0.5, 0.8)),
+        ),
+        ("glass", MaterialType::Dielectric(Dielectric::new(1.5))),
+    ];
+    let count = materials.len() as Float;
+    for (i, (name, material)) in materials.into_iter().enumerate() {
+        let x = 1.1 * (i as Float - (count - 1.0) / 2.0);
+        objects.add(sphere(
+            name.to_string(),
+            Point3::new(x, 0.5, 0.0),
+            0.5,
+            material,
+        ));
+    }
+    objects.add(light(
+        "light_1",
+        Color::new(10.0, 10.0, 10.0),
+        Point3::new(-3.0, 6.0, 4.0),
+    ));
+    objects.add(light(
+        "light_2",
+        Color::new(4.0, 4.0, 5.0),
+        Point3::new(4.0, 5.0, -2.0),
+    ));
+    objects
+}
+
+/// The objects of the furnace: white spheres in a sphere emitting 1 all around.
+fn furnace() -> ObjectList {
+    let white = Color::new(1.0, 1.0, 1.0);
+    ObjectList::new(vec![
+        sphere(
+            "lambertian".to_string(),
+            Point3::new(-1.1, 0.0, 0.0),
+            1.0,
+            MaterialType::Lambertian(Lambertian::new(white)),
+        ),
+        sphere(
+            "cook_torrance".to_string(),
+            Point3::new(1.1, 0.0, 0.0),
+            1.0,
+            MaterialType::CookTorrance(CookTorrance::new(white, 0.5, 1.0)),
+        ),
+        sphere(
+            "furnace".to_string(),
+            Point3::zero(),
+            100.0,
+            MaterialType::Emissive(Emissive::new(white, Point3::zero(), 100.0)),
+        ),
+    ])
 }
@@ -1,24 +1,230 @@
-// Type alias
+use crate::Float;
 use crate::Vec3;
-pub type Color = Vec3;
+use crate::common;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Sub};
+
+/// A linear RGB radiance or reflectance, apart from the `Vec3` of the positions and
+/// directions so that the two don't mix by mistake.
+///
+/// It is written `(e: (r, g, b))` in the scene files, as a `Vec3`.
+#[derive(Copy, Clone, Default, Deserialize, Serialize, Debug)]
+pub struct Color {
+    e: [Float; 3],
+}
 
 impl Color {
-    pub fn r(&self) -> f32 {
-        self.x()
+    pub const fn new(r: Float, g: Float, b: Float) -> Color {
+        Color { e: [r, g, b] }
+    }
+    pub const fn zero() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+    /// The same value in the three channels.
+    pub const fn splat(value: Float) -> Self {
+        Self::new(value, value, value)
     }
-    pub fn g(&self) -> f32 {
-        self.y()
+    pub fn random() -> Color {
+        Color::new(common::random(), common::random(), common::random())
     }
-    pub fn b(&self) -> f32 {
-        self.z()
+    pub fn random_range(min: Float, max: Float) -> Color {
+        Color::new(
+            common::random_range(min, max),
+            common::random_range(min, max),
+            common::random_range(min, max),
+        )
     }
-    pub fn rgb(&self) -> (f32, f32, f32) {
+    pub fn r(&self) -> Float {
+        self.e[0]
+    }
+    pub fn g(&self) -> Float {
+        self.e[1]
+    }
+    pub fn b(&self) -> Float {
+        self.e[2]
+    }
+    pub fn rgb(&self) -> (Float, Float, Float) {
         (self.r(), self.g(), self.b())
     }
-    pub fn zero() -> Self {
-        Self::new(0.0, 0.0, 0.0)
+    pub fn max_component(&self) -> Float {
+        self.r().max(self.g()).max(self.b())
+    }
+    /// The average of the channels.
+    pub fn average(&self) -> Float {
+        (self.r() + self.g() + self.b()) / 3.0
+    }
+    // Relative luminance of a linear Rec.709 color
+    pub fn luminance(&self) -> Float {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+    /// The sum of the squared channels, e.g. of the difference of two colors to tell
+    /// how far apart they are.
+    pub fn length_squared(&self) -> Float {
+        self.e.iter().map(|c| c * c).sum()
+    }
+    pub fn is_black(&self) -> bool {
+        self.e.iter().all(|&c| c == 0.0)
+    }
+    pub fn is_finite(&self) -> bool {
+        self.e.iter().all(|c| c.is_finite())
+    }
+    pub fn lerp(self, other: Color, t: Float) -> Color {
+        self * (1.0 - t) + other * t
+    }
+    pub fn clamp(self, min: Float, max: Float) -> Color {
+        self.map(|c| common::clamp(c, min, max))
+    }
+    /// Applies `f` to each channel.
+    pub fn map(self, f: impl Fn(Float) -> Float) -> Color {
+        Color::new(f(self.r()), f(self.g()), f(self.b()))
+    }
+    pub fn sqrt(self) -> Color {
+        self.map(Float::sqrt)
+    }
+    pub fn exp(self) -> Color {
+        self.map(Float::exp)
+    }
+    /// Encodes a linear color with the sRGB transfer function, for the display.
+    pub fn to_srgb(self) -> Color {
+        self.map(|c| {
+            if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        })
     }
-    pub fn max_component(&self) -> f32 {
-        self.x().max(self.y()).max(self.z())
+    /// Decodes a color encoded with the sRGB transfer function, e.g. of a texture, to
+    /// linear.
+    pub fn from_srgb(self) -> Color {
+        self.map(|c| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    }
+}
+
+impl From<[Float; 3]> for Color {
+    fn from(e: [Float; 3]) -> Self {
+        Color { e }
+    }
+}
+
+/// The color of a vector, e.g. of a normal written to an AOV.
+impl From<Vec3> for Color {
+    fn from(v: Vec3) -> Self {
+        Color::new(v.x(), v.y(), v.z())
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} {} {}", self.e[0], self.e[1], self.e[2])
+    }
+}
+
+impl Index<usize> for Color {
+    type Output = Float;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.e[i]
+    }
+}
+
+impl IndexMut<usize> for Color {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.e[i]
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, c: Color) -> Color {
+        Color::new(self.r() + c.r(), self.g() + c.g(), self.b() + c.b())
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, c: Color) {
+        *self = *self + c;
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+
+    fn sub(self, c: Color) -> Color {
+        Color::new(self.r() - c.r(), self.g() - c.g(), self.b() - c.b())
+    }
+}
+
+// The product of the channels, e.g. a radiance filtered by a reflectance
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, c: Color) -> Color {
+        Color::new(self.r() * c.r(), self.g() * c.g(), self.b() * c.b())
+    }
+}
+
+impl MulAssign for Color {
+    fn mul_assign(&mut self, c: Color) {
+        *self = *self * c;
+    }
+}
+
+impl Mul<Float> for Color {
+    type Output = Color;
+
+    fn mul(self, t: Float) -> Color {
+        Color::new(self.r() * t, self.g() * t, self.b() * t)
+    }
+}
+
+impl Mul<Color> for Float {
+    type Output = Color;
+
+    fn mul(self, c: Color) -> Color {
+        c * self
+    }
+}
+
+impl MulAssign<Float> for Color {
+    fn mul_assign(&mut self, t: Float) {
+        *self = *self * t;
+    }
+}
+
+impl Div for Color {
+    type Output = Color;
+
+    fn div(self, c: Color) -> Color {
+        Color::new(self.r() / c.r(), self.g() / c.g(), self.b() / c.b())
+    }
+}
+
+impl Div<Float> for Color {
+    type Output = Color;
+
+    fn div(self, t: Float) -> Color {
+        Color::new(self.r() / t, self.g() / t, self.b() / t)
+    }
+}
+
+impl DivAssign<Float> for Color {
+    fn div_assign(&mut self, t: Float) {
+        *self = *self / t;
+    }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color {
+        iter.fold(Color::zero(), |sum, c| sum + c)
     }
 }
@@ -1,25 +1,89 @@
 // Constants
 
-use std::f32::consts::PI;
+use crate::Float;
+use crate::consts::PI;
+use crate::rng::Pcg32;
+use std::cell::RefCell;
+
+type RandomSource = Box<dyn FnMut() -> Float>;
+
+thread_local! {
+    // Optional override of the uniform random numbers returned by `random()` on this thread
+    static RANDOM_SOURCE: RefCell<Option<RandomSource>> = const { RefCell::new(None) };
+    // Generator of `random()` on this thread, seeded from the entropy of the system
+    // until a stream is set with `with_random_stream`
+    static RNG: RefCell<Pcg32> = RefCell::new(Pcg32::new(rand::random(), rand::random()));
+}
 
 // Utility functions
 
-pub fn degrees_to_radians(degrees: f32) -> f32 {
+pub fn degrees_to_radians(degrees: Float) -> Float {
     degrees * PI / 180.0
 }
 
-pub fn random() -> f32 {
+pub fn random() -> Float {
     // Return a random real in [0.0, 1.0)
-    rand::random()
+    RANDOM_SOURCE.with(|source| match source.borrow_mut().as_mut() {
+        Some(next) => next(),
+        None => RNG.with(|rng| rng.borrow_mut().next_f32() as Float),
+    })
+}
+
+// Run `f` with `random()` drawing its values from a stream of a seed on the current
+// thread, e.g. one per pixel, so that they do not depend on the thread running `f`.
+// The previous generator is restored even if `f` panics, e.g. caught by a thread pool.
+pub fn with_random_stream<R>(seed: u64, stream: u64, f: impl FnOnce() -> R) -> R {
+    let previous = RNG.with(|rng| rng.replace(Pcg32::new(seed, stream)));
+    let _restore = RestoreRng(Some(previous));
+    f()
+}
+
+// Puts the generator of the thread back when dropped, by `f` returning or unwinding.
+struct RestoreRng(Option<Pcg32>);
+
+impl Drop for RestoreRng {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            let _ = RNG.try_with(|rng| *rng.borrow_mut() = previous);
+        }
+    }
+}
+
+// Shuffle `values` with `random()`.
+pub fn shuffle<T>(values: &mut [T]) {
+    for i in (1..values.len()).rev() {
+        let j = ((random() * (i + 1) as Float) as usize).min(i);
+        values.swap(i, j);
+    }
 }
 
-pub fn random_range(min: f32, max: f32) -> f32 {
+// Run `f` with `random()` drawing its values from `source` on the current thread.
+// Used by integrators that must control every random decision of a path (e.g. Metropolis).
+// The previous source is restored even if `f` panics.
+pub fn with_random_source<R>(source: impl FnMut() -> Float + 'static, f: impl FnOnce() -> R) -> R {
+    let previous = RANDOM_SOURCE.with(|s| s.borrow_mut().replace(Box::new(source)));
+    let _restore = RestoreSource(Some(previous));
+    f()
+}
+
+// Puts the random source of the thread back when dropped, by `f` returning or unwinding.
+struct RestoreSource(Option<Option<RandomSource>>);
+
+impl Drop for RestoreSource {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            let _ = RANDOM_SOURCE.try_with(|s| *s.borrow_mut() = previous);
+        }
+    }
+}
+
+pub fn random_range(min: Float, max: Float) -> Float {
     // Return a random real in [min, max)
     min + (max - min) * random()
 }
 
 #[allow(dead_code)]
-pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
+pub fn clamp(x: Float, min: Float, max: Float) -> Float {
     if x < min {
         return min;
     }
@@ -29,13 +93,13 @@ pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
     x
 }
 
-pub fn balance_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
+pub fn balance_heuristic(pdf_a: Float, pdf_b: Float) -> Float {
     let pdf_a2 = pdf_a * pdf_a;
     let pdf_b2 = pdf_b * pdf_b;
     pdf_a2 / (pdf_a2 + pdf_b2 + 1e-6)
 }
 
-pub fn random2() -> (f32, f32) {
+pub fn random2() -> (Float, Float) {
     (random(), random())
 }
 
@@ -43,8 +107,37 @@ pub trait Lerp {
     fn lerp(self, b: Self, t: Self) -> Self;
 }
 
-impl Lerp for f32 {
-    fn lerp(self, b: f32, t: f32) -> f32 {
+impl Lerp for Float {
+    fn lerp(self, b: Float, t: Float) -> Float {
         self * (1.0 - t) + b * t
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    #[test]
+    fn restores_the_stream_after_a_panic() {
+        let expected = with_random_stream(1, 2, || (random(), random()));
+        let drawn = with_random_stream(1, 2, || {
+            let first = random();
+            let caught = catch_unwind(AssertUnwindSafe(|| {
+                with_random_stream(3, 4, || -> Float { panic!("in the stream") })
+            }));
+            assert!(caught.is_err());
+            (first, random())
+        });
+        assert_eq!(drawn, expected);
+    }
+
+    #[test]
+    fn restores_the_source_after_a_panic() {
+        let caught = catch_unwind(AssertUnwindSafe(|| {
+            with_random_source(|| 0.5, || -> Float { panic!("in the source") })
+        }));
+        assert!(caught.is_err());
+        assert!(RANDOM_SOURCE.with(|s| s.borrow().is_none()));
+    }
+}
@@ -2,12 +2,39 @@ mod vec3;
 pub use vec3::Point3;
 pub use vec3::Vec3;
 pub use vec3::{
-    align_to_normal, cross, dot, random_cosine_direction, random_in_unit_disk,
-    random_in_unit_sphere, random_unit_vector, reflect, refract, unit_vector,
+    concentric_sample_disk, cosine_direction, cross, dot, random_cosine_direction,
+    random_in_unit_disk, random_in_unit_sphere, random_unit_vector, reflect, refract,
+    sphere_direction, unit_vector,
 };
 mod common;
 pub use common::Lerp;
 pub use common::{balance_heuristic, clamp};
-pub use common::{degrees_to_radians, random, random_range, random2};
+pub use common::{
+    degrees_to_radians, random, random_range, random2, shuffle, with_random_source,
+    with_random_stream,
+};
 mod color;
 pub use color::Color;
+mod rng;
+pub use rng::Pcg32;
+mod onb;
+pub use onb::Onb;
+mod mat4;
+pub use mat4::Mat4;
+mod quat;
+pub use quat::Quat;
+
+/// The scalar of the math, `f64` with the `f64` feature for scenes of distant geometry
+/// that lack the precision of `f32`.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+/// The scalar of the math, `f64` with the `f64` feature for scenes of distant geometry
+/// that lack the precision of `f32`.
+#[cfg(feature = "f64")]
+pub type Float = f64;
+/// The mathematical constants of `Float`.
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+/// The mathematical constants of `Float`.
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
@@ -0,0 +1,256 @@
+use crate::Float;
+use crate::vec3::{Point3, Vec3, cross};
+use serde::{Deserialize, Serialize};
+use std::ops::Mul;
+
+/// A 4x4 row-major matrix applied to column vectors, the affine transforms of the
+/// imported scenes, the instances and the animations.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Mat4 {
+    m: [[Float; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4 { m }
+    }
+
+    /// The transform of a row-major matrix.
+    pub fn from_rows(m: [[Float; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    /// The transform of a column-major matrix, as written by pbrt.
+    pub fn from_columns(values: &[Float; 16]) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = values[j * 4 + i];
+            }
+        }
+        Mat4 { m }
+    }
+
+    pub fn translate(delta: Vec3) -> Self {
+        Mat4::from_rows([
+            [1.0, 0.0, 0.0, delta.x()],
+            [0.0, 1.0, 0.0, delta.y()],
+            [0.0, 0.0, 1.0, delta.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(x: Float, y: Float, z: Float) -> Self {
+        Mat4::from_rows([
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// A rotation of `degrees` around `axis`, counterclockwise looking down the axis.
+    pub fn rotate(degrees: Float, axis: Vec3) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = crate::degrees_to_radians(degrees).sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        Mat4::from_rows([
+            [
+                x * x + (1.0 - x * x) * cos,
+                x * y * (1.0 - cos) - z * sin,
+                x * z * (1.0 - cos) + y * sin,
+                0.0,
+            ],
+            [
+                x * y * (1.0 - cos) + z * sin,
+                y * y + (1.0 - y * y) * cos,
+                y * z * (1.0 - cos) - x * sin,
+                0.0,
+            ],
+            [
+                x * z * (1.0 - cos) - y * sin,
+                y * z * (1.0 - cos) + x * sin,
+                z * z + (1.0 - z * z) * cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// The camera-to-world transform of a camera at `eye` looking at `look` down its
+    /// z axis, its x axis being `up` cross the viewing direction.
+    pub fn look_at(eye: Point3, look: Point3, up: Vec3) -> Self {
+        let dir = (look - eye).unit_vector();
+        let right = cross(up.unit_vector(), dir).unit_vector();
+        let new_up = cross(dir, right);
+        Mat4::from_rows([
+            [right.x(), new_up.x(), dir.x(), eye.x()],
+            [right.y(), new_up.y(), dir.y(), eye.y()],
+            [right.z(), new_up.z(), dir.z(), eye.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// The transform applying `other`, then this one, also written `self * other`.
+    pub fn compose(&self, other: &Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+
+    /// The inverse transform, `None` for a singular matrix.
+    pub fn inverse(&self) -> Option<Mat4> {
+        // Gauss-Jordan elimination with partial pivoting
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap_or(column);
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inv.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inv[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inv[row][j] -= factor * inv[column][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { m: inv })
+    }
+
+    /// The matrix, row-major.
+    pub fn rows(&self) -> [[Float; 4]; 4] {
+        self.m
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Mat4 { m }
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 || w == 0.0 {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x / w, y / w, z / w)
+        }
+    }
+
+    pub fn transform_direction(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    /// The vector multiplied by the transpose of the matrix, which transforms the
+    /// normals when this is the inverse of the transform of the points.
+    pub fn transpose_direction(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[1][0] * v.y() + m[2][0] * v.z(),
+            m[0][1] * v.x() + m[1][1] * v.y() + m[2][1] * v.z(),
+            m[0][2] * v.x() + m[1][2] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    /// The factor scaling the lengths, for the radius of a transformed sphere: the
+    /// cube root of the volume scale, exact for uniform scales.
+    pub fn uniform_scale(&self) -> Float {
+        let m = &self.m;
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        determinant.abs().cbrt()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        self.compose(&other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_identity(m: &Mat4) {
+        let identity = Mat4::identity().rows();
+        for (row, expected) in m.rows().iter().zip(identity) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!((value - expected).abs() < 1e-5, "{m:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn inverts_transforms() {
+        let m = Mat4::translate(Vec3::new(1.0, -2.0, 3.0))
+            .compose(&Mat4::rotate(35.0, Vec3::new(1.0, 1.0, 0.0)))
+            .compose(&Mat4::scale(2.0, 0.5, 4.0));
+        let inverse = m.inverse().unwrap();
+        assert_identity(&m.compose(&inverse));
+        assert_identity(&inverse.compose(&m));
+        let look_at = Mat4::look_at(
+            Point3::new(1.0, 2.0, 3.0),
+            Point3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_identity(&look_at.compose(&look_at.inverse().unwrap()));
+    }
+
+    #[test]
+    fn has_no_inverse_when_singular() {
+        assert!(Mat4::scale(1.0, 0.0, 1.0).inverse().is_none());
+    }
+
+    #[test]
+    fn composes_right_to_left() {
+        // Scaled first, then translated
+        let m = Mat4::translate(Vec3::new(1.0, 0.0, 0.0)).compose(&Mat4::scale(2.0, 2.0, 2.0));
+        let p = m.transform_point(Point3::new(1.0, 1.0, 1.0));
+        assert_eq!([p.x(), p.y(), p.z()], [3.0, 2.0, 2.0]);
+        assert_eq!(m.uniform_scale(), 2.0);
+    }
+}
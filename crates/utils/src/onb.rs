@@ -0,0 +1,45 @@
+use crate::vec3::{Vec3, cross, dot, unit_vector};
+
+/// An orthonormal basis of its `w` axis along a normal, bringing the directions
+/// sampled around the z axis, e.g. cosine-weighted or GGX, to the world and back.
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Builds the basis of `w` along `normal`, a unit vector, its `u` and `v` axes
+    /// tangent to it.
+    pub fn from_normal(normal: Vec3) -> Self {
+        let up = if normal.z().abs() < 0.999 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = unit_vector(cross(up, normal));
+        let v = cross(normal, u);
+        Onb { u, v, w: normal }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    /// Brings a direction of the basis, z up, to the world.
+    pub fn to_world(&self, local: Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+
+    /// Brings a direction of the world to the basis, z up.
+    pub fn to_local(&self, world: Vec3) -> Vec3 {
+        Vec3::new(dot(world, self.u), dot(world, self.v), dot(world, self.w))
+    }
+}
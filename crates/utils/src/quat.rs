@@ -0,0 +1,257 @@
+use crate::Float;
+use crate::mat4::Mat4;
+use crate::vec3::{Vec3, cross, dot};
+use serde::{Deserialize, Serialize};
+use std::ops::Mul;
+
+/// A rotation as a unit quaternion `w + xi + yj + zk`, interpolated without the gimbal
+/// lock of Euler angles, e.g. for camera rigs and instances.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Quat {
+    v: Vec3,
+    w: Float,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quat {
+    pub fn identity() -> Self {
+        Quat {
+            v: Vec3::zero(),
+            w: 1.0,
+        }
+    }
+
+    /// A rotation of `degrees` around `axis`, counterclockwise looking down the axis,
+    /// as `Mat4::rotate`.
+    pub fn from_axis_angle(axis: Vec3, degrees: Float) -> Self {
+        let (sin, cos) = (crate::degrees_to_radians(degrees) / 2.0).sin_cos();
+        Quat {
+            v: sin * axis.unit_vector(),
+            w: cos,
+        }
+    }
+
+    /// The rotation of a transform, the upper 3x3 of `m` with its columns normalized to
+    /// take the scale out. A shear or a mirroring is not a rotation, and gives a wrong
+    /// one.
+    pub fn from_mat4(m: &Mat4) -> Self {
+        let mut m = m.rows();
+        for column in 0..3 {
+            let length = (0..3)
+                .map(|row| m[row][column].powi(2))
+                .sum::<Float>()
+                .sqrt();
+            if length > 0.0 {
+                for row in m.iter_mut().take(3) {
+                    row[column] /= length;
+                }
+            }
+        }
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quat {
+                v: Vec3::new(
+                    (m[2][1] - m[1][2]) / s,
+                    (m[0][2] - m[2][0]) / s,
+                    (m[1][0] - m[0][1]) / s,
+                ),
+                w: s / 4.0,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quat {
+                v: Vec3::new(s / 4.0, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s),
+                w: (m[2][1] - m[1][2]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quat {
+                v: Vec3::new((m[0][1] + m[1][0]) / s, s / 4.0, (m[1][2] + m[2][1]) / s),
+                w: (m[0][2] - m[2][0]) / s,
+            }
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quat {
+                v: Vec3::new((m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.0),
+                w: (m[1][0] - m[0][1]) / s,
+            }
+        };
+        q.normalize()
+    }
+
+    pub fn x(&self) -> Float {
+        self.v.x()
+    }
+    pub fn y(&self) -> Float {
+        self.v.y()
+    }
+    pub fn z(&self) -> Float {
+        self.v.z()
+    }
+    pub fn w(&self) -> Float {
+        self.w
+    }
+
+    pub fn length(&self) -> Float {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quat {
+        let length = self.length();
+        Quat {
+            v: self.v / length,
+            w: self.w / length,
+        }
+    }
+
+    /// The inverse rotation of a unit quaternion.
+    pub fn conjugate(&self) -> Quat {
+        Quat {
+            v: -self.v,
+            w: self.w,
+        }
+    }
+
+    pub fn dot(&self, other: &Quat) -> Float {
+        dot(self.v, other.v) + self.w * other.w
+    }
+
+    /// The rotation applying `other`, then this one, also written `self * other`.
+    pub fn compose(&self, other: &Quat) -> Quat {
+        Quat {
+            v: self.w * other.v + other.w * self.v + cross(self.v, other.v),
+            w: self.w * other.w - dot(self.v, other.v),
+        }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let t = 2.0 * cross(self.v, v);
+        v + self.w * t + cross(self.v, t)
+    }
+
+    /// The rotation a fraction `t` of the way from this one to `other`, at a constant
+    /// angular speed along the shortest arc.
+    pub fn slerp(&self, other: &Quat, t: Float) -> Quat {
+        let mut cos = self.dot(other);
+        // The shortest arc, `q` and `-q` being the same rotation
+        let other = if cos < 0.0 {
+            cos = -cos;
+            Quat {
+                v: -other.v,
+                w: -other.w,
+            }
+        } else {
+            *other
+        };
+        let (a, b) = if cos > 0.9995 {
+            // Nearly the same rotations, linearly interpolated
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat {
+            v: a * self.v + b * other.v,
+            w: a * self.w + b * other.w,
+        }
+        .normalize()
+    }
+
+    /// The rotation matrix of a unit quaternion.
+    pub fn to_mat4(&self) -> Mat4 {
+        let (x, y, z, w) = (self.x(), self.y(), self.z(), self.w);
+        Mat4::from_rows([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, other: Quat) -> Quat {
+        self.compose(&other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_rotation(a: &Quat, b: &Quat) {
+        // `q` and `-q` are the same rotation
+        assert!(a.dot(b).abs() > 1.0 - 1e-5, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn round_trips_through_matrices() {
+        for (axis, degrees) in [
+            (Vec3::new(0.0, 1.0, 0.0), 30.0),
+            (Vec3::new(1.0, 2.0, 3.0), 120.0),
+            (Vec3::new(-1.0, 0.5, 0.0), 179.0),
+            (Vec3::new(0.0, 0.0, 1.0), 270.0),
+        ] {
+            let q = Quat::from_axis_angle(axis, degrees);
+            assert_same_rotation(&Quat::from_mat4(&q.to_mat4()), &q);
+            assert_same_rotation(&Quat::from_mat4(&Mat4::rotate(degrees, axis)), &q);
+        }
+    }
+
+    #[test]
+    fn takes_the_scale_out_of_matrices() {
+        let q = Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 60.0);
+        let m = Mat4::translate(Vec3::new(1.0, 2.0, 3.0))
+            .compose(&q.to_mat4())
+            .compose(&Mat4::scale(2.0, 0.5, 3.0));
+        assert_same_rotation(&Quat::from_mat4(&m), &q);
+    }
+
+    #[test]
+    fn slerps_between_the_endpoints() {
+        let a = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 10.0);
+        let b = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_same_rotation(&a.slerp(&b, 0.0), &a);
+        assert_same_rotation(&a.slerp(&b, 1.0), &b);
+        assert_same_rotation(
+            &a.slerp(&b, 0.5),
+            &Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 50.0),
+        );
+    }
+
+    #[test]
+    fn slerps_along_the_shortest_arc() {
+        let a = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0);
+        // 300 degrees one way is 60 degrees the other way, the quaternions facing away
+        let b = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 300.0);
+        assert!(a.dot(&b) < 0.0);
+        assert_same_rotation(
+            &a.slerp(&b, 0.5),
+            &Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -30.0),
+        );
+    }
+}
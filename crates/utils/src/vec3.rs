@@ -1,3 +1,4 @@
+use crate::Float;
 use crate::common;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter, Result};
@@ -6,18 +7,21 @@ use std::ops::{Index, IndexMut};
 
 #[derive(Copy, Clone, Default, Deserialize, Serialize, Debug)]
 pub struct Vec3 {
-    e: [f32; 3],
+    e: [Float; 3],
 }
 
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+    pub fn new(x: Float, y: Float, z: Float) -> Vec3 {
         Vec3 { e: [x, y, z] }
     }
+    pub fn zero() -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
     pub fn random() -> Vec3 {
         Vec3::new(common::random(), common::random(), common::random())
     }
 
-    pub fn random_range(min: f32, max: f32) -> Vec3 {
+    pub fn random_range(min: Float, max: Float) -> Vec3 {
         Vec3::new(
             common::random_range(min, max),
             common::random_range(min, max),
@@ -25,35 +29,35 @@ impl Vec3 {
         )
     }
 
-    pub fn x(&self) -> f32 {
+    pub fn x(&self) -> Float {
         self.e[0]
     }
 
-    pub fn y(&self) -> f32 {
+    pub fn y(&self) -> Float {
         self.e[1]
     }
 
-    pub fn z(&self) -> f32 {
+    pub fn z(&self) -> Float {
         self.e[2]
     }
     // This the length of the vector
-    pub fn length(&self) -> f32 {
-        f32::sqrt(self.length_squared())
+    pub fn length(&self) -> Float {
+        Float::sqrt(self.length_squared())
     }
     // This is the square of the length of the vector
-    pub fn length_squared(&self) -> f32 {
+    pub fn length_squared(&self) -> Float {
         self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
     }
     pub fn near_zero(&self) -> bool {
-        const EPS: f32 = 1.0e-8;
+        const EPS: Float = 1.0e-8;
         // Return true if the vector is close to zero in all dimensions
         self.e[0].abs() < EPS && self.e[1].abs() < EPS && self.e[2].abs() < EPS
     }
     // Linear interpolation between two Vec3s: self and other, by t ∈ [0,1]
-    pub fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+    pub fn lerp(self, other: Vec3, t: Float) -> Vec3 {
         self * (1.0 - t) + other * t
     }
-    pub fn clamp(self, min: f32, max: f32) -> Vec3 {
+    pub fn clamp(self, min: Float, max: Float) -> Vec3 {
         Vec3::new(
             common::clamp(self.x(), min, max),
             common::clamp(self.y(), min, max),
@@ -63,13 +67,13 @@ impl Vec3 {
     pub fn unit_vector(self) -> Vec3 {
         self / self.length()
     }
-    pub fn rotate(self, r_x: f32, r_y: f32, r_z: f32) -> Vec3 {
-        let cos_x = f32::cos(r_x);
-        let sin_x = f32::sin(r_x);
-        let cos_y = f32::cos(r_y);
-        let sin_y = f32::sin(r_y);
-        let cos_z = f32::cos(r_z);
-        let sin_z = f32::sin(r_z);
+    pub fn rotate(self, r_x: Float, r_y: Float, r_z: Float) -> Vec3 {
+        let cos_x = Float::cos(r_x);
+        let sin_x = Float::sin(r_x);
+        let cos_y = Float::cos(r_y);
+        let sin_y = Float::sin(r_y);
+        let cos_z = Float::cos(r_z);
+        let sin_z = Float::sin(r_z);
 
         Vec3::new(
             self.x() * (cos_y * cos_z) + self.y() * (cos_x * sin_z + sin_x * sin_y * cos_z)
@@ -81,25 +85,11 @@ impl Vec3 {
         )
     }
 }
-impl From<[f32; 3]> for Vec3 {
-    fn from(arr: [f32; 3]) -> Self {
+impl From<[Float; 3]> for Vec3 {
+    fn from(arr: [Float; 3]) -> Self {
         Vec3::new(arr[0], arr[1], arr[2])
     }
 }
-pub fn align_to_normal(local: Vec3, normal: Vec3) -> Vec3 {
-    // Assume Z-up in local, rotate to match `normal`
-    let up = if normal.z().abs() < 0.999 {
-        Vec3::new(0.0, 0.0, 1.0)
-    } else {
-        Vec3::new(1.0, 0.0, 0.0)
-    };
-
-    let tangent = unit_vector(cross(up, normal));
-    let bitangent = cross(normal, tangent);
-
-    local.x() * tangent + local.y() * bitangent + local.z() * normal
-}
-
 // Type alias
 pub type Point3 = Vec3;
 
@@ -111,7 +101,7 @@ impl Display for Vec3 {
 }
 
 impl Index<usize> for Vec3 {
-    type Output = f32;
+    type Output = Float;
 
     fn index(&self, i: usize) -> &Self::Output {
         &self.e[i]
@@ -140,16 +130,16 @@ impl AddAssign for Vec3 {
     }
 }
 
-// Vec3 *= f32
-impl MulAssign<f32> for Vec3 {
-    fn mul_assign(&mut self, t: f32) {
+// Vec3 *= Float
+impl MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, t: Float) {
         *self = *self * t;
     }
 }
 
-// Vec3 /= f32
-impl DivAssign<f32> for Vec3 {
-    fn div_assign(&mut self, t: f32) {
+// Vec3 /= Float
+impl DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, t: Float) {
         *self = *self / t;
     }
 }
@@ -181,8 +171,8 @@ impl Mul for Vec3 {
     }
 }
 
-// f32 * Vec3
-impl Mul<Vec3> for f32 {
+// Float * Vec3
+impl Mul<Vec3> for Float {
     type Output = Vec3;
 
     fn mul(self, v: Vec3) -> Vec3 {
@@ -190,25 +180,25 @@ impl Mul<Vec3> for f32 {
     }
 }
 
-// Vec3 * f32
-impl Mul<f32> for Vec3 {
+// Vec3 * Float
+impl Mul<Float> for Vec3 {
     type Output = Vec3;
 
-    fn mul(self, t: f32) -> Vec3 {
+    fn mul(self, t: Float) -> Vec3 {
         Vec3::new(self.x() * t, self.y() * t, self.z() * t)
     }
 }
 
-// Vec3 / f32
-impl Div<f32> for Vec3 {
+// Vec3 / Float
+impl Div<Float> for Vec3 {
     type Output = Vec3;
 
-    fn div(self, t: f32) -> Vec3 {
+    fn div(self, t: Float) -> Vec3 {
         Vec3::new(self.x() / t, self.y() / t, self.z() / t)
     }
 }
 
-pub fn dot(u: Vec3, v: Vec3) -> f32 {
+pub fn dot(u: Vec3, v: Vec3) -> Float {
     u.e[0] * v.e[0] + u.e[1] * v.e[1] + u.e[2] * v.e[2]
 }
 
@@ -238,6 +228,14 @@ pub fn random_unit_vector() -> Vec3 {
     unit_vector(random_in_unit_sphere())
 }
 
+// Map a point of the unit square onto a direction of the unit sphere, uniformly.
+pub fn sphere_direction(u: Float, v: Float) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = Float::sqrt(Float::max(1.0 - z * z, 0.0));
+    let phi = 2.0 * crate::consts::PI * v;
+    Vec3::new(r * Float::cos(phi), r * Float::sin(phi), z)
+}
+
 pub fn random_in_unit_disk() -> Vec3 {
     loop {
         let p = Vec3::new(
@@ -252,25 +250,50 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
+// Map a point of the unit square onto the unit disk (Shirley-Chiu concentric mapping),
+// preserving the stratification of the input samples.
+pub fn concentric_sample_disk(u: Float, v: Float) -> Vec3 {
+    let ox = 2.0 * u - 1.0;
+    let oy = 2.0 * v - 1.0;
+    if ox == 0.0 && oy == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, crate::consts::FRAC_PI_4 * (oy / ox))
+    } else {
+        (
+            oy,
+            crate::consts::FRAC_PI_2 - crate::consts::FRAC_PI_4 * (ox / oy),
+        )
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * dot(v, n) * n
 }
 
-pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
-    let cos_theta = f32::min(dot(-uv, n), 1.0);
+pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: Float) -> Vec3 {
+    let cos_theta = Float::min(dot(-uv, n), 1.0);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
-    let r_out_parallel = -f32::sqrt(f32::abs(1.0 - r_out_perp.length_squared())) * n;
+    let r_out_parallel = -Float::sqrt(Float::abs(1.0 - r_out_perp.length_squared())) * n;
     r_out_perp + r_out_parallel
 }
 
 pub fn random_cosine_direction() -> Vec3 {
     let r1 = common::random();
     let r2 = common::random();
-    let z = f32::sqrt(1.0 - r2);
+    cosine_direction(r1, r2)
+}
+
+// Map a point of the unit square onto a cosine-weighted direction of the hemisphere
+// around z, e.g. a sample of a `Sampler`.
+pub fn cosine_direction(r1: Float, r2: Float) -> Vec3 {
+    let z = Float::sqrt(1.0 - r2);
 
-    let phi = 2.0 * std::f32::consts::PI * r1;
-    let x = f32::cos(phi) * f32::sqrt(r2);
-    let y = f32::sin(phi) * f32::sqrt(r2);
+    let phi = 2.0 * crate::consts::PI * r1;
+    let x = Float::cos(phi) * Float::sqrt(r2);
+    let y = Float::sin(phi) * Float::sqrt(r2);
 
     Vec3::new(x, y, z)
 }